@@ -0,0 +1,208 @@
+//! Proves the same JSON documents with the two validity checks in this crate and reports `k`, rows used and proving time:
+//! - `JsonCircuit`: the direct gate circuit in `circuits/json.rs` (PSE halo2_proofs)
+//! - `StateMachineConfig` + `StateMachineChip`: the lookup chip in `state_machine_chip/json_gate.rs` (halo2-lib's halo2_proofs)
+//!
+//! Run with `cargo run --release --example json_bench`
+//!
+//! Summary of the tradeoffs (see the printed numbers for the current values):
+//! - Rows: the gate circuit uses one row per byte plus a start row, and its tables (the 224 row string byte table and
+//!   the grammar's) fit under 247 rows, so documents up to 240 bytes fit in k = 8. The chip uses two rows per byte plus
+//!   one, but its transition table alone is 24658 rows, so it never goes below k = 15. Past ~16400 bytes the chip is
+//!   bound by the document too, and is then always one k above the gate circuit
+//! - Columns: the gate circuit has 15 advice columns and 3 lookups (byte class, container stack, and the 14 column
+//!   grammar table, which sets its degree of 7); the chip has one transcript column (plus the flex gate's) and a single
+//!   3 column lookup, so each row is much cheaper to commit to
+//! - Coverage: the gate circuit checks the whole grammar, literals, negative numbers and exponents included, nested
+//!   up to `MAX_DEPTH` = 6. The chip checks keys, strings and numbers with a '.', nested up to `MAX_NESTING` = 3, and
+//!   rejects `true`, `false`, `null`, '-' and exponents, so the gate circuit now subsumes it
+//! Rule of thumb: the gate circuit, unless documents are long plain strings and numbers, where the chip's narrower rows
+//! may still make up for its taller table
+//!
+//! Then it re-proves one document with the chip in chunks (`ChunkedProofContext`), after changing one value in place:
+//! in full, every chunk again, and incrementally with `reprove_with_change`, only the chunk the value is in. Every chunk
+//! is a full k = 15 proof, so the incremental re-prove costs about one chunk's proof against the document's chunk count
+use ark_std::{end_timer, start_timer};
+use halo2_scaffold::circuits::json::{min_k_for_json, JsonCircuit, JsonConfigParams};
+use halo2_scaffold::state_machine_chip::json_gate::{
+    min_k_for_state_machine, reprove_with_change, verify_chunked, ChunkedProofContext, StateMachineChip,
+    StateMachineConfig, StateMachineStrategy, CHUNK_K,
+};
+use halo2_scaffold::state_machine_chip::json_state_machine::{transition_table, ActionId};
+use rand::rngs::OsRng;
+
+const DOCUMENTS: [&str; 3] = [
+    "{\"a\":1}",
+    "{\"name\": \"halo2\", \"version\": 2, \"tags\": {\"zk\": \"yes\", \"escaped\": \"a\\\"b\"}}",
+    "{\"a\": [{\"b\": \"deeply nested value with some padding to make it longer\"}]}",
+];
+
+// Large enough for the transition table and every document above
+const STATE_MACHINE_K: usize = 15;
+
+mod gate_circuit {
+
+    use super::*;
+    use halo2_proofs::{
+        halo2curves::bn256::{Bn256, Fr, G1Affine},
+        plonk::{create_proof, keygen_pk, keygen_vk},
+        poly::kzg::{commitment::{KZGCommitmentScheme, ParamsKZG}, multiopen::ProverSHPLONK},
+        transcript::{Blake2bWrite, Challenge255, TranscriptWriterBuffer},
+    };
+
+    pub fn prove(json: &str, k: u32) -> usize {
+
+        let circuit = JsonCircuit::<Fr>::from_str(json).unwrap();
+        let params = ParamsKZG::<Bn256>::setup(k, OsRng);
+        let vk = keygen_vk(&params, &circuit).expect("vk should not fail");
+        let pk = keygen_pk(&params, vk, &circuit).expect("pk should not fail");
+
+        let pf_time = start_timer!(|| format!("Gate circuit proof, {} bytes, k = {}", json.len(), k));
+        let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
+        create_proof::<
+            KZGCommitmentScheme<Bn256>,
+            ProverSHPLONK<'_, Bn256>,
+            Challenge255<G1Affine>,
+            _,
+            Blake2bWrite<Vec<u8>, G1Affine, Challenge255<_>>,
+            _,
+        >(&params, &pk, &[circuit], &[&[]], OsRng, &mut transcript)
+        .expect("prover should not fail");
+        let proof = transcript.finalize();
+        end_timer!(pf_time);
+
+        proof.len()
+    }
+}
+
+mod lookup_chip {
+
+    use super::*;
+    use halo2_base::halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner},
+        halo2curves::bn256::{Bn256, Fr, G1Affine},
+        plonk::{create_proof, keygen_pk, keygen_vk, Circuit, ConstraintSystem, Error},
+        poly::kzg::{commitment::{KZGCommitmentScheme, ParamsKZG}, multiopen::ProverSHPLONK},
+        transcript::{Blake2bWrite, Challenge255, TranscriptWriterBuffer},
+    };
+    use halo2_base::Context;
+
+    #[derive(Clone, Default)]
+    struct StateMachineCircuit {
+        input: String,
+    }
+
+    impl Circuit<Fr> for StateMachineCircuit {
+
+        type Config = StateMachineConfig<Fr>;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            StateMachineConfig::configure(meta, StateMachineStrategy::Vertical, &[1], 1, STATE_MACHINE_K)
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+
+            let chip = StateMachineChip::<Fr>::from_lookup_table(StateMachineStrategy::Vertical, &transition_table());
+            let mut ctx = Context::new(false, 0);
+            chip.assign_input(&mut ctx, self.input.as_bytes())?;
+
+            config.load_lookup_table(&mut layouter, "./data/lookup_table.txt")?;
+            config.assign_transcript(&mut layouter, &ctx)
+        }
+    }
+
+    pub fn prove(json: &str) -> usize {
+
+        let k = STATE_MACHINE_K as u32;
+        let circuit = StateMachineCircuit { input: String::from(json) };
+        let params = ParamsKZG::<Bn256>::setup(k, OsRng);
+        let vk = keygen_vk(&params, &circuit).expect("vk should not fail");
+        let pk = keygen_pk(&params, vk, &circuit).expect("pk should not fail");
+
+        let chip = StateMachineChip::<Fr>::from_lookup_table(StateMachineStrategy::Vertical, &transition_table());
+        let instances = chip.instances(json.bytes().map(ActionId::from));
+
+        let pf_time = start_timer!(|| format!("Lookup chip proof, {} bytes, k = {}", json.len(), k));
+        let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
+        create_proof::<
+            KZGCommitmentScheme<Bn256>,
+            ProverSHPLONK<'_, Bn256>,
+            Challenge255<G1Affine>,
+            _,
+            Blake2bWrite<Vec<u8>, G1Affine, Challenge255<_>>,
+            _,
+        >(&params, &pk, &[circuit], &[&[&instances[..]]], OsRng, &mut transcript)
+        .expect("prover should not fail");
+        let proof = transcript.finalize();
+        end_timer!(pf_time);
+
+        proof.len()
+    }
+}
+
+mod incremental {
+
+    use super::*;
+    use halo2_base::halo2_proofs::{halo2curves::bn256::Bn256, poly::kzg::commitment::ParamsKZG};
+
+    const CHUNK_LEN: usize = 256;
+
+    // An object of `fields` string values, one of them changed in place
+    pub fn compare(fields: usize) {
+
+        let document = format!("{{{}}}", (0..fields).map(|i| format!("\"k{:03}\":\"v{:03}\"", i, i)).collect::<Vec<_>>().join(","));
+        let at = document.find(&format!("\"v{:03}\"", fields / 2)).expect("the middle value") + 1;
+        let mut changed = document.clone().into_bytes();
+        changed[at] = b'w';
+
+        let params = ParamsKZG::<Bn256>::setup(CHUNK_K as u32, OsRng);
+        let mut ctx = ChunkedProofContext::new(params, CHUNK_LEN).expect("chunks fit k");
+
+        // The first run generates the keys, so it is left out of the comparison
+        let first_time = start_timer!(|| format!("Chunked proof with keygen, {} bytes", document.len()));
+        ctx.prove(document.as_bytes()).expect("prover should not fail");
+        end_timer!(first_time);
+
+        let full_time = start_timer!(|| format!("Full re-prove, {} chunks of {} bytes", ctx.chunks().len(), CHUNK_LEN));
+        let full = ctx.prove(&changed).expect("prover should not fail");
+        end_timer!(full_time);
+
+        // And back, through the one chunk holding the value
+        let incremental_time = start_timer!(|| "Incremental re-prove");
+        let incremental = reprove_with_change(&mut ctx, at..at + 1, b"v").expect("prover should not fail");
+        end_timer!(incremental_time);
+
+        assert_eq!(ctx.document(), document.as_bytes());
+        assert!(verify_chunked(ctx.params(), ctx.chunks()).is_ok());
+        println!("  chunks re-proven: {} in full, {:?} incrementally", full.len(), incremental);
+    }
+}
+
+fn main() {
+
+    for json in DOCUMENTS {
+
+        // The gate circuit needs k >= 8 for its byte table, plus ~9 rows for blinding
+        let gate_rows = json.len();
+        let gate_k = min_k_for_json(gate_rows, &JsonConfigParams::default());
+
+        let chip_rows = 2 * json.len() + 1;
+        assert!(min_k_for_state_machine(json.len()) <= STATE_MACHINE_K, "increase STATE_MACHINE_K");
+
+        println!("document of {} bytes", json.len());
+        println!("  gate circuit: k = {}, {} rows", gate_k, gate_rows);
+        let gate_proof = gate_circuit::prove(json, gate_k);
+        println!("  gate circuit: proof of {} bytes", gate_proof);
+
+        println!("  lookup chip:  k = {}, {} transcript rows, {} table rows", STATE_MACHINE_K, chip_rows, transition_table().len());
+        let chip_proof = lookup_chip::prove(json);
+        println!("  lookup chip:  proof of {} bytes", chip_proof);
+    }
+
+    println!("chunked document, one value changed");
+    incremental::compare(80);
+}
@@ -1,491 +1,8483 @@
 #![allow(unused_imports)]
 use halo2_proofs::{
-    circuit::{Layouter, SimpleFloorPlanner, Value},
-    halo2curves::FieldExt,
-    plonk::{Advice, Circuit, Column, ConstraintSystem, Error, Expression, Selector},
-    poly::Rotation,
+    circuit::{AssignedCell, Layouter, Region, SimpleFloorPlanner, Value},
+    halo2curves::{bn256::{Bn256, Fr, G1Affine}, FieldExt},
+    plonk::{keygen_vk, verify_proof, Advice, Circuit, Column, ConstraintSystem, Error, Expression, Fixed, Instance, Selector, TableColumn, VerifyingKey, VirtualCells},
+    poly::{
+        commitment::{Params, ParamsProver},
+        kzg::{commitment::{KZGCommitmentScheme, ParamsKZG}, multiopen::VerifierSHPLONK, strategy::SingleStrategy},
+        Rotation,
+    },
+    transcript::{Blake2bRead, Challenge255, TranscriptReadBuffer},
+    SerdeFormat,
 };
 use std::cell::RefCell;
 use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::marker::PhantomData;
+use crate::circuits::poseidon::{PoseidonConfig, PoseidonInput, PoseidonParams};
+use crate::state_machine_chip::json_state_machine::escape_len;
+use crate::state_machine_chip::json_parser::{parse, JsonNode, ParseError};
 
 // Chip for reading JSON strings
 // Consider the following json string: {"x": {"a{\""}":1}}. We want to prove that x["a{}"] == 1
-// (1) Validity check. Raw string is in the first column; not_esc tracks "{" chars in quotes " " / ' '
-//      | raw   | [special_chars]   | not_str   | str_escaped   | level     | [query]    | struct_selector       | [start/end_select]  |
-//      | {     | ...               | 1         | 0             | 1         | ...        | 0                     | 1                   |
-//      | "     | ...               | 0         | 0             | 1         | ...        | 1                     | 0                   |
-//      | x     | ...               | 0         | 0             | 1         | ...        | 1                     | 0                   |
-//      | "     | ...               | 1         | 0             | 1         | ...        | 1                     | 0                   |
-//      | :     | ...               | 1         | 0             | 1         | ...        | 1                     | 0                   |
-//      | {     | ...               | 1         | 0             | 2         | ...        | 1                     | 0                   |
-//      | "     | ...               | 0         | 0             | 2         | ...        | 1                     | 0                   |
-//      | a     | ...               | 0         | 0             | 2         | ...        | 1                     | 0                   |
-//      | \     | ...               | 0         | 1             | 2         | ...        | 1                     | 0                   |
-//      | "     | ...               | 0         | 0             | 2         | ...        | 1                     | 0                   |
-//      | {     | ...               | 0         | 0             | 2         | ...        | 1                     | 0                   |
-//      | }     | ...               | 0         | 0             | 2         | ...        | 1                     | 0                   |
-//      | "     | ...               | 1         | 0             | 2         | ...        | 1                     | 0                   |
-//      | :     | ...               | 1         | 0             | 2         | ...        | 1                     | 0                   |
-//      | 1     | ...               | 1         | 0             | 2         | ...        | 1                     | 0                   |
-//      | }     | ...               | 1         | 0             | 1         | ...        | 1                     | 0                   |
-//      | }     | ...               | 1         | 0             | 0         | ...        | 0                     | 1                   |
+// (1) Validity check. Raw string is in the first column, one byte per row after a start row. Each byte looks up
+//      (previous state, class, innermost container) in the grammar's transition table; the stack holds the open
+//      containers, one bit each under a leading 1 (0 for '{', 1 for '['), and level counts them
+//      | raw   | [class]   | state         | stack | not_str   | str_escaped   | level     | [query]    | body_select | [start/end_select]  |
+//      |       |           | Start         | 1     | 1         | 0             | 0         | ...        | 0           | 1 / 0               |
+//      | {     | ...       | ObjectOpen    | 2     | 1         | 0             | 1         | ...        | 1           | 0 / 0               |
+//      | "     | ...       | Key           | 2     | 0         | 0             | 1         | ...        | 1           | 0 / 0               |
+//      | x     | ...       | Key           | 2     | 0         | 0             | 1         | ...        | 1           | 0 / 0               |
+//      | "     | ...       | AfterKey      | 2     | 1         | 0             | 1         | ...        | 1           | 0 / 0               |
+//      | :     | ...       | ValueExpected | 2     | 1         | 0             | 1         | ...        | 1           | 0 / 0               |
+//      | {     | ...       | ObjectOpen    | 4     | 1         | 0             | 2         | ...        | 1           | 0 / 0               |
+//      | "     | ...       | Key           | 4     | 0         | 0             | 2         | ...        | 1           | 0 / 0               |
+//      | a     | ...       | Key           | 4     | 0         | 0             | 2         | ...        | 1           | 0 / 0               |
+//      | \     | ...       | KeyEscaped    | 4     | 0         | 1             | 2         | ...        | 1           | 0 / 0               |
+//      | "     | ...       | Key           | 4     | 0         | 0             | 2         | ...        | 1           | 0 / 0               |
+//      | {     | ...       | Key           | 4     | 0         | 0             | 2         | ...        | 1           | 0 / 0               |
+//      | }     | ...       | Key           | 4     | 0         | 0             | 2         | ...        | 1           | 0 / 0               |
+//      | "     | ...       | AfterKey      | 4     | 1         | 0             | 2         | ...        | 1           | 0 / 0               |
+//      | :     | ...       | ValueExpected | 4     | 1         | 0             | 2         | ...        | 1           | 0 / 0               |
+//      | 1     | ...       | Integer       | 4     | 1         | 0             | 2         | ...        | 1           | 0 / 0               |
+//      | }     | ...       | AfterValue    | 2     | 1         | 0             | 1         | ...        | 1           | 0 / 0               |
+//      | }     | ...       | AfterValue    | 1     | 1         | 0             | 0         | ...        | 1           | 0 / 1               |
 
 // (2) Query check matches both the level and the key, value pairs
 //      TBD
 //      - Can use halo2-lib more extensively here for substring matching
 //      - For efficiency, share the raw column
+//      - Required non-empty field: `KeyValueCircuit::non_empty_from_str`, with the value witnessed and only its opening
+//        quote and first byte of content probed. The key is found as `KeyValueCircuit`'s, so its position is hidden
+//      - Value in a public set (e.g. "status" is one of "active", "inactive", "pending"): `ValueInSetCircuit`, with the
+//        key found like `KeyValueCircuit`'s and the value's length witnessed over a window of probes as wide as the
+//        longest entry
+//      - Equal values at two key paths (e.g. `a.id == b.ref`): `PathEqualityCircuit`, each key after the first probed
+//        at a witnessed position, inside the previous step's object by the byte table's innermost opener
+//      - Substring of a value (e.g. "log" contains "ERROR"): `SubstringCircuit`, the key found like `KeyValueCircuit`'s
+//        and the substring probed at a witnessed position, kept inside the value's string by the byte table's last
+//        opening quote
+//      - Closed schema (top-level keys are exactly a given set): `SchemaCircuit`, a lookup each way between the
+//        witnessed top-level keys (`KeyRunsConfig`) and the schema's entries
 // (3) TODO
 //      - DEFER to Regex: form of keys and values
 //      - DEFER to RLC: Substring existence
-//      - Support variable hidden rows to mask the length of the string
+//      - Support variable hidden rows to mask the length of the string: `JsonConfigParams::expose_length` pads the
+//        document with `PAD_BYTE` up to a fixed size, with is_padding set by the grammar from the first pad byte on
+//        (exactly one 0 -> 1 step), and `LengthCircuit` exposes the true length anyway as an instance
+//      - Array length: `ArrayCircuit`, a counter column over the values whose previous byte has the queried array's '['
+//        as its innermost opener, exposed as an instance; `array_len` is the reference, and `[]` counts 0. The '[' is
+//        probed at a witnessed position, where the key found like `KeyValueCircuit`'s has its value
+//      - Field of an array element (e.g. `[{"id":1},{"id":2}][1].id == 2`, index public): `ElementFieldCircuit`, the
+//        element witnessed and looked up in the counter above at the index, and its key probed directly inside it
+//      - Typed arrays (every element of a queried array has one claimed type): `ArrayCircuit` with an element type,
+//        checked against the value_type of each element's first byte; `JsonNode::is_array_of` is the reference. The
+//        array's ']' is probed at a witnessed position
+//      - Selective disclosure: `KeyCommitmentsCircuit`, a salted Poseidon commitment per top-level key slot in
+//        document order, exposed as instances, over each key's bytes packed from its window (`KeyRunsConfig`). The
+//        halo2-lib Poseidon chip lives on halo2-base's halo2, so `circuits::poseidon` has the same permutation for this
+//        config
+//      - Top-level key ranges as instances: `KeyRangesCircuit`, with the runs of the key flag column
+//        (`JsonConfigParams::key_flag`) witnessed into up to `MAX_TOP_LEVEL_KEYS` slots, see `KeyRunsConfig`
+//      - Top-level keys in strictly increasing order: `SortedKeysCircuit`, each key's packed window (`KeyRunsConfig`)
+//        range-checked larger than the one before
+//      - Key counts per level as instances: `KeyCountsCircuit`, one accumulator column per level up to `MAX_DEPTH`,
+//        incremented where `key` starts at that level, with the levels from the cap on summed into the last count
+//      - Numeric values are never accumulated into field elements yet. Every run of digits outside strings is counted
+//        in `digit_run` and looked up in 0..=MAX_NUMBER_DIGITS, so a range / equality check on numbers can accumulate
+//        each run without wrapping. The pre-flight refuses a longer run with its index before the circuit is built
+//      - Chained log records: `ChainRecordCircuit` exposes (previous commitment, new commitment), the new one a Poseidon
+//        hash of the previous one and the record (`record_commitment`). `verify_chain` verifies each record's proof and
+//        chains those pairs
+//      - Negative proofs: `ValidityCircuit`, on `JsonConfigParams::soft_validity`, proves any document and exposes
+//        whether it is valid as an instance
+//      - Base64 payloads: `Base64Circuit`, a decode config (4 sextets -> 3 bytes per row, '=' trimming the last group)
+//        whose bytes are copied from `raw`, on the `ValidityCircuit` config. The base64 input stays a witness
+//      - Merge Patch (RFC 7386) one level deep: `MergePatchCircuit`, three witnessed objects on `KeyValueConfig`s, each
+//        result key copying the window and value of its key in the base or the patch, and kept or deleted keys proven
+//        absent from the patch or the result by a product of differences over the windows. `JsonNode::merge_patch` is
+//        the reference, for patches without object values
+//      - Commitment to a `serde_json::Value`: `CanonicalCommitmentCircuit`, a hidden document and its canonical form
+//        (`JsonNode::to_canonical_string`) proven equal up to key order as in `StructuralEqualityCircuit`, the form
+//        compact with each object's keys increasing, then hashed with Poseidon as `value_commitment`, public
+//      - Incremental re-proving: on the lookup chip, where a state id carries a chunk's boundary. `json_gate` proves a
+//        document in chunks (`ChunkedProofContext`), and `reprove_with_change` re-proves only those a change reaches.
+//        This chip proves a document in one circuit, where a changed byte changes every column commitment
+//      - Equality up to key order at every level: `StructuralEqualityCircuit`, every key of one witnessed object
+//        matched to one of the other, each probed at a witnessed position inside the object of its parent's match.
+//        Every ':' outside strings is a matched key's, so none is left over; arrays and other values compare byte for
+//        byte
+//      - Fixed-layout verifier: `verify_fixed(proof, instances)`, for `LengthCircuit` at one padded length and k
+//        (`FIXED_LEN`, `FIXED_K`), against KZG params and a verifying key read from `params/`, where the scaffold's
+//        `gen_srs` and `write_fixed_vk` serialize them once. It runs no keygen, and rejects a proof of another layout
 
 
 #[derive(Clone, Copy, Debug)]
 pub struct JsonConfig {
 
     raw: Column<Advice>,
+    class: Column<Advice>,    // `ByteClass` of raw, from the byte class table
+    ctx_flag: Column<Advice>, // 1 on the classes whose transition depends on the innermost container: ',' '}' ']'
 
-    backslash_inv: Column<Advice>,
-    double_quote_inv: Column<Advice>,
-    open_brace_inv: Column<Advice>,
-    close_brace_inv: Column<Advice>,
-
-    backslash: Column<Advice>,      // \
-    double_quote: Column<Advice>,   // "
-    open_brace: Column<Advice>,     // {
-    close_brace: Column<Advice>,    // }
+    state: Column<Advice>,      // `GrammarState` after this byte
+    stack: Column<Advice>,      // open containers as bits below a leading 1, innermost lowest: 0 for '{', 1 for '['
+    container: Column<Advice>,  // innermost open container, see `Container`
+    push: Column<Advice>,       // this byte opens a container
+    pop: Column<Advice>,        // this byte closes one
+    value_type: Column<Advice>, // `ValueType` of the value this byte starts, 0 elsewhere
+    accept: Column<Advice>,     // the document may end after this byte, if no container is open
 
     not_str: Column<Advice>,
     str_escaped: Column<Advice>,
-    level: Column<Advice>,
-    level_inv: Column<Advice>,
+    level: Column<Advice>,       // number of open containers, objects and arrays alike
+    char_class: Column<Advice>,  // see `CharClass`
+    any_invalid: Column<Advice>, // latched to 1 by the first byte the grammar rejects, and must be 0 at the end
+    digit_run: Column<Advice>,   // digits so far in the run outside strings this byte is in, 0 on any other byte
+    digit_inv: Column<Advice>,   // inverse of (class - `ByteClass::Digit`) * (class - `ByteClass::HighDigit`), or 0
+
+    body_selector: Selector,  // every byte
+    start_selector: Selector, // the row before the first byte, holding the start state
+    end_selector: Selector,   // the last byte
+
+    class_table: [TableColumn; 3],       // (byte, class, ctx_flag), see `byte_class_rows`
+    stack_table: [TableColumn; 2],       // (stack, container), see `stack_rows`
+    transition_table: [TableColumn; 14], // see `transition_rows`
+    string_byte_table: TableColumn,      // raw - 0x20 for every byte allowed unescaped inside a string
+    digit_run_table: TableColumn,        // 0..=MAX_NUMBER_DIGITS
+    string_charset: Option<&'static [u8]>,
+
+    top_level_scalar: bool,
+    utf8: Option<Utf8Config>,
+    keys: Option<KeyConfig>,
+    validity: Option<ValidityConfig>,
+    length: Option<LengthConfig>,
+    elems: Option<ElemsConfig>,
+}
+
+// Columns for the strict UTF-8 check, see `JsonConfigParams::strict_utf8`
+#[derive(Clone, Copy, Debug)]
+struct Utf8Config {
+    pending: Column<Advice>, // UTF-8 decoding state after this byte, see `utf8_step`
+    table: [TableColumn; 3], // (byte, pending before, pending after) for every valid step
+}
+
+// Columns for the key flags, see `JsonConfigParams::key_flag`
+#[derive(Clone, Copy, Debug)]
+struct KeyConfig {
+    key: Column<Advice>, // 1 from the byte after a key's opening quote to the byte before its ':', at any level
+    top_key: Column<Advice>, // key, at level 1
+    key_count: Column<Advice>, // number of keys started so far
+    level_one_inv: Column<Advice>, // inverse of level - 1
+}
+
+// Columns for the validity result, see `JsonConfigParams::soft_validity`. Only assigned on the last byte
+#[derive(Clone, Copy, Debug)]
+struct ValidityConfig {
+    is_valid: Column<Advice>, // 1 iff nothing was rejected, a whole value ended and every container is closed
+    root_inv: Column<Advice>, // inverse of stack - 1, or 0 if the stack is empty
+}
+
+// Columns for the document length, see `JsonConfigParams::expose_length`
+#[derive(Clone, Copy, Debug)]
+struct LengthConfig {
+    is_padding: Column<Advice>, // 1 from the first `PAD_BYTE` after the document on, as the grammar has it
+    length: Column<Advice>,     // number of bytes before the padding so far
+}
+
+// Columns for the element counts, see `JsonConfigParams::max_elems`
+#[derive(Clone, Copy, Debug)]
+struct ElemsConfig {
+    at: [Column<Advice>; MAX_DEPTH],    // 1 in the column of the level before this byte, none at level 0
+    count: [Column<Advice>; MAX_DEPTH], // members / elements so far of the container open at each level 1..=MAX_DEPTH
+    value_inv: Column<Advice>,          // inverse of value_type, or 0
+    table: TableColumn,                 // 0..=max_elems
+    max_elems: usize,
+}
 
-    body_selector: Selector,
-    start_selector: Selector,
-    end_selector: Selector,
-    json_all: Selector,
+// Strings checked against `JsonConfigParams::string_charset`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CharsetScope {
+    #[default]
+    All,
+    KeysOnly,   // the contents of keys, as flagged by `key`
+    ValuesOnly, // every other string, so also the opening quotes of keys
+}
+
+// Options for `JsonConfig::configure_with_params`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonConfigParams {
+    // Equality is always on for raw, not_str, level, char_class and value_type, the columns other circuits copy from.
+    // Turning it on for the remaining columns only adds to the permutation argument
+    pub equality_on_all_columns: bool,
+    // Only allow these bytes unescaped inside strings (e.g. printable ASCII), instead of everything from 0x20 up.
+    // Applies to the strings of `string_charset_scope`, and must contain b' ', whose table entry is also the input
+    // outside strings. Control chars are left to the grammar, whatever the charset says
+    pub string_charset: Option<&'static [u8]>,
+    // Which strings `string_charset` applies to. Narrowing it needs `key_flag`
+    pub string_charset_scope: CharsetScope,
+    // Accept a top-level scalar (a number, string, true, false or null, e.g. `42`, `"abc"`) instead of an object or
+    // array. The grammar starts in `GrammarState::StartScalar` instead, which opens no container
+    pub top_level_scalar: bool,
+    // Require string contents to be well-formed UTF-8 (no stray continuation bytes, overlongs or surrogates).
+    // Adds one advice column and a 499 row table, so it needs k >= 9
+    pub strict_utf8: bool,
+    // Flag the keys: their contents, closing quote and any whitespace before the ':' (`key`, at any level, and
+    // `top_key` at level 1), with a running count of keys. Adds four advice columns. The flags and the count have
+    // equality on, for circuits that check keys (e.g. `KeyRangesCircuit`)
+    pub key_flag: bool,
+    // Prove any document, valid or not, with its validity in the `is_valid` cell instead of the end state being
+    // constrained (see `ValidityCircuit`). The grammar's tables get a row for every rejected step and every control
+    // char, so a rejected byte latches `any_invalid` instead of failing. Adds two advice columns, and the transition
+    // table needs k >= 11 (k >= 12 with `expose_length`). Nesting past `MAX_DEPTH`, `string_charset`, `strict_utf8`,
+    // `max_elems` and digit runs past `MAX_NUMBER_DIGITS` are still hard constraints
+    pub soft_validity: bool,
+    // Allow `PAD_BYTE` after a complete top-level value, so that documents of any length up to a fixed size share one
+    // layout, and count the bytes before it into the `length` cell (see `LengthCircuit`). The grammar sets is_padding
+    // on the first pad byte and every byte after it, and no other byte may follow. Adds two advice columns. With
+    // `soft_validity`, the count only means something for a valid document
+    pub expose_length: bool,
+    // No object or array may hold more than this many members / elements, as `validate_with_max_elems`. A stack of
+    // counters, one per level up to `MAX_DEPTH`: opening a container resets the counter of its level, and a value
+    // starting one level in adds to it. The innermost counter is looked up in a 0..=max_elems table, so a member is
+    // counted at its value rather than its key. Adds 2 * MAX_DEPTH + 1 advice columns, and a hard constraint even with
+    // `soft_validity`
+    pub max_elems: Option<usize>,
 }
 
 impl JsonConfig {
 
     pub fn configure<F: FieldExt>(meta: &mut ConstraintSystem<F>) -> Self {
+        Self::configure_with_params(meta, JsonConfigParams::default())
+    }
+
+    // Every byte row looks up (previous state, byte class, innermost container) in the transition table, which only
+    // holds the steps `grammar_step` allows, so every row is checked against the grammar whatever the prover assigns.
+    // Containers are a stack of bits in one column, so a close always matches its open
+    pub fn configure_with_params<F: FieldExt>(meta: &mut ConstraintSystem<F>, params: JsonConfigParams) -> Self {
 
-        let [ raw, backslash_inv, double_quote_inv, open_brace_inv, 
-                close_brace_inv, backslash, double_quote, open_brace, 
-                close_brace, not_str, str_escaped, level, level_inv] = [(); 13].map(|_| meta.advice_column());
+        let [raw, class, ctx_flag, state, stack, container, push, pop, value_type, accept, not_str, str_escaped, level,
+             char_class, any_invalid, digit_run, digit_inv] = [(); 17].map(|_| meta.advice_column());
 
-        let body_selector = meta.selector();
+        let body_selector = meta.complex_selector(); // also used in lookups
         let start_selector = meta.selector();
         let end_selector = meta.selector();
-        let json_all = meta.selector();
 
-        [raw, backslash_inv, double_quote_inv, open_brace_inv, close_brace_inv, 
-         backslash, double_quote, open_brace, close_brace, not_str, str_escaped, level, level_inv].map(|column| meta.enable_equality(column));
+        let class_table = [(); 3].map(|_| meta.lookup_table_column());
+        let stack_table = [(); 2].map(|_| meta.lookup_table_column());
+        let transition_table = [(); 14].map(|_| meta.lookup_table_column());
+        let string_byte_table = meta.lookup_table_column();
+        let digit_run_table = meta.lookup_table_column();
 
-        // Set boolean columns to 0 or 1
-        meta.create_gate("Booleans", |meta|{
+        if let Some(charset) = params.string_charset {
+            assert!(charset.contains(&b' '), "string_charset must contain b' '");
+        }
+        if params.string_charset_scope != CharsetScope::All {
+            assert!(params.key_flag, "string_charset_scope needs key_flag");
+        }
 
-            let e = meta.query_advice(not_str, Rotation::cur());
-            let bs = meta.query_advice(backslash, Rotation::cur());
-            let dq = meta.query_advice(double_quote, Rotation::cur());
-            let ob = meta.query_advice(open_brace, Rotation::cur());
-            let cb = meta.query_advice(close_brace, Rotation::cur());
+        [raw, not_str, level, char_class, value_type].map(|column| meta.enable_equality(column));
+        if params.equality_on_all_columns {
+            [class, ctx_flag, state, stack, container, push, pop, accept, str_escaped, any_invalid].map(|column| meta.enable_equality(column));
+        }
+
+        // The row before the first byte: the start state, no container open, outside any string, no digit run. Its
+        // level is pinned to 0 outright, which is stronger than a `level * level_inv` check, so there is no level_inv
+        // column to keep consistent
+        meta.create_gate("Start state", |meta| {
+
+            let s = meta.query_advice(state, Rotation::cur());
+            let st = meta.query_advice(stack, Rotation::cur());
+            let c = meta.query_advice(container, Rotation::cur());
+            let l = meta.query_advice(level, Rotation::cur());
+            let ns = meta.query_advice(not_str, Rotation::cur());
             let str_esc = meta.query_advice(str_escaped, Rotation::cur());
+            let any = meta.query_advice(any_invalid, Rotation::cur());
+            let run = meta.query_advice(digit_run, Rotation::cur());
 
-            let all = meta.query_selector(json_all);
+            let start_s = meta.query_selector(start_selector);
 
             let one = Expression::Constant(F::one());
-            let expr_1 = all.clone() * e.clone() * (one.clone() - e);
-            let expr_2 = all.clone() * bs.clone() * (one.clone() - bs);
-            let expr_3 = all.clone() * dq.clone() * (one.clone() - dq);
-            let expr_4 = all.clone() * ob.clone() * (one.clone() - ob);
-            let expr_5 = all.clone() * cb.clone() * (one.clone() - cb);
-            let expr_6 = all.clone() * str_esc.clone() * (one.clone() - str_esc.clone());
+            let start = if params.top_level_scalar { GrammarState::StartScalar } else { GrammarState::Start };
 
-            vec![expr_1, expr_2, expr_3, expr_4, expr_5, expr_6]
+            vec![
+                start_s.clone() * (s - Expression::Constant(F::from(start as u64))),
+                start_s.clone() * (st - one.clone()), // empty stack
+                start_s.clone() * c,
+                start_s.clone() * l,
+                start_s.clone() * (one - ns),
+                start_s.clone() * str_esc,
+                start_s.clone() * any,
+                start_s * run,
+            ]
 
         });
 
-        // Set char flags
-        // TODO:
-        //     - Can you simplify this with lookup?
-        meta.create_gate("Char booleans", |meta| {
+        // The class of every byte. The control chars outside the grammar's whitespace are only in the table with
+        // `soft_validity`, and rejected otherwise
+        meta.lookup("Byte class", |meta| {
 
             let r = meta.query_advice(raw, Rotation::cur());
-            let bs = meta.query_advice(backslash, Rotation::cur());
-            let dq = meta.query_advice(double_quote, Rotation::cur());
-            let ob = meta.query_advice(open_brace, Rotation::cur());
-            let cb = meta.query_advice(close_brace, Rotation::cur());
+            let cl = meta.query_advice(class, Rotation::cur());
+            let f = meta.query_advice(ctx_flag, Rotation::cur());
+            let struct_s = meta.query_selector(body_selector);
+
+            vec![(struct_s.clone() * r, class_table[0]), (struct_s.clone() * cl, class_table[1]), (struct_s * f, class_table[2])]
+
+        });
 
-            let bs_inv = meta.query_advice(backslash_inv, Rotation::cur());
-            let dq_inv = meta.query_advice(double_quote_inv, Rotation::cur());
-            let ob_inv = meta.query_advice(open_brace_inv, Rotation::cur());
-            let cb_inv = meta.query_advice(close_brace_inv, Rotation::cur());
+        // The innermost container is read off the stack, and the stack stays below 2^(MAX_DEPTH + 1)
+        meta.lookup("Container stack", |meta| {
 
+            let st = meta.query_advice(stack, Rotation::cur());
+            let c = meta.query_advice(container, Rotation::cur());
             let struct_s = meta.query_selector(body_selector);
 
-            let one = Expression::Constant(F::one());
+            vec![(struct_s.clone() * st, stack_table[0]), (struct_s * c, stack_table[1])]
+
+        });
 
-            // if not matched, set flag = 0
-            let expr_1 = struct_s.clone() * (r.clone() - Expression::Constant(F::from(0x5c))) * bs.clone();
-            let expr_2 = struct_s.clone() * (r.clone() - Expression::Constant(F::from(0x22))) * dq.clone();
-            let expr_3 = struct_s.clone() * (r.clone() - Expression::Constant(F::from(0x7b))) * ob.clone();
-            let expr_4 = struct_s.clone() * (r.clone() - Expression::Constant(F::from(0x7d))) * cb.clone();
+        let keys = if params.key_flag {
+            Some(Self::configure_keys(meta, level, [body_selector, start_selector]))
+        } else {
+            None
+        };
+        let length = if params.expose_length {
+            Some(Self::configure_length(meta, [body_selector, start_selector]))
+        } else {
+            None
+        };
 
-            // If match, flag = 1. Requires an inversion column. See is_zero.rs
-            let expr_5 = struct_s.clone() * ((r.clone() - Expression::Constant(F::from(0x5c))) * bs_inv.clone() + bs.clone() - one.clone());
-            let expr_6 = struct_s.clone() * ((r.clone() - Expression::Constant(F::from(0x22))) * dq_inv.clone() + dq.clone() - one.clone());
-            let expr_7 = struct_s.clone() * ((r.clone() - Expression::Constant(F::from(0x7b))) * ob_inv.clone() + ob.clone() - one.clone());
-            let expr_8 = struct_s.clone() * ((r.clone() - Expression::Constant(F::from(0x7d))) * cb_inv.clone() + cb.clone() - one.clone());
+        // One step of the grammar per byte, up to the first rejected one. The container only matters to ',' '}' and
+        // ']', and is 0 for the rest so that the table needs no row per container for them
+        meta.lookup("Grammar", |meta| {
 
-            vec![expr_1, expr_2, expr_3, expr_4, expr_5, expr_6, expr_7, expr_8]
+            let s_prev = meta.query_advice(state, Rotation::prev());
+            let c_prev = meta.query_advice(container, Rotation::prev());
+            let cl = meta.query_advice(class, Rotation::cur());
+            let f = meta.query_advice(ctx_flag, Rotation::cur());
+            let any = meta.query_advice(any_invalid, Rotation::cur());
+            let any_prev = meta.query_advice(any_invalid, Rotation::prev());
+            let outputs = [state, not_str, str_escaped, char_class, push, pop, value_type, accept]
+                .map(|column| meta.query_advice(column, Rotation::cur()));
+
+            let one = Expression::Constant(F::one());
+            let q = meta.query_selector(body_selector) * (one - any_prev);
+
+            let inputs = [s_prev, cl, c_prev * f].into_iter().chain(outputs).chain([any]);
+            let mut lookups: Vec<_> = inputs.zip(transition_table).map(|(input, column)| (q.clone() * input, column)).collect();
+            if let Some(keys) = keys {
+                lookups.push((q.clone() * meta.query_advice(keys.key, Rotation::cur()), transition_table[12]));
+            }
+            if let Some(length) = length {
+                lookups.push((q * meta.query_advice(length.is_padding, Rotation::cur()), transition_table[13]));
+            }
+            lookups
 
         });
 
-        // Check terminal conditions
-        meta.create_gate("Terminal conditions", |meta| {
+        // '{' pushes a 0 bit and '[' a 1, and a close pops the innermost bit, which the grammar already matched
+        // against the close through the container
+        meta.create_gate("Container stack", |meta| {
 
-            let r = meta.query_advice(raw, Rotation::cur());
-            let bs = meta.query_advice(backslash, Rotation::cur());
-            let dq = meta.query_advice(double_quote, Rotation::cur());
-            let ob = meta.query_advice(open_brace, Rotation::cur());
-            let cb = meta.query_advice(close_brace, Rotation::cur());
-            let e = meta.query_advice(not_str, Rotation::cur());
+            let st = meta.query_advice(stack, Rotation::cur());
+            let st_prev = meta.query_advice(stack, Rotation::prev());
+            let c_prev = meta.query_advice(container, Rotation::prev());
+            let pu = meta.query_advice(push, Rotation::cur());
+            let po = meta.query_advice(pop, Rotation::cur());
+            let vt = meta.query_advice(value_type, Rotation::cur());
             let l = meta.query_advice(level, Rotation::cur());
+            let l_prev = meta.query_advice(level, Rotation::prev());
 
-            let start_s = meta.query_selector(start_selector);
-            let end_s = meta.query_selector(end_selector);
+            let struct_s = meta.query_selector(body_selector);
 
             let one = Expression::Constant(F::one());
+            let two = Expression::Constant(F::from(2));
+            let object = Expression::Constant(F::from(ValueType::Object as u64));
 
-            // start conditions
-            let expr_1 = start_s.clone() * (r.clone() - Expression::Constant(F::from(0x7b))); // raw = { 
-            let expr_2 = start_s.clone() * (one.clone() - e.clone()); // booleans e = 1
-            let expr_3 = start_s.clone() * (one.clone() - ob.clone()); // booleans ob = 1
-            let expr_4 = start_s.clone() * bs.clone(); // booleans bs = 0
-            let expr_5 = start_s.clone() * cb.clone();
-            let expr_6 = start_s.clone() * dq.clone();
-            let expr_7 = start_s.clone() * (l.clone() - one.clone()); // level = 1
-            let expr_8 = start_s * (l.clone() - one.clone()); // level = 1
-
-            // end conditions
-            let expr_9 = end_s.clone() * (one.clone() - e.clone()); // booleans e = 1
-            let expr_10 = end_s.clone() * (one.clone() - cb.clone()); // booleans cb = 1; note that this forces r = }
+            let expr_1 = struct_s.clone() * (one.clone() - pu.clone() - po.clone()) * (st.clone() - st_prev.clone());
+            let expr_2 = struct_s.clone() * pu.clone() * (st.clone() - two.clone() * st_prev.clone() - (vt - object));
+            let expr_3 = struct_s.clone() * po.clone() * (st_prev - two * st - (c_prev - one));
+            let expr_4 = struct_s * (l - l_prev - pu + po);
 
-            vec![expr_1, expr_2, expr_3, expr_4, expr_5, expr_6, expr_7, expr_8, expr_9, expr_10]
+            vec![expr_1, expr_2, expr_3, expr_4]
 
         });
 
-        // Toggle not_str depending on whether raw[row] is in a nested string
-        // Supports:
-        //      - " " quotes (JSON doesn't allow single quotes anyway)
-        //      - \" escaping
-        meta.create_gate("Toggle not_str", |meta| {
-
-            let dq = meta.query_advice(double_quote, Rotation::cur());
-            let e = meta.query_advice(not_str, Rotation::cur());
-            let e_prev = meta.query_advice(not_str, Rotation(-1));
-            let str_esc_prev = meta.query_advice(str_escaped, Rotation(-1));
+        // Once set, any_invalid stays set, so a later byte can't repair an earlier rejection. The grammar lookup stops
+        // at the first rejected byte, which only matches the table with any_invalid = 1. Only `soft_validity` has such
+        // rows, one per rejected step, so any_invalid is set there and nowhere else
+        meta.create_gate("Sticky invalid", |meta| {
 
+            let any = meta.query_advice(any_invalid, Rotation::cur());
+            let any_prev = meta.query_advice(any_invalid, Rotation::prev());
             let struct_s = meta.query_selector(body_selector);
 
             let one = Expression::Constant(F::one());
-            let expr_1 = struct_s.clone() * (one.clone() - dq.clone()) * (e.clone() - e_prev.clone()); // if r != " then e == e_prev
-            let expr_2 = struct_s.clone() * dq.clone() * (one.clone() - str_esc_prev.clone()) * (e.clone() + e_prev.clone() - one.clone()); // if r == " and str_esc_prev != 1 then e + e_prev == 1
 
-            vec![expr_1, expr_2]
+            vec![struct_s * any_prev * (one - any)]
 
         });
 
-        // Handle backslash escapes
-        meta.create_gate("Backslash escaping", |meta| {
+        // The document ends complete: after a whole value, with every container closed and nothing rejected. With
+        // `soft_validity` that is only computed into is_valid
+        let validity = if params.soft_validity {
+            Some(Self::configure_validity(meta, [accept, stack, any_invalid], end_selector))
+        } else {
+            meta.create_gate("End state", |meta| {
+
+                let a = meta.query_advice(accept, Rotation::cur());
+                let st = meta.query_advice(stack, Rotation::cur());
+                let any = meta.query_advice(any_invalid, Rotation::cur());
+                let end_s = meta.query_selector(end_selector);
+
+                let one = Expression::Constant(F::one());
+
+                vec![end_s.clone() * (one.clone() - a), end_s.clone() * (st - one), end_s * any]
+
+            });
+            None
+        };
+
+        // Unescaped string content looks up raw - 0x20 in the string byte table. Outside strings the input is 0, i.e.
+        // raw = 0x20, which is in the table. Control chars never get this far, the grammar rejects them in strings
+        if params.string_charset.is_some() {
+            meta.lookup("String charset", |meta| {
+
+                let r = meta.query_advice(raw, Rotation::cur());
+                let ns = meta.query_advice(not_str, Rotation::cur());
+                let str_esc_prev = meta.query_advice(str_escaped, Rotation::prev());
+
+                let struct_s = meta.query_selector(body_selector);
 
-            let bs = meta.query_advice(backslash, Rotation::cur());
+                let one = Expression::Constant(F::one());
+                let in_str = struct_s * (one.clone() - ns) * (one.clone() - str_esc_prev);
+                let in_scope = match (params.string_charset_scope, keys) {
+                    (CharsetScope::KeysOnly, Some(keys)) => meta.query_advice(keys.key, Rotation::cur()),
+                    (CharsetScope::ValuesOnly, Some(keys)) => one - meta.query_advice(keys.key, Rotation::cur()),
+                    _ => one,
+                };
+
+                vec![(in_str * in_scope * (r - Expression::Constant(F::from(0x20))), string_byte_table)]
+
+            });
+        }
+
+        // Every run of digits outside strings is counted and looked up in 0..=MAX_NUMBER_DIGITS, so that a number's
+        // digits accumulate into a field element without wrapping. Any other byte, a digit inside a string included,
+        // resets the count, and so does the first rejected byte: a hard constraint even with `soft_validity`, up to it
+        meta.create_gate("Digit run", |meta| {
+
+            let cl = meta.query_advice(class, Rotation::cur());
             let ns = meta.query_advice(not_str, Rotation::cur());
-            let str_esc = meta.query_advice(str_escaped, Rotation::cur());
-            let str_esc_prev = meta.query_advice(str_escaped, Rotation(-1));
+            let any = meta.query_advice(any_invalid, Rotation::cur());
+            let run = meta.query_advice(digit_run, Rotation::cur());
+            let run_prev = meta.query_advice(digit_run, Rotation::prev());
+            let inv = meta.query_advice(digit_inv, Rotation::cur());
 
             let struct_s = meta.query_selector(body_selector);
 
             let one = Expression::Constant(F::one());
-            let expr_1 = struct_s.clone() * ns.clone() * bs.clone(); // if not_str == 1 then bs == 0; no backslash chars in non-strings
-            let expr_2 = struct_s.clone() *  str_esc_prev.clone() * str_esc.clone(); // if str_esc_prev, then str_esc == 0
-            let expr_3 = struct_s.clone() * (one.clone() - ns.clone()) * (one.clone() - str_esc_prev.clone()) * (bs.clone() - str_esc.clone()); // if not_str == 0 and not already escaped, then str_escape == backslash
+            let class_is = |c: ByteClass| cl.clone() - Expression::Constant(F::from(c as u64));
+            let not_digit = class_is(ByteClass::Digit) * class_is(ByteClass::HighDigit);
+            let is_digit = one.clone() - not_digit.clone() * inv;
 
-            vec![expr_1, expr_2, expr_3]
+            vec![
+                struct_s.clone() * not_digit * is_digit.clone(),
+                struct_s * (run - (one.clone() - any) * ns * is_digit * (run_prev + one)),
+            ]
 
         });
 
-        // Start at level 0 and +1 for every { in raw and -1 for every } in raw, if not_esc == 1
-        meta.create_gate("Count {} levels", |meta| {
-
-            let ob = meta.query_advice(open_brace, Rotation::cur());
-            let cb = meta.query_advice(close_brace, Rotation::cur());
-            let l = meta.query_advice(level, Rotation::cur());
-            let l_prev = meta.query_advice(level, Rotation(-1));
-            let not_str = meta.query_advice(not_str, Rotation::cur());
+        meta.lookup("Digit run bound", |meta| {
 
+            let run = meta.query_advice(digit_run, Rotation::cur());
             let struct_s = meta.query_selector(body_selector);
+
+            vec![(struct_s * run, digit_run_table)]
+
+        });
+
+        let utf8 = if params.strict_utf8 {
+            Some(Self::configure_utf8(meta, raw, not_str, body_selector, start_selector))
+        } else {
+            None
+        };
+        let elems = params.max_elems.map(|max_elems| Self::configure_elems(meta, [level, push, value_type], body_selector, max_elems));
+
+        Self {
+            raw, class, ctx_flag, state, stack, container, push, pop, value_type, accept, not_str, str_escaped, level,
+            char_class, any_invalid, digit_run, digit_inv, body_selector, start_selector, end_selector, class_table,
+            stack_table, transition_table, string_byte_table, digit_run_table, string_charset: params.string_charset, top_level_scalar: params.top_level_scalar,
+            utf8, keys, validity, length, elems,
+        }
+
+    }
+
+    // is_valid = (1 - any_invalid) * accept * (stack == 1) on the last byte, the conditions of the hard end state
+    fn configure_validity<F: FieldExt>(
+        meta: &mut ConstraintSystem<F>,
+        [accept, stack, any_invalid]: [Column<Advice>; 3],
+        end_selector: Selector,
+    ) -> ValidityConfig {
+
+        let [is_valid, root_inv] = [(); 2].map(|_| meta.advice_column());
+        meta.enable_equality(is_valid);
+
+        meta.create_gate("Validity", |meta| {
+
+            let a = meta.query_advice(accept, Rotation::cur());
+            let st = meta.query_advice(stack, Rotation::cur());
+            let any = meta.query_advice(any_invalid, Rotation::cur());
+            let v = meta.query_advice(is_valid, Rotation::cur());
+            let inv = meta.query_advice(root_inv, Rotation::cur());
             let end_s = meta.query_selector(end_selector);
 
             let one = Expression::Constant(F::one());
-            let expr_1 = struct_s.clone() * (one.clone() - ob.clone() - cb.clone()) * (l.clone() - l_prev.clone()); // if r != { or } then l == l_prev
-            let expr_2 = struct_s.clone() * not_str.clone() * ob.clone() * (l.clone() - l_prev.clone() - one.clone()); // if r == { and not_str then l == l_prev + 1
-            let expr_3 = struct_s.clone() * not_str.clone() * cb.clone() * (l.clone() - l_prev.clone() + one.clone()); // if r == } and not_str then l == l_prev - 1
-            let expr_4 = end_s.clone() * not_str.clone() * cb.clone() * (l.clone() - l_prev.clone() + one.clone()); // if r == } then l == l_prev - 1 at the end,
+            let open = st - one.clone();
+            let is_root = one.clone() - open.clone() * inv;
+
+            let expr_1 = end_s.clone() * open * is_root.clone();
+            let expr_2 = end_s * (v - (one - any) * a * is_root);
+
+            vec![expr_1, expr_2]
+
+        });
+
+        ValidityConfig { is_valid, root_inv }
+    }
+
+    // The grammar lookup sets `key` on the bytes of keys, so only the count and the level-1 copy are left here
+    fn configure_keys<F: FieldExt>(
+        meta: &mut ConstraintSystem<F>,
+        level: Column<Advice>,
+        [body_selector, start_selector]: [Selector; 2],
+    ) -> KeyConfig {
+
+        let [key, top_key, key_count, level_one_inv] = [(); 4].map(|_| meta.advice_column());
+        [key, top_key, key_count].map(|column| meta.enable_equality(column));
+
+        // No key before the first byte
+        meta.create_gate("Key flags (start)", |meta| {
+
+            let k = meta.query_advice(key, Rotation::cur());
+            let n = meta.query_advice(key_count, Rotation::cur());
+            let start_s = meta.query_selector(start_selector);
+
+            vec![start_s.clone() * k, start_s * n]
 
-            vec![expr_1, expr_2, expr_3, expr_4]
-            
         });
 
-        // Check that the JSON level structure is valid
-        meta.create_gate("Check level structure", |meta| {
+        meta.create_gate("Key flags", |meta| {
 
             let l = meta.query_advice(level, Rotation::cur());
-            let l_inv = meta.query_advice(level_inv, Rotation::cur());
+            let k = meta.query_advice(key, Rotation::cur());
+            let k_prev = meta.query_advice(key, Rotation::prev());
+            let t = meta.query_advice(top_key, Rotation::cur());
+            let n = meta.query_advice(key_count, Rotation::cur());
+            let n_prev = meta.query_advice(key_count, Rotation::prev());
+            let l1_inv = meta.query_advice(level_one_inv, Rotation::cur());
 
-            let end_s = meta.query_selector(end_selector);
             let struct_s = meta.query_selector(body_selector);
 
             let one = Expression::Constant(F::one());
-            let expr_1 = end_s.clone() * l.clone(); // if end_s, level == 0
-            let expr_2 = struct_s.clone() * (one - l * l_inv ); // if struct_s, level > 0
+            let l_one = l - one.clone();
+            let is_level_one = one.clone() - l_one.clone() * l1_inv;
 
-            vec![expr_1, expr_2]
+            let expr_1 = struct_s.clone() * l_one * is_level_one.clone();
+            let expr_2 = struct_s.clone() * (t - k.clone() * is_level_one); // top_key = key at level 1
+            let expr_3 = struct_s * (n - n_prev - k * (one - k_prev)); // +1 where a run of key starts
 
-        });
+            vec![expr_1, expr_2, expr_3]
 
-        Self { raw, backslash_inv, double_quote_inv, open_brace_inv, close_brace_inv, backslash, double_quote, 
-            open_brace, close_brace, not_str, str_escaped, level, level_inv, body_selector, start_selector, end_selector, json_all }
+        });
 
+        KeyConfig { key, top_key, key_count, level_one_inv }
     }
 
-}
+    // The grammar lookup sets is_padding, so only the count is left here: +1 for every byte before the padding
+    fn configure_length<F: FieldExt>(
+        meta: &mut ConstraintSystem<F>,
+        [body_selector, start_selector]: [Selector; 2],
+    ) -> LengthConfig {
 
-// The circuit struct; F should be u8 or u16
-#[derive(Clone, Default)]
-pub struct JsonCircuit<F: FieldExt> {
-    pub raw: Vec<Value<F>>,
-    // pub key: Vec<F>
-    // pub value: Vec<F>
-}
+        let [is_padding, length] = [(); 2].map(|_| meta.advice_column());
+        meta.enable_equality(length);
 
-// Implementation. Right now it only supports checking that the JSON is structurally valid
-// TODO: 
-//  - Need to compose this with RLC for the query
-impl<F: FieldExt> Circuit<F> for JsonCircuit<F> {
-    
-    type Config = JsonConfig;
-    type FloorPlanner = SimpleFloorPlanner;
+        meta.create_gate("Length (start)", |meta| {
 
-    fn without_witnesses(&self) -> Self {
-        unimplemented!()
-    }
+            let p = meta.query_advice(is_padding, Rotation::cur());
+            let len = meta.query_advice(length, Rotation::cur());
+            let start_s = meta.query_selector(start_selector);
 
-    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
-        JsonConfig::configure(meta)
+            vec![start_s.clone() * p, start_s * len]
+
+        });
+
+        meta.create_gate("Length", |meta| {
+
+            let p = meta.query_advice(is_padding, Rotation::cur());
+            let len = meta.query_advice(length, Rotation::cur());
+            let len_prev = meta.query_advice(length, Rotation::prev());
+            let struct_s = meta.query_selector(body_selector);
+
+            let one = Expression::Constant(F::one());
+
+            vec![struct_s * (len - len_prev - (one - p))]
+
+        });
+
+        LengthConfig { is_padding, length }
     }
 
-    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+    // The level before each byte, one-hot in `at`, picks the counter a value starting on this byte adds to, and the one
+    // a container opening on it resets. Every counter is reset by the push that opens its level before it is read, so
+    // the start row needs no gate of its own
+    fn configure_elems<F: FieldExt>(
+        meta: &mut ConstraintSystem<F>,
+        [level, push, value_type]: [Column<Advice>; 3],
+        body_selector: Selector,
+        max_elems: usize,
+    ) -> ElemsConfig {
 
-        let bs_ord = F::from(0x5c); // backslash
-        let dq_ord = F::from(0x22); // double quote
-        let ob_ord = F::from(0x7b); // open brace
-        let cb_ord = F::from(0x7d); // close brace
-        let special_chars = vec![bs_ord, dq_ord, ob_ord, cb_ord];
-        let special_chars_column = vec![config.backslash, config.double_quote, config.open_brace, config.close_brace];
-        let special_chars_inv_column = vec![config.backslash_inv, config.double_quote_inv, config.open_brace_inv, config.close_brace_inv];
+        let at = [(); MAX_DEPTH].map(|_| meta.advice_column());
+        let count = [(); MAX_DEPTH].map(|_| meta.advice_column());
+        let value_inv = meta.advice_column();
+        let table = meta.lookup_table_column();
 
-        layouter.assign_region(
-            || "Json circuit",
-            |mut region| {
+        // At most one of `at` is set, in the column of the previous level, and none at level 0
+        meta.create_gate("Element levels", |meta| {
 
-                let mut not_str = F::one();
-                let mut level = F::zero();
-                let mut level_inv = F::one();
-                let mut str_esc = F::zero();
-                let mut str_esc_prev = F::zero();
+            let l_prev = meta.query_advice(level, Rotation::prev());
+            let flags = at.map(|column| meta.query_advice(column, Rotation::cur()));
+            let struct_s = meta.query_selector(body_selector);
 
-                let n = self.raw.len();
+            let one = Expression::Constant(F::one());
+            let any = flags.iter().fold(Expression::Constant(F::zero()), |sum, flag| sum + flag.clone());
+            let weighted = flags.iter().enumerate()
+                .fold(Expression::Constant(F::zero()), |sum, (idx, flag)| sum + flag.clone() * Expression::Constant(F::from(idx as u64 + 1)));
 
-                for (idx, r) in self.raw.iter().enumerate() {
+            let mut constraints: Vec<Expression<F>> = flags.iter().map(|flag| struct_s.clone() * flag.clone() * (one.clone() - flag.clone())).collect();
+            constraints.push(struct_s.clone() * any.clone() * (one - any));
+            constraints.push(struct_s * (weighted - l_prev));
+            constraints
 
-                    // println!("idx {:?} : raw = {:?}, \t not_str = {:?}", idx, r, not_str);
+        });
 
-                    let _r = region.assign_advice(
-                        || format!("raw input at idx = {}", idx),
-                        config.raw,
-                        idx,
-                        || *r,
-                    )?;
+        // starts = 1 on the first byte of a value. A push to level d + 1 resets counter d, and a value starting at
+        // level d adds 1 to it: the push of a nested container is one element of its parent
+        meta.create_gate("Element counts", |meta| {
 
-                    for (jdx, special_char) in special_chars.iter().enumerate() {
+            let pu = meta.query_advice(push, Rotation::cur());
+            let vt = meta.query_advice(value_type, Rotation::cur());
+            let inv = meta.query_advice(value_inv, Rotation::cur());
+            let flags = at.map(|column| meta.query_advice(column, Rotation::cur()));
+            let struct_s = meta.query_selector(body_selector);
 
-                        let _flag = _r.value().map(|x| {
+            let one = Expression::Constant(F::one());
+            let starts = vt.clone() * inv;
+            let top = flags.iter().fold(one.clone(), |rest, flag| rest - flag.clone());
 
-                            if x == special_char {
+            let mut constraints = vec![struct_s.clone() * vt * (one.clone() - starts.clone())];
+            for (idx, &column) in count.iter().enumerate() {
+                let n = meta.query_advice(column, Rotation::cur());
+                let n_prev = meta.query_advice(column, Rotation::prev());
+                let opens = pu.clone() * if idx == 0 { top.clone() } else { flags[idx - 1].clone() };
+                constraints.push(struct_s.clone() * (n - (one.clone() - opens) * (n_prev + flags[idx].clone() * starts.clone())));
+            }
+            constraints
 
-                                if x == &dq_ord && str_esc_prev == F::zero() {
-                                    not_str = F::one() - not_str;
-                                } else if x == &ob_ord {
-                                    level = level + not_str;
-                                    level_inv = if level == F::zero() {F::one()} else {level.invert().unwrap()};
-                                } else if x == &cb_ord {
-                                    level = level - not_str;
-                                    level_inv = if level == F::zero() {F::one()} else {level.invert().unwrap()};
-                                } else if x == &bs_ord && str_esc_prev == F::zero() {
-                                    str_esc = (F::one() - not_str) * F::one();
-                                }
+        });
 
-                                F::one()
+        // The innermost counter, the only one a byte adds to. 0 at level 0, which is in the table
+        meta.lookup("Element count", |meta| {
 
-                            } else {
+            let struct_s = meta.query_selector(body_selector);
+            let innermost = at.iter().zip(count.iter())
+                .fold(Expression::Constant(F::zero()), |sum, (&flag, &n)| sum + meta.query_advice(flag, Rotation::cur()) * meta.query_advice(n, Rotation::cur()));
 
-                                F::zero()
+            vec![(struct_s * innermost, table)]
 
-                            }
-                        });
+        });
 
-                        let _inv = _r.value().map(|x| if x == special_char {F::one()} else {(*x - special_char).invert().unwrap()});
-                        let _adv_flag = region.assign_advice(
-                            || format!("flag for special char {}", jdx),
-                            special_chars_column[jdx],
-                            idx,
-                            || _flag,
-                        )?;
-                        let _adv_inv = region.assign_advice(
-                            || format!("inv for special char {}", jdx),
-                            special_chars_inv_column[jdx],
-                            idx,
-                            || _inv,
-                        )?;
+        ElemsConfig { at, count, value_inv, table, max_elems }
+    }
 
-                    }
+    // Every string byte is one valid step of the UTF-8 decoder. Rows outside strings look up (0x20, 0, 0),
+    // so a sequence cannot run into a closing quote, and the state is reset between strings
+    fn configure_utf8<F: FieldExt>(
+        meta: &mut ConstraintSystem<F>,
+        raw: Column<Advice>,
+        not_str: Column<Advice>,
+        body_selector: Selector,
+        start_selector: Selector,
+    ) -> Utf8Config {
 
-                    // Handle str_esc step: 
-                    // if str_esc_prev, escape is nullified: str_esc = str_esc * ( 1 - str_esc_prev )
-                    // update str_esc_prev
-                    str_esc = str_esc * (F::one() - str_esc_prev);
-                    str_esc_prev = str_esc;
+        let pending = meta.advice_column();
+        let table = [(); 3].map(|_| meta.lookup_table_column());
 
-                    // Write state variables
-                    let _not_str = region.assign_advice(
-                        || format!("not_str at idx = {}", idx),
-                        config.not_str,
-                        idx,
-                        || Value::known(not_str),
-                    )?;
+        // No sequence is open before the first byte
+        meta.create_gate("UTF-8 start", |meta| {
 
-                    let _str_escaped = region.assign_advice(
-                        || format!("str_escaped at idx = {}", idx),
-                        config.str_escaped,
-                        idx, 
-                        || Value::known(str_esc)
-                    )?;
+            let p = meta.query_advice(pending, Rotation::cur());
+            let start_s = meta.query_selector(start_selector);
 
-                    let _level = region.assign_advice(
-                        || format!("level at idx = {}", idx),
-                        config.level,
-                        idx,
-                        || Value::known(level),
-                    )?;
+            vec![start_s * p]
+
+        });
+
+        meta.lookup("UTF-8 in strings", |meta| {
 
-                    let _level_inv = region.assign_advice(
-                        || format!("level_inv at idx = {}", idx),
-                        config.level_inv,
+            let r = meta.query_advice(raw, Rotation::cur());
+            let ns = meta.query_advice(not_str, Rotation::cur());
+            let p = meta.query_advice(pending, Rotation::cur());
+            let p_prev = meta.query_advice(pending, Rotation(-1));
+
+            let struct_s = meta.query_selector(body_selector);
+
+            let one = Expression::Constant(F::one());
+            let in_str = struct_s.clone() * (one - ns);
+            let byte = in_str.clone() * r + (struct_s.clone() - in_str) * Expression::Constant(F::from(0x20));
+
+            vec![(byte, table[0]), (struct_s.clone() * p_prev, table[1]), (struct_s * p, table[2])]
+
+        });
+
+        Utf8Config { pending, table }
+    }
+
+    // Bytes 0x20..=0xff, or those of `string_charset`, stored as raw - 0x20. Loaded whatever the charset, as other
+    // circuits look up byte differences in it (e.g. `SortedKeysCircuit`)
+    pub fn load_string_byte_table<F: FieldExt>(&self, layouter: &mut impl Layouter<F>) -> Result<(), Error> {
+
+        let bytes = string_bytes(self.string_charset);
+        layouter.assign_table(
+            || "String byte table",
+            |mut table| {
+                for (idx, &b) in bytes.iter().enumerate() {
+                    table.assign_cell(
+                        || format!("string byte {:#x}", b),
+                        self.string_byte_table,
                         idx,
-                        || Value::known(level_inv),
+                        || Value::known(F::from(b - 0x20)),
                     )?;
+                }
+                Ok(())
+            }
+        )
+    }
 
-                    // Set the selectors
-                    config.json_all.enable(&mut region, idx)?;
-                    if idx == 0 {
-                        config.start_selector.enable(&mut region, idx)?;
-                    } else if idx < n - 1 {
-                        config.body_selector.enable(&mut region, idx)?;
-                    } else {
-                        config.end_selector.enable(&mut region, idx)?;
-                    }
+    // The byte class, container stack and transition tables. Each starts with an all-zero row, which the lookups
+    // take on the rows without a byte
+    pub fn load_grammar_tables<F: FieldExt>(&self, layouter: &mut impl Layouter<F>) -> Result<(), Error> {
 
+        let (soft, padding) = (self.validity.is_some(), self.length.is_some());
+        let classes: Vec<Vec<u64>> = byte_class_rows(soft, padding).iter().map(|row| row.to_vec()).collect();
+        let stacks: Vec<Vec<u64>> = stack_rows().iter().map(|row| row.to_vec()).collect();
+        let transitions: Vec<Vec<u64>> = transition_rows(soft, padding).iter().map(|row| row.to_vec()).collect();
+
+        let digit_runs: Vec<Vec<u64>> = (0..=MAX_NUMBER_DIGITS as u64).map(|n| vec![n]).collect();
+
+        let tables: [(&str, &[TableColumn], Vec<Vec<u64>>); 4] = [
+            ("Byte class table", &self.class_table, classes),
+            ("Container stack table", &self.stack_table, stacks),
+            ("Transition table", &self.transition_table, transitions),
+            ("Digit run table", &[self.digit_run_table], digit_runs),
+        ];
+        for (name, columns, rows) in tables {
+            layouter.assign_table(
+                || name,
+                |mut table| {
+                    for (idx, row) in rows.iter().enumerate() {
+                        for (column, value) in columns.iter().zip(row.iter()) {
+                            table.assign_cell(|| format!("{} row {}", name, idx), *column, idx, || Value::known(F::from(*value)))?;
+                        }
+                    }
+                    Ok(())
                 }
+            )?;
+        }
+
+        Ok(())
+    }
+
+    // 0..=max_elems. No-op without `max_elems`
+    pub fn load_elems_table<F: FieldExt>(&self, layouter: &mut impl Layouter<F>) -> Result<(), Error> {
 
+        let elems = match self.elems {
+            Some(elems) => elems,
+            None => return Ok(()),
+        };
+
+        layouter.assign_table(
+            || "Element count table",
+            |mut table| {
+                for n in 0..=elems.max_elems {
+                    table.assign_cell(|| format!("count {}", n), elems.table, n, || Value::known(F::from(n as u64)))?;
+                }
                 Ok(())
             }
         )
+    }
+
+    // Every valid (byte, pending before, pending after) step. No-op without `strict_utf8`
+    pub fn load_utf8_table<F: FieldExt>(&self, layouter: &mut impl Layouter<F>) -> Result<(), Error> {
 
+        let utf8 = match self.utf8 {
+            Some(utf8) => utf8,
+            None => return Ok(()),
+        };
+
+        let rows = utf8_table();
+        layouter.assign_table(
+            || "UTF-8 table",
+            |mut table| {
+                for (idx, row) in rows.iter().enumerate() {
+                    for (column, value) in utf8.table.iter().zip(row.iter()) {
+                        table.assign_cell(|| format!("utf8 step {}", idx), *column, idx, || Value::known(F::from(*value)))?;
+                    }
+                }
+                Ok(())
+            }
+        )
     }
 
 }
 
+// The circuit struct; F should be u8 or u16
+#[derive(Clone, Default)]
+pub struct JsonCircuit<F: FieldExt> {
+    pub raw: Vec<Value<F>>,
+    // First byte the grammar rejects, or that nests containers past `MAX_DEPTH`, as `rejected_at`. Only a report for
+    // the caller: the circuit checks every row whatever this says, and has no satisfying witness past this byte
+    pub invalid_at: Option<usize>,
+    // pub key: Vec<F>
+    // pub value: Vec<F>
+}
 
-#[cfg(test)]
-mod test {
+// Longest input the constructors accept by default. One row per byte, so this needs k = 17
+pub const DEFAULT_MAX_LEN: usize = 1 << 16;
 
-    use halo2_proofs::{
-        arithmetic::Field, circuit::Value, dev::MockProver, halo2curves::bn256::Fr,
-    };
-    use rand::rngs::OsRng;
-    use super::JsonCircuit;
+// Rows halo2 keeps back for blinding, as in `json_gate::min_k_for_state_machine`
+const UNUSABLE_ROWS: usize = 9;
 
-    #[test]
-    fn field_operations_test() {
+// Deepest nesting of objects and arrays. The container stack table holds every stack up to this depth, 2^(MAX_DEPTH + 1)
+// rows, so this keeps it below the transition table
+pub const MAX_DEPTH: usize = 6;
 
-        let test_str = String::from("abc");
-        let char_arr = test_str.chars().collect::<Vec<char>>();
-        println!("\nFirst Output: {:?}", char_arr.clone().into_iter().map(|x| format!("0x{:x}", x as u64)).collect::<Vec<String>>());
+// Longest digit run the pre-flight accepts: 10^76 < 2^253, so any run up to this fits in a bn254 scalar (253 bits of
+// capacity) without wrapping once numbers are accumulated
+pub const MAX_NUMBER_DIGITS: usize = 76;
 
-        let _arr = char_arr.into_iter().map(|x| Value::known(Fr::from(x as u64))).collect::<Vec<Value<Fr>>>();
-        println!("\nSecond Output: {:?}", _arr[0]);
+// Most top-level keys the circuits on `KeyRunsConfig` take. Every slot has its row whether a key fills it or not, so
+// that the layout does not depend on how many keys there are
+pub const MAX_TOP_LEVEL_KEYS: usize = 8;
 
-    }
+// Longest top-level key the circuits reading the keys' bytes take, see `KeyRunsConfig`. A key window packs its bytes
+// into one field element, so this stays under 32
+pub const MAX_KEY_LEN: usize = 16;
 
-    #[test]
-    fn test_simple_json() {
-        
-        let k = 5;
+// Fills a document up to a fixed size, see `JsonConfigParams::expose_length`. No JSON text contains it: outside strings
+// it is no token, and inside them control chars must be escaped
+pub const PAD_BYTE: u8 = 0x00;
 
-        let test_json = String::from("{\"a\": 1, \"b\": 2}");
-        let arr = test_json.chars().map(|x| Value::known(Fr::from(x as u64))).collect::<Vec<Value<Fr>>>();
-        let circuit = JsonCircuit { raw: arr };
+// Smallest k that fits a document of `input_len` bytes, one row per byte plus the start row, and the tables of a
+// config with `params`: the string byte table (224 rows, or the charset's size), the grammar's byte class, container
+// stack and transition tables (over 300 rows, near 2000 with `soft_validity`), the 77 row digit run table, the 499
+// row UTF-8 table, and the element count table (`max_elems` + 1 rows). Each table has its own columns, so the tallest
+// one counts
+pub fn min_k_for_json(input_len: usize, params: &JsonConfigParams) -> u32 {
 
-        MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
+    let string_byte_table = string_bytes(params.string_charset).len();
+    let (soft, padding) = (params.soft_validity, params.expose_length);
+    let grammar_tables = [byte_class_rows(soft, padding).len(), stack_rows().len(), transition_rows(soft, padding).len(), MAX_NUMBER_DIGITS + 1];
+    let utf8_table = if params.strict_utf8 { utf8_table().len() } else { 0 };
+    let elems_table = params.max_elems.map_or(0, |max_elems| max_elems + 1);
+
+    let rows = [input_len + 1, string_byte_table, utf8_table, elems_table].into_iter().chain(grammar_tables).max().unwrap_or(0) + UNUSABLE_ROWS;
+    rows.next_power_of_two().trailing_zeros()
+}
+
+// Bytes allowed unescaped inside strings: 0x20..=0xff, or those of `charset`. Control chars stay out whatever the
+// charset says
+fn string_bytes(charset: Option<&[u8]>) -> Vec<u64> {
+    match charset {
+        Some(charset) => charset.iter().filter(|&&b| b >= 0x20).map(|&b| b as u64).collect(),
+        None => (0x20..=0xff).collect(),
     }
+}
 
-    #[test]
-    fn test_json_escaped_chars() {
-        
-        let k = 5;
+// Every valid (byte, pending before, pending after) step of `utf8_step`, 499 of them
+fn utf8_table() -> Vec<[u64; 3]> {
+    let mut rows = vec![];
+    for pending in 0..8 {
+        for b in 0..=0xffu8 {
+            if let Some(next) = utf8_step(pending, b) {
+                rows.push([b as u64, pending, next]);
+            }
+        }
+    }
+    rows
+}
 
-        let test_json = String::from("{\"a{}\": 1, \"b\": \"\\\"\"}");
-        let arr = test_json.chars().map(|x| Value::known(Fr::from(x as u64))).collect::<Vec<Value<Fr>>>();
-        let circuit = JsonCircuit { raw: arr };
+// Byte classes of the grammar. Letters get a class of their own where an escape, a \u digit or a literal tells them
+// apart; everything else from 0x20 up is only ever string content
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteClass {
+    LeftBrace = 1,
+    RightBrace,
+    LeftBracket,
+    RightBracket,
+    Colon,
+    Comma,
+    Quote,
+    Backslash,
+    Space,        // ' ', whitespace or string content
+    ControlSpace, // '\t' '\n' '\r', whitespace only
+    Digit,     // '0'..='7'
+    HighDigit, // '8' '9', also the second hex digit of a high surrogate after \uD
+    Point,
+    Minus,
+    Plus,
+    Slash,
+    Hex,     // 'c' 'C' 'F', hex digits with no other use
+    HexHigh, // 'A' 'B', the same, but also the second hex digit of a high surrogate after \uD
+    HexD,    // 'd' 'D', the first hex digit of a surrogate
+    A,
+    B,
+    E,
+    UpperE, // 'E', a hex digit or an exponent
+    F,
+    L,
+    N,
+    R,
+    S,
+    T,
+    U,
+    Other,   // any other byte from 0x20 up
+    Control, // the control chars other than '\t' '\n' '\r', which the grammar never accepts
+    Pad,     // `PAD_BYTE`, with `JsonConfigParams::expose_length` only. Control otherwise
+}
 
-        MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
+impl ByteClass {
+
+    const ALL: [ByteClass; 33] = {
+        use ByteClass::*;
+        [LeftBrace, RightBrace, LeftBracket, RightBracket, Colon, Comma, Quote, Backslash, Space, ControlSpace, Digit,
+         HighDigit, Point, Minus, Plus, Slash, Hex, HexHigh, HexD, A, B, E, UpperE, F, L, N, R, S, T, U, Other, Control,
+         Pad]
+    };
+
+    pub fn of(b: u8) -> Self {
+        use ByteClass::*;
+        match b {
+            b'{' => LeftBrace,
+            b'}' => RightBrace,
+            b'[' => LeftBracket,
+            b']' => RightBracket,
+            b':' => Colon,
+            b',' => Comma,
+            b'"' => Quote,
+            b'\\' => Backslash,
+            b' ' => Space,
+            b'\t' | b'\n' | b'\r' => ControlSpace,
+            b'0'..=b'7' => Digit,
+            b'8' | b'9' => HighDigit,
+            b'.' => Point,
+            b'-' => Minus,
+            b'+' => Plus,
+            b'/' => Slash,
+            b'c' | b'C' | b'F' => Hex,
+            b'A' | b'B' => HexHigh,
+            b'd' | b'D' => HexD,
+            b'a' => A,
+            b'b' => B,
+            b'e' => E,
+            b'E' => UpperE,
+            b'f' => F,
+            b'l' => L,
+            b'n' => N,
+            b'r' => R,
+            b's' => S,
+            b't' => T,
+            b'u' => U,
+            0x00..=0x1f => Control,
+            _ => Other,
+        }
     }
 
-    #[test]
-    fn test_json_escaped_chars_2() {
-        
-        let k = 6;
+    // `of`, with `PAD_BYTE` as padding when `padding` is on
+    fn of_padded(b: u8, padding: bool) -> Self {
+        match b {
+            PAD_BYTE if padding => ByteClass::Pad,
+            _ => Self::of(b),
+        }
+    }
 
-        let test_json = String::from("{\"a{}\": \" \\\" { \\\" { \\\" \", \"b\": \"\\\"\"}");
-        let arr = test_json.chars().map(|x| Value::known(Fr::from(x as u64))).collect::<Vec<Value<Fr>>>();
-        let circuit = JsonCircuit { raw: arr };
+    // '0'..='9', split in two classes for the surrogate escapes
+    fn digit(self) -> bool {
+        matches!(self, ByteClass::Digit | ByteClass::HighDigit)
+    }
 
-        MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
+    // Whether the step on this class depends on the innermost container
+    fn reads_container(self) -> bool {
+        matches!(self, ByteClass::Comma | ByteClass::RightBrace | ByteClass::RightBracket | ByteClass::Pad)
     }
+}
 
-    // Note that this does not check for valid key - value formats, which we will leave to a regex parser
-    #[test]
-    fn test_json_escaped_chars_3() {
-        
-        let k = 6;
+// Innermost open container, as the container stack table reads it off the stack
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Container {
+    None = 0, // at the top level
+    Object = 1,
+    Array = 2,
+}
 
-        let test_json = String::from("{\"a{}\": \"1\" \"2\", \"b\": \"\\\"\"}");
-        let arr = test_json.chars().map(|x| Value::known(Fr::from(x as u64))).collect::<Vec<Value<Fr>>>();
-        let circuit = JsonCircuit { raw: arr };
+impl Container {
 
-        MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
+    fn of(stack: u64) -> Self {
+        match stack {
+            0 | 1 => Container::None,
+            _ if stack & 1 == 0 => Container::Object,
+            _ => Container::Array,
+        }
+    }
+}
+
+// Type of the value a byte starts, in the value_type column
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueType {
+    None = 0, // the byte starts no value
+    String = 1,
+    Number = 2,
+    Object = 3,
+    Array = 4,
+    Literal = 5, // true, false or null
+}
+
+// States of the grammar, after each byte. Containers are on the stack instead, so the states after a value are the
+// same at any depth, and ',' '}' ']' read the innermost container to know where they may go
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GrammarState {
+    Start = 1,   // before the top-level '{' or '['
+    StartScalar, // before a top-level scalar, see `JsonConfigParams::top_level_scalar`
+    ObjectOpen,  // after '{': a key or '}'
+    KeyExpected, // after ',' in an object
+    Key,
+    KeyEscaped,
+    KeyUnicode1, // after \u in a key, then after each hex digit
+    KeyUnicode2,
+    KeyUnicode3,
+    KeyUnicode4,
+    KeyUnicodeD,     // after \uD: a high surrogate needs a low one to follow, a low one is never first
+    KeyHigh3,        // after \uD and 8..=B, then after each hex digit
+    KeyHigh4,
+    KeyHighEnd,      // after a high surrogate: the backslash of its low one
+    KeyPairEscaped,  // then the u
+    KeyPair1,        // then D
+    KeyPairD,        // then C..=F, and the last two hex digits as `KeyUnicode3`
+    AfterKey,      // after a key's closing quote: ':'
+    ValueExpected, // after ':', or after ',' in an array
+    ArrayOpen,     // after '[': a value or ']'
+    AfterValue,    // after a whole value: ',' or a close in a container, only whitespace at the top level
+    Minus,         // after a number's '-'
+    Integer,
+    Point,
+    Fraction,
+    Exponent,     // after the 'e' or 'E'
+    ExponentSign, // after the exponent's '+' or '-'
+    ExponentDigits,
+    String,
+    StringEscaped,
+    StringUnicode1,
+    StringUnicode2,
+    StringUnicode3,
+    StringUnicode4,
+    StringUnicodeD, // as the key states
+    StringHigh3,
+    StringHigh4,
+    StringHighEnd,
+    StringPairEscaped,
+    StringPair1,
+    StringPairD,
+    True1, // after "t", "tr", "tru"
+    True2,
+    True3,
+    False1,
+    False2,
+    False3,
+    False4,
+    Null1,
+    Null2,
+    Null3,
+    Padded,  // after the first `PAD_BYTE`, see `JsonConfigParams::expose_length`
+    Invalid, // after a rejected byte
+}
+
+impl GrammarState {
+
+    const ALL: [GrammarState; 53] = {
+        use GrammarState::*;
+        [Start, StartScalar, ObjectOpen, KeyExpected, Key, KeyEscaped, KeyUnicode1, KeyUnicode2, KeyUnicode3,
+         KeyUnicode4, KeyUnicodeD, KeyHigh3, KeyHigh4, KeyHighEnd, KeyPairEscaped, KeyPair1, KeyPairD, AfterKey,
+         ValueExpected, ArrayOpen, AfterValue, Minus, Integer, Point, Fraction, Exponent, ExponentSign, ExponentDigits,
+         String, StringEscaped, StringUnicode1, StringUnicode2, StringUnicode3, StringUnicode4, StringUnicodeD,
+         StringHigh3, StringHigh4, StringHighEnd, StringPairEscaped, StringPair1, StringPairD, True1, True2, True3,
+         False1, False2, False3, False4, Null1, Null2, Null3, Padded, Invalid]
+    };
+
+    // Inside a key or string value, from its opening quote up to the byte before its closing quote
+    fn in_string(self) -> bool {
+        use GrammarState::*;
+        matches!(self, Key | KeyEscaped | KeyUnicode1 | KeyUnicode2 | KeyUnicode3 | KeyUnicode4 | KeyUnicodeD | KeyHigh3
+            | KeyHigh4 | KeyHighEnd | KeyPairEscaped | KeyPair1 | KeyPairD | String | StringEscaped | StringUnicode1
+            | StringUnicode2 | StringUnicode3 | StringUnicode4 | StringUnicodeD | StringHigh3 | StringHigh4
+            | StringHighEnd | StringPairEscaped | StringPair1 | StringPairD)
+    }
+
+    // After a backslash, the low surrogate's included
+    fn escaped(self) -> bool {
+        use GrammarState::*;
+        matches!(self, KeyEscaped | KeyPairEscaped | StringEscaped | StringPairEscaped)
+    }
+
+    // A whole value ends here, or may: a number has no closing byte. Padding only follows a whole top-level value
+    fn accepts(self) -> bool {
+        use GrammarState::*;
+        matches!(self, AfterValue | Integer | Fraction | ExponentDigits | Padded)
+    }
+}
+
+// What the grammar assigns for one byte besides the state it moves to, see `grammar_step`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Step {
+    state: GrammarState,
+    char_class: CharClass,
+    push: bool,
+    pop: bool,
+    value_type: ValueType,
+    key: bool, // the byte is part of a key, see `JsonConfigParams::key_flag`
+}
+
+impl Step {
+
+    fn to(state: GrammarState, char_class: CharClass) -> Self {
+        Step { state, char_class, push: false, pop: false, value_type: ValueType::None, key: false }
+    }
+
+    // Where the trace goes on a rejected byte, and stays
+    fn invalid() -> Self {
+        Step::to(GrammarState::Invalid, CharClass::Normal)
+    }
+
+    fn value(self, value_type: ValueType) -> Self {
+        Step { value_type, ..self }
+    }
+
+    fn key(self) -> Self {
+        Step { key: true, ..self }
+    }
+}
+
+// The JSON grammar (RFC 8259, but numbers may have leading zeros), one byte at a time. `container` is the
+// innermost open container for the classes that read it, and `Container::None` for the rest. None rejects the byte
+fn grammar_step(state: GrammarState, class: ByteClass, container: Container) -> Option<Step> {
+
+    use ByteClass as B;
+    use CharClass::{Normal, StringContent, Structural};
+    use GrammarState as G;
+
+    let ws = matches!(class, B::Space | B::ControlSpace);
+    let digit = class.digit();
+    // Hex digits, and the second one after \uD, which tells a high surrogate (D8..=DB) from a low one (DC..=DF)
+    let high = matches!(class, B::HighDigit | B::HexHigh | B::A | B::B);
+    let low = matches!(class, B::Hex | B::HexD | B::E | B::UpperE | B::F);
+    let hex = class == B::Digit || high || low;
+
+    // After a whole value: ',' or its container's close, or more whitespace. At the top level, also the padding
+    let after_value = || match (class, container) {
+        _ if ws => Some(Step::to(G::AfterValue, Normal)),
+        (B::Pad, Container::None) => Some(Step::to(G::Padded, Normal)),
+        (B::Comma, Container::Object) => Some(Step::to(G::KeyExpected, Normal)),
+        (B::Comma, Container::Array) => Some(Step::to(G::ValueExpected, Normal)),
+        (B::RightBrace, Container::Object) | (B::RightBracket, Container::Array) => Some(Step { pop: true, ..Step::to(G::AfterValue, Structural) }),
+        _ => None,
+    };
+
+    // The first byte of a value. Containers only inside a container or at the top level of a document, scalars
+    // (strings, numbers and literals) only inside a container or at the top level of a scalar, see
+    // `JsonConfigParams::top_level_scalar`
+    let value_start = |containers: bool, scalars: bool| match class {
+        B::LeftBrace if containers => Some(Step { push: true, ..Step::to(G::ObjectOpen, Structural) }.value(ValueType::Object)),
+        B::LeftBracket if containers => Some(Step { push: true, ..Step::to(G::ArrayOpen, Structural) }.value(ValueType::Array)),
+        B::Quote if scalars => Some(Step::to(G::String, Structural).value(ValueType::String)),
+        _ if digit && scalars => Some(Step::to(G::Integer, Normal).value(ValueType::Number)),
+        B::Minus if scalars => Some(Step::to(G::Minus, Normal).value(ValueType::Number)),
+        B::T if scalars => Some(Step::to(G::True1, Normal).value(ValueType::Literal)),
+        B::F if scalars => Some(Step::to(G::False1, Normal).value(ValueType::Literal)),
+        B::N if scalars => Some(Step::to(G::Null1, Normal).value(ValueType::Literal)),
+        _ => None,
+    };
+
+    // One byte of a key or string value, `key` telling which. A \u escape of a high surrogate must be followed by one
+    // of a low surrogate, and a low surrogate must follow a high one
+    let in_string = |key: bool| {
+        let (open, escaped, unicode, close) = match key {
+            true => (G::Key, G::KeyEscaped, G::KeyUnicode1, G::AfterKey),
+            false => (G::String, G::StringEscaped, G::StringUnicode1, G::AfterValue),
+        };
+        let to = |key_state: GrammarState, string_state: GrammarState| Step::to(if key { key_state } else { string_state }, StringContent);
+        let step = match (state, class) {
+            (G::Key | G::String, B::Quote) => Step::to(close, Structural),
+            (G::Key | G::String, B::Backslash) => Step::to(escaped, StringContent),
+            (G::Key | G::String, B::ControlSpace | B::Control | B::Pad) => return None,
+            (G::Key | G::String, _) => Step::to(open, StringContent),
+            (G::KeyEscaped | G::StringEscaped, B::Quote | B::Backslash | B::Slash | B::B | B::F | B::N | B::R | B::T) => Step::to(open, StringContent),
+            (G::KeyEscaped | G::StringEscaped, B::U) => Step::to(unicode, StringContent),
+            (G::KeyUnicode1 | G::StringUnicode1, B::HexD) => to(G::KeyUnicodeD, G::StringUnicodeD),
+            (G::KeyUnicode1 | G::StringUnicode1, _) if hex => to(G::KeyUnicode2, G::StringUnicode2),
+            (G::KeyUnicode2 | G::StringUnicode2, _) if hex => to(G::KeyUnicode3, G::StringUnicode3),
+            (G::KeyUnicodeD | G::StringUnicodeD, B::Digit) => to(G::KeyUnicode3, G::StringUnicode3),
+            (G::KeyUnicodeD | G::StringUnicodeD, _) if high => to(G::KeyHigh3, G::StringHigh3),
+            (G::KeyHigh3 | G::StringHigh3, _) if hex => to(G::KeyHigh4, G::StringHigh4),
+            (G::KeyHigh4 | G::StringHigh4, _) if hex => to(G::KeyHighEnd, G::StringHighEnd),
+            (G::KeyHighEnd | G::StringHighEnd, B::Backslash) => to(G::KeyPairEscaped, G::StringPairEscaped),
+            (G::KeyPairEscaped | G::StringPairEscaped, B::U) => to(G::KeyPair1, G::StringPair1),
+            (G::KeyPair1 | G::StringPair1, B::HexD) => to(G::KeyPairD, G::StringPairD),
+            (G::KeyPairD | G::StringPairD, _) if low => to(G::KeyUnicode3, G::StringUnicode3),
+            (G::KeyUnicode3 | G::StringUnicode3, _) if hex => to(G::KeyUnicode4, G::StringUnicode4),
+            (G::KeyUnicode4 | G::StringUnicode4, _) if hex => Step::to(open, StringContent),
+            _ => return None,
+        };
+        Some(if key { step.key() } else { step })
+    };
+
+    // The next letter of a literal, the last one ending the value
+    let letter = |expected: ByteClass, next: GrammarState| match class == expected {
+        true => Some(Step::to(next, Normal)),
+        false => None,
+    };
+
+    match state {
+        G::Start | G::StartScalar | G::ObjectOpen | G::KeyExpected | G::ValueExpected | G::ArrayOpen if ws => Some(Step::to(state, Normal)),
+        G::Start => value_start(true, false),
+        G::StartScalar => value_start(false, true),
+        G::ObjectOpen if class == B::RightBrace && container == Container::Object => Some(Step { pop: true, ..Step::to(G::AfterValue, Structural) }),
+        G::ObjectOpen | G::KeyExpected if class == B::Quote => Some(Step::to(G::Key, Structural)),
+        G::ObjectOpen | G::KeyExpected => None,
+        G::Key | G::KeyEscaped | G::KeyUnicode1 | G::KeyUnicode2 | G::KeyUnicode3 | G::KeyUnicode4 | G::KeyUnicodeD
+            | G::KeyHigh3 | G::KeyHigh4 | G::KeyHighEnd | G::KeyPairEscaped | G::KeyPair1 | G::KeyPairD => in_string(true),
+        G::AfterKey if ws => Some(Step::to(G::AfterKey, Normal).key()),
+        G::AfterKey if class == B::Colon => Some(Step::to(G::ValueExpected, Normal)),
+        G::AfterKey => None,
+        G::ArrayOpen if class == B::RightBracket && container == Container::Array => Some(Step { pop: true, ..Step::to(G::AfterValue, Structural) }),
+        G::ValueExpected | G::ArrayOpen => value_start(true, true),
+        G::AfterValue => after_value(),
+        G::Minus if digit => Some(Step::to(G::Integer, Normal)),
+        G::Minus => None,
+        G::Integer if digit => Some(Step::to(G::Integer, Normal)),
+        G::Integer if class == B::Point => Some(Step::to(G::Point, Normal)),
+        G::Point if digit => Some(Step::to(G::Fraction, Normal)),
+        G::Point => None,
+        G::Fraction if digit => Some(Step::to(G::Fraction, Normal)),
+        G::Integer | G::Fraction if matches!(class, B::E | B::UpperE) => Some(Step::to(G::Exponent, Normal)),
+        G::Exponent if matches!(class, B::Plus | B::Minus) => Some(Step::to(G::ExponentSign, Normal)),
+        G::Exponent | G::ExponentSign | G::ExponentDigits if digit => Some(Step::to(G::ExponentDigits, Normal)),
+        G::Exponent | G::ExponentSign => None,
+        G::Integer | G::Fraction | G::ExponentDigits => after_value(),
+        G::String | G::StringEscaped | G::StringUnicode1 | G::StringUnicode2 | G::StringUnicode3 | G::StringUnicode4
+            | G::StringUnicodeD | G::StringHigh3 | G::StringHigh4 | G::StringHighEnd | G::StringPairEscaped | G::StringPair1
+            | G::StringPairD => in_string(false),
+        G::True1 => letter(B::R, G::True2),
+        G::True2 => letter(B::U, G::True3),
+        G::True3 => letter(B::E, G::AfterValue),
+        G::False1 => letter(B::A, G::False2),
+        G::False2 => letter(B::L, G::False3),
+        G::False3 => letter(B::S, G::False4),
+        G::False4 => letter(B::E, G::AfterValue),
+        G::Null1 => letter(B::U, G::Null2),
+        G::Null2 => letter(B::L, G::Null3),
+        G::Null3 => letter(B::L, G::AfterValue),
+        G::Padded if class == B::Pad && container == Container::None => Some(Step::to(G::Padded, Normal)),
+        G::Padded | G::Invalid => None,
+    }
+}
+
+// The all-zero row, then (byte, class, ctx_flag) for every byte. Without `soft`, the control chars are left out, so
+// the byte class lookup rejects them. With `padding`, `PAD_BYTE` is in as `ByteClass::Pad`
+fn byte_class_rows(soft: bool, padding: bool) -> Vec<[u64; 3]> {
+    let classes = (0..=0xffu8).map(|b| (b, ByteClass::of_padded(b, padding)))
+        .filter(|&(_, class)| soft || class != ByteClass::Control)
+        .map(|(b, class)| [b as u64, class as u64, class.reads_container() as u64]);
+    [[0; 3]].into_iter().chain(classes).collect()
+}
+
+// The all-zero row, then (stack, innermost container) for every stack up to `MAX_DEPTH` open containers
+fn stack_rows() -> Vec<[u64; 2]> {
+    let stacks = (1..1u64 << (MAX_DEPTH + 1)).map(|stack| [stack, Container::of(stack) as u64]);
+    [[0; 2]].into_iter().chain(stacks).collect()
+}
+
+// The all-zero row, then every step `grammar_step` allows: (previous state, class, container, state, not_str,
+// str_escaped, char_class, push, pop, value_type, accept, invalid, key, is_padding). The container is only enumerated
+// for the classes that read it, and `ByteClass::Pad` only with `padding`. With `soft`, every step it rejects too, to
+// `GrammarState::Invalid` with invalid = 1, so that every (previous state, class, container) has exactly one row
+fn transition_rows(soft: bool, padding: bool) -> Vec<[u64; 14]> {
+
+    let containers = |class: ByteClass| match class.reads_container() {
+        true => vec![Container::None, Container::Object, Container::Array],
+        false => vec![Container::None],
+    };
+
+    let mut rows = vec![[0; 14]];
+    for prev in GrammarState::ALL {
+        for class in ByteClass::ALL.into_iter().filter(|&class| padding || class != ByteClass::Pad) {
+            for container in containers(class) {
+                match grammar_step(prev, class, container) {
+                    Some(step) => rows.push(transition_row(prev, class, container, step, false)),
+                    None if soft && prev != GrammarState::Invalid => rows.push(transition_row(prev, class, container, Step::invalid(), true)),
+                    None => (),
+                }
+            }
+        }
+    }
+    rows
+}
+
+fn transition_row(prev: GrammarState, class: ByteClass, container: Container, step: Step, invalid: bool) -> [u64; 14] {
+    let state = step.state;
+    [
+        prev as u64, class as u64, container as u64, state as u64, !state.in_string() as u64, state.escaped() as u64,
+        step.char_class as u64, step.push as u64, step.pop as u64, step.value_type as u64, state.accepts() as u64,
+        invalid as u64, step.key as u64, (state == GrammarState::Padded) as u64,
+    ]
+}
+
+// From `JsonCircuit::check_k`: the document does not fit in 2^k rows
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CapacityError {
+    pub k: u32,
+    pub required_k: u32,
+    pub rows: usize, // rows the document needs, one per byte plus the start row
+}
+
+impl Display for CapacityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "a {} row document does not fit in k = {}, use k >= {}", self.rows, self.k, self.required_k)
+    }
+}
+
+impl<F: FieldExt> JsonCircuit<F> {
+
+    // See `from_bytes_with_max_len`
+    pub fn from_str(json: &str) -> Result<Self, ParseError> {
+        Self::from_bytes(json.as_bytes())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        Self::from_bytes_with_max_len(bytes, DEFAULT_MAX_LEN)
+    }
+
+    // A document the grammar rejects still gets a circuit, with the rejected byte in `invalid_at`, and it fails to
+    // prove there, an unpaired surrogate escape included. A digit run longer than `MAX_NUMBER_DIGITS` is refused here
+    // instead, with its index, as only the digit run lookup fails on it. A top-level scalar is only accepted with
+    // `JsonConfigParams::top_level_scalar`
+    pub fn from_bytes_with_max_len(bytes: &[u8], max_len: usize) -> Result<Self, ParseError> {
+
+        // Fail before building anything: untrusted input should not be able to size the circuit
+        if bytes.len() > max_len {
+            return Err(ParseError::TooLong(max_len));
+        }
+
+        // No value at all, so there is nothing for the gates to check. Only JSON's four whitespace bytes count: a form
+        // feed is a byte the grammar rejects
+        if bytes.iter().all(|b| matches!(b, b' ' | b'\t' | b'\n' | b'\r')) {
+            return Err(ParseError::Empty);
+        }
+
+        let trace = compute_trace(bytes);
+        let invalid_at = rejected_at(&trace);
+        if invalid_at.is_none() {
+            if let Some(err) = precise_error(bytes, &trace) {
+                return Err(err);
+            }
+        }
+
+        let raw = bytes.iter().map(|&b| Value::known(F::from(b as u64))).collect();
+        Ok(Self { raw, invalid_at })
+    }
+
+    // Rows `synthesize` assigns: the start row, then one per byte
+    pub fn rows(&self) -> usize {
+        self.raw.len() + 1
+    }
+
+    // Call before keygen / proving: an oversized document otherwise fails deep inside the layouter.
+    // `params` are those of the config the circuit is proven with, as its tables take rows too
+    pub fn check_k(&self, k: u32, params: &JsonConfigParams) -> Result<(), CapacityError> {
+        let required_k = min_k_for_json(self.raw.len(), params);
+        if k < required_k {
+            Err(CapacityError { k, required_k, rows: self.rows() })
+        } else {
+            Ok(())
+        }
+    }
+
+}
+
+// Index of the first byte the grammar rejects, or that opens a container past `MAX_DEPTH`: the first row no witness
+// can satisfy
+fn rejected_at(trace: &Trace) -> Option<usize> {
+    let rejected = trace.any_invalid.iter().position(|&x| x);
+    let too_deep = trace.level.iter().position(|&l| l as usize > MAX_DEPTH);
+    rejected.into_iter().chain(too_deep).min()
+}
+
+// What a config with `params` rejects of the document, as a parse error: the grammar's first rejected byte, a
+// container past `MAX_DEPTH` or past `max_elems`, a document cut short, and the checks
+// `JsonCircuit::from_bytes_with_max_len` refuses documents for. Ok means the circuit for `bytes` has a satisfying witness
+pub fn preflight(bytes: &[u8], params: &JsonConfigParams) -> Result<(), ParseError> {
+
+    if bytes.iter().all(|b| matches!(b, b' ' | b'\t' | b'\n' | b'\r')) {
+        return Err(ParseError::Empty);
+    }
+
+    let start = if params.top_level_scalar { GrammarState::StartScalar } else { GrammarState::Start };
+    let trace = trace_from(bytes, start, params.expose_length);
+
+    // A rejected byte right after the top-level value closes is trailing, any other is unexpected
+    let rejected = trace.any_invalid.iter().position(|&x| x).map(|idx| match idx {
+        _ if idx > 0 && trace.level[idx - 1] == 0 && trace.state[idx - 1].accepts() => ParseError::TrailingChars(idx),
+        _ => ParseError::UnexpectedChar(idx),
+    });
+    let too_deep = trace.level.iter().position(|&l| l as usize > MAX_DEPTH).map(ParseError::TooDeep);
+    let too_many = params.max_elems.and_then(|max_elems| over_max_elems(&trace, max_elems)).map(ParseError::TooManyElements);
+    let complete = trace.state.last().map_or(false, |s| s.accepts()) && trace.level.last() == Some(&0);
+    let cut_short = if complete { None } else { Some(ParseError::UnexpectedEnd(bytes.len())) };
+
+    let first = rejected.into_iter().chain(too_deep).chain(too_many).chain(precise_error(bytes, &trace)).chain(cut_short)
+        .min_by_key(|err| err.index());
+    match first {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+// Index of the first value that takes its container past `max_elems`, see `JsonConfigParams::max_elems`. For a member,
+// that is its value, where `validate_with_max_elems` has its key
+fn over_max_elems(trace: &Trace, max_elems: usize) -> Option<usize> {
+    (0..trace.elems.len()).find(|&idx| {
+        let level = if idx == 0 { 0 } else { trace.level[idx - 1] as usize };
+        (1..=MAX_DEPTH).contains(&level) && trace.elems[idx][level - 1] > max_elems as u64
+    })
+}
+
+// The first error better reported where it starts than at the row the circuit fails on: a \u escape of an unpaired
+// surrogate, at its backslash rather than the byte that breaks the pair, and a digit run longer than
+// `MAX_NUMBER_DIGITS`, which the grammar accepts and only the digit run lookup rejects
+fn precise_error(bytes: &[u8], trace: &Trace) -> Option<ParseError> {
+
+    // Escapes up to the first rejected byte, whose surrogate pairing `escape_len` checks. The low half of a pair is
+    // skipped
+    let mut surrogate = None;
+    let mut idx = 0;
+    while idx < bytes.len() && trace.state[idx] != GrammarState::Invalid {
+        if trace.str_escaped[idx] {
+            match escape_len(bytes, idx) {
+                Ok(len) => idx += len,
+                Err(err) => {
+                    surrogate = Some(err);
+                    break;
+                },
+            }
+        } else {
+            idx += 1;
+        }
+    }
+
+    let too_long = long_number_at(bytes).map(ParseError::NumberTooLong);
+    surrogate.into_iter().chain(too_long).min_by_key(|err| err.index())
+}
+
+// Index of the first digit past `MAX_NUMBER_DIGITS` in a run of digits outside strings
+fn long_number_at(bytes: &[u8]) -> Option<usize> {
+
+    let mut in_str = false;
+    let mut escaped = false;
+    let mut run = 0;
+    for (idx, &b) in bytes.iter().enumerate() {
+        if in_str {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_str = false,
+                _ => {},
+            }
+            continue;
+        }
+        if b.is_ascii_digit() {
+            run += 1;
+            if run > MAX_NUMBER_DIGITS {
+                return Some(idx);
+            }
+        } else {
+            run = 0;
+            in_str = b == b'"';
+        }
+    }
+    None
+}
+
+// (level, start, len) of the contents of every key, in document order. Quotes are excluded, escapes are not decoded.
+// Level counts all enclosing containers, so a top-level key is at level 1
+fn key_runs(bytes: &[u8]) -> Vec<(usize, usize, usize)> {
+
+    let mut runs = vec![];
+    let mut containers: Vec<u8> = vec![]; // open brackets
+    let mut in_str = false;
+    let mut escaped = false;
+    let mut expect_key = false;
+    let mut key_start: Option<usize> = None;
+
+    for (idx, &b) in bytes.iter().enumerate() {
+
+        if in_str {
+            if escaped {
+                escaped = false;
+            } else if b == b'\\' {
+                escaped = true;
+            } else if b == b'"' {
+                in_str = false;
+                if let Some(start) = key_start.take() {
+                    runs.push((containers.len(), start, idx - start));
+                }
+            }
+            continue;
+        }
+
+        match b {
+            b'"' => {
+                in_str = true;
+                if expect_key {
+                    key_start = Some(idx + 1);
+                }
+                expect_key = false;
+            },
+            b'{' | b'[' => {
+                containers.push(b);
+                expect_key = b == b'{';
+            },
+            b'}' | b']' => { containers.pop(); },
+            b',' => expect_key = containers.last() == Some(&b'{'),
+            _ => {},
+        }
+    }
+
+    runs
+}
+
+// (start, len) of the contents of every top-level key, in document order. The reference for `KeyRangesCircuit`
+pub fn top_level_key_ranges(bytes: &[u8]) -> Vec<(usize, usize)> {
+    key_runs(bytes).into_iter().filter(|run| run.0 == 1).map(|(_, start, len)| (start, len)).collect()
+}
+
+// As `top_level_key_ranges`, for the circuits on `KeyRunsConfig`. Fails at the opening quote of the first key past
+// `MAX_TOP_LEVEL_KEYS`
+fn top_level_runs(bytes: &[u8]) -> Result<Vec<(usize, usize)>, ParseError> {
+    let ranges = top_level_key_ranges(bytes);
+    match ranges.get(MAX_TOP_LEVEL_KEYS) {
+        Some(&(start, _)) => Err(ParseError::TooManyElements(start - 1)),
+        None => Ok(ranges),
+    }
+}
+
+// As `top_level_runs`, for the circuits that read the keys' bytes too. Fails at the first byte past `MAX_KEY_LEN` of a
+// longer key
+fn top_level_windows(bytes: &[u8]) -> Result<Vec<(usize, usize)>, ParseError> {
+    let ranges = top_level_runs(bytes)?;
+    match ranges.iter().find(|range| range.1 > MAX_KEY_LEN) {
+        Some(&(start, _)) => Err(ParseError::TooLong(start + MAX_KEY_LEN)),
+        None => Ok(ranges),
+    }
+}
+
+// The byte table of `KeyRunsConfig` over bytes with these raw values, string flags and levels: per byte whether it is
+// whitespace outside strings, the position (index + 1) of the next byte that is not, of the first byte of the
+// innermost open container and of the last opening quote, then of the first byte of the container open at each level
+// from 1. Then the position of the first byte that is not such whitespace, for the start row. Positions are 0 where
+// there is none
+fn byte_table(raw: &[u8], in_str: &[bool], levels: &[usize]) -> (Vec<[usize; 4 + MAX_DEPTH]>, usize) {
+
+    let ws: Vec<bool> = raw.iter().zip(in_str).map(|(b, &in_str)| !in_str && b" \t\n\r".contains(b)).collect();
+    let mut next = vec![0; raw.len() + 1];
+    for idx in (0..raw.len()).rev() {
+        next[idx] = if ws[idx] { next[idx + 1] } else { idx + 1 };
+    }
+
+    let mut openers = [0; MAX_DEPTH];
+    let (mut level, mut was_in_str, mut string) = (0, false, 0);
+    let rows = (0..raw.len()).map(|idx| {
+        let l = levels[idx].min(MAX_DEPTH);
+        if l == level + 1 {
+            openers[l - 1] = idx + 1;
+        }
+        if in_str[idx] && !was_in_str {
+            string = idx + 1;
+        }
+        (level, was_in_str) = (l, in_str[idx]);
+
+        let mut row = [0; 4 + MAX_DEPTH];
+        row[..4].copy_from_slice(&[ws[idx] as usize, next[idx + 1], if l > 0 { openers[l - 1] } else { 0 }, string]);
+        row[4..].copy_from_slice(&openers);
+        row
+    }).collect();
+
+    (rows, next[0])
+}
+
+// A key's bytes (at most `MAX_KEY_LEN`) as one field element, big-endian and zero-padded to `MAX_KEY_LEN` bytes, as
+// the key windows of `KeyRunsConfig` pack them
+pub fn pack_key<F: FieldExt>(key: &[u8]) -> F {
+    key.iter().chain(std::iter::repeat(&0)).take(MAX_KEY_LEN).fold(F::zero(), |acc, &b| acc * F::from(256) + F::from(b as u64))
+}
+
+// Number of keys at each level 1..=cap (cap >= 1), with deeper keys folded into the last bucket. The reference for
+// `KeyCountsCircuit`
+pub fn key_counts_per_level(bytes: &[u8], cap: usize) -> Vec<usize> {
+    let mut counts = vec![0; cap];
+    for (level, _, _) in key_runs(bytes) {
+        counts[level.min(cap) - 1] += 1;
+    }
+    counts
+}
+
+// Whether the top-level keys are in strictly increasing byte order (so also free of duplicates). The reference for
+// `SortedKeysCircuit`
+pub fn top_level_keys_sorted(bytes: &[u8]) -> bool {
+    let keys: Vec<&[u8]> = top_level_key_ranges(bytes).into_iter().map(|(start, len)| &bytes[start..start + len]).collect();
+    keys.windows(2).all(|pair| pair[0] < pair[1])
+}
+
+// Raw bytes of the value of top-level `key` (string values keep their quotes), without surrounding whitespace.
+// The first occurrence wins. Off-circuit, the reference for `KeyValueCircuit` and the set check in the TODO above
+pub fn top_level_value<'a>(bytes: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    let (_, start, len) = key_runs(bytes).into_iter().find(|&(level, start, len)| level == 1 && &bytes[start..start + len] == key)?;
+    value_after_key(bytes, start, len)
+}
+
+// A key of `key_tree`: its contents, the index of the key whose value is the object holding it (None at the top
+// level), its start as in `key_runs`, and its value as `value_after_key`
+type KeyNode<'a> = (&'a [u8], Option<usize>, usize, &'a [u8]);
+
+// Every key, in document order. None when an object sits inside an array, where its keys have no such parent. The
+// reference for the pairs of `StructuralEqualityCircuit`
+fn key_tree(bytes: &[u8]) -> Option<Vec<KeyNode<'_>>> {
+
+    let offset = |value: &[u8]| value.as_ptr() as usize - bytes.as_ptr() as usize;
+    let runs = key_runs(bytes);
+    let mut tree: Vec<KeyNode> = vec![];
+    for &(level, start, len) in &runs {
+
+        // The innermost value around the key is its object only one level up
+        let holder = tree.iter().rposition(|&(_, _, _, value)| (offset(value)..offset(value) + value.len()).contains(&start));
+        let parent = match holder {
+            Some(idx) if runs[idx].0 + 1 == level => Some(idx),
+            None if level == 1 => None,
+            _ => return None,
+        };
+        tree.push((&bytes[start..start + len], parent, start, value_after_key(bytes, start, len)?));
+    }
+
+    Some(tree)
+}
+
+// Raw bytes of the value of the key whose contents are `start..start + len`, as `top_level_value`
+fn value_after_key(bytes: &[u8], start: usize, len: usize) -> Option<&[u8]> {
+
+    // Skip past the closing quote, whitespace and the ':'
+    let mut idx = start + len + 1;
+    while idx < bytes.len() && bytes[idx] != b':' {
+        idx += 1;
+    }
+    let value_start = idx + 1;
+
+    // The value ends at the first ',' or '}' at its own level, outside strings
+    let mut depth = 0;
+    let mut in_str = false;
+    let mut escaped = false;
+    let mut value_end = bytes.len();
+    for (idx, &b) in bytes.iter().enumerate().skip(value_start) {
+        if in_str {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_str = false,
+                _ => {},
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_str = true,
+            b'{' | b'[' => depth += 1,
+            b',' | b'}' if depth == 0 => {
+                value_end = idx;
+                break;
+            },
+            b'}' | b']' => depth -= 1,
+            _ => {},
+        }
+    }
+
+    let value = &bytes[value_start.min(value_end)..value_end];
+    let trimmed_start = value.iter().position(|b| !b.is_ascii_whitespace())?;
+    let trimmed_end = value.iter().rposition(|b| !b.is_ascii_whitespace())? + 1;
+    Some(&value[trimmed_start..trimmed_end])
+}
+
+// Where the contents of each key of `path` start: the first key of an object at each step, the first one at the top
+// level, then each next one directly inside the previous one's value, which must be an object. None if a step is
+// missing. The reference for `PathEqualityCircuit`
+fn path_key_starts(bytes: &[u8], path: &[&[u8]]) -> Option<Vec<usize>> {
+
+    let runs = key_runs(bytes);
+    let mut scope = 0..bytes.len();
+    let mut starts = vec![];
+
+    for (depth, key) in path.iter().enumerate() {
+        let &(_, start, len) = runs.iter().find(|&&(level, start, len)| level == depth + 1 && scope.contains(&start) && &bytes[start..start + len] == *key)?;
+        let value = value_after_key(bytes, start, len)?;
+        let value_start = value.as_ptr() as usize - bytes.as_ptr() as usize;
+        scope = value_start..value_start + value.len();
+        starts.push(start);
+    }
+
+    Some(starts)
+}
+
+// Number of elements of the array `value` opens with (e.g. from `top_level_value`): its first element plus the commas
+// at its own level, which is what the counter column in the TODO above counts. `[]` counts 0. None if `value` is not an
+// array. The reference for `ArrayCircuit`
+pub fn array_len(value: &[u8]) -> Option<usize> {
+
+    if value.first() != Some(&b'[') {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut in_str = false;
+    let mut escaped = false;
+    let mut len = 0;
+    for &b in value {
+        if in_str {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_str = false,
+                _ => {},
+            }
+            continue;
+        }
+        if depth == 1 && len == 0 && !b.is_ascii_whitespace() && b != b']' {
+            len = 1;
+        }
+        match b {
+            b'"' => in_str = true,
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(len);
+                }
+            },
+            b',' if depth == 1 => len += 1,
+            _ => {},
+        }
+    }
+
+    // Not closed
+    None
+}
+
+// Whether the value of top-level `key` is one of `set`, compared as raw JSON (so string entries include their quotes).
+// The reference for `ValueInSetCircuit`
+pub fn top_level_value_in_set(bytes: &[u8], key: &[u8], set: &[&[u8]]) -> bool {
+    top_level_value(bytes, key).map_or(false, |value| set.contains(&value))
+}
+
+// Where `needle` first starts in the contents of the string value of top-level `key`, compared as raw bytes (escapes
+// are not decoded). None if the value is not a string or does not contain it. The reference for `SubstringCircuit`
+pub fn top_level_value_substring(bytes: &[u8], key: &[u8], needle: &[u8]) -> Option<usize> {
+    let value = top_level_value(bytes, key)?;
+    let contents = value.strip_prefix(b"\"")?.strip_suffix(b"\"")?;
+    match needle.len() {
+        0 => Some(0),
+        len => contents.windows(len).position(|window| window == needle),
+    }
+}
+
+// Top-level keys not in `schema` (extra) and schema entries with no top-level key (missing), as raw key bytes.
+// The document conforms to the closed schema iff both are empty. The reference for `SchemaCircuit`, which also
+// counts a repeated key as extra
+pub fn schema_mismatches(bytes: &[u8], schema: &[&[u8]]) -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+
+    let keys: Vec<&[u8]> = top_level_key_ranges(bytes).into_iter().map(|(start, len)| &bytes[start..start + len]).collect();
+    let extra = keys.iter().filter(|key| !schema.contains(key)).map(|key| key.to_vec()).collect();
+    let missing = schema.iter().filter(|entry| !keys.contains(entry)).map(|entry| entry.to_vec()).collect();
+    (extra, missing)
+}
+
+// First record whose (previous, new) commitment pair doesn't continue the feed, where `feed` holds the genesis
+// commitment followed by one commitment per record. None if every link matches and the lengths agree (a missing or
+// extra record is reported at the first index past the shorter side). The chain check of `verify_chain`
+pub fn commitment_chain_break<C: PartialEq>(links: &[(C, C)], feed: &[C]) -> Option<usize> {
+
+    let broken = links.iter().enumerate().position(|(idx, (prev, next))| {
+        feed.get(idx) != Some(prev) || feed.get(idx + 1) != Some(next)
+    });
+
+    match broken {
+        Some(idx) => Some(idx),
+        None if feed.len() != links.len() + 1 => Some(links.len()),
+        None => None,
+    }
+}
+
+// One record's proof, with its public inputs: (previous commitment, new commitment) first, then anything else, as
+// `ChainRecordCircuit` exposes them
+#[derive(Clone, Debug)]
+pub struct ChainedProof {
+    pub proof: Vec<u8>,
+    pub instances: Vec<Fr>,
+}
+
+// Verify every record's proof (KZG, SHPLONK, Blake2b transcript, as `create_proof` in examples/json_bench.rs) against
+// one verifying key, then chain the verified records along `commitments` as `commitment_chain_break`. Err with the
+// first record that fails either way. One key means one layout, so records have to share a length for now
+pub fn verify_chain(params: &ParamsKZG<Bn256>, vk: &VerifyingKey<G1Affine>, proofs: &[ChainedProof], commitments: &[Fr]) -> Result<(), usize> {
+
+    let verified = |record: &ChainedProof| {
+        let mut transcript = Blake2bRead::<_, G1Affine, Challenge255<_>>::init(&record.proof[..]);
+        verify_proof::<
+            KZGCommitmentScheme<Bn256>,
+            VerifierSHPLONK<'_, Bn256>,
+            Challenge255<G1Affine>,
+            Blake2bRead<&[u8], G1Affine, Challenge255<G1Affine>>,
+            SingleStrategy<'_, Bn256>,
+        >(params.verifier_params(), vk, SingleStrategy::new(params), &[&[&record.instances[..]]], &mut transcript)
+        .is_ok()
+    };
+
+    // Only verified records are chained, so a bad proof also ends the chain
+    let checked = proofs.iter().position(|record| record.instances.len() < 2 || !verified(record)).unwrap_or(proofs.len());
+    let links: Vec<(Fr, Fr)> = proofs[..checked].iter().map(|record| (record.instances[0], record.instances[1])).collect();
+
+    match commitment_chain_break(&links, commitments) {
+        Some(idx) => Err(idx),
+        None if checked < proofs.len() => Err(checked),
+        None => Ok(()),
+    }
+}
+
+// One layout for a verifier with nothing to generate: `LengthCircuit` padded to `FIXED_LEN` bytes at k = `FIXED_K`, so
+// a single verifying key covers every document up to that size, and every proof has the same size. The KZG params
+// are read from `FIXED_PARAMS`, where `gen_srs` in the scaffold writes them (an unsafe setup; ship a ceremony's
+// instead), and the key from `FIXED_VK`, serialized next to them once by `write_fixed_vk`
+pub const FIXED_K: u32 = 9;
+pub const FIXED_LEN: usize = 480; // the most `min_k_for_json` fits in `FIXED_K` with `expose_length` is 502
+pub const FIXED_PARAMS: &str = "params/kzg_bn254_9.srs";
+pub const FIXED_VK: &str = "params/json_length_9.vk";
+
+// Keygen for the fixed layout on the params at `FIXED_PARAMS`, serialized to `FIXED_VK`. Run once, before shipping
+// `verify_fixed`
+pub fn write_fixed_vk() -> io::Result<()> {
+
+    let params = read_fixed_params()?;
+    let circuit = LengthCircuit::<Fr> { raw: vec![Value::unknown(); FIXED_LEN], len: 0 };
+    let vk: VerifyingKey<G1Affine> = keygen_vk(&params, &circuit).map_err(|err| io::Error::new(io::ErrorKind::Other, format!("{err:?}")))?;
+    vk.write(&mut BufWriter::new(File::create(FIXED_VK)?), SerdeFormat::RawBytes)
+}
+
+fn read_fixed_params() -> io::Result<ParamsKZG<Bn256>> {
+
+    let params = ParamsKZG::<Bn256>::read(&mut BufReader::new(File::open(FIXED_PARAMS)?))?;
+    if params.k() != FIXED_K {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{FIXED_PARAMS} is not for k = {FIXED_K}")));
+    }
+    Ok(params)
+}
+
+// Verify a proof of the fixed layout (KZG, SHPLONK, Blake2b transcript, as `verify_chain`), `instances` being the
+// document's length as `LengthCircuit::instances`, against the serialized key: no keygen. Ok(false) for a proof of any
+// other layout, which only its own key verifies, and Err when the params or the key can't be read
+pub fn verify_fixed(proof: &[u8], instances: &[Fr]) -> io::Result<bool> {
+
+    let params = read_fixed_params()?;
+    let vk = VerifyingKey::<G1Affine>::read::<_, LengthCircuit<Fr>>(&mut BufReader::new(File::open(FIXED_VK)?), SerdeFormat::RawBytes)?;
+
+    let mut transcript = Blake2bRead::<_, G1Affine, Challenge255<_>>::init(proof);
+    let verified = verify_proof::<
+        KZGCommitmentScheme<Bn256>,
+        VerifierSHPLONK<'_, Bn256>,
+        Challenge255<G1Affine>,
+        Blake2bRead<&[u8], G1Affine, Challenge255<G1Affine>>,
+        SingleStrategy<'_, Bn256>,
+    >(params.verifier_params(), &vk, SingleStrategy::new(&params), &[&[instances]], &mut transcript)
+    .is_ok();
+    Ok(verified)
+}
+
+// Expected values of the state columns, one entry per byte
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trace {
+    pub class: Vec<ByteClass>,
+    pub state: Vec<GrammarState>,
+    pub stack: Vec<u64>, // see `JsonConfig::stack`. Saturates far past `MAX_DEPTH`, where the stack lookup already fails
+    pub push: Vec<bool>,
+    pub pop: Vec<bool>,
+    pub value_type: Vec<ValueType>,
+    pub not_str: Vec<bool>,
+    pub str_escaped: Vec<bool>,
+    pub level: Vec<u64>,
+    pub utf8_pending: Vec<u64>, // only assigned with `strict_utf8`. Malformed sequences reset to 0, which the lookup rejects
+    pub char_class: Vec<CharClass>,
+    pub any_invalid: Vec<bool>, // from the first byte the grammar rejects on
+    pub digit_run: Vec<u64>, // see `JsonConfig::digit_run`. Not capped, the digit run lookup rejects a run past the bound
+    pub key: Vec<bool>, // the key flags and count, only assigned with `key_flag`
+    pub top_key: Vec<bool>,
+    pub key_count: Vec<u64>,
+    pub is_padding: Vec<bool>, // the padding and the length so far, only assigned with `expose_length`
+    pub length: Vec<u64>,
+    pub elems: Vec<[u64; MAX_DEPTH]>, // see `ElemsConfig::count`, only assigned with `max_elems`
+}
+
+// Role of a byte in the document, assigned to the char_class column so that other chips can copy it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CharClass {
+    Normal = 0,        // outside strings and not a brace, bracket or quote: ':' ',' whitespace, numbers, literals
+    Structural = 1,    // '{' '}' '[' ']' outside strings, and the quotes that open / close a string
+    StringContent = 2, // inside a string, escapes included
+}
+
+// What the is_valid cell holds after the whole trace: nothing rejected, a whole value ended, no container open
+fn is_valid(trace: &Trace) -> bool {
+    let last = trace.state.len() - 1;
+    !trace.any_invalid[last] && trace.state[last].accepts() && trace.stack[last] == 1
+}
+
+// Witness generation for the state columns of a document. `JsonChip::assign` assigns exactly this
+pub fn compute_trace(bytes: &[u8]) -> Trace {
+    trace_from(bytes, GrammarState::Start, false)
+}
+
+// `compute_trace` from `start`, so also for a top-level scalar, and with `PAD_BYTE` as padding if `padding`. From the
+// first rejected byte on, the state is `GrammarState::Invalid` outside any string, and the stack and level stay where
+// they were
+fn trace_from(bytes: &[u8], start: GrammarState, padding: bool) -> Trace {
+
+    let mut trace = Trace {
+        class: vec![], state: vec![], stack: vec![], push: vec![], pop: vec![], value_type: vec![], not_str: vec![],
+        str_escaped: vec![], level: vec![], utf8_pending: vec![], char_class: vec![], any_invalid: vec![], digit_run: vec![],
+        key: vec![], top_key: vec![], key_count: vec![], is_padding: vec![], length: vec![], elems: vec![],
+    };
+
+    let mut state = start;
+    let mut stack = 1u64;
+    let mut level = 0;
+    let mut utf8_pending = 0;
+    let mut key_count = 0;
+    let mut key_prev = false;
+    let mut length = 0;
+    let mut elems = [0; MAX_DEPTH];
+    let mut digit_run = 0;
+
+    for &b in bytes {
+
+        let class = ByteClass::of_padded(b, padding);
+        let container = if class.reads_container() { Container::of(stack) } else { Container::None };
+        let step = grammar_step(state, class, container).unwrap_or_else(Step::invalid);
+
+        // A value counts in the container it starts in, before a push opens its own
+        if step.value_type != ValueType::None && (1..=MAX_DEPTH as u64).contains(&level) {
+            elems[level as usize - 1] += 1;
+        }
+        if step.push {
+            stack = stack.saturating_mul(2).saturating_add(step.value_type as u64 - ValueType::Object as u64);
+            level += 1;
+            if level as usize <= MAX_DEPTH {
+                elems[level as usize - 1] = 0;
+            }
+        }
+        if step.pop {
+            stack /= 2;
+            level -= 1;
+        }
+        state = step.state;
+        let not_str = !state.in_string();
+        utf8_pending = if not_str { 0 } else { utf8_step(utf8_pending, b).unwrap_or(0) };
+        key_count += (step.key && !key_prev) as u64;
+        key_prev = step.key;
+        length += (state != GrammarState::Padded) as u64;
+        digit_run = if not_str && class.digit() && state != GrammarState::Invalid { digit_run + 1 } else { 0 };
+
+        trace.class.push(class);
+        trace.state.push(state);
+        trace.stack.push(stack);
+        trace.push.push(step.push);
+        trace.pop.push(step.pop);
+        trace.value_type.push(step.value_type);
+        trace.not_str.push(not_str);
+        trace.str_escaped.push(state.escaped());
+        trace.level.push(level);
+        trace.utf8_pending.push(utf8_pending);
+        trace.char_class.push(step.char_class);
+        trace.any_invalid.push(state == GrammarState::Invalid);
+        trace.digit_run.push(digit_run);
+        trace.key.push(step.key);
+        trace.top_key.push(step.key && level == 1);
+        trace.key_count.push(key_count);
+        trace.is_padding.push(state == GrammarState::Padded);
+        trace.length.push(length);
+        trace.elems.push(elems);
+    }
+
+    trace
+}
+
+// One step of a UTF-8 decoder. The state is the number of continuation bytes still expected (0..=3), or 4..=7 when
+// the next one has a narrower range: after E0 (no overlongs), ED (no surrogates), F0 (no overlongs), F4 (<= U+10FFFF).
+// None on a malformed sequence
+fn utf8_step(pending: u64, b: u8) -> Option<u64> {
+    match (pending, b) {
+        (0, 0x00..=0x7f) => Some(0),
+        (0, 0xc2..=0xdf) => Some(1),
+        (0, 0xe0) => Some(4),
+        (0, 0xed) => Some(5),
+        (0, 0xe1..=0xef) => Some(2),
+        (0, 0xf0) => Some(6),
+        (0, 0xf4) => Some(7),
+        (0, 0xf1..=0xf3) => Some(3),
+        (1..=3, 0x80..=0xbf) => Some(pending - 1),
+        (4, 0xa0..=0xbf) | (5, 0x80..=0x9f) => Some(1),
+        (6, 0x90..=0xbf) | (7, 0x80..=0x8f) => Some(2),
+        _ => None,
+    }
+}
+
+// `compute_trace` as a table, one row per byte, with each change of level called out
+#[cfg(feature = "debug")]
+pub fn render_trace(bytes: &[u8]) -> String {
+
+    let trace = compute_trace(bytes);
+    let mut out = String::from("  idx  byte  not_str  str_esc  level\n");
+
+    let mut prev_level = 0;
+    for (idx, &b) in bytes.iter().enumerate() {
+
+        let byte = if b.is_ascii_graphic() || b == b' ' { format!("'{}'", b as char) } else { format!("{:#04x}", b) };
+        out += &format!(
+            "{:>5}  {:>4}  {:>7}  {:>7}  {:>5}",
+            idx, byte, trace.not_str[idx] as u8, trace.str_escaped[idx] as u8, trace.level[idx]
+        );
+
+        if trace.level[idx] != prev_level {
+            out += &format!("  level {} -> {}", prev_level, trace.level[idx]);
+        }
+        prev_level = trace.level[idx];
+        out += "\n";
+    }
+
+    out
+}
+
+// Anything that is not a byte is witnessed as 0, which has no class either
+fn to_byte<F: FieldExt>(x: &F) -> u8 {
+    let v = x.get_lower_128();
+    if v < 0x100 && F::from(v as u64) == *x { v as u8 } else { 0 }
+}
+
+// Handles to the cells assigned by `JsonChip::assign`, one per byte, so an outer circuit can copy-constrain against them
+#[derive(Clone, Debug)]
+pub struct JsonAssigned<F: FieldExt> {
+    pub raw: Vec<AssignedCell<F, F>>,
+    pub not_str: Vec<AssignedCell<F, F>>,
+    pub level: Vec<AssignedCell<F, F>>,
+    pub char_class: Vec<AssignedCell<F, F>>,
+    pub value_type: Vec<AssignedCell<F, F>>,
+    // Key flags and count, see `JsonConfigParams::key_flag`. Empty without it
+    pub key: Vec<AssignedCell<F, F>>,
+    pub top_key: Vec<AssignedCell<F, F>>,
+    pub key_count: Vec<AssignedCell<F, F>>,
+    // State after the last byte: a complete document ends at level 0, outside a string
+    pub final_level: AssignedCell<F, F>,
+    pub final_not_str: AssignedCell<F, F>,
+    // 1 for a valid document and 0 otherwise, see `JsonConfigParams::soft_validity`. None without it
+    pub is_valid: Option<AssignedCell<F, F>>,
+    // Number of bytes before the padding, see `JsonConfigParams::expose_length`. None without it
+    pub length: Option<AssignedCell<F, F>>,
+}
+
+// The JSON validity check as a chip, for use inside another circuit's `synthesize`
+#[derive(Clone, Debug)]
+pub struct JsonChip<F: FieldExt> {
+    config: JsonConfig,
+    _marker: PhantomData<F>,
+}
+
+impl<F: FieldExt> JsonChip<F> {
+
+    pub fn construct(config: JsonConfig) -> Self {
+        Self { config, _marker: PhantomData }
+    }
+
+    pub fn configure(meta: &mut ConstraintSystem<F>) -> JsonConfig {
+        JsonConfig::configure(meta)
+    }
+
+    // Load the lookup tables. Call once per circuit, however many documents are assigned
+    pub fn load(&self, layouter: &mut impl Layouter<F>) -> Result<(), Error> {
+        self.config.load_string_byte_table(layouter)?;
+        self.config.load_grammar_tables(layouter)?;
+        self.config.load_utf8_table(layouter)?;
+        self.config.load_elems_table(layouter)
+    }
+
+    // Assign one document in its own region: the start row, then one byte per row
+    pub fn assign(&self, layouter: &mut impl Layouter<F>, raw: &[Value<F>]) -> Result<JsonAssigned<F>, Error> {
+
+        // The state columns come from the same trace the tests use as the oracle
+        let start = if self.config.top_level_scalar { GrammarState::StartScalar } else { GrammarState::Start };
+        let padding = self.config.length.is_some();
+        let trace: Value<Trace> = Value::<Vec<F>>::from_iter(raw.iter().cloned())
+            .map(|raw| trace_from(&raw.iter().map(to_byte).collect::<Vec<u8>>(), start, padding));
+
+        self.assign_with_trace(layouter, raw, trace)
+    }
+
+    // Separate from `assign` so that tests can forge the state columns
+    fn assign_with_trace(&self, layouter: &mut impl Layouter<F>, raw: &[Value<F>], trace: Value<Trace>) -> Result<JsonAssigned<F>, Error> {
+
+        let config = self.config;
+
+        // The end gate needs at least one byte
+        if raw.is_empty() {
+            return Err(Error::Synthesis);
+        }
+
+        layouter.assign_region(
+            || "Json circuit",
+            |mut region| {
+
+                let n = raw.len();
+                let start = if config.top_level_scalar { GrammarState::StartScalar } else { GrammarState::Start };
+
+                // The start row holds the state before the first byte, the same for every document
+                let start_row = [
+                    (config.state, start as u64), (config.stack, 1), (config.not_str, 1), (config.raw, 0), (config.class, 0),
+                    (config.ctx_flag, 0), (config.container, 0), (config.push, 0), (config.pop, 0), (config.value_type, 0),
+                    (config.accept, 0), (config.str_escaped, 0), (config.level, 0), (config.char_class, 0), (config.any_invalid, 0),
+                    (config.digit_run, 0),
+                ];
+                let optional = config.utf8.map(|utf8| utf8.pending).into_iter()
+                    .chain(config.keys.into_iter().flat_map(|keys| [keys.key, keys.top_key, keys.key_count, keys.level_one_inv]))
+                    .chain(config.length.into_iter().flat_map(|length| [length.is_padding, length.length]))
+                    .chain(config.elems.into_iter().flat_map(|elems| elems.at.into_iter().chain(elems.count).chain([elems.value_inv])));
+                for (column, value) in start_row.into_iter().chain(optional.map(|column| (column, 0))) {
+                    region.assign_advice(|| "start row", column, 0, || Value::known(F::from(value)))?;
+                }
+                config.start_selector.enable(&mut region, 0)?;
+
+                let mut raw_cells = Vec::with_capacity(n);
+                let mut not_str_cells = Vec::with_capacity(n);
+                let mut level_cells = Vec::with_capacity(n);
+                let mut char_class_cells = Vec::with_capacity(n);
+                let mut value_type_cells = Vec::with_capacity(n);
+                let mut key_cells = vec![];
+                let mut top_key_cells = vec![];
+                let mut key_count_cells = vec![];
+                let mut is_valid_cell = None;
+                let mut length_cell = None;
+
+                for (idx, r) in raw.iter().enumerate() {
+
+                    let offset = idx + 1;
+                    let field = |f: &dyn Fn(&Trace) -> u64| trace.as_ref().map(|t| F::from(f(t)));
+                    let class = trace.as_ref().map(|t| t.class[idx]);
+
+                    let raw_cell = region.assign_advice(
+                        || format!("raw input at idx = {}", idx),
+                        config.raw,
+                        offset,
+                        || *r,
+                    )?;
+
+                    region.assign_advice(
+                        || format!("class at idx = {}", idx),
+                        config.class,
+                        offset,
+                        || class.map(|c| F::from(c as u64)),
+                    )?;
+
+                    region.assign_advice(
+                        || format!("ctx_flag at idx = {}", idx),
+                        config.ctx_flag,
+                        offset,
+                        || class.map(|c| F::from(c.reads_container() as u64)),
+                    )?;
+
+                    // Write state variables
+                    let columns: [(&str, Column<Advice>, &dyn Fn(&Trace) -> u64); 7] = [
+                        ("state", config.state, &|t| t.state[idx] as u64),
+                        ("stack", config.stack, &|t| t.stack[idx]),
+                        ("container", config.container, &|t| Container::of(t.stack[idx]) as u64),
+                        ("push", config.push, &|t| t.push[idx] as u64),
+                        ("pop", config.pop, &|t| t.pop[idx] as u64),
+                        ("accept", config.accept, &|t| t.state[idx].accepts() as u64),
+                        ("str_escaped", config.str_escaped, &|t| t.str_escaped[idx] as u64),
+                    ];
+                    for (name, column, f) in columns {
+                        region.assign_advice(|| format!("{} at idx = {}", name, idx), column, offset, || field(f))?;
+                    }
+
+                    let value_type_cell = region.assign_advice(
+                        || format!("value_type at idx = {}", idx),
+                        config.value_type,
+                        offset,
+                        || field(&|t| t.value_type[idx] as u64),
+                    )?;
+
+                    let not_str_cell = region.assign_advice(
+                        || format!("not_str at idx = {}", idx),
+                        config.not_str,
+                        offset,
+                        || field(&|t| t.not_str[idx] as u64),
+                    )?;
+
+                    let level_cell = region.assign_advice(
+                        || format!("level at idx = {}", idx),
+                        config.level,
+                        offset,
+                        || field(&|t| t.level[idx]),
+                    )?;
+
+                    let char_class_cell = region.assign_advice(
+                        || format!("char_class at idx = {}", idx),
+                        config.char_class,
+                        offset,
+                        || field(&|t| t.char_class[idx] as u64),
+                    )?;
+
+                    region.assign_advice(
+                        || format!("any_invalid at idx = {}", idx),
+                        config.any_invalid,
+                        offset,
+                        || field(&|t| t.any_invalid[idx] as u64),
+                    )?;
+
+                    region.assign_advice(
+                        || format!("digit_run at idx = {}", idx),
+                        config.digit_run,
+                        offset,
+                        || field(&|t| t.digit_run[idx]),
+                    )?;
+
+                    let class_is = |c: ByteClass| class.map(|class| F::from(class as u64) - F::from(c as u64));
+                    let not_digit = class_is(ByteClass::Digit) * class_is(ByteClass::HighDigit);
+                    region.assign_advice(
+                        || format!("digit_inv at idx = {}", idx),
+                        config.digit_inv,
+                        offset,
+                        || not_digit.map(|d| d.invert().unwrap_or(F::zero())),
+                    )?;
+
+                    if let Some(utf8) = config.utf8 {
+                        region.assign_advice(
+                            || format!("utf8_pending at idx = {}", idx),
+                            utf8.pending,
+                            offset,
+                            || field(&|t| t.utf8_pending[idx]),
+                        )?;
+                    }
+
+                    if let Some(keys) = config.keys {
+
+                        let level_one_inv = level_cell.value().map(|l| if *l == F::one() {F::one()} else {(*l - F::one()).invert().unwrap()});
+
+                        key_cells.push(region.assign_advice(
+                            || format!("key at idx = {}", idx),
+                            keys.key,
+                            offset,
+                            || field(&|t| t.key[idx] as u64),
+                        )?);
+                        top_key_cells.push(region.assign_advice(
+                            || format!("top_key at idx = {}", idx),
+                            keys.top_key,
+                            offset,
+                            || field(&|t| t.top_key[idx] as u64),
+                        )?);
+                        key_count_cells.push(region.assign_advice(
+                            || format!("key_count at idx = {}", idx),
+                            keys.key_count,
+                            offset,
+                            || field(&|t| t.key_count[idx]),
+                        )?);
+                        region.assign_advice(|| format!("level_one_inv at idx = {}", idx), keys.level_one_inv, offset, || level_one_inv)?;
+                    }
+
+                    if let Some(elems) = config.elems {
+
+                        let level_prev = |t: &Trace| if idx == 0 { 0 } else { t.level[idx - 1] };
+                        let value_inv = value_type_cell.value().map(|vt| vt.invert().unwrap_or(F::zero()));
+
+                        for (depth, (&at, &count)) in elems.at.iter().zip(elems.count.iter()).enumerate() {
+                            region.assign_advice(
+                                || format!("at level {} at idx = {}", depth + 1, idx),
+                                at,
+                                offset,
+                                || field(&|t| (level_prev(t) == depth as u64 + 1) as u64),
+                            )?;
+                            region.assign_advice(
+                                || format!("count at level {} at idx = {}", depth + 1, idx),
+                                count,
+                                offset,
+                                || field(&|t| t.elems[idx][depth]),
+                            )?;
+                        }
+                        region.assign_advice(|| format!("value_inv at idx = {}", idx), elems.value_inv, offset, || value_inv)?;
+                    }
+
+                    if let Some(length) = config.length {
+                        region.assign_advice(
+                            || format!("is_padding at idx = {}", idx),
+                            length.is_padding,
+                            offset,
+                            || field(&|t| t.is_padding[idx] as u64),
+                        )?;
+                        length_cell = Some(region.assign_advice(
+                            || format!("length at idx = {}", idx),
+                            length.length,
+                            offset,
+                            || field(&|t| t.length[idx]),
+                        )?);
+                    }
+
+                    raw_cells.push(raw_cell);
+                    not_str_cells.push(not_str_cell);
+                    level_cells.push(level_cell);
+                    char_class_cells.push(char_class_cell);
+                    value_type_cells.push(value_type_cell);
+
+                    // Set the selectors
+                    config.body_selector.enable(&mut region, offset)?;
+                    if idx == n - 1 {
+                        config.end_selector.enable(&mut region, offset)?;
+                    }
+
+                    if let (Some(validity), true) = (config.validity, idx == n - 1) {
+
+                        let open = field(&|t| t.stack[idx]).map(|st| st - F::one());
+                        let root_inv = open.map(|open| open.invert().unwrap_or(F::zero()));
+
+                        is_valid_cell = Some(region.assign_advice(
+                            || "is_valid",
+                            validity.is_valid,
+                            offset,
+                            || field(&|t| is_valid(t) as u64),
+                        )?);
+                        region.assign_advice(|| "root_inv", validity.root_inv, offset, || root_inv)?;
+                    }
+
+                }
+
+                let final_level = level_cells.last().cloned().ok_or(Error::Synthesis)?;
+                let final_not_str = not_str_cells.last().cloned().ok_or(Error::Synthesis)?;
+
+                Ok(JsonAssigned {
+                    raw: raw_cells, not_str: not_str_cells, level: level_cells, char_class: char_class_cells, value_type: value_type_cells,
+                    key: key_cells, top_key: top_key_cells, key_count: key_count_cells, final_level, final_not_str,
+                    is_valid: is_valid_cell, length: length_cell,
+                })
+            }
+        )
+
+    }
+
+}
+
+// Implementation. Right now it only supports checking that the JSON is structurally valid
+// TODO:
+//  - Need to compose this with RLC for the query
+impl<F: FieldExt> Circuit<F> for JsonCircuit<F> {
+
+    type Config = JsonConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        unimplemented!()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        JsonChip::configure(meta)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+
+        let chip = JsonChip::construct(config);
+        chip.load(&mut layouter)?;
+        chip.assign(&mut layouter, &self.raw)?;
+
+        Ok(())
+
+    }
+
+}
+
+
+// Any document, with its validity public: 1 if it is valid and 0 if not (see `instances`), on the chip configured
+// with `JsonConfigParams::soft_validity`. Valid as the grammar has it: the checks `preflight` adds on top are not in
+// the circuit, so `from_bytes` refuses the documents they would turn from valid into invalid
+#[derive(Clone, Default)]
+pub struct ValidityCircuit<F: FieldExt> {
+    pub raw: Vec<Value<F>>,
+    pub valid: bool,
+}
+
+impl<F: FieldExt> ValidityCircuit<F> {
+
+    pub fn from_str(json: &str) -> Result<Self, ParseError> {
+        Self::from_bytes(json.as_bytes())
+    }
+
+    // Fails where neither outcome can be proven: no byte at all, more than `DEFAULT_MAX_LEN`, or nesting past
+    // `MAX_DEPTH` or a digit run past `MAX_NUMBER_DIGITS` before any rejected byte
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+
+        if bytes.len() > DEFAULT_MAX_LEN {
+            return Err(ParseError::TooLong(DEFAULT_MAX_LEN));
+        }
+        if bytes.is_empty() {
+            return Err(ParseError::Empty);
+        }
+
+        let trace = compute_trace(bytes);
+        if let Some(idx) = trace.level.iter().position(|&l| l as usize > MAX_DEPTH) {
+            return Err(ParseError::TooDeep(idx));
+        }
+        let rejected = trace.any_invalid.iter().position(|&x| x).unwrap_or(bytes.len());
+        if let Some(idx) = long_number_at(bytes).filter(|&idx| idx < rejected) {
+            return Err(ParseError::NumberTooLong(idx));
+        }
+        let valid = is_valid(&trace);
+
+        let raw = bytes.iter().map(|&b| Value::known(F::from(b as u64))).collect();
+        Ok(Self { raw, valid })
+    }
+
+    pub fn instances(&self) -> Vec<F> {
+        vec![F::from(self.valid as u64)]
+    }
+}
+
+impl<F: FieldExt> Circuit<F> for ValidityCircuit<F> {
+
+    type Config = (JsonConfig, Column<Instance>);
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { raw: vec![Value::unknown(); self.raw.len()], ..self.clone() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+
+        let json = JsonConfig::configure_with_params(meta, JsonConfigParams { soft_validity: true, ..Default::default() });
+        let instance = meta.instance_column();
+        meta.enable_equality(instance);
+
+        (json, instance)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+
+        let chip = JsonChip::construct(config.0);
+        chip.load(&mut layouter)?;
+        let assigned = chip.assign(&mut layouter, &self.raw)?;
+
+        let is_valid = assigned.is_valid.ok_or(Error::Synthesis)?;
+        layouter.constrain_instance(is_valid.cell(), config.1, 0)
+    }
+}
+
+
+// A document padded with `PAD_BYTE` up to a fixed size, with its true length public (see `instances`), on the chip
+// configured with `JsonConfigParams::expose_length`. The layout only depends on the padded size, so every document up
+// to it shares one verifying key
+#[derive(Clone, Default)]
+pub struct LengthCircuit<F: FieldExt> {
+    pub raw: Vec<Value<F>>,
+    pub len: usize, // bytes before the padding
+}
+
+impl<F: FieldExt> LengthCircuit<F> {
+
+    pub fn from_str(json: &str, padded_len: usize) -> Result<Self, ParseError> {
+        Self::from_bytes(json.as_bytes(), padded_len)
+    }
+
+    // `bytes` followed by `padded_len - bytes.len()` pad bytes. The document itself must not contain `PAD_BYTE`, or
+    // the padding would start there
+    pub fn from_bytes(bytes: &[u8], padded_len: usize) -> Result<Self, ParseError> {
+
+        if bytes.len() > padded_len {
+            return Err(ParseError::TooLong(padded_len));
+        }
+        if let Some(idx) = bytes.iter().position(|&b| b == PAD_BYTE) {
+            return Err(ParseError::UnexpectedChar(idx));
+        }
+
+        let params = JsonConfigParams { expose_length: true, ..Default::default() };
+        preflight(bytes, &params)?;
+
+        let raw = bytes.iter().chain(std::iter::repeat(&PAD_BYTE).take(padded_len - bytes.len()))
+            .map(|&b| Value::known(F::from(b as u64))).collect();
+        Ok(Self { raw, len: bytes.len() })
+    }
+
+    pub fn instances(&self) -> Vec<F> {
+        vec![F::from(self.len as u64)]
+    }
+}
+
+impl<F: FieldExt> Circuit<F> for LengthCircuit<F> {
+
+    type Config = (JsonConfig, Column<Instance>);
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { raw: vec![Value::unknown(); self.raw.len()], ..self.clone() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+
+        let json = JsonConfig::configure_with_params(meta, JsonConfigParams { expose_length: true, ..Default::default() });
+        let instance = meta.instance_column();
+        meta.enable_equality(instance);
+
+        (json, instance)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+
+        let chip = JsonChip::construct(config.0);
+        chip.load(&mut layouter)?;
+        let assigned = chip.assign(&mut layouter, &self.raw)?;
+
+        let length = assigned.length.ok_or(Error::Synthesis)?;
+        layouter.constrain_instance(length.cell(), config.1, 0)
+    }
+}
+
+
+// Columns for decoding base64 into a document's bytes, see `Base64Circuit`. One row per group of four chars
+#[derive(Clone, Copy, Debug)]
+pub struct Base64Config {
+    chars: [Column<Advice>; 4],
+    sextets: [Column<Advice>; 4],
+    pad: [Column<Advice>; 2],   // the third and fourth char are '='
+    bytes: [Column<Advice>; 3], // the decoded bytes, copied from the document's raw cells, and 0 where a '=' drops them
+    split: [Column<Advice>; 4], // sextet 1 as (>> 4, & 15) and sextet 2 as (>> 2, & 3)
+    q_group: Selector,
+    q_inner: Selector,     // a group without '='
+    q_pad: [Selector; 2],  // the last group ends in one '=', and in two
+    alphabet_table: [TableColumn; 4], // (1, char, sextet, pad), see `base64_alphabet_rows`
+    split_table: [TableColumn; 5],    // (sextet, sextet >> 4, sextet & 15, sextet >> 2, sextet & 3)
+}
+
+impl Base64Config {
+
+    // Every char is in the alphabet or '=', the bytes are the sextets' bits in order, and '=' only ends the last
+    // group, as many times as the layout drops bytes, with the bits it drops all 0
+    pub fn configure<F: FieldExt>(meta: &mut ConstraintSystem<F>) -> Self {
+
+        let chars = [(); 4].map(|_| meta.advice_column());
+        let sextets = [(); 4].map(|_| meta.advice_column());
+        let pad = [(); 2].map(|_| meta.advice_column());
+        let bytes = [(); 3].map(|_| meta.advice_column());
+        let split = [(); 4].map(|_| meta.advice_column());
+        bytes.map(|column| meta.enable_equality(column));
+
+        let q_group = meta.complex_selector(); // also used in lookups
+        let q_inner = meta.selector();
+        let q_pad = [(); 2].map(|_| meta.selector());
+
+        let alphabet_table = [(); 4].map(|_| meta.lookup_table_column());
+        let split_table = [(); 5].map(|_| meta.lookup_table_column());
+
+        // The leading 1 keeps the all-zero row out of reach of the enabled rows, so a 0 byte is no 'A'. Only the last
+        // two chars of a group may be '='
+        for i in 0..4 {
+            meta.lookup("Base64 alphabet", |meta| {
+
+                let c = meta.query_advice(chars[i], Rotation::cur());
+                let sx = meta.query_advice(sextets[i], Rotation::cur());
+                let q = meta.query_selector(q_group);
+                let p = match i {
+                    2 | 3 => q.clone() * meta.query_advice(pad[i - 2], Rotation::cur()),
+                    _ => Expression::Constant(F::zero()),
+                };
+
+                vec![(q.clone(), alphabet_table[0]), (q.clone() * c, alphabet_table[1]), (q * sx, alphabet_table[2]), (p, alphabet_table[3])]
+
+            });
+        }
+
+        // The split parts are in range, so the bytes are too
+        meta.lookup("Base64 split", |meta| {
+
+            let s1 = meta.query_advice(sextets[1], Rotation::cur());
+            let [h1, l1] = [0, 1].map(|i| meta.query_advice(split[i], Rotation::cur()));
+            let q = meta.query_selector(q_group);
+
+            vec![(q.clone() * s1, split_table[0]), (q.clone() * h1, split_table[1]), (q * l1, split_table[2])]
+
+        });
+
+        meta.lookup("Base64 split", |meta| {
+
+            let s2 = meta.query_advice(sextets[2], Rotation::cur());
+            let [h2, l2] = [2, 3].map(|i| meta.query_advice(split[i], Rotation::cur()));
+            let q = meta.query_selector(q_group);
+
+            vec![(q.clone() * s2, split_table[0]), (q.clone() * h2, split_table[3]), (q * l2, split_table[4])]
+
+        });
+
+        meta.create_gate("Base64 decode", |meta| {
+
+            let [s0, _, _, s3] = sextets.map(|column| meta.query_advice(column, Rotation::cur()));
+            let [h1, l1, h2, l2] = split.map(|column| meta.query_advice(column, Rotation::cur()));
+            let [b0, b1, b2] = bytes.map(|column| meta.query_advice(column, Rotation::cur()));
+            let [p2, p3] = pad.map(|column| meta.query_advice(column, Rotation::cur()));
+            let q = meta.query_selector(q_group);
+
+            let one = Expression::Constant(F::one());
+            let c = |x: u64| Expression::Constant(F::from(x));
+
+            vec![
+                q.clone() * (b0 - c(4) * s0 - h1),
+                q.clone() * (b1.clone() - c(16) * l1 - h2),
+                q.clone() * (b2.clone() - c(64) * l2 - s3),
+                q.clone() * p2.clone() * (one - p3.clone()), // "x=" is no ending
+                q.clone() * p3 * b2, // a '=' drops its byte, which must come out 0
+                q * p2 * b1,
+            ]
+
+        });
+
+        meta.create_gate("Base64 padding", |meta| {
+
+            let [p2, p3] = pad.map(|column| meta.query_advice(column, Rotation::cur()));
+            let q_in = meta.query_selector(q_inner);
+            let [q_one, q_two] = q_pad.map(|q| meta.query_selector(q));
+
+            let one = Expression::Constant(F::one());
+
+            vec![q_in * p3.clone(), q_one * (one.clone() - p3), q_two * (one - p2)]
+
+        });
+
+        Self { chars, sextets, pad, bytes, split, q_group, q_inner, q_pad, alphabet_table, split_table }
+    }
+
+    pub fn load<F: FieldExt>(&self, layouter: &mut impl Layouter<F>) -> Result<(), Error> {
+
+        let alphabet: Vec<Vec<u64>> = base64_alphabet_rows().iter().map(|row| row.to_vec()).collect();
+        let splits: Vec<Vec<u64>> = (0..64u64).map(|sx| vec![sx, sx >> 4, sx & 15, sx >> 2, sx & 3]).collect();
+
+        let tables: [(&str, &[TableColumn], Vec<Vec<u64>>); 2] = [
+            ("Base64 alphabet table", &self.alphabet_table, alphabet),
+            ("Base64 split table", &self.split_table, splits),
+        ];
+        for (name, columns, rows) in tables {
+            layouter.assign_table(
+                || name,
+                |mut table| {
+                    for (idx, row) in rows.iter().enumerate() {
+                        for (column, value) in columns.iter().zip(row.iter()) {
+                            table.assign_cell(|| format!("{} row {}", name, idx), *column, idx, || Value::known(F::from(*value)))?;
+                        }
+                    }
+                    Ok(())
+                }
+            )?;
+        }
+
+        Ok(())
+    }
+
+    // Decode `chars` into the document `assigned` holds: each of its raw cells is copied into the byte it decodes
+    // to, so the document is exactly the decoded bytes. The number of '=' follows from the document's length
+    pub fn assign<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, assigned: &JsonAssigned<F>, chars: &[Value<F>]) -> Result<(), Error> {
+
+        let groups = chars.len() / 4;
+        let n = assigned.raw.len();
+        if chars.len() % 4 != 0 || n + 2 < 3 * groups || n > 3 * groups {
+            return Err(Error::Synthesis);
+        }
+        let dropped = 3 * groups - n;
+
+        layouter.assign_region(
+            || "Base64 decode",
+            |mut region| {
+
+                for (g, group) in chars.chunks(4).enumerate() {
+
+                    let c: Vec<Value<u8>> = group.iter().map(|c| c.map(|c| to_byte(&c))).collect();
+                    let sx: Vec<Value<u64>> = c.iter().map(|c| c.map(|c| base64_sextet(c).unwrap_or(0))).collect();
+                    let decoded = [
+                        sx[0].zip(sx[1]).map(|(s0, s1)| s0 << 2 | s1 >> 4),
+                        sx[1].zip(sx[2]).map(|(s1, s2)| (s1 & 15) << 4 | s2 >> 2),
+                        sx[2].zip(sx[3]).map(|(s2, s3)| (s2 & 3) << 6 | s3),
+                    ];
+                    let split = [sx[1].map(|s| s >> 4), sx[1].map(|s| s & 15), sx[2].map(|s| s >> 2), sx[2].map(|s| s & 3)];
+
+                    for i in 0..4 {
+                        region.assign_advice(|| format!("char {} of group {}", i, g), self.chars[i], g, || group[i])?;
+                        region.assign_advice(|| format!("sextet {} of group {}", i, g), self.sextets[i], g, || sx[i].map(F::from))?;
+                        region.assign_advice(|| format!("split {} of group {}", i, g), self.split[i], g, || split[i].map(F::from))?;
+                    }
+                    for i in 0..2 {
+                        let p = c[i + 2].map(|c| F::from((c == b'=') as u64));
+                        region.assign_advice(|| format!("pad {} of group {}", i + 2, g), self.pad[i], g, || p)?;
+                    }
+                    for (i, byte) in decoded.into_iter().enumerate() {
+                        match assigned.raw.get(3 * g + i) {
+                            Some(raw) => raw.copy_advice(|| "decoded byte", &mut region, self.bytes[i], g)?,
+                            None => region.assign_advice(|| "dropped byte", self.bytes[i], g, || byte.map(F::from))?,
+                        };
+                    }
+
+                    self.q_group.enable(&mut region, g)?;
+                    match (g + 1 == groups, dropped) {
+                        (true, 1) => self.q_pad[0].enable(&mut region, g)?,
+                        (true, 2) => {
+                            self.q_pad[0].enable(&mut region, g)?;
+                            self.q_pad[1].enable(&mut region, g)?;
+                        },
+                        _ => self.q_inner.enable(&mut region, g)?,
+                    }
+                }
+
+                Ok(())
+            }
+        )
+    }
+}
+
+// Sextet of a base64 char (RFC 4648, standard alphabet), None for '=' and anything else
+fn base64_sextet(c: u8) -> Option<u64> {
+    match c {
+        b'A'..=b'Z' => Some((c - b'A') as u64),
+        b'a'..=b'z' => Some((c - b'a') as u64 + 26),
+        b'0'..=b'9' => Some((c - b'0') as u64 + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+// The all-zero row, then (1, char, sextet, pad) for the 64 chars of the alphabet and '='
+fn base64_alphabet_rows() -> Vec<[u64; 4]> {
+    let alphabet = (0..=0xffu8).filter_map(|c| base64_sextet(c).map(|sx| [1, c as u64, sx, 0]));
+    [[0; 4]].into_iter().chain(alphabet).chain([[1, b'=' as u64, 0, 1]]).collect()
+}
+
+// The bytes `chars` decode to, as `Base64Config` checks them: groups of four, '=' only as the last one or two chars,
+// and the bits a '=' drops all 0. Errors index into `chars`
+pub fn base64_decode(chars: &[u8]) -> Result<Vec<u8>, ParseError> {
+
+    if chars.len() % 4 != 0 {
+        return Err(ParseError::UnexpectedEnd(chars.len()));
+    }
+
+    let data = chars.iter().rposition(|&c| c != b'=').map_or(0, |idx| idx + 1);
+    if chars.len() - data > 2 {
+        return Err(ParseError::UnexpectedChar(data));
+    }
+
+    let mut sextets = Vec::with_capacity(data);
+    for (idx, &c) in chars[..data].iter().enumerate() {
+        sextets.push(base64_sextet(c).ok_or(ParseError::UnexpectedChar(idx))?);
+    }
+
+    let mut bytes = vec![];
+    for group in sextets.chunks(4) {
+        let bits = group.iter().fold(0, |acc, &sx| acc << 6 | sx) << (6 * (4 - group.len()));
+        bytes.extend_from_slice(&bits.to_be_bytes()[5..5 + group.len() * 3 / 4]);
+    }
+
+    // The bits a '=' drops must be 0, or two encodings would decode to the same bytes
+    let kept = sextets.len() * 6 - bytes.len() * 8;
+    if kept > 0 && sextets.last().map_or(false, |&sx| sx & ((1 << kept) - 1) != 0) {
+        return Err(ParseError::UnexpectedChar(data - 1));
+    }
+
+    Ok(bytes)
+}
+
+
+// A base64 payload decodes to a document, with the document's validity public as in `ValidityCircuit`. The payload
+// and the document both stay witnesses; their lengths are fixed by the layout
+#[derive(Clone, Default)]
+pub struct Base64Circuit<F: FieldExt> {
+    pub chars: Vec<Value<F>>,
+    pub len: usize, // decoded bytes
+    pub valid: bool,
+}
+
+impl<F: FieldExt> Base64Circuit<F> {
+
+    pub fn from_str(b64: &str) -> Result<Self, ParseError> {
+        Self::from_bytes(b64.as_bytes())
+    }
+
+    // Fails on a payload that is not base64, and where `ValidityCircuit::from_bytes` fails on the decoded document
+    pub fn from_bytes(chars: &[u8]) -> Result<Self, ParseError> {
+
+        let bytes = base64_decode(chars)?;
+        let valid = ValidityCircuit::<F>::from_bytes(&bytes)?.valid;
+
+        let chars = chars.iter().map(|&c| Value::known(F::from(c as u64))).collect();
+        Ok(Self { chars, len: bytes.len(), valid })
+    }
+
+    pub fn instances(&self) -> Vec<F> {
+        vec![F::from(self.valid as u64)]
+    }
+}
+
+impl<F: FieldExt> Circuit<F> for Base64Circuit<F> {
+
+    type Config = (JsonConfig, Base64Config, Column<Instance>);
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { chars: vec![Value::unknown(); self.chars.len()], ..self.clone() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+
+        let (json, instance) = ValidityCircuit::configure(meta);
+        let base64 = Base64Config::configure(meta);
+
+        (json, base64, instance)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+
+        let (json, base64, instance) = config;
+
+        // The document is witnessed from the decoded chars, and then pinned to them by the decode region
+        let decoded: Value<Vec<u8>> = Value::<Vec<F>>::from_iter(self.chars.iter().cloned())
+            .map(|chars| base64_decode(&chars.iter().map(to_byte).collect::<Vec<u8>>()).unwrap_or_default());
+        let raw: Vec<Value<F>> = (0..self.len)
+            .map(|idx| decoded.as_ref().map(|bytes| F::from(bytes.get(idx).copied().unwrap_or(0) as u64)))
+            .collect();
+
+        let chip = JsonChip::construct(json);
+        chip.load(&mut layouter)?;
+        base64.load(&mut layouter)?;
+        let assigned = chip.assign(&mut layouter, &raw)?;
+        base64.assign(&mut layouter, &assigned, &self.chars)?;
+
+        let is_valid = assigned.is_valid.ok_or(Error::Synthesis)?;
+        layouter.constrain_instance(is_valid.cell(), instance, 0)
+    }
+}
+
+
+// Validity plus one top-level pair: the document contains `"key":value` at level 1, with the value followed by a ','
+// still at level 1 or the '}' closing the document, so the value is whole. Whitespace may sit around the ':' and
+// after the value. The key and value bytes are public inputs, in that order (see `instances`).
+// Where the pair sits is a witness: the key is looked up among the top-level keys' windows (`KeyRunsConfig`) at the
+// slot starting where it does, and the ':', the value and its terminator are probes of the byte table, each at the
+// previous one's next byte that is not whitespace. So the verifying key depends on the lengths of the document, key
+// and value only, and the document has at most `MAX_TOP_LEVEL_KEYS` keys of at most `MAX_KEY_LEN` bytes
+#[derive(Clone, Default)]
+pub struct KeyValueCircuit<F: FieldExt> {
+    pub raw: Vec<Value<F>>,
+    pub key: Vec<u8>,
+    pub value: Vec<u8>, // empty with `non_empty`
+    pub key_start: Option<usize>, // first byte of the key's contents, a witness. Always Some from `from_str`
+    pub non_empty: bool, // the value is a witness instead, only proven a string with at least one byte of content
+}
+
+// From `KeyValueCircuit::from_str`: there is no valid document to prove the pair in
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyValueError {
+    Parse(ParseError), // from `JsonCircuit::from_str`, or the pre-flight's rejection
+    MissingKey,        // no top-level key with these bytes
+    NotAnArray,        // from `ArrayCircuit::from_str`: the queried value is not an array
+    NoSuchElement,     // from `ElementFieldCircuit::from_str`: the array has no element at the index
+    NotAnObject,       // from `MergePatchCircuit::from_str`: a document is not an object
+    NestedPatch,       // from `MergePatchCircuit::from_str`: a patch value is an object, which merges one level deeper
+    ObjectInArray,     // from `StructuralEqualityCircuit::from_str`: arrays compare byte for byte, objects in them too
+}
+
+impl From<ParseError> for KeyValueError {
+    fn from(e: ParseError) -> Self {
+        KeyValueError::Parse(e)
+    }
+}
+
+impl Display for KeyValueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyValueError::Parse(e) => write!(f, "{} at {}", e.message(), e.index()),
+            KeyValueError::MissingKey => write!(f, "no such top-level key"),
+            KeyValueError::NotAnArray => write!(f, "not an array"),
+            KeyValueError::NoSuchElement => write!(f, "no element at that index"),
+            KeyValueError::NotAnObject => write!(f, "not an object"),
+            KeyValueError::NestedPatch => write!(f, "patch value is an object"),
+            KeyValueError::ObjectInArray => write!(f, "object inside an array"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct KeyValueConfig {
+    runs: KeyRunsConfig,
+    table: ByteTableConfig, // the runs'
+    key_level: Column<Advice>, // on a pair's end probe
+    q_pair_end: Selector,
+    instance: Column<Instance>,
+}
+
+impl KeyValueConfig {
+
+    fn configure<F: FieldExt>(meta: &mut ConstraintSystem<F>) -> Self {
+
+        let (runs, table) = KeyRunsConfig::configure_with_table(meta);
+        let key_level = meta.advice_column();
+        let q_pair_end = meta.selector();
+        let instance = runs.instance;
+        let constant = meta.fixed_column();
+
+        meta.enable_equality(key_level);
+        meta.enable_constant(constant);
+
+        // The first byte after the value that is not whitespace is a ',' still in the object holding its key, or the
+        // '}' closing that object. Its level is the level after it, so a value cut short inside a nested object or
+        // array is at a deeper level
+        meta.create_gate("Pair end", |meta| {
+            let t = meta.query_advice(table.probe[2], Rotation::cur());
+            let l = meta.query_advice(table.probe[4], Rotation::cur());
+            let d = meta.query_advice(key_level, Rotation::cur());
+            let q = meta.query_selector(q_pair_end);
+
+            let comma = t.clone() - Expression::Constant(F::from(0x2c));
+            let brace = t - Expression::Constant(F::from(0x7d));
+            vec![
+                q.clone() * comma.clone() * brace.clone(),
+                q.clone() * brace * (l.clone() - d.clone()),
+                q * comma * (l - d + Expression::Constant(F::one())),
+            ]
+        });
+
+        KeyValueConfig { runs, table, key_level, q_pair_end, instance }
+    }
+
+    // Assign the document, its top-level keys and the byte table
+    fn assign<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, raw: &[Value<F>]) -> Result<KeyRunsAssigned<F>, Error> {
+        self.runs.synthesize(layouter, raw)
+    }
+
+    // Find `"key":` at `level` from `key_start`, the witnessed index of the key's first content byte, with the key's
+    // `key_len` bytes read from the instance from `instance_row`. A top-level key, with no `scope`, is looked up whole
+    // among the windows (see `KeyRunsConfig`), at the slot whose start it is. A deeper key is probed byte by byte, its
+    // opening quote at the scope's level directly inside the object whose '{' is at the scope's position: that '{' is
+    // the quote's innermost opener. Returns the position of the value: the first byte after the ':' that is not
+    // whitespace, a probe's next
+    fn pin_key<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, doc: &KeyRunsAssigned<F>, key_start: Value<usize>, key_len: usize, instance_row: usize, scope: Option<(&AssignedCell<F, F>, usize)>) -> Result<AssignedCell<F, F>, Error> {
+
+        match scope {
+            None if key_len <= MAX_KEY_LEN => self.pin_top_level_key(layouter, doc, key_start, key_len, instance_row),
+            Some(scope) if scope.1 > 1 => self.pin_nested_key(layouter, doc, key_start, key_len, instance_row, scope),
+            _ => Err(Error::Synthesis),
+        }
+    }
+
+    fn pin_top_level_key<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, doc: &KeyRunsAssigned<F>, key_start: Value<usize>, key_len: usize, instance_row: usize) -> Result<AssignedCell<F, F>, Error> {
+
+        let starts = Value::<Vec<F>>::from_iter(doc.keys.iter().map(|[_, _, start, _]| start.value().copied()));
+        let start = key_start.map(|start| F::from(start as u64));
+        let slot = start.zip(starts).map(|(start, starts)| starts.iter().position(|&s| s == start).map_or(F::zero(), |j| F::from(j as u64 + 1)));
+
+        layouter.assign_region(
+            || "Key",
+            |mut region| {
+
+                let [slot_col, start_col, len_col] = self.table.key;
+                let slot = region.assign_advice(|| "slot", slot_col, 0, || slot)?;
+                region.assign_advice(|| "start", start_col, 0, || start)?;
+                let len = region.assign_advice_from_constant(|| "len", len_col, 0, F::from(key_len as u64))?;
+                let colon = self.assign_key_end(&mut region, doc, len.value().copied() + start)?;
+
+                // The key's bytes at their offsets in the slot's window
+                for i in 0..key_len {
+                    let offset = i + 1;
+                    slot.copy_advice(|| "slot", &mut region, slot_col, offset)?;
+                    region.assign_advice_from_constant(|| "offset", start_col, offset, F::from(i as u64))?;
+                    region.assign_advice_from_instance(|| "key", self.instance, instance_row + i, len_col, offset)?;
+                    self.table.q_key_byte.enable(&mut region, offset)?;
+                }
+
+                Ok(colon[8].clone())
+            }
+        )
+    }
+
+    // Find the `"key":` of the top-level key at `slot`, active (see `KeyRunsConfig`), whatever its bytes. Returns the
+    // position of its value as `pin_key` does
+    fn pin_slot<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, doc: &KeyRunsAssigned<F>, slot: usize) -> Result<AssignedCell<F, F>, Error> {
+
+        let [_, slot, start, len] = doc.keys.get(slot).ok_or(Error::Synthesis)?;
+        layouter.assign_region(
+            || "Key slot",
+            |mut region| {
+                let [slot_col, start_col, len_col] = self.table.key;
+                slot.copy_advice(|| "slot", &mut region, slot_col, 0)?;
+                let start = start.copy_advice(|| "start", &mut region, start_col, 0)?;
+                let len = len.copy_advice(|| "len", &mut region, len_col, 0)?;
+                let colon = self.assign_key_end(&mut region, doc, len.value().copied() + start.value().copied())?;
+                Ok(colon[8].clone())
+            }
+        )
+    }
+
+    // From row 0, the closing quote of a top-level key whose (slot, start, len) is on the row and whose start + len
+    // is `end`, then the ':' after any whitespace. Returns the ':' probe
+    fn assign_key_end<F: FieldExt>(&self, region: &mut Region<'_, F>, doc: &KeyRunsAssigned<F>, end: Value<F>) -> Result<[AssignedCell<F, F>; 11], Error> {
+
+        let class = |c: CharClass| F::from(c as u64);
+        self.table.q_key_slot.enable(region, 0)?;
+
+        let quote = self.table.assign_probe(region, 0, &doc.bytes, end + Value::known(F::one()), None)?;
+        region.constrain_constant(quote[2].cell(), F::from(0x22))?;
+        region.constrain_constant(quote[3].cell(), class(CharClass::Structural))?;
+
+        let colon = self.table.assign_probe(region, 1, &doc.bytes, quote[8].value().copied(), None)?;
+        self.table.q_skip.enable(region, 1)?;
+        region.constrain_constant(colon[2].cell(), F::from(0x3a))?;
+        region.constrain_constant(colon[3].cell(), class(CharClass::Normal))?;
+        Ok(colon)
+    }
+
+    fn pin_nested_key<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, doc: &KeyRunsAssigned<F>, key_start: Value<usize>, key_len: usize, instance_row: usize, scope: (&AssignedCell<F, F>, usize)) -> Result<AssignedCell<F, F>, Error> {
+
+        let (key, colon) = self.probe_key(layouter, doc, key_start, key_len, scope)?;
+        for (i, byte) in key.iter().enumerate() {
+            layouter.constrain_instance(byte.cell(), self.instance, instance_row + i)?;
+        }
+        Ok(colon[8].clone())
+    }
+
+    // Probe `"key":` as `pin_key` does a deeper key, at any level with the '{' at the scope's position, top-level keys
+    // too. Returns the key's bytes and the ':' probe
+    fn probe_key<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, doc: &KeyRunsAssigned<F>, key_start: Value<usize>, key_len: usize, (scope, level): (&AssignedCell<F, F>, usize)) -> Result<KeyProbe<F>, Error> {
+
+        let class = |c: CharClass| F::from(c as u64);
+        layouter.assign_region(
+            || "Nested key",
+            |mut region| {
+
+                let open = self.table.assign_probe(&mut region, 0, &doc.bytes, scope.value().copied(), None)?;
+                region.constrain_equal(open[1].cell(), scope.cell())?;
+                region.constrain_constant(open[2].cell(), F::from(0x7b))?;
+
+                // | "  | key ... | "  | :  , the ':' after any whitespace
+                let mut pos = key_start.map(|start| F::from(start as u64));
+                let mut probes = vec![];
+                for offset in 1..key_len + 3 {
+                    let probe = self.table.assign_probe(&mut region, offset, &doc.bytes, pos, None)?;
+                    if offset > 1 {
+                        self.table.q_step.enable(&mut region, offset)?;
+                    }
+                    pos = pos + Value::known(F::one());
+                    probes.push(probe);
+                }
+                let colon = self.table.assign_probe(&mut region, key_len + 3, &doc.bytes, probes[key_len + 1][8].value().copied(), None)?;
+                self.table.q_skip.enable(&mut region, key_len + 3)?;
+
+                let (opening, closing) = (&probes[0], &probes[key_len + 1]);
+                region.constrain_constant(opening[2].cell(), F::from(0x22))?;
+                region.constrain_constant(opening[3].cell(), class(CharClass::Structural))?;
+                region.constrain_constant(opening[4].cell(), F::from(level as u64))?;
+                region.constrain_equal(opening[9].cell(), scope.cell())?;
+                for probe in &probes[1..=key_len] {
+                    region.constrain_constant(probe[3].cell(), class(CharClass::StringContent))?;
+                }
+                region.constrain_constant(closing[2].cell(), F::from(0x22))?;
+                region.constrain_constant(closing[3].cell(), class(CharClass::Structural))?;
+                region.constrain_constant(colon[2].cell(), F::from(0x3a))?;
+                region.constrain_constant(colon[3].cell(), class(CharClass::Normal))?;
+
+                let key = probes[1..=key_len].iter().map(|probe| probe[2].clone()).collect::<Vec<_>>();
+                Ok((key, colon))
+            }
+        )
+    }
+
+    // Pin the `value_len` bytes of the value of a key at `level` from `start` (see `pin_key`), then whitespace and a
+    // ',' still in the key's object or the '}' closing it. The bytes are read from the instance from `instance_row`,
+    // or stay witnessed with None. Returns them
+    fn pin_value<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, doc: &KeyRunsAssigned<F>, start: &AssignedCell<F, F>, value_len: usize, level: usize, instance_row: Option<usize>) -> Result<Vec<AssignedCell<F, F>>, Error> {
+
+        if value_len == 0 {
+            return Err(Error::Synthesis);
+        }
+
+        let bytes = layouter.assign_region(
+            || "Key value pair",
+            |mut region| {
+
+                let mut bytes = vec![];
+                let mut pos = start.value().copied();
+                for offset in 0..value_len {
+
+                    let probe = self.table.assign_probe(&mut region, offset, &doc.bytes, pos, None)?;
+                    match offset {
+                        0 => region.constrain_equal(probe[1].cell(), start.cell())?,
+                        _ => self.table.q_step.enable(&mut region, offset)?,
+                    }
+
+                    pos = pos + Value::known(F::one());
+                    bytes.push(probe);
+                }
+
+                // The value ends outside any string
+                let next = bytes[value_len - 1][8].value().copied();
+                let end = self.table.assign_probe(&mut region, value_len, &doc.bytes, next, None)?;
+                self.table.q_skip.enable(&mut region, value_len)?;
+                region.constrain_constant(end[5].cell(), F::one())?;
+                region.assign_advice_from_constant(|| "key level", self.key_level, value_len, F::from(level as u64))?;
+                self.q_pair_end.enable(&mut region, value_len)?;
+
+                Ok(bytes.into_iter().map(|[_, _, raw, ..]| raw).collect::<Vec<_>>())
+            }
+        )?;
+
+        if let Some(row) = instance_row {
+            for (i, byte) in bytes.iter().enumerate() {
+                layouter.constrain_instance(byte.cell(), self.instance, row + i)?;
+            }
+        }
+        Ok(bytes)
+    }
+
+    // Pin an opening quote followed by string content at `start` (see `pin_key`), so the value is a string and not
+    // "". Where it ends is left to the grammar, so its length is not baked in
+    fn pin_non_empty<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, doc: &KeyRunsAssigned<F>, start: &AssignedCell<F, F>) -> Result<(), Error> {
+
+        let class = |c: CharClass| F::from(c as u64);
+        layouter.assign_region(
+            || "Non-empty value",
+            |mut region| {
+
+                let quote = self.table.assign_probe(&mut region, 0, &doc.bytes, start.value().copied(), None)?;
+                region.constrain_equal(quote[1].cell(), start.cell())?;
+                region.constrain_constant(quote[2].cell(), F::from(0x22))?;
+                region.constrain_constant(quote[3].cell(), class(CharClass::Structural))?;
+
+                let content = self.table.assign_probe(&mut region, 1, &doc.bytes, start.value().copied() + Value::known(F::one()), None)?;
+                self.table.q_step.enable(&mut region, 1)?;
+                region.constrain_constant(content[3].cell(), class(CharClass::StringContent))
+            }
+        )
+    }
+
+    // Probe a '{' at `start`, a value's position (see `pin_key`), so the value is an object. With None, a '{' at
+    // level 1 at a witnessed position: only the document's first byte opens a container at level 1, so the document
+    // is an object. Returns the '{' position
+    fn pin_object<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, doc: &KeyRunsAssigned<F>, start: Option<&AssignedCell<F, F>>) -> Result<AssignedCell<F, F>, Error> {
+
+        let rows = Value::<Vec<(F, F)>>::from_iter(doc.bytes.iter().map(|row| row[1].value().copied().zip(row[3].value().copied())));
+        let open = rows.map(|rows| rows.iter().position(|&row| row == (F::from(0x7b), F::one())).map_or(F::zero(), |idx| F::from(idx as u64 + 1)));
+
+        layouter.assign_region(
+            || "Object",
+            |mut region| {
+                let probe = self.table.assign_probe(&mut region, 0, &doc.bytes, start.map_or(open, |start| start.value().copied()), None)?;
+                match start {
+                    Some(start) => region.constrain_equal(probe[1].cell(), start.cell())?,
+                    None => region.constrain_constant(probe[4].cell(), F::one())?,
+                }
+                region.constrain_constant(probe[2].cell(), F::from(0x7b))?;
+                region.constrain_constant(probe[3].cell(), F::from(CharClass::Structural as u64))?;
+                Ok(probe[1].clone())
+            }
+        )
+    }
+}
+
+impl<F: FieldExt> KeyValueCircuit<F> {
+
+    // Fails on documents the pre-flight rejects, which have no satisfiable circuit (see `JsonCircuit`), past
+    // `MAX_TOP_LEVEL_KEYS` keys or `MAX_KEY_LEN` bytes in a key, and when the key is missing, rather than leaving them
+    // to fail in `synthesize`
+    pub fn from_str(json: &str, key: &str, value: &str) -> Result<Self, KeyValueError> {
+
+        let document = JsonCircuit::<F>::from_str(json)?;
+        preflight(json.as_bytes(), &JsonConfigParams::default())?;
+        top_level_windows(json.as_bytes())?;
+
+        let key_start = key_runs(json.as_bytes())
+            .into_iter()
+            .find(|&(level, start, len)| level == 1 && &json.as_bytes()[start..start + len] == key.as_bytes())
+            .map(|(_, start, _)| start)
+            .ok_or(KeyValueError::MissingKey)?;
+
+        Ok(Self { raw: document.raw, key: key.as_bytes().to_vec(), value: value.as_bytes().to_vec(), key_start: Some(key_start), non_empty: false })
+    }
+
+    // As `from_str`, for a required non-empty string field. The value stays a witness, so only the key is public. An
+    // empty or non-string value still gives a circuit, which then fails to prove
+    pub fn non_empty_from_str(json: &str, key: &str) -> Result<Self, KeyValueError> {
+        Ok(Self { non_empty: true, ..Self::from_str(json, key, "")? })
+    }
+
+    pub fn instances(&self) -> Vec<F> {
+        self.key.iter().chain(self.value.iter()).map(|&b| F::from(b as u64)).collect()
+    }
+}
+
+impl<F: FieldExt> Circuit<F> for KeyValueCircuit<F> {
+
+    type Config = KeyValueConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { raw: vec![Value::unknown(); self.raw.len()], ..self.clone() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        KeyValueConfig::configure(meta)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+
+        let key_start = self.key_start.ok_or(Error::Synthesis)?;
+
+        // Key and value bytes are the public inputs, only the key with `non_empty`
+        let doc = config.assign(&mut layouter, &self.raw)?;
+        let value_start = config.pin_key(&mut layouter, &doc, Value::known(key_start), self.key.len(), 0, None)?;
+        if self.non_empty {
+            return config.pin_non_empty(&mut layouter, &doc, &value_start);
+        }
+        config.pin_value(&mut layouter, &doc, &value_start, self.value.len(), 1, Some(self.key.len()))?;
+
+        Ok(())
+    }
+}
+
+// Validity plus the values at two key paths being byte-equal, e.g. `a.id == b.ref`. Each path is a key at level 1,
+// then a key directly inside the previous key's value, an object. The first key is found like `KeyValueCircuit`'s, and
+// each later one by probes at a witnessed position, its opening quote at its step's level with the '{' where the
+// previous value starts as its innermost opener. The last values are pinned with one length, the first path's, and
+// copy-constrained byte for byte, so both exist and have equal lengths. The keys of the first path, then of the second,
+// are the public inputs (see `instances`); the values and every position stay hidden
+#[derive(Clone, Default)]
+pub struct PathEqualityCircuit<F: FieldExt> {
+    pub raw: Vec<Value<F>>,
+    pub paths: [Vec<Vec<u8>>; 2],
+    pub key_starts: [Vec<usize>; 2], // per step, as `KeyValueCircuit::key_start`
+    pub value_len: usize, // the first path's value, witness only
+}
+
+impl<F: FieldExt> PathEqualityCircuit<F> {
+
+    // Fails as `KeyValueCircuit::from_str`, with `MissingKey` for an empty path or one with a missing step. Values of
+    // different lengths or bytes still give a circuit, which then fails to prove
+    pub fn from_str(json: &str, paths: [&[&str]; 2]) -> Result<Self, KeyValueError> {
+
+        let document = JsonCircuit::<F>::from_str(json)?;
+        preflight(json.as_bytes(), &JsonConfigParams::default())?;
+
+        let bytes = json.as_bytes();
+        let paths = paths.map(|path| path.iter().map(|key| key.as_bytes().to_vec()).collect::<Vec<_>>());
+        let mut key_starts = [vec![], vec![]];
+        for (starts, path) in key_starts.iter_mut().zip(&paths) {
+            let keys: Vec<&[u8]> = path.iter().map(Vec::as_slice).collect();
+            *starts = path_key_starts(bytes, &keys).filter(|starts| !starts.is_empty()).ok_or(KeyValueError::MissingKey)?;
+        }
+
+        let last = |i: usize| (key_starts[i][paths[i].len() - 1], paths[i][paths[i].len() - 1].len());
+        let (start, len) = last(0);
+        let value_len = value_after_key(bytes, start, len).ok_or(KeyValueError::MissingKey)?.len();
+
+        Ok(Self { raw: document.raw, paths, key_starts, value_len })
+    }
+
+    pub fn instances(&self) -> Vec<F> {
+        self.paths.iter().flatten().flatten().map(|&b| F::from(b as u64)).collect()
+    }
+}
+
+impl<F: FieldExt> Circuit<F> for PathEqualityCircuit<F> {
+
+    type Config = KeyValueConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { raw: vec![Value::unknown(); self.raw.len()], ..self.clone() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        KeyValueConfig::configure(meta)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+
+        let doc = config.assign(&mut layouter, &self.raw)?;
+
+        let mut values = vec![];
+        let mut instance_row = 0;
+        for (path, key_starts) in self.paths.iter().zip(&self.key_starts) {
+
+            if path.is_empty() || key_starts.len() != path.len() {
+                return Err(Error::Synthesis);
+            }
+
+            // Each step's value is the object holding the next step's key
+            let mut value_start: Option<AssignedCell<F, F>> = None;
+            for (step, key) in path.iter().enumerate() {
+                let start = config.pin_key(&mut layouter, &doc, Value::known(key_starts[step]), key.len(), instance_row, value_start.as_ref().map(|start| (start, step + 1)))?;
+                value_start = Some(start);
+                instance_row += key.len();
+            }
+
+            let value_start = value_start.ok_or(Error::Synthesis)?;
+            values.push(config.pin_value(&mut layouter, &doc, &value_start, self.value_len, path.len(), None)?);
+        }
+
+        layouter.assign_region(
+            || "Equal values",
+            |mut region| {
+                for (a, b) in values[0].iter().zip(&values[1]) {
+                    region.constrain_equal(a.cell(), b.cell())?;
+                }
+                Ok(())
+            }
+        )
+    }
+}
+
+// Validity plus one top-level key whose value is one of a public set, the value itself hidden. The key bytes, then
+// every entry padded with 0 to the longest, are the public inputs (see `instances`). The key is found as in
+// `KeyValueCircuit`, and the value's length is witnessed, so no position is baked in. A window of byte-table probes
+// from the value's first byte flags the value's bytes, a run of 1s that ends at whitespace or a ',' at level 1, or at
+// the closing '}'. Probes past the end of the document are off, and only after the value has ended. One bit per entry,
+// exactly one of them set, and each flagged byte (then 0 past the value) is the sum of bit * entry byte over the
+// entries. No byte of a document is 0, the byte class lookup rejects it, so the padding also rules out entries of
+// another length
+#[derive(Clone, Default)]
+pub struct ValueInSetCircuit<F: FieldExt> {
+    pub raw: Vec<Value<F>>,
+    pub key: Vec<u8>,
+    pub set: Vec<Vec<u8>>, // raw JSON, as `top_level_value_in_set`
+    pub key_start: Option<usize>, // as `KeyValueCircuit::key_start`
+    pub value_len: usize, // witness only
+    pub chosen: Option<usize>, // the entry equal to the value, whose bit is set. None sets no bit
+}
+
+#[derive(Clone, Debug)]
+pub struct ValueInSetConfig {
+    pair: KeyValueConfig,
+    bit: Column<Advice>,
+    entry: Column<Advice>,
+    acc: Column<Advice>,
+    q_first: Selector,
+    q_next: Selector,
+    window: [Column<Advice>; 2], // in_value, masked = in_value * byte, beside the probes
+    q_window: Selector,
+    q_window_next: Selector,
+}
+
+impl<F: FieldExt> ValueInSetCircuit<F> {
+
+    // A value outside the set still gives a circuit, which then fails to prove. Fails as `KeyValueCircuit::from_str`
+    pub fn from_str(json: &str, key: &str, set: &[&[u8]]) -> Result<Self, KeyValueError> {
+
+        let pair = KeyValueCircuit::<F>::from_str(json, key, "")?;
+        let value = top_level_value(json.as_bytes(), key.as_bytes()).ok_or(KeyValueError::MissingKey)?;
+        let chosen = set.iter().position(|&entry| entry == value);
+
+        Ok(Self {
+            raw: pair.raw, key: pair.key, set: set.iter().map(|entry| entry.to_vec()).collect(), key_start: pair.key_start,
+            value_len: value.len(), chosen,
+        })
+    }
+
+    pub fn instances(&self) -> Vec<F> {
+        let width = self.set.iter().map(Vec::len).max().unwrap_or(0);
+        let entries = self.set.iter().flat_map(|entry| (0..width).map(move |i| entry.get(i).copied().unwrap_or(0)));
+        self.key.iter().copied().chain(entries).map(|b| F::from(b as u64)).collect()
+    }
+}
+
+impl<F: FieldExt> Circuit<F> for ValueInSetCircuit<F> {
+
+    type Config = ValueInSetConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { raw: vec![Value::unknown(); self.raw.len()], value_len: 0, chosen: None, ..self.clone() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+
+        let pair = KeyValueConfig::configure(meta);
+        let bit = meta.advice_column();
+        let entry = meta.advice_column();
+        let acc = meta.advice_column();
+        let q_first = meta.selector();
+        let q_next = meta.selector();
+        let window = [(); 2].map(|_| meta.advice_column());
+        let q_window = meta.selector();
+        let q_window_next = meta.selector();
+
+        meta.enable_equality(bit);
+        meta.enable_equality(entry);
+        meta.enable_equality(acc);
+        window.map(|column| meta.enable_equality(column));
+
+        // acc sums bit * entry over one block of rows, one row per entry
+        meta.create_gate("Set entry sum (first)", |meta| {
+            let b = meta.query_advice(bit, Rotation::cur());
+            let e = meta.query_advice(entry, Rotation::cur());
+            let a = meta.query_advice(acc, Rotation::cur());
+            let q_first = meta.query_selector(q_first);
+            vec![
+                q_first.clone() * (a - b.clone() * e),
+                q_first * b.clone() * (Expression::Constant(F::one()) - b),
+            ]
+        });
+
+        meta.create_gate("Set entry sum", |meta| {
+            let b = meta.query_advice(bit, Rotation::cur());
+            let e = meta.query_advice(entry, Rotation::cur());
+            let a = meta.query_advice(acc, Rotation::cur());
+            let a_prev = meta.query_advice(acc, Rotation::prev());
+            let q_next = meta.query_selector(q_next);
+            vec![
+                q_next.clone() * (a - a_prev - b.clone() * e),
+                q_next * b.clone() * (Expression::Constant(F::one()) - b),
+            ]
+        });
+
+        let [in_value, masked] = window;
+        let probe = pair.table.probe;
+
+        // A flagged byte is a byte of the document, the probe is on
+        meta.create_gate("Value window", |meta| {
+            let on = meta.query_advice(probe[0], Rotation::cur());
+            let r = meta.query_advice(probe[2], Rotation::cur());
+            let v = meta.query_advice(in_value, Rotation::cur());
+            let m = meta.query_advice(masked, Rotation::cur());
+            let q = meta.query_selector(q_window);
+
+            let one = Expression::Constant(F::one());
+            vec![
+                q.clone() * v.clone() * (one.clone() - v.clone()),
+                q.clone() * v.clone() * (one - on),
+                q * (m - v * r),
+            ]
+        });
+
+        // in_value only steps down, and where it does the value has ended: at whitespace or a ',' at level 1, or at
+        // the '}' closing the document, outside any string
+        meta.create_gate("Value end", |meta| {
+            let on = meta.query_advice(probe[0], Rotation::cur());
+            let r = meta.query_advice(probe[2], Rotation::cur());
+            let l = meta.query_advice(probe[4], Rotation::cur());
+            let ns = meta.query_advice(probe[5], Rotation::cur());
+            let w = meta.query_advice(probe[7], Rotation::cur());
+            let v = meta.query_advice(in_value, Rotation::cur());
+            let v_prev = meta.query_advice(in_value, Rotation::prev());
+            let q = meta.query_selector(q_window_next);
+
+            let one = Expression::Constant(F::one());
+            let comma = r.clone() - Expression::Constant(F::from(0x2c));
+            let brace = r - Expression::Constant(F::from(0x7d));
+            let end = q.clone() * (v_prev.clone() - v.clone());
+            let not_ws = one.clone() - w;
+
+            vec![
+                q * v * (one.clone() - v_prev),
+                end.clone() * (one.clone() - on),
+                end.clone() * not_ws.clone() * comma.clone() * brace.clone(),
+                end.clone() * (one.clone() - ns),
+                end.clone() * brace * (l.clone() - one),
+                end * not_ws * comma * l,
+            ]
+        });
+
+        ValueInSetConfig { pair, bit, entry, acc, q_first, q_next, window, q_window, q_window_next }
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+
+        let width = self.set.iter().map(Vec::len).max().unwrap_or(0);
+        let key_start = self.key_start.ok_or(Error::Synthesis)?;
+        if self.set.is_empty() {
+            return Err(Error::Synthesis);
+        }
+
+        // The key bytes are public, the entries are copied from the instances below
+        let doc = config.pair.assign(&mut layouter, &self.raw)?;
+        let value_start = config.pair.pin_key(&mut layouter, &doc, Value::known(key_start), self.key.len(), 0, None)?;
+
+        // Byte i of the value for i < width, then the byte that ends it
+        let masked = layouter.assign_region(
+            || "Value window",
+            |mut region| {
+
+                let [in_value, masked] = config.window;
+                let rows = width + 1;
+
+                let mut masked_cells = vec![];
+                let mut pos = value_start.value().copied();
+                for i in 0..rows {
+
+                    let on = pos.map(|pos| F::from((pos.get_lower_128() as usize <= self.raw.len()) as u64));
+                    let probe = config.pair.table.assign_probe(&mut region, i, &doc.bytes, pos, Some(on))?;
+                    match i {
+                        0 => region.constrain_equal(probe[1].cell(), value_start.cell())?,
+                        _ => config.pair.table.q_step.enable(&mut region, i)?,
+                    }
+                    pos = pos + Value::known(F::one());
+
+                    // The value has at least one byte, and ends within the window
+                    let v = Value::known(F::from((i < self.value_len) as u64));
+                    let v = match i {
+                        0 => region.assign_advice_from_constant(|| "in_value", in_value, i, F::one())?,
+                        _ if i == rows - 1 => region.assign_advice_from_constant(|| "in_value", in_value, i, F::zero())?,
+                        _ => region.assign_advice(|| "in_value", in_value, i, || v)?,
+                    };
+                    masked_cells.push(region.assign_advice(|| "masked", masked, i, || v.value().copied() * probe[2].value().copied())?);
+
+                    config.q_window.enable(&mut region, i)?;
+                    if i > 0 {
+                        config.q_window_next.enable(&mut region, i)?;
+                    }
+                }
+
+                Ok(masked_cells)
+            }
+        )?;
+
+        layouter.assign_region(
+            || "Set membership",
+            |mut region| {
+
+                // Block i < width sums byte i of the chosen entry, and the last block sums the bits
+                let mut offset = 0;
+                let mut bits = vec![];
+                for i in 0..=width {
+
+                    let mut sum = Value::known(F::zero());
+                    let mut acc = None;
+                    for j in 0..self.set.len() {
+
+                        let b = Value::known(if self.chosen == Some(j) { F::one() } else { F::zero() });
+                        let bit = region.assign_advice(|| "bit", config.bit, offset, || b)?;
+                        let entry = if i < width {
+                            region.assign_advice_from_instance(|| "entry", config.pair.instance, self.key.len() + j * width + i, config.entry, offset)?
+                        } else {
+                            region.assign_advice_from_constant(|| "entry", config.entry, offset, F::one())?
+                        };
+                        sum = sum + b * entry.value().copied();
+                        acc = Some(region.assign_advice(|| "acc", config.acc, offset, || sum)?);
+
+                        match j {
+                            0 => config.q_first.enable(&mut region, offset)?,
+                            _ => config.q_next.enable(&mut region, offset)?,
+                        }
+
+                        // The same bits in every block
+                        match i {
+                            0 => bits.push(bit),
+                            _ => region.constrain_equal(bit.cell(), bits[j].cell())?,
+                        }
+                        offset += 1;
+                    }
+
+                    let acc = acc.ok_or(Error::Synthesis)?;
+                    match masked.get(i) {
+                        Some(masked) if i < width => region.constrain_equal(acc.cell(), masked.cell())?,
+                        _ => region.constrain_constant(acc.cell(), F::one())?,
+                    }
+                }
+
+                Ok(())
+            }
+        )
+    }
+}
+
+// Validity plus a substring of one top-level key's string value, e.g. "log" contains "ERROR". The key bytes, then the
+// substring's, are the public inputs (see `instances`). The key is found as in `KeyValueCircuit`, and the value starts
+// with an opening quote. The substring is probed at a witnessed position, every byte string content whose last opening
+// quote is the value's, so it can't reach past the value's closing quote. The substring is matched on raw bytes, and
+// neither position is baked in
+#[derive(Clone, Default)]
+pub struct SubstringCircuit<F: FieldExt> {
+    pub raw: Vec<Value<F>>,
+    pub key: Vec<u8>,
+    pub needle: Vec<u8>,
+    pub key_start: Option<usize>, // as `KeyValueCircuit::key_start`
+    pub offset: usize, // where the substring starts in the value's contents, as `top_level_value_substring`. A witness
+}
+
+impl<F: FieldExt> SubstringCircuit<F> {
+
+    // Fails as `KeyValueCircuit::from_str`. A value without the substring still gives a circuit, at offset 0, which
+    // then fails to prove
+    pub fn from_str(json: &str, key: &str, needle: &str) -> Result<Self, KeyValueError> {
+
+        let pair = KeyValueCircuit::<F>::from_str(json, key, "")?;
+        let offset = top_level_value_substring(json.as_bytes(), key.as_bytes(), needle.as_bytes()).unwrap_or(0);
+
+        Ok(Self { raw: pair.raw, key: pair.key, needle: needle.as_bytes().to_vec(), key_start: pair.key_start, offset })
+    }
+
+    pub fn instances(&self) -> Vec<F> {
+        self.key.iter().chain(self.needle.iter()).map(|&b| F::from(b as u64)).collect()
+    }
+}
+
+impl<F: FieldExt> Circuit<F> for SubstringCircuit<F> {
+
+    type Config = KeyValueConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { raw: vec![Value::unknown(); self.raw.len()], ..self.clone() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        KeyValueConfig::configure(meta)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+
+        // Key and substring bytes are the public inputs
+        let key_start = self.key_start.ok_or(Error::Synthesis)?;
+        let doc = config.assign(&mut layouter, &self.raw)?;
+        let value_start = config.pin_key(&mut layouter, &doc, Value::known(key_start), self.key.len(), 0, None)?;
+
+        let class = |c: CharClass| F::from(c as u64);
+        let needle = layouter.assign_region(
+            || "Substring",
+            |mut region| {
+
+                let quote = config.table.assign_probe(&mut region, 0, &doc.bytes, value_start.value().copied(), None)?;
+                region.constrain_equal(quote[1].cell(), value_start.cell())?;
+                region.constrain_constant(quote[2].cell(), F::from(0x22))?;
+                region.constrain_constant(quote[3].cell(), class(CharClass::Structural))?;
+
+                // | "  | contents ... | substring ... |
+                let mut pos = value_start.value().copied() + Value::known(F::from(self.offset as u64 + 1));
+                let mut needle = vec![];
+                for offset in 1..=self.needle.len() {
+                    let probe = config.table.assign_probe(&mut region, offset, &doc.bytes, pos, None)?;
+                    if offset > 1 {
+                        config.table.q_step.enable(&mut region, offset)?;
+                    }
+                    region.constrain_constant(probe[3].cell(), class(CharClass::StringContent))?;
+                    region.constrain_equal(probe[10].cell(), value_start.cell())?;
+                    pos = pos + Value::known(F::one());
+                    needle.push(probe[2].clone());
+                }
+
+                Ok(needle)
+            }
+        )?;
+
+        for (i, byte) in needle.iter().enumerate() {
+            layouter.constrain_instance(byte.cell(), config.instance, self.key.len() + i)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Validity plus the number of elements of one array and the type they all share, public in that order, then the key
+// bytes if any (see `instances`). The array is the document itself, its '[' probed at a witnessed position at level 1,
+// or a top-level key's value, the key found like `KeyValueCircuit`'s and a '[' probed where its value starts. The
+// elements are the values starting right inside it, i.e. the bytes with a value_type whose previous byte has the '['
+// as its innermost opener, and a counter column over the whole document adds them up. Its ']' is probed at a witnessed
+// position too, right after a byte with the '[' as its innermost opener, so it closes the array. No position is baked
+// in
+#[derive(Clone, Default)]
+pub struct ArrayCircuit<F: FieldExt> {
+    pub raw: Vec<Value<F>>,
+    pub key: Option<Vec<u8>>,
+    pub key_start: Option<usize>, // as `KeyValueCircuit::key_start`, with a key
+    pub open: usize,  // the array's '[', a witness
+    pub close: usize, // its ']', a witness
+    pub len: usize,   // as `array_len`
+    pub element_type: Option<ValueType>, // every element's type, or None for any
+}
+
+#[derive(Clone, Debug)]
+pub struct ArrayConfig {
+    pair: KeyValueConfig,
+    // One row per byte: pos, value_type, the previous byte's innermost opener, the array's '[', claimed type,
+    // value_type inverse, inverse of the previous opener minus the '[', element flag, element count
+    scan: [Column<Advice>; 9],
+    q_scan: Selector,
+    q_scan_next: Selector,
+    pick: [Column<Advice>; 2], // an element's position and index, looked up in the scan
+    q_pick: Selector,
+    instance: Column<Instance>,
+}
+
+impl<F: FieldExt> ArrayCircuit<F> {
+
+    // The array at `key`, or the document itself with None. Fails as `KeyValueCircuit::from_str`, and on a value that
+    // is not an array. Elements of another type than `element_type` still give a circuit, which then fails to prove
+    pub fn from_str(json: &str, key: Option<&str>, element_type: Option<ValueType>) -> Result<Self, KeyValueError> {
+
+        let document = JsonCircuit::<F>::from_str(json)?;
+        preflight(json.as_bytes(), &JsonConfigParams::default())?;
+        top_level_windows(json.as_bytes())?;
+
+        let bytes = json.as_bytes();
+        let (value, key_start) = match key {
+            Some(key) => {
+                let pair = KeyValueCircuit::<F>::from_str(json, key, "")?;
+                (top_level_value(bytes, key.as_bytes()).ok_or(KeyValueError::MissingKey)?, pair.key_start)
+            }
+            None => (json.trim_start().as_bytes(), None),
+        };
+        let len = array_len(value).ok_or(KeyValueError::NotAnArray)?;
+
+        // Where the level first drops back to the one outside the array
+        let open = value.as_ptr() as usize - bytes.as_ptr() as usize;
+        let trace = compute_trace(bytes);
+        let close = (open + 1..bytes.len()).find(|&idx| trace.level[idx] < trace.level[open]).ok_or(KeyValueError::NotAnArray)?;
+
+        Ok(Self { raw: document.raw, key: key.map(|key| key.as_bytes().to_vec()), key_start, open, close, len, element_type })
+    }
+
+    pub fn instances(&self) -> Vec<F> {
+        let key = self.key.iter().flatten().map(|&b| F::from(b as u64));
+        [F::from(self.len as u64), F::from(self.element_type.map_or(0, |t| t as u64))].into_iter().chain(key).collect()
+    }
+}
+
+impl ArrayConfig {
+
+    // The counting gates, on a `KeyValueConfig` configured by the caller
+    fn configure_on<F: FieldExt>(meta: &mut ConstraintSystem<F>, pair: KeyValueConfig) -> Self {
+
+        let instance = pair.instance;
+        let scan = [(); 9].map(|_| meta.advice_column());
+        let pick = [(); 2].map(|_| meta.advice_column());
+        let [q_scan, q_pick] = [(); 2].map(|_| meta.complex_selector());
+        let q_scan_next = meta.selector();
+
+        let [pos, vt, prev_inner, open, claimed, vt_inv, prev_inv, element, count] = scan;
+        scan.map(|column| meta.enable_equality(column));
+        pick.map(|column| meta.enable_equality(column));
+
+        // A value starting right inside the array is an element, whose type is then the claimed one unless that is 0
+        meta.create_gate("Array scan", |meta| {
+            let v = meta.query_advice(vt, Rotation::cur());
+            let i = meta.query_advice(prev_inner, Rotation::cur());
+            let o = meta.query_advice(open, Rotation::cur());
+            let t = meta.query_advice(claimed, Rotation::cur());
+            let v_inv = meta.query_advice(vt_inv, Rotation::cur());
+            let d_inv = meta.query_advice(prev_inv, Rotation::cur());
+            let e = meta.query_advice(element, Rotation::cur());
+            let q = meta.query_selector(q_scan);
+
+            let one = Expression::Constant(F::one());
+            let starts_value = v.clone() * v_inv;
+            let d = i - o;
+            let inside = one.clone() - d.clone() * d_inv;
+
+            vec![
+                q.clone() * v.clone() * (one - starts_value.clone()),
+                q.clone() * d * inside.clone(),
+                q.clone() * (e.clone() - starts_value * inside),
+                q * e * (v - t.clone()) * t,
+            ]
+        });
+
+        meta.create_gate("Array scan (next)", |meta| {
+            let o = meta.query_advice(open, Rotation::cur());
+            let o_prev = meta.query_advice(open, Rotation::prev());
+            let t = meta.query_advice(claimed, Rotation::cur());
+            let t_prev = meta.query_advice(claimed, Rotation::prev());
+            let e = meta.query_advice(element, Rotation::cur());
+            let n = meta.query_advice(count, Rotation::cur());
+            let n_prev = meta.query_advice(count, Rotation::prev());
+            let q = meta.query_selector(q_scan_next);
+            vec![q.clone() * (o - o_prev), q.clone() * (t - t_prev), q * (n - n_prev - e)]
+        });
+
+        // An element at the position, with the index counted before it. The leading 1 keeps the pick off the rows
+        // past the scan, which are all 0
+        meta.lookup_any("Array element", |meta| {
+            let q = meta.query_selector(q_pick);
+            let p = meta.query_advice(pick[0], Rotation::cur());
+            let idx = meta.query_advice(pick[1], Rotation::cur());
+            let q_scan = meta.query_selector(q_scan);
+            let pos = meta.query_advice(pos, Rotation::cur());
+            let e = meta.query_advice(element, Rotation::cur());
+            let n = meta.query_advice(count, Rotation::cur());
+            vec![
+                (q.clone(), q_scan.clone()),
+                (q.clone() * p, q_scan.clone() * pos),
+                (q.clone(), q_scan.clone() * e.clone()),
+                (q * idx, q_scan * (n - e)),
+            ]
+        });
+
+        ArrayConfig { pair, scan, q_scan, q_scan_next, pick, q_pick, instance }
+    }
+
+    // Probe the '[' at `open`: at `value_start`, where a key's value starts (see `KeyValueConfig::pin_key`), or at
+    // level 1, the document's own, with None. Returns its position
+    fn pin_open<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, doc: &KeyRunsAssigned<F>, open: Value<F>, value_start: Option<&AssignedCell<F, F>>) -> Result<AssignedCell<F, F>, Error> {
+
+        let table = &self.pair.table;
+        layouter.assign_region(
+            || "Array open",
+            |mut region| {
+                let probe = table.assign_probe(&mut region, 0, &doc.bytes, open, None)?;
+                match value_start {
+                    Some(start) => region.constrain_equal(probe[1].cell(), start.cell())?,
+                    None => region.constrain_constant(probe[4].cell(), F::one())?,
+                }
+                region.constrain_constant(probe[2].cell(), F::from(0x5b))?;
+                region.constrain_constant(probe[3].cell(), F::from(CharClass::Structural as u64))?;
+                Ok(probe[1].clone())
+            }
+        )
+    }
+
+    // Probe the ']' at `close` and the byte before it, which has the '[' at `open` as its innermost opener: the ']'
+    // closes that array
+    fn pin_close<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, doc: &KeyRunsAssigned<F>, open: &AssignedCell<F, F>, close: Value<F>) -> Result<(), Error> {
+
+        let table = &self.pair.table;
+        layouter.assign_region(
+            || "Array close",
+            |mut region| {
+                let last = table.assign_probe(&mut region, 0, &doc.bytes, close - Value::known(F::one()), None)?;
+                region.constrain_equal(last[9].cell(), open.cell())?;
+                let close = table.assign_probe(&mut region, 1, &doc.bytes, close, None)?;
+                table.q_step.enable(&mut region, 1)?;
+                region.constrain_constant(close[2].cell(), F::from(0x5d))?;
+                region.constrain_constant(close[3].cell(), F::from(CharClass::Structural as u64))
+            }
+        )
+    }
+
+    // Count the elements of the array whose '[' is at `open` over every byte of the document. The claimed type is read
+    // from the instance at `claimed_row`, or fixed to 0 (any type) with None. Returns the count after the last byte
+    fn assign_scan<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, doc: &KeyRunsAssigned<F>, open: &AssignedCell<F, F>, claimed_row: Option<usize>) -> Result<AssignedCell<F, F>, Error> {
+
+        layouter.assign_region(
+            || "Array scan",
+            |mut region| {
+
+                let [pos, vt, prev_inner, o_col, claimed, vt_inv, prev_inv, element, count] = self.scan;
+                let invert = |x: Value<F>| x.map(|x| x.invert().unwrap_or(F::zero()));
+
+                let o = open.copy_advice(|| "open", &mut region, o_col, 0)?.value().copied();
+                let t = match claimed_row {
+                    Some(row) => region.assign_advice_from_instance(|| "claimed", self.instance, row, claimed, 0)?,
+                    None => region.assign_advice_from_constant(|| "claimed", claimed, 0, F::zero())?,
+                }.value().copied();
+
+                let mut n = Value::known(F::zero());
+                let mut last = None;
+                for (offset, row) in doc.bytes.iter().enumerate() {
+
+                    row[0].copy_advice(|| "pos", &mut region, pos, offset)?;
+                    let v = row[5].copy_advice(|| "value_type", &mut region, vt, offset)?.value().copied();
+                    let i = match offset {
+                        0 => region.assign_advice_from_constant(|| "prev_inner", prev_inner, offset, F::zero())?,
+                        _ => doc.bytes[offset - 1][8].copy_advice(|| "prev_inner", &mut region, prev_inner, offset)?,
+                    }.value().copied();
+                    if offset > 0 {
+                        region.assign_advice(|| "open", o_col, offset, || o)?;
+                        region.assign_advice(|| "claimed", claimed, offset, || t)?;
+                        self.q_scan_next.enable(&mut region, offset)?;
+                    }
+                    region.assign_advice(|| "vt_inv", vt_inv, offset, || invert(v))?;
+                    region.assign_advice(|| "prev_inv", prev_inv, offset, || invert(i - o))?;
+
+                    let e = v.zip(i - o).map(|(v, d)| F::from((v != F::zero() && d == F::zero()) as u64));
+                    region.assign_advice(|| "element", element, offset, || e)?;
+                    n = n + e;
+                    self.q_scan.enable(&mut region, offset)?;
+
+                    // The first byte has no previous opener, so it is no element
+                    last = Some(match offset {
+                        0 => region.assign_advice_from_constant(|| "count", count, offset, F::zero())?,
+                        _ => region.assign_advice(|| "count", count, offset, || n)?,
+                    });
+                }
+
+                last.ok_or(Error::Synthesis)
+            }
+        )
+    }
+
+    // The element at `element`, a witness, with the index read from the instance at `index_row`. Returns its
+    // position
+    fn pick_element<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, element: Value<F>, index_row: usize) -> Result<AssignedCell<F, F>, Error> {
+        layouter.assign_region(
+            || "Array element",
+            |mut region| {
+                self.q_pick.enable(&mut region, 0)?;
+                region.assign_advice_from_instance(|| "index", self.instance, index_row, self.pick[1], 0)?;
+                region.assign_advice(|| "element", self.pick[0], 0, || element)
+            }
+        )
+    }
+}
+
+impl<F: FieldExt> Circuit<F> for ArrayCircuit<F> {
+
+    type Config = ArrayConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { raw: vec![Value::unknown(); self.raw.len()], ..self.clone() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let pair = KeyValueConfig::configure(meta);
+        ArrayConfig::configure_on(meta, pair)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+
+        // The count, the claimed type, then the key bytes are the public inputs
+        let doc = config.pair.assign(&mut layouter, &self.raw)?;
+        let value_start = match &self.key {
+            Some(key) => {
+                let key_start = self.key_start.ok_or(Error::Synthesis)?;
+                Some(config.pair.pin_key(&mut layouter, &doc, Value::known(key_start), key.len(), 2, None)?)
+            }
+            None => None,
+        };
+        let open = config.pin_open(&mut layouter, &doc, Value::known(F::from(self.open as u64 + 1)), value_start.as_ref())?;
+        config.pin_close(&mut layouter, &doc, &open, Value::known(F::from(self.close as u64 + 1)))?;
+
+        let count = config.assign_scan(&mut layouter, &doc, &open, Some(1))?;
+        layouter.constrain_instance(count.cell(), config.instance, 0)
+    }
+}
+
+// Validity plus one field of one element of an array of objects, e.g. `[{"id":1},{"id":2}]` has `"id":2` in the element
+// at index 1. The index, the key bytes and the value bytes are the public inputs, in that order (see `instances`). The
+// array is the document itself, its '[' probed as `ArrayCircuit`'s. The element's '{' is at a witnessed position,
+// looked up among the rows of the array's counter column (`ArrayConfig`) that start an element, with as many elements
+// before it as the index. The key is probed directly inside that object as `PathEqualityCircuit` probes a step, and
+// pinned with its value like `KeyValueCircuit`'s pair at level 2. No position is baked in
+#[derive(Clone, Default)]
+pub struct ElementFieldCircuit<F: FieldExt> {
+    pub raw: Vec<Value<F>>,
+    pub index: usize,
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    pub open: usize,    // the array's '[', a witness
+    pub element: usize, // the selected element's '{', a witness
+    pub key_start: Option<usize>, // as `KeyValueCircuit::key_start`, inside the element
+}
+
+impl<F: FieldExt> ElementFieldCircuit<F> {
+
+    // Fails as `KeyValueCircuit::from_str`, on a document that is not an array, past the last element, and with
+    // `MissingKey` when the element is not an object with the key. Another value still gives a circuit, which then
+    // fails to prove
+    pub fn from_str(json: &str, index: usize, key: &str, value: &str) -> Result<Self, KeyValueError> {
+
+        let document = JsonCircuit::<F>::from_str(json)?;
+        preflight(json.as_bytes(), &JsonConfigParams::default())?;
+
+        let bytes = json.as_bytes();
+        let open = bytes.iter().position(|b| !b.is_ascii_whitespace()).filter(|&idx| bytes[idx] == b'[').ok_or(KeyValueError::NotAnArray)?;
+
+        // Elements are the values starting right inside the array, as `ArrayConfig` counts them
+        let trace = compute_trace(bytes);
+        let element = (open + 1..bytes.len())
+            .filter(|&idx| trace.value_type[idx] != ValueType::None && trace.level[idx - 1] == trace.level[open])
+            .nth(index)
+            .ok_or(KeyValueError::NoSuchElement)?;
+        let element_end = (element + 1..bytes.len()).find(|&idx| trace.level[idx] <= trace.level[open]).unwrap_or(bytes.len());
+
+        let key_start = key_runs(bytes)
+            .into_iter()
+            .find(|&(level, start, len)| level == 2 && (element..element_end).contains(&start) && &bytes[start..start + len] == key.as_bytes())
+            .map(|(_, start, _)| start)
+            .ok_or(KeyValueError::MissingKey)?;
+
+        Ok(Self {
+            raw: document.raw, index, key: key.as_bytes().to_vec(), value: value.as_bytes().to_vec(), open, element,
+            key_start: Some(key_start),
+        })
+    }
+
+    pub fn instances(&self) -> Vec<F> {
+        let bytes = self.key.iter().chain(self.value.iter()).map(|&b| F::from(b as u64));
+        std::iter::once(F::from(self.index as u64)).chain(bytes).collect()
+    }
+}
+
+impl<F: FieldExt> Circuit<F> for ElementFieldCircuit<F> {
+
+    type Config = ArrayConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { raw: vec![Value::unknown(); self.raw.len()], ..self.clone() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let pair = KeyValueConfig::configure(meta);
+        ArrayConfig::configure_on(meta, pair)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+
+        let key_start = self.key_start.ok_or(Error::Synthesis)?;
+        let doc = config.pair.assign(&mut layouter, &self.raw)?;
+
+        // Elements of any type count, the selected one with the index before it
+        let open = config.pin_open(&mut layouter, &doc, Value::known(F::from(self.open as u64 + 1)), None)?;
+        config.assign_scan(&mut layouter, &doc, &open, None)?;
+        let element = config.pick_element(&mut layouter, Value::known(F::from(self.element as u64 + 1)), 0)?;
+
+        // Key and value bytes follow the index
+        let value_start = config.pair.pin_key(&mut layouter, &doc, Value::known(key_start), self.key.len(), 1, Some((&element, 2)))?;
+        config.pair.pin_value(&mut layouter, &doc, &value_start, self.value.len(), 2, Some(1 + self.key.len()))?;
+
+        Ok(())
+    }
+}
+
+// Validity of three objects, a base, a patch and a result, all witnesses, plus the result being the patch applied to
+// the base as an RFC 7386 merge patch one level deep: a top-level key whose patch value is null is deleted, any other
+// patch value replaces the base's, and the base's other keys are kept. `JsonNode::merge_patch` is the reference, on
+// patches whose values are not objects (those merge deeper). Each document's top-level keys are found slot by slot as
+// in `KeyValueCircuit`, with every slot past its key count inactive. Each key of the result copies its packed window
+// (see `KeyRunsConfig`), length and value bytes from its source key in the base or the patch. A kept base key is
+// proven absent from the patch, and a copied patch value is no null nor object. A base key the patch replaces copies
+// a patch key's window, and a deleted patch key's value is `null` and its key absent from the result. Absence is a
+// product of the key's differences with the document's active windows, with an inverse. Where each key comes from,
+// the key counts and the value lengths are baked in; the positions stay witnesses, and nothing is public
+#[derive(Clone, Default)]
+pub struct MergePatchCircuit<F: FieldExt> {
+    pub raw: [Vec<Value<F>>; 3], // the base, the patch and the result
+    pub sources: Vec<PatchSource>, // per top-level key of the result
+    pub value_lens: Vec<usize>,    // of the result's top-level values
+    pub replaced: Vec<Option<usize>>, // per top-level key of the base, the patch's key with its bytes
+    pub patch_keys: usize,
+}
+
+// The key a top-level key of a merge patch's result copies, by its slot
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PatchSource {
+    Base(usize),
+    Patch(usize),
+}
+
+#[derive(Clone, Debug)]
+pub struct MergePatchConfig {
+    docs: [KeyValueConfig; 3],
+    // A key, then per slot of a document its active flag, packed window and the product so far of key - window over
+    // the active slots. The product's inverse after the last slot
+    absent: [Column<Advice>; 4],
+    q_absent: Selector,
+    q_absent_end: Selector,
+    q_replacing: Selector, // a value's first byte and its inverse distance to 'n' and '{', on `absent`
+}
+
+impl MergePatchConfig {
+
+    // Prove `key` is none of the active windows of `doc`
+    fn assign_absent<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, key: &AssignedCell<F, F>, doc: &KeyRunsAssigned<F>) -> Result<(), Error> {
+
+        let [key_col, active_col, packed_col, product_col] = self.absent;
+        layouter.assign_region(
+            || "Absent key",
+            |mut region| {
+
+                let mut product = region.assign_advice_from_constant(|| "product", product_col, 0, F::one())?.value().copied();
+                for (offset, ([active, ..], packed)) in doc.keys.iter().zip(&doc.packed).enumerate() {
+                    let k = key.copy_advice(|| "key", &mut region, key_col, offset)?;
+                    let a = active.copy_advice(|| "active", &mut region, active_col, offset)?;
+                    let p = packed.copy_advice(|| "packed", &mut region, packed_col, offset)?;
+                    self.q_absent.enable(&mut region, offset)?;
+
+                    let factor = a.value().zip(p.value()).zip(k.value()).map(|((&a, &p), &k)| a * (k - p) + F::one() - a);
+                    product = product * factor;
+                    region.assign_advice(|| "product", product_col, offset + 1, || product)?;
+                }
+
+                let last = doc.keys.len();
+                region.assign_advice(|| "inverse", packed_col, last, || product.map(|p| p.invert().unwrap_or(F::zero())))?;
+                self.q_absent_end.enable(&mut region, last)
+            }
+        )
+    }
+
+    // Prove the value whose first byte is `first` is no null, which deletes, nor an object, which merges deeper
+    fn assign_replacing<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, first: &AssignedCell<F, F>) -> Result<(), Error> {
+
+        let [byte_col, _, inverse_col, _] = self.absent;
+        layouter.assign_region(
+            || "Replacing value",
+            |mut region| {
+                let byte = first.copy_advice(|| "first byte", &mut region, byte_col, 0)?;
+                let distance = byte.value().map(|&b| (b - F::from(0x6e)) * (b - F::from(0x7b)));
+                region.assign_advice(|| "inverse", inverse_col, 0, || distance.map(|d| d.invert().unwrap_or(F::zero())))?;
+                self.q_replacing.enable(&mut region, 0)
+            }
+        )
+    }
+}
+
+impl<F: FieldExt> MergePatchCircuit<F> {
+
+    // Fails as `KeyValueCircuit::from_str` on any of the three documents, on one that is not an object, on a patch with
+    // an object value, and with `MissingKey` when a result key is in neither the base nor the patch or a base key the
+    // patch leaves alone is not in the result. Other wrong results, such as another value or a key the patch deletes,
+    // still give a circuit, which then fails to prove
+    pub fn from_str(base: &str, patch: &str, result: &str) -> Result<Self, KeyValueError> {
+
+        let mut raw = [vec![], vec![], vec![]];
+        let mut pairs: [Vec<(&[u8], &[u8])>; 3] = [vec![], vec![], vec![]];
+        for ((json, raw), pairs) in [base, patch, result].into_iter().zip(&mut raw).zip(&mut pairs) {
+
+            *raw = JsonCircuit::<F>::from_str(json)?.raw;
+            preflight(json.as_bytes(), &JsonConfigParams::default())?;
+
+            let bytes = json.as_bytes();
+            if bytes.iter().find(|b| !b.is_ascii_whitespace()) != Some(&b'{') {
+                return Err(KeyValueError::NotAnObject);
+            }
+            for (start, len) in top_level_windows(bytes)? {
+                let value = value_after_key(bytes, start, len).ok_or(KeyValueError::MissingKey)?;
+                pairs.push((&bytes[start..start + len], value));
+            }
+        }
+
+        let [base, patch, result] = &pairs;
+        if patch.iter().any(|(_, value)| value.first() == Some(&b'{')) {
+            return Err(KeyValueError::NestedPatch);
+        }
+
+        // The patch's key wins, as the first one with the bytes
+        let find = |pairs: &[(&[u8], &[u8])], key: &[u8]| pairs.iter().position(|(k, _)| *k == key);
+        let mut sources = vec![];
+        for (key, _) in result {
+            sources.push(match (find(patch, key), find(base, key)) {
+                (Some(j), _) => PatchSource::Patch(j),
+                (None, Some(i)) => PatchSource::Base(i),
+                (None, None) => return Err(KeyValueError::MissingKey),
+            });
+        }
+
+        let replaced: Vec<Option<usize>> = base.iter().map(|(key, _)| find(patch, key)).collect();
+        if replaced.iter().enumerate().any(|(i, j)| j.is_none() && !sources.contains(&PatchSource::Base(i))) {
+            return Err(KeyValueError::MissingKey);
+        }
+
+        Ok(Self { raw, sources, value_lens: result.iter().map(|(_, value)| value.len()).collect(), replaced, patch_keys: patch.len() })
+    }
+}
+
+impl<F: FieldExt> Circuit<F> for MergePatchCircuit<F> {
+
+    type Config = MergePatchConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { raw: self.raw.clone().map(|raw| vec![Value::unknown(); raw.len()]), ..self.clone() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+
+        let docs = [(); 3].map(|_| KeyValueConfig::configure(meta));
+        let absent = [(); 4].map(|_| meta.advice_column());
+        let [q_absent, q_absent_end, q_replacing] = [(); 3].map(|_| meta.selector());
+
+        absent.into_iter().for_each(|column| meta.enable_equality(column));
+
+        // An inactive slot's factor is 1
+        let [key, active, packed, product] = absent;
+        meta.create_gate("Absent key", |meta| {
+            let [k, a, p, acc] = [key, active, packed, product].map(|column| meta.query_advice(column, Rotation::cur()));
+            let next = meta.query_advice(product, Rotation::next());
+            let q = meta.query_selector(q_absent);
+            vec![q * (next - acc * (a.clone() * (k - p) + Expression::Constant(F::one()) - a))]
+        });
+
+        meta.create_gate("Absent key end", |meta| {
+            let acc = meta.query_advice(product, Rotation::cur());
+            let inv = meta.query_advice(packed, Rotation::cur());
+            let q = meta.query_selector(q_absent_end);
+            vec![q * (acc * inv - Expression::Constant(F::one()))]
+        });
+
+        meta.create_gate("Replacing value", |meta| {
+            let b = meta.query_advice(key, Rotation::cur());
+            let inv = meta.query_advice(packed, Rotation::cur());
+            let q = meta.query_selector(q_replacing);
+            let distance = (b.clone() - Expression::Constant(F::from(0x6e))) * (b - Expression::Constant(F::from(0x7b)));
+            vec![q * (distance * inv - Expression::Constant(F::one()))]
+        });
+
+        MergePatchConfig { docs, absent, q_absent, q_absent_end, q_replacing }
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+
+        let counts = [self.replaced.len(), self.patch_keys, self.sources.len()];
+        let repeated = (1..self.sources.len()).any(|r| self.sources[..r].contains(&self.sources[r]));
+        if counts.iter().any(|&count| count > MAX_TOP_LEVEL_KEYS) || repeated || self.value_lens.len() != self.sources.len() {
+            return Err(Error::Synthesis);
+        }
+
+        let mut docs = vec![];
+        for ((pair, raw), count) in config.docs.iter().zip(&self.raw).zip(counts) {
+            let doc = pair.assign(&mut layouter, raw)?;
+            pair.pin_object(&mut layouter, &doc, None)?;
+            layouter.assign_region(
+                || "Key count",
+                |mut region| {
+                    for [active, ..] in &doc.keys[count..] {
+                        region.constrain_constant(active.cell(), F::zero())?;
+                    }
+                    Ok(())
+                }
+            )?;
+            docs.push(doc);
+        }
+        let [base_pair, patch_pair, result_pair] = &config.docs;
+        let [base, patch, result] = [&docs[0], &docs[1], &docs[2]];
+
+        // Each result key is a copy of its source's, value and all
+        for (r, (&source, &value_len)) in self.sources.iter().zip(&self.value_lens).enumerate() {
+
+            let (pair, doc, slot) = match source {
+                PatchSource::Base(i) => (base_pair, base, i),
+                PatchSource::Patch(j) => (patch_pair, patch, j),
+            };
+            let start = result_pair.pin_slot(&mut layouter, result, r)?;
+            let value = result_pair.pin_value(&mut layouter, result, &start, value_len, 1, None)?;
+            let source_start = pair.pin_slot(&mut layouter, doc, slot)?;
+            let source_value = pair.pin_value(&mut layouter, doc, &source_start, value_len, 1, None)?;
+
+            layouter.assign_region(
+                || "Copied pair",
+                |mut region| {
+                    region.constrain_equal(result.packed[r].cell(), doc.packed[slot].cell())?;
+                    region.constrain_equal(result.keys[r][3].cell(), doc.keys[slot][3].cell())?;
+                    for (a, b) in value.iter().zip(&source_value) {
+                        region.constrain_equal(a.cell(), b.cell())?;
+                    }
+                    Ok(())
+                }
+            )?;
+
+            match source {
+                PatchSource::Base(_) => config.assign_absent(&mut layouter, &base.packed[slot], patch)?,
+                PatchSource::Patch(_) => config.assign_replacing(&mut layouter, &value[0])?,
+            }
+        }
+
+        // Every other base key is one the patch has
+        for (i, replaced) in self.replaced.iter().enumerate() {
+            match replaced {
+                Some(j) if *j < MAX_TOP_LEVEL_KEYS => layouter.assign_region(
+                    || "Replaced key",
+                    |mut region| {
+                        region.constrain_equal(base.packed[i].cell(), patch.packed[*j].cell())?;
+                        region.constrain_equal(base.keys[i][3].cell(), patch.keys[*j][3].cell())
+                    }
+                )?,
+                None if self.sources.contains(&PatchSource::Base(i)) => {},
+                _ => return Err(Error::Synthesis),
+            }
+        }
+
+        // And every other patch key deletes
+        for j in (0..self.patch_keys).filter(|&j| !self.sources.contains(&PatchSource::Patch(j))) {
+
+            let start = patch_pair.pin_slot(&mut layouter, patch, j)?;
+            let value = patch_pair.pin_value(&mut layouter, patch, &start, 4, 1, None)?;
+            layouter.assign_region(
+                || "Null value",
+                |mut region| {
+                    for (byte, &b) in value.iter().zip(b"null") {
+                        region.constrain_constant(byte.cell(), F::from(b as u64))?;
+                    }
+                    Ok(())
+                }
+            )?;
+            config.assign_absent(&mut layouter, &patch.packed[j], result)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Validity of two objects, both witnesses, plus their being equal up to the order of keys at every level: objects
+// compare as sets of pairs, arrays and other values byte for byte (so `[1, 2]` is not `[1,2]`, and an object inside an
+// array is not supported). `JsonNode::to_canonical_string` is the reference, on such documents. Every key of the first
+// document is a pair, matched to a key of the second with the same bytes. Each key is probed as `PathEqualityCircuit`
+// probes a step, at a witnessed position in each document, directly inside the object its parent pair's value opens
+// (the document itself at the top level), so matched pairs sit in matched objects. A pair's value is an object in
+// both documents, or has the same bytes in both. And every ':' outside strings in a document, one per key, is the ':'
+// of a pair, with as many of them as pairs: the pairs are all the keys of both. The pairs' parents and the lengths of
+// their keys and values are baked in; the positions stay witnesses, and nothing is public
+#[derive(Clone, Default)]
+pub struct StructuralEqualityCircuit<F: FieldExt> {
+    pub raw: [Vec<Value<F>>; 2],
+    pub pairs: Vec<MatchedPair>, // every key of the first document, in document order
+}
+
+// A key of both documents of a `StructuralEqualityCircuit`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatchedPair {
+    pub key_len: usize,
+    pub parent: Option<usize>, // the earlier pair whose value is the object holding the key, None at the top level
+    pub key_starts: [usize; 2], // first byte of the key's contents in each document, witnesses
+    pub value_len: Option<usize>, // None for an object value
+}
+
+#[derive(Clone, Debug)]
+pub struct StructuralEqualityConfig {
+    docs: [KeyValueConfig; 2],
+    // One row per byte of a document: the document's index + 1, pos, raw, not_str, inverse of raw - ':', 1 on a ':'
+    // outside strings, and the count of those so far
+    scan: [Column<Advice>; 7],
+    q_scan: Selector,
+    q_scan_next: Selector,
+    colons: [Column<Advice>; 2], // the document's index + 1 and the position of a pair's ':'
+    q_colon: Selector,
+}
+
+impl StructuralEqualityConfig {
+
+    fn configure<F: FieldExt>(meta: &mut ConstraintSystem<F>) -> Self {
+
+        let docs = [(); 2].map(|_| KeyValueConfig::configure(meta));
+        let scan = [(); 7].map(|_| meta.advice_column());
+        let colons = [(); 2].map(|_| meta.advice_column());
+        let [q_scan, q_colon] = [(); 2].map(|_| meta.complex_selector());
+        let q_scan_next = meta.selector();
+
+        scan.into_iter().chain(colons).for_each(|column| meta.enable_equality(column));
+
+        let [doc, pos, raw, not_str, inv, colon, count] = scan;
+        meta.create_gate("Colon scan", |meta| {
+            let d = meta.query_advice(raw, Rotation::cur()) - Expression::Constant(F::from(0x3a));
+            let s = meta.query_advice(not_str, Rotation::cur());
+            let i = meta.query_advice(inv, Rotation::cur());
+            let c = meta.query_advice(colon, Rotation::cur());
+            let q = meta.query_selector(q_scan);
+
+            let is_colon = Expression::Constant(F::one()) - d.clone() * i;
+            vec![q.clone() * d * is_colon.clone(), q * (c - s * is_colon)]
+        });
+
+        meta.create_gate("Colon scan (next)", |meta| {
+            let c = meta.query_advice(colon, Rotation::cur());
+            let n = meta.query_advice(count, Rotation::cur());
+            let n_prev = meta.query_advice(count, Rotation::prev());
+            let q = meta.query_selector(q_scan_next);
+            vec![q * (n - n_prev - c)]
+        });
+
+        meta.lookup_any("Colon of a pair", |meta| {
+            let q = meta.query_selector(q_scan) * meta.query_advice(colon, Rotation::cur());
+            let q_colon = meta.query_selector(q_colon);
+            [(doc, colons[0]), (pos, colons[1])].into_iter()
+                .map(|(column, table)| (q.clone() * meta.query_advice(column, Rotation::cur()), q_colon.clone() * meta.query_advice(table, Rotation::cur())))
+                .collect()
+        });
+
+        StructuralEqualityConfig { docs, scan, q_scan, q_scan_next, colons, q_colon }
+    }
+
+    // Assign both documents, pin every pair in each and match them up, see `StructuralEqualityCircuit`. Returns the
+    // first document and its pairs' key bytes
+    fn assign_equal<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, raw: &[Vec<Value<F>>; 2], pairs: &[MatchedPair]) -> Result<FirstDocKeys<F>, Error> {
+
+        if pairs.iter().enumerate().any(|(idx, pair)| pair.parent.map_or(false, |parent| parent >= idx)) {
+            return Err(Error::Synthesis);
+        }
+
+        // Per document, the key bytes and the value bytes (none for an object) of every pair
+        let mut docs = vec![];
+        let mut keys = vec![];
+        let mut values = vec![];
+        for (d, (pair_config, raw)) in self.docs.iter().zip(raw).enumerate() {
+
+            let doc = pair_config.assign(layouter, raw)?;
+            let root = pair_config.pin_object(layouter, &doc, None)?;
+
+            let mut starts: Vec<AssignedCell<F, F>> = vec![];
+            let mut levels: Vec<usize> = vec![];
+            let mut colons = vec![];
+            let (mut doc_keys, mut doc_values) = (vec![], vec![]);
+            for pair in pairs {
+
+                let (scope, level) = match pair.parent {
+                    Some(parent) => (&starts[parent], levels[parent] + 1),
+                    None => (&root, 1),
+                };
+                let (key, colon) = pair_config.probe_key(layouter, &doc, Value::known(pair.key_starts[d]), pair.key_len, (scope, level))?;
+                let start = colon[8].clone();
+                let value = match pair.value_len {
+                    Some(len) => pair_config.pin_value(layouter, &doc, &start, len, level, None)?,
+                    None => pair_config.pin_object(layouter, &doc, Some(&start)).map(|_| vec![])?,
+                };
+
+                starts.push(start);
+                levels.push(level);
+                colons.push(colon[1].clone());
+                doc_keys.push(key);
+                doc_values.push(value);
+            }
+
+            self.assign_scan(layouter, &doc, d, &colons)?;
+            keys.push(doc_keys);
+            values.push(doc_values);
+            docs.push(doc);
+        }
+
+        layouter.assign_region(
+            || "Equal pairs",
+            |mut region| {
+                let bytes = |d: usize| keys[d].iter().flatten().chain(values[d].iter().flatten());
+                for (a, b) in bytes(0).zip(bytes(1)) {
+                    region.constrain_equal(a.cell(), b.cell())?;
+                }
+                Ok(())
+            }
+        )?;
+
+        Ok((docs.swap_remove(0), keys.swap_remove(0)))
+    }
+
+
+    // Scan the `index`th document for its ':'s outside strings, each of them one of `colons`, and as many as them
+    fn assign_scan<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, doc: &KeyRunsAssigned<F>, index: usize, colons: &[AssignedCell<F, F>]) -> Result<(), Error> {
+
+        let tag = F::from(index as u64 + 1);
+        let [doc_col, pos_col] = self.colons;
+        layouter.assign_region(
+            || "Pair colons",
+            |mut region| {
+                for (offset, colon) in colons.iter().enumerate() {
+                    region.assign_advice_from_constant(|| "doc", doc_col, offset, tag)?;
+                    colon.copy_advice(|| "colon", &mut region, pos_col, offset)?;
+                    self.q_colon.enable(&mut region, offset)?;
+                }
+                Ok(())
+            }
+        )?;
+
+        layouter.assign_region(
+            || "Colon scan",
+            |mut region| {
+
+                let [doc_col, pos, raw, not_str, inv, colon, count] = self.scan;
+                let mut n = Value::known(F::zero());
+                let mut last = None;
+                for (offset, row) in doc.bytes.iter().enumerate() {
+
+                    region.assign_advice_from_constant(|| "doc", doc_col, offset, tag)?;
+                    row[0].copy_advice(|| "pos", &mut region, pos, offset)?;
+                    let d = row[1].copy_advice(|| "raw", &mut region, raw, offset)?.value().map(|&b| b - F::from(0x3a));
+                    let s = row[4].copy_advice(|| "not_str", &mut region, not_str, offset)?.value().copied();
+                    region.assign_advice(|| "inv", inv, offset, || d.map(|d| d.invert().unwrap_or(F::zero())))?;
+                    self.q_scan.enable(&mut region, offset)?;
+
+                    let c = d.zip(s).map(|(d, s)| if d == F::zero() { s } else { F::zero() });
+                    region.assign_advice(|| "colon", colon, offset, || c)?;
+                    n = n + c;
+
+                    // The first byte of a document is no ':'
+                    last = Some(match offset {
+                        0 => region.assign_advice_from_constant(|| "count", count, offset, F::zero())?,
+                        _ => region.assign_advice(|| "count", count, offset, || n)?,
+                    });
+                    if offset > 0 {
+                        self.q_scan_next.enable(&mut region, offset)?;
+                    }
+                }
+
+                let last = last.ok_or(Error::Synthesis)?;
+                region.constrain_constant(last.cell(), F::from(colons.len() as u64))
+            }
+        )
+    }
+}
+
+impl<F: FieldExt> StructuralEqualityCircuit<F> {
+
+    // Fails as `KeyValueCircuit::from_str` on either document, on one that is not an object or has an object inside an
+    // array, and with `MissingKey` when a key of the first has no key with its bytes in the matching object of the
+    // second. Other differences, such as another value or an extra key in the second, still give a circuit, which
+    // then fails to prove
+    pub fn from_str(first: &str, second: &str) -> Result<Self, KeyValueError> {
+
+        let mut raw = [vec![], vec![]];
+        let mut trees = [vec![], vec![]];
+        for ((json, raw), tree) in [first, second].into_iter().zip(&mut raw).zip(&mut trees) {
+
+            *raw = JsonCircuit::<F>::from_str(json)?.raw;
+            preflight(json.as_bytes(), &JsonConfigParams::default())?;
+
+            let bytes = json.as_bytes();
+            if bytes.iter().find(|b| !b.is_ascii_whitespace()) != Some(&b'{') {
+                return Err(KeyValueError::NotAnObject);
+            }
+            top_level_windows(bytes)?;
+            *tree = key_tree(bytes).ok_or(KeyValueError::ObjectInArray)?;
+        }
+
+        // Parents come first, so each pair's parent is matched before it
+        let [first, second] = &trees;
+        let mut matched: Vec<usize> = vec![];
+        let mut pairs = vec![];
+        for (key, parent, start, value) in first {
+            let parent_match = parent.map(|parent| matched[parent]);
+            let other = (0..second.len())
+                .find(|&idx| !matched.contains(&idx) && second[idx].1 == parent_match && second[idx].0 == *key)
+                .ok_or(KeyValueError::MissingKey)?;
+            matched.push(other);
+
+            let value_len = Some(value.len()).filter(|_| value.first() != Some(&b'{'));
+            pairs.push(MatchedPair { key_len: key.len(), parent: *parent, key_starts: [*start, second[other].2], value_len });
+        }
+
+        Ok(Self { raw, pairs })
+    }
+}
+
+impl<F: FieldExt> Circuit<F> for StructuralEqualityCircuit<F> {
+
+    type Config = StructuralEqualityConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { raw: self.raw.clone().map(|raw| vec![Value::unknown(); raw.len()]), ..self.clone() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        StructuralEqualityConfig::configure(meta)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+        config.assign_equal(&mut layouter, &self.raw, &self.pairs).map(|_| ())
+    }
+}
+
+// Validity of a hidden document plus a commitment to the value it parses to, public: `value_commitment`, over
+// `JsonNode::to_canonical_string` of it. The canonical form is a second witness, proven equal to the document up to
+// key order as in `StructuralEqualityCircuit` (it first, so its pairs are in canonical order), free of whitespace
+// outside strings by the byte table, and with the keys of each object strictly increasing: each key is packed as
+// `pack_key` packs the top-level ones, and the packed key after it is larger, compared as in `SortedKeysCircuit`.
+// Then it is hashed byte by byte. Those fix the canonical form of a document, so two encodings of one value commit
+// alike, within what `StructuralEqualityCircuit` supports: arrays, strings and numbers as written, so the canonical
+// form only differs from the document in its whitespace and key order. Keys are at most `MAX_KEY_LEN` bytes. The
+// canonical form's shape is baked in, as `StructuralEqualityCircuit` bakes the first document's, and the lengths of
+// both documents
+#[derive(Clone, Default)]
+pub struct CanonicalCommitmentCircuit<F: FieldExt> {
+    pub raw: [Vec<Value<F>>; 2], // the canonical form, then the document
+    pub pairs: Vec<MatchedPair>, // every key of the canonical form, as `StructuralEqualityCircuit::pairs`
+    pub commitment: F,
+}
+
+#[derive(Clone, Debug)]
+pub struct CanonicalCommitmentConfig {
+    equal: StructuralEqualityConfig,
+    // A byte, the bytes so far packed, and on the last row of a difference the two packed keys, as in
+    // `SortedKeysConfig`
+    order: [Column<Advice>; 4],
+    byte_table: TableColumn,
+    q_byte: Selector,
+    q_first: Selector,
+    q_next: Selector,
+    q_last: Selector,
+    poseidon: PoseidonConfig,
+}
+
+// Commitment to a value, as `CanonicalCommitmentCircuit` computes it: Poseidon over each byte of
+// `JsonNode::to_canonical_string` of the value as one input, with its length as the domain. `serde_json` writes the
+// numbers, so a document commits to its `serde_json::Value` when it writes them alike
+pub fn value_commitment<F: FieldExt>(params: &PoseidonParams<F>, value: &serde_json::Value) -> F {
+    canonical_commitment(params, JsonNode::from_value(value).to_canonical_string().as_bytes())
+}
+
+fn canonical_commitment<F: FieldExt>(params: &PoseidonParams<F>, canonical: &[u8]) -> F {
+    let inputs: Vec<F> = canonical.iter().map(|&b| F::from(b as u64)).collect();
+    params.hash(F::from(canonical.len() as u64), &inputs)
+}
+
+impl<F: FieldExt> CanonicalCommitmentCircuit<F> {
+
+    // Fails as `StructuralEqualityCircuit::from_str`, with the document's canonical form for the first document, and
+    // past `MAX_KEY_LEN` bytes in a key. A document whose arrays, strings or numbers the canonical form rewrites fails
+    // with `MissingKey` or still gives a circuit, which then fails to prove
+    pub fn from_str(json: &str) -> Result<Self, KeyValueError> {
+
+        let canonical = parse(json)?.to_canonical_string();
+        let equal = StructuralEqualityCircuit::<F>::from_str(&canonical, json)?;
+        if let Some(pair) = equal.pairs.iter().find(|pair| pair.key_len > MAX_KEY_LEN) {
+            return Err(ParseError::TooLong(pair.key_starts[1] + MAX_KEY_LEN).into());
+        }
+
+        let commitment = canonical_commitment(&PoseidonParams::new(), canonical.as_bytes());
+        Ok(Self { raw: equal.raw, pairs: equal.pairs, commitment })
+    }
+
+    pub fn instances(&self) -> Vec<F> {
+        vec![self.commitment]
+    }
+}
+
+impl CanonicalCommitmentConfig {
+
+    // Prove the key `next` sorts after `prev`, both at most `MAX_KEY_LEN` bytes: each packed over `MAX_KEY_LEN` rows,
+    // then their difference less 1 over as many rows of bytes
+    fn assign_order<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, prev: &[AssignedCell<F, F>], next: &[AssignedCell<F, F>]) -> Result<(), Error> {
+
+        if prev.len() > MAX_KEY_LEN || next.len() > MAX_KEY_LEN {
+            return Err(Error::Synthesis);
+        }
+
+        let [byte, acc, prev_col, next_col] = self.order;
+        layouter.assign_region(
+            || "Sibling order",
+            |mut region| {
+
+                // Zero-padded, most significant first
+                let mut packed = vec![];
+                for (k, key) in [prev, next].into_iter().enumerate() {
+                    let mut sum = Value::known(F::zero());
+                    let mut last = None;
+                    for idx in 0..MAX_KEY_LEN {
+                        let offset = k * MAX_KEY_LEN + idx;
+                        let b = match key.get(idx) {
+                            Some(cell) => cell.copy_advice(|| "byte", &mut region, byte, offset)?,
+                            None => region.assign_advice_from_constant(|| "byte", byte, offset, F::zero())?,
+                        };
+                        sum = sum * Value::known(F::from(256)) + b.value().copied();
+                        last = Some(region.assign_advice(|| "acc", acc, offset, || sum)?);
+                        match idx {
+                            0 => self.q_first.enable(&mut region, offset)?,
+                            _ => self.q_next.enable(&mut region, offset)?,
+                        }
+                    }
+                    packed.push(last.ok_or(Error::Synthesis)?);
+                }
+
+                // Out of order, the difference wraps and its low bytes can't add up to it
+                let d = packed[0].value().zip(packed[1].value()).map(|(&p, &n)| n - p - F::one());
+                let bytes = d.map(|d| {
+                    let d = d.get_lower_128();
+                    (0..MAX_KEY_LEN).rev().map(|idx| F::from((d >> (8 * idx)) as u8 as u64)).collect::<Vec<F>>()
+                });
+
+                let mut sum = Value::known(F::zero());
+                for idx in 0..MAX_KEY_LEN {
+                    let offset = 2 * MAX_KEY_LEN + idx;
+                    let b = bytes.as_ref().map(|bytes| bytes[idx]);
+                    sum = sum * Value::known(F::from(256)) + b;
+                    region.assign_advice(|| "byte", byte, offset, || b)?;
+                    region.assign_advice(|| "acc", acc, offset, || sum)?;
+                    self.q_byte.enable(&mut region, offset)?;
+                    match idx {
+                        0 => self.q_first.enable(&mut region, offset)?,
+                        _ => self.q_next.enable(&mut region, offset)?,
+                    }
+                }
+
+                let offset = 3 * MAX_KEY_LEN - 1;
+                packed[0].copy_advice(|| "prev", &mut region, prev_col, offset)?;
+                packed[1].copy_advice(|| "next", &mut region, next_col, offset)?;
+                self.q_last.enable(&mut region, offset)
+            }
+        )
+    }
+}
+
+impl<F: FieldExt> Circuit<F> for CanonicalCommitmentCircuit<F> {
+
+    type Config = CanonicalCommitmentConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { raw: self.raw.clone().map(|raw| vec![Value::unknown(); raw.len()]), ..self.clone() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+
+        let equal = StructuralEqualityConfig::configure(meta);
+        let order = [(); 4].map(|_| meta.advice_column());
+        let byte_table = meta.lookup_table_column();
+        let q_byte = meta.complex_selector();
+        let [q_first, q_next, q_last] = [(); 3].map(|_| meta.selector());
+        let poseidon = PoseidonConfig::configure(meta);
+
+        order.map(|column| meta.enable_equality(column));
+
+        let [byte, acc, prev, next] = order;
+        meta.create_gate("Sibling order (first)", |meta| {
+            let b = meta.query_advice(byte, Rotation::cur());
+            let d = meta.query_advice(acc, Rotation::cur());
+            let q = meta.query_selector(q_first);
+            vec![q * (d - b)]
+        });
+
+        meta.create_gate("Sibling order (next)", |meta| {
+            let b = meta.query_advice(byte, Rotation::cur());
+            let d = meta.query_advice(acc, Rotation::cur());
+            let d_prev = meta.query_advice(acc, Rotation::prev());
+            let q = meta.query_selector(q_next);
+            vec![q * (d - d_prev * Expression::Constant(F::from(256)) - b)]
+        });
+
+        meta.create_gate("Sibling order", |meta| {
+            let d = meta.query_advice(acc, Rotation::cur());
+            let p = meta.query_advice(prev, Rotation::cur());
+            let n = meta.query_advice(next, Rotation::cur());
+            let q = meta.query_selector(q_last);
+            vec![q * (n - p - Expression::Constant(F::one()) - d)]
+        });
+
+        meta.lookup("Sibling order byte", |meta| {
+            let q = meta.query_selector(q_byte);
+            let b = meta.query_advice(byte, Rotation::cur());
+            vec![(q * b, byte_table)]
+        });
+
+        CanonicalCommitmentConfig { equal, order, byte_table, q_byte, q_first, q_next, q_last, poseidon }
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+
+        layouter.assign_table(
+            || "Byte table",
+            |mut table| {
+                for b in 0..256 {
+                    table.assign_cell(|| format!("byte {:#x}", b), config.byte_table, b, || Value::known(F::from(b as u64)))?;
+                }
+                Ok(())
+            }
+        )?;
+
+        let (canonical, keys) = config.equal.assign_equal(&mut layouter, &self.raw, &self.pairs)?;
+
+        // No whitespace outside strings
+        layouter.assign_region(
+            || "Compact",
+            |mut region| {
+                for row in &canonical.bytes {
+                    region.constrain_constant(row[6].cell(), F::zero())?;
+                }
+                Ok(())
+            }
+        )?;
+
+        // Each key against the next one in its object
+        for (idx, pair) in self.pairs.iter().enumerate() {
+            if let Some(next) = (idx + 1..self.pairs.len()).find(|&next| self.pairs[next].parent == pair.parent) {
+                config.assign_order(&mut layouter, &keys[idx], &keys[next])?;
+            }
+        }
+
+        let inputs: Vec<PoseidonInput<F>> = canonical.bytes.iter().map(|row| PoseidonInput::Cell(&row[1])).collect();
+        let domain = F::from(canonical.bytes.len() as u64);
+        let (commitment, _) = config.poseidon.hash(&mut layouter, &PoseidonParams::new(), domain, &inputs)?;
+        layouter.constrain_instance(commitment.cell(), config.equal.docs[0].instance, 0)
+    }
+}
+
+// Validity plus the (start, len) of the contents of each top-level key, public in document order and padded with
+// (0, 0) up to `MAX_TOP_LEVEL_KEYS` (see `instances`). The ranges are witnessed from the key flags, see `KeyRunsConfig`
+#[derive(Clone, Default)]
+pub struct KeyRangesCircuit<F: FieldExt> {
+    pub raw: Vec<Value<F>>,
+    pub ranges: Vec<(usize, usize)>, // as `top_level_key_ranges`
+}
+
+// Shared by the circuits that expose something about the top-level keys without fixing where they sit. The "Key runs"
+// region copies top_key and char_class for every byte and follows the runs of top_key, each one top-level key from
+// its first content byte to the byte before its ':'. Along a run it carries the key's slot (1 for the first key, and
+// so on), its start and its content length so far, and `end` flags the byte after it. The "Top-level keys" region
+// has a row per slot up to `MAX_TOP_LEVEL_KEYS`, the active ones first, and a lookup each way ties those rows to the
+// run ends: every active row is some run's (slot, start, len), and every run's is on some row. Slots count up by
+// one, so the active rows are exactly the keys in document order and the rest are all 0. With windows, each slot also
+// gets `MAX_KEY_LEN` rows holding the key's bytes, each looked up among its run's content bytes at its offset, then
+// 0 past its length, and packed big-endian into one cell. Content bytes are never 0, so two keys compare as their
+// packed windows do. With the byte table, which turns the windows on too, every byte row also carries what a
+// circuit needs to find a value without fixing where it sits: its level, not_str, value_type, whether it is whitespace
+// outside strings, the position (byte index + 1) of the next byte that is not, and of the first byte of the innermost
+// open container and of the last opening quote. Probes are rows holding a position and the byte's row there, looked up
+// against the byte rows, so a position is a witness like any other. It is all witnessed, so the verifying key depends
+// on the document's length only
+#[derive(Clone, Debug)]
+pub struct KeyRunsConfig {
+    json: JsonConfig,
+    // raw, top_key, char_class, 1 on a key's content, 1 on the byte after a run, byte index + 1, then the run's slot,
+    // start and content length so far
+    runs: [Column<Advice>; 9],
+    // 1 on a slot holding a key, then its slot, start and length
+    keys: [Column<Advice>; 4],
+    q_first: Selector,
+    q_byte: Selector,
+    q_key: Selector,
+    q_key_first: Selector,
+    q_key_next: Selector,
+    windows: Option<KeyWindowsConfig>,
+    table: Option<ByteTableConfig>,
+    instance: Column<Instance>,
+}
+
+// Columns for the key windows, see `KeyRunsConfig`
+#[derive(Clone, Copy, Debug)]
+struct KeyWindowsConfig {
+    fixed: [Column<Fixed>; 2], // the window's slot, and the offset into it
+    // 1 on the key's bytes, their count so far, the byte or 0, the bytes so far packed, and slot and offset where the
+    // first is 1
+    window: [Column<Advice>; 6],
+    q_win: Selector,
+    q_win_first: Selector,
+    q_win_next: Selector,
+}
+
+// Columns for the byte table, see `KeyRunsConfig`
+#[derive(Clone, Copy, Debug)]
+struct ByteTableConfig {
+    // level, not_str, value_type, 1 on whitespace outside strings, then the position of the next byte that is not (0
+    // past the last byte), of the first byte of the innermost open container (0 outside all) and of the last opening
+    // quote (0 before any)
+    table: [Column<Advice>; 7],
+    depth: [Column<Advice>; MAX_DEPTH + 1], // 1 at the byte's level
+    openers: [Column<Advice>; MAX_DEPTH],   // position of the first byte of the container open at each level from 1
+    q_last: Selector,
+    // A probe: 1 where it is looked up, then a position and the byte row there, as (pos, raw, char_class) of the runs
+    // and `table`
+    probe: [Column<Advice>; 11],
+    q_probe: Selector,
+    q_step: Selector, // the position is the previous probe's + 1
+    q_skip: Selector, // the position is the previous probe's next
+    // A top-level key found in the windows: (slot, start, len) on its first row, then (slot, offset, byte) per byte
+    key: [Column<Advice>; 3],
+    q_key_slot: Selector,
+    q_key_byte: Selector,
+}
+
+// Handles from `KeyRunsConfig::synthesize`: (active, slot, start, len) for every slot, and the packed window of every
+// slot, empty without windows. Then the row of every byte as a probe sees it (pos, raw, char_class, then the byte
+// table), empty without the table
+#[derive(Clone, Debug)]
+struct KeyRunsAssigned<F: FieldExt> {
+    keys: Vec<[AssignedCell<F, F>; 4]>,
+    packed: Vec<AssignedCell<F, F>>,
+    bytes: Vec<ByteRow<F>>,
+}
+
+// A byte row as a probe sees it, see `KeyRunsAssigned`
+type ByteRow<F> = [AssignedCell<F, F>; 10];
+
+// What `assign_runs` hands back: the run ends, then the byte rows
+type RunsAndBytes<F> = (Value<Vec<[F; 3]>>, Vec<ByteRow<F>>);
+
+// What `KeyValueConfig::probe_key` hands back: the key's bytes, then the ':' probe
+type KeyProbe<F> = (Vec<AssignedCell<F, F>>, [AssignedCell<F, F>; 11]);
+
+// What `StructuralEqualityConfig::assign_equal` hands back: the first document, then the bytes of each of its keys
+type FirstDocKeys<F> = (KeyRunsAssigned<F>, Vec<Vec<AssignedCell<F, F>>>);
+
+impl KeyRunsConfig {
+
+    fn configure<F: FieldExt>(meta: &mut ConstraintSystem<F>, windows: bool) -> Self {
+
+        let json = JsonConfig::configure_with_params(meta, JsonConfigParams { key_flag: true, ..Default::default() });
+        let runs = [(); 9].map(|_| meta.advice_column());
+        let keys = [(); 4].map(|_| meta.advice_column());
+        let [q_first, q_key_first, q_key_next] = [(); 3].map(|_| meta.selector());
+        let [q_byte, q_key] = [(); 2].map(|_| meta.complex_selector());
+        let instance = meta.instance_column();
+
+        let [raw, top, cc, content, end, pos, slot, start, len] = runs;
+        let [active, out_slot, out_start, out_len] = keys;
+        [raw, top, cc, active, out_slot, out_start, out_len].map(|column| meta.enable_equality(column));
+        meta.enable_equality(instance);
+
+        // Nothing before the first byte
+        meta.create_gate("Key runs (start)", |meta| {
+            let q = meta.query_selector(q_first);
+            [top, pos, slot, start, len].map(|column| q.clone() * meta.query_advice(column, Rotation::cur()))
+        });
+
+        // A run starts where top_key goes 0 -> 1, taking the next slot, with this byte as its start and no content yet
+        meta.create_gate("Key runs", |meta| {
+            let t = meta.query_advice(top, Rotation::cur());
+            let t_prev = meta.query_advice(top, Rotation::prev());
+            let c = meta.query_advice(cc, Rotation::cur());
+            let k = meta.query_advice(content, Rotation::cur());
+            let e = meta.query_advice(end, Rotation::cur());
+            let p = meta.query_advice(pos, Rotation::cur());
+            let p_prev = meta.query_advice(pos, Rotation::prev());
+            let n = meta.query_advice(slot, Rotation::cur());
+            let n_prev = meta.query_advice(slot, Rotation::prev());
+            let s = meta.query_advice(start, Rotation::cur());
+            let s_prev = meta.query_advice(start, Rotation::prev());
+            let l = meta.query_advice(len, Rotation::cur());
+            let l_prev = meta.query_advice(len, Rotation::prev());
+            let q = meta.query_selector(q_byte);
+
+            let one = Expression::Constant(F::one());
+            let begins = t.clone() * (one.clone() - t_prev.clone());
+
+            vec![
+                // c * (c - 1) is 2 on `CharClass::StringContent` and 0 on the other two
+                q.clone() * (k.clone() * Expression::Constant(F::from(2)) - t.clone() * c.clone() * (c - one.clone())),
+                q.clone() * (e - t_prev * (one.clone() - t)),
+                q.clone() * (p.clone() - p_prev - one.clone()),
+                q.clone() * (n - n_prev - begins.clone()),
+                q.clone() * (s - s_prev.clone() - begins.clone() * (p - one.clone() - s_prev)),
+                q * (l - (one - begins) * l_prev - k),
+            ]
+        });
+
+        // An inactive slot is all 0, and the active ones come first and count up from 1
+        meta.create_gate("Top-level keys", |meta| {
+            let a = meta.query_advice(active, Rotation::cur());
+            let s = meta.query_advice(out_start, Rotation::cur());
+            let l = meta.query_advice(out_len, Rotation::cur());
+            let q = meta.query_selector(q_key);
+
+            let one = Expression::Constant(F::one());
+            vec![q.clone() * a.clone() * (one.clone() - a.clone()), q.clone() * (one.clone() - a.clone()) * s, q * (one - a) * l]
+        });
+
+        meta.create_gate("Top-level keys (first)", |meta| {
+            let a = meta.query_advice(active, Rotation::cur());
+            let n = meta.query_advice(out_slot, Rotation::cur());
+            let q = meta.query_selector(q_key_first);
+            vec![q * (n - a)]
+        });
+
+        meta.create_gate("Top-level keys (next)", |meta| {
+            let a = meta.query_advice(active, Rotation::cur());
+            let a_prev = meta.query_advice(active, Rotation::prev());
+            let n = meta.query_advice(out_slot, Rotation::cur());
+            let n_prev = meta.query_advice(out_slot, Rotation::prev());
+            let q = meta.query_selector(q_key_next);
+
+            let one = Expression::Constant(F::one());
+            vec![q.clone() * (n - a.clone() * (n_prev + one.clone())), q * a * (one - a_prev)]
+        });
+
+        // Rows off the selectors give (0, 0, 0) on both sides, which the inactive slots and the bytes ending no run need
+        meta.lookup_any("Top-level key is a run", |meta| {
+            let q_key = meta.query_selector(q_key);
+            let q_byte = meta.query_selector(q_byte);
+            let e = meta.query_advice(end, Rotation::cur());
+            [(out_slot, slot), (out_start, start), (out_len, len)]
+                .map(|(key, run)| (q_key.clone() * meta.query_advice(key, Rotation::cur()), q_byte.clone() * e.clone() * meta.query_advice(run, Rotation::cur())))
+                .to_vec()
+        });
+
+        meta.lookup_any("Run is a top-level key", |meta| {
+            let q_key = meta.query_selector(q_key);
+            let q_byte = meta.query_selector(q_byte);
+            let e = meta.query_advice(end, Rotation::cur());
+            [(slot, out_slot), (start, out_start), (len, out_len)]
+                .map(|(run, key)| (q_byte.clone() * e.clone() * meta.query_advice(run, Rotation::cur()), q_key.clone() * meta.query_advice(key, Rotation::cur())))
+                .to_vec()
+        });
+
+        let windows = windows.then(|| Self::configure_windows(meta, runs, q_byte));
+
+        Self { json, runs, keys, q_first, q_byte, q_key, q_key_first, q_key_next, windows, table: None, instance }
+    }
+
+    // With the windows and the byte table, which is returned too
+    fn configure_with_table<F: FieldExt>(meta: &mut ConstraintSystem<F>) -> (Self, ByteTableConfig) {
+
+        let mut config = Self::configure(meta, false);
+        let windows = Self::configure_windows(meta, config.runs, config.q_byte);
+        let table = Self::configure_table(meta, &config, windows);
+        config.windows = Some(windows);
+        config.table = Some(table);
+        (config, table)
+    }
+
+    fn configure_table<F: FieldExt>(meta: &mut ConstraintSystem<F>, config: &Self, windows: KeyWindowsConfig) -> ByteTableConfig {
+
+        let KeyRunsConfig { runs, keys, q_first, q_byte, q_key, .. } = *config;
+
+        let table = [(); 7].map(|_| meta.advice_column());
+        let depth = [(); MAX_DEPTH + 1].map(|_| meta.advice_column());
+        let openers = [(); MAX_DEPTH].map(|_| meta.advice_column());
+        let probe = [(); 11].map(|_| meta.advice_column());
+        let key = [(); 3].map(|_| meta.advice_column());
+        let [q_probe, q_key_slot, q_key_byte] = [(); 3].map(|_| meta.complex_selector());
+        let [q_last, q_step, q_skip] = [(); 3].map(|_| meta.selector());
+
+        let [raw, _, cc, _, _, pos, _, _, _] = runs;
+        let [level, not_str, _, ws, next, inner, string] = table;
+        meta.enable_equality(pos);
+        table.map(|column| meta.enable_equality(column));
+        probe.map(|column| meta.enable_equality(column));
+        key.map(|column| meta.enable_equality(column));
+
+        meta.create_gate("Byte table (start)", |meta| {
+            let q = meta.query_selector(q_first);
+            let ns = meta.query_advice(not_str, Rotation::cur());
+            let mut constraints = vec![q.clone() * (ns - Expression::Constant(F::one()))];
+            constraints.extend([level, string].into_iter().chain(openers).map(|column| q.clone() * meta.query_advice(column, Rotation::cur())));
+            constraints
+        });
+
+        // A byte at level l opening a container is the first byte of the one open at l until the next such byte
+        meta.create_gate("Byte table", |meta| {
+            let r = meta.query_advice(raw, Rotation::cur());
+            let p = meta.query_advice(pos, Rotation::cur());
+            let l = meta.query_advice(level, Rotation::cur());
+            let l_prev = meta.query_advice(level, Rotation::prev());
+            let ns = meta.query_advice(not_str, Rotation::cur());
+            let ns_prev = meta.query_advice(not_str, Rotation::prev());
+            let w = meta.query_advice(ws, Rotation::cur());
+            let n = meta.query_advice(next, Rotation::cur());
+            let n_prev = meta.query_advice(next, Rotation::prev());
+            let i = meta.query_advice(inner, Rotation::cur());
+            let s = meta.query_advice(string, Rotation::cur());
+            let s_prev = meta.query_advice(string, Rotation::prev());
+            let b = depth.map(|column| meta.query_advice(column, Rotation::cur()));
+            let q = meta.query_selector(q_byte);
+
+            let one = Expression::Constant(F::one());
+            let d = l.clone() - l_prev;
+            let push = d.clone() * (d + one.clone()) * Expression::Constant(F::from(2).invert().unwrap());
+            let opens_string = ns_prev * (one.clone() - ns.clone());
+            let only_ws = [0x20, 0x09, 0x0a, 0x0d].map(|byte| r.clone() - Expression::Constant(F::from(byte)))
+                .into_iter().fold(q.clone() * w.clone(), |acc, factor| acc * factor);
+
+            let mut constraints = vec![
+                q.clone() * w.clone() * (one.clone() - w.clone()),
+                q.clone() * w.clone() * (one.clone() - ns),
+                only_ws,
+                q.clone() * (n_prev - w.clone() * n - (one.clone() - w) * p.clone()),
+                q.clone() * (s - s_prev.clone() - opens_string * (p.clone() - s_prev)),
+                q.clone() * (b.iter().fold(Expression::Constant(F::zero()), |acc, b| acc + b.clone()) - one.clone()),
+                q.clone() * (b.iter().enumerate().fold(Expression::Constant(F::zero()), |acc, (j, b)| acc + b.clone() * Expression::Constant(F::from(j as u64))) - l),
+            ];
+            constraints.extend(b.iter().map(|b| q.clone() * b.clone() * (one.clone() - b.clone())));
+
+            let mut sum = Expression::Constant(F::zero());
+            for (j, column) in openers.into_iter().enumerate() {
+                let o = meta.query_advice(column, Rotation::cur());
+                let o_prev = meta.query_advice(column, Rotation::prev());
+                constraints.push(q.clone() * (o.clone() - o_prev.clone() - push.clone() * b[j + 1].clone() * (p.clone() - o_prev)));
+                sum = sum + b[j + 1].clone() * o;
+            }
+            constraints.push(q * (i - sum));
+            constraints
+        });
+
+        meta.create_gate("Byte table (last)", |meta| {
+            let n = meta.query_advice(next, Rotation::cur());
+            let q = meta.query_selector(q_last);
+            vec![q * n]
+        });
+
+        // The leading 1 keeps a probe off the rows past the byte rows, which are all 0
+        meta.lookup_any("Byte at a position", |meta| {
+            let q_probe = meta.query_selector(q_probe);
+            let q_byte = meta.query_selector(q_byte);
+            let on = q_probe * meta.query_advice(probe[0], Rotation::cur());
+            let rows = [pos, raw, cc].into_iter().chain(table);
+            std::iter::once((on.clone(), q_byte.clone()))
+                .chain(probe[1..].iter().zip(rows).map(|(&p, t)| (on.clone() * meta.query_advice(p, Rotation::cur()), q_byte.clone() * meta.query_advice(t, Rotation::cur()))))
+                .collect()
+        });
+
+        meta.create_gate("Probe positions", |meta| {
+            let p = meta.query_advice(probe[1], Rotation::cur());
+            let p_prev = meta.query_advice(probe[1], Rotation::prev());
+            let n_prev = meta.query_advice(probe[8], Rotation::prev());
+            let q_step = meta.query_selector(q_step);
+            let q_skip = meta.query_selector(q_skip);
+            vec![q_step * (p.clone() - p_prev - Expression::Constant(F::one())), q_skip * (p - n_prev)]
+        });
+
+        // A key's closing quote follows its contents, on the same row as the probe there
+        let [slot, start, len] = key;
+        let [active, out_slot, out_start, out_len] = keys;
+        meta.create_gate("Top-level key end", |meta| {
+            let p = meta.query_advice(probe[1], Rotation::cur());
+            let s = meta.query_advice(start, Rotation::cur());
+            let l = meta.query_advice(len, Rotation::cur());
+            let q = meta.query_selector(q_key_slot);
+            vec![q * (p - s - l - Expression::Constant(F::one()))]
+        });
+
+        meta.lookup_any("Top-level key slot", |meta| {
+            let q_slot = meta.query_selector(q_key_slot);
+            let q_key = meta.query_selector(q_key);
+            std::iter::once((q_slot.clone(), q_key.clone() * meta.query_advice(active, Rotation::cur())))
+                .chain([(slot, out_slot), (start, out_start), (len, out_len)].map(|(k, column)| (q_slot.clone() * meta.query_advice(k, Rotation::cur()), q_key.clone() * meta.query_advice(column, Rotation::cur()))))
+                .collect()
+        });
+
+        // The slot is an active one, so this never matches the 0 rows past the windows
+        meta.lookup_any("Top-level key bytes", |meta| {
+            let q_bytes = meta.query_selector(q_key_byte);
+            let q_win = meta.query_selector(windows.q_win);
+            let [_, _, byte, _, key_slot, key_off] = windows.window;
+            key.into_iter().zip([key_slot, key_off, byte])
+                .map(|(k, column)| (q_bytes.clone() * meta.query_advice(k, Rotation::cur()), q_win.clone() * meta.query_advice(column, Rotation::cur())))
+                .collect()
+        });
+
+        ByteTableConfig { table, depth, openers, q_last, probe, q_probe, q_step, q_skip, key, q_key_slot, q_key_byte }
+    }
+
+    fn configure_windows<F: FieldExt>(meta: &mut ConstraintSystem<F>, runs: [Column<Advice>; 9], q_byte: Selector) -> KeyWindowsConfig {
+
+        let fixed = [(); 2].map(|_| meta.fixed_column());
+        let window = [(); 6].map(|_| meta.advice_column());
+        let q_win = meta.complex_selector();
+        let [q_win_first, q_win_next] = [(); 2].map(|_| meta.selector());
+
+        let [w_slot, w_off] = fixed;
+        let [w_in, count, byte, packed, key_slot, key_off] = window;
+        let [raw, _, _, content, _, _, slot, _, len] = runs;
+        [count, packed].map(|column| meta.enable_equality(column));
+
+        meta.create_gate("Key windows", |meta| {
+            let i = meta.query_advice(w_in, Rotation::cur());
+            let b = meta.query_advice(byte, Rotation::cur());
+            let ks = meta.query_advice(key_slot, Rotation::cur());
+            let ko = meta.query_advice(key_off, Rotation::cur());
+            let s = meta.query_fixed(w_slot, Rotation::cur());
+            let o = meta.query_fixed(w_off, Rotation::cur());
+            let q = meta.query_selector(q_win);
+
+            let one = Expression::Constant(F::one());
+            vec![
+                q.clone() * i.clone() * (one.clone() - i.clone()),
+                q.clone() * (one - i.clone()) * b,
+                q.clone() * (ks - i.clone() * s),
+                q * (ko - i * o),
+            ]
+        });
+
+        meta.create_gate("Key windows (first)", |meta| {
+            let i = meta.query_advice(w_in, Rotation::cur());
+            let n = meta.query_advice(count, Rotation::cur());
+            let b = meta.query_advice(byte, Rotation::cur());
+            let p = meta.query_advice(packed, Rotation::cur());
+            let q = meta.query_selector(q_win_first);
+            vec![q.clone() * (n - i), q * (p - b)]
+        });
+
+        // The key's bytes come first, and each shifts the ones before it up a byte
+        meta.create_gate("Key windows (next)", |meta| {
+            let i = meta.query_advice(w_in, Rotation::cur());
+            let i_prev = meta.query_advice(w_in, Rotation::prev());
+            let n = meta.query_advice(count, Rotation::cur());
+            let n_prev = meta.query_advice(count, Rotation::prev());
+            let b = meta.query_advice(byte, Rotation::cur());
+            let p = meta.query_advice(packed, Rotation::cur());
+            let p_prev = meta.query_advice(packed, Rotation::prev());
+            let q = meta.query_selector(q_win_next);
+
+            let one = Expression::Constant(F::one());
+            vec![
+                q.clone() * i.clone() * (one - i_prev),
+                q.clone() * (n - n_prev - i),
+                q * (p - p_prev * Expression::Constant(F::from(256)) - b),
+            ]
+        });
+
+        // Past the key, and off the selectors, it is (0, 0, 0)
+        meta.lookup_any("Key window bytes", |meta| {
+            let q_win = meta.query_selector(q_win);
+            let q_byte = meta.query_selector(q_byte);
+            let k = meta.query_advice(content, Rotation::cur());
+            let one = Expression::Constant(F::one());
+            vec![
+                (q_win.clone() * meta.query_advice(key_slot, Rotation::cur()), q_byte.clone() * k.clone() * meta.query_advice(slot, Rotation::cur())),
+                (q_win.clone() * meta.query_advice(key_off, Rotation::cur()), q_byte.clone() * k.clone() * (meta.query_advice(len, Rotation::cur()) - one)),
+                (q_win * meta.query_advice(byte, Rotation::cur()), q_byte * k * meta.query_advice(raw, Rotation::cur())),
+            ]
+        });
+
+        KeyWindowsConfig { fixed, window, q_win, q_win_first, q_win_next }
+    }
+
+    // Assign the document and its top-level keys
+    fn synthesize<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, raw: &[Value<F>]) -> Result<KeyRunsAssigned<F>, Error> {
+
+        let chip = JsonChip::construct(self.json);
+        chip.load(layouter)?;
+        let json = chip.assign(layouter, raw)?;
+
+        let (ends, bytes) = self.assign_runs(layouter, &json)?;
+        let keys = self.assign_keys(layouter, ends)?;
+        let packed = match self.windows {
+            Some(windows) => Self::assign_windows(layouter, windows, &json, &keys)?,
+            None => vec![],
+        };
+
+        Ok(KeyRunsAssigned { keys, packed, bytes })
+    }
+
+    // The "Key runs" region, with the byte table if there is one. Returns (slot, start, len) at every run end, in
+    // order, and the byte rows as `KeyRunsAssigned::bytes`
+    fn assign_runs<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, json: &JsonAssigned<F>) -> Result<RunsAndBytes<F>, Error> {
+
+        layouter.assign_region(
+            || "Key runs",
+            |mut region| {
+
+                let [raw, top, cc, content, end, pos, slot, start, len] = self.runs;
+                let zero = Value::known(F::zero());
+                let one = Value::known(F::one());
+                let string_content = F::from(CharClass::StringContent as u64);
+
+                for column in [raw, top, pos, slot, start, len] {
+                    region.assign_advice(|| "start row", column, 0, || zero)?;
+                }
+                self.q_first.enable(&mut region, 0)?;
+
+                let (mut t_prev, mut n, mut s, mut l) = (zero, zero, zero, zero);
+                let mut ends = Vec::with_capacity(json.top_key.len());
+                let mut rows = Vec::with_capacity(json.top_key.len());
+                for (idx, cell) in json.top_key.iter().enumerate() {
+
+                    let offset = idx + 1;
+                    let r = json.raw[idx].copy_advice(|| "raw", &mut region, raw, offset)?;
+                    let t = cell.copy_advice(|| "top_key", &mut region, top, offset)?.value().copied();
+                    let c_cell = json.char_class[idx].copy_advice(|| "char_class", &mut region, cc, offset)?;
+                    let c = c_cell.value().copied();
+                    let p = Value::known(F::from(offset as u64));
+
+                    let begins = t * (one - t_prev);
+                    let k = t * c.map(|c| F::from((c == string_content) as u64));
+                    let e = t_prev * (one - t);
+                    n = n + begins;
+                    s = s + begins * (p - one - s);
+                    l = (one - begins) * l + k;
+
+                    region.assign_advice(|| "content", content, offset, || k)?;
+                    region.assign_advice(|| "end", end, offset, || e)?;
+                    rows.push([region.assign_advice(|| "pos", pos, offset, || p)?, r, c_cell]);
+                    region.assign_advice(|| "slot", slot, offset, || n)?;
+                    region.assign_advice(|| "start", start, offset, || s)?;
+                    region.assign_advice(|| "len", len, offset, || l)?;
+                    self.q_byte.enable(&mut region, offset)?;
+
+                    ends.push(e.zip(n).zip(s.zip(l)).map(|((e, n), (s, l))| (e == F::one()).then_some([n, s, l])));
+                    t_prev = t;
+                }
+
+                let ends = Value::<Vec<Option<[F; 3]>>>::from_iter(ends).map(|ends| ends.into_iter().flatten().collect());
+                let bytes = match self.table {
+                    Some(table) => Self::assign_table(&mut region, table, json, rows)?,
+                    None => vec![],
+                };
+                Ok((ends, bytes))
+            }
+        )
+    }
+
+    // The byte table, on the rows of the "Key runs" region. `rows` holds (pos, raw, char_class) of every byte
+    fn assign_table<F: FieldExt>(region: &mut Region<'_, F>, config: ByteTableConfig, json: &JsonAssigned<F>, rows: Vec<[AssignedCell<F, F>; 3]>) -> Result<Vec<ByteRow<F>>, Error> {
+
+        let [level, not_str, value_type, ws, next, inner, string] = config.table;
+        let values = |cells: &[AssignedCell<F, F>]| Value::<Vec<F>>::from_iter(cells.iter().map(|cell| cell.value().copied()));
+        let raw = values(&json.raw).map(|raw| raw.iter().map(to_byte).collect::<Vec<_>>());
+        let levels = values(&json.level).map(|levels| levels.iter().map(|l| l.get_lower_128() as usize).collect::<Vec<_>>());
+        let in_str = values(&json.not_str).map(|not_str| not_str.iter().map(|&ns| ns == F::zero()).collect::<Vec<_>>());
+        let table = raw.zip(in_str).zip(levels).map(|((raw, in_str), levels)| byte_table(&raw, &in_str, &levels));
+        let at = |idx: usize, j: usize| table.as_ref().map(|(rows, _)| F::from(rows[idx][j] as u64));
+
+        let zero = Value::known(F::zero());
+        for column in [level, string].into_iter().chain(config.openers) {
+            region.assign_advice(|| "start row", column, 0, || zero)?;
+        }
+        region.assign_advice(|| "start row", not_str, 0, || Value::known(F::one()))?;
+        region.assign_advice(|| "start row", next, 0, || table.as_ref().map(|(_, first)| F::from(*first as u64)))?;
+
+        let mut bytes = Vec::with_capacity(rows.len());
+        for (idx, [p, r, c]) in rows.into_iter().enumerate() {
+
+            let offset = idx + 1;
+            let l = json.level[idx].copy_advice(|| "level", region, level, offset)?;
+            let ns = json.not_str[idx].copy_advice(|| "not_str", region, not_str, offset)?;
+            let vt = json.value_type[idx].copy_advice(|| "value_type", region, value_type, offset)?;
+            let w = region.assign_advice(|| "ws", ws, offset, || at(idx, 0))?;
+            let n = region.assign_advice(|| "next", next, offset, || at(idx, 1))?;
+            let i = region.assign_advice(|| "inner", inner, offset, || at(idx, 2))?;
+            let s = region.assign_advice(|| "string", string, offset, || at(idx, 3))?;
+
+            let depth = l.value().map(|l| l.get_lower_128() as usize);
+            for (j, column) in config.depth.into_iter().enumerate() {
+                region.assign_advice(|| "depth", column, offset, || depth.map(|d| F::from((d == j) as u64)))?;
+            }
+            for (j, column) in config.openers.into_iter().enumerate() {
+                region.assign_advice(|| "opener", column, offset, || at(idx, 4 + j))?;
+            }
+
+            bytes.push([p, r, c, l, ns, vt, w, n, i, s]);
+        }
+
+        match bytes.len() {
+            0 => return Err(Error::Synthesis),
+            len => config.q_last.enable(region, len)?,
+        }
+        Ok(bytes)
+    }
+
+    // The "Top-level keys" region, one slot per entry of `ends`. Past `MAX_TOP_LEVEL_KEYS` they are left out, and the
+    // lookup from the run ends fails
+    fn assign_keys<F: FieldExt>(&self, layouter: &mut impl Layouter<F>, ends: Value<Vec<[F; 3]>>) -> Result<Vec<[AssignedCell<F, F>; 4]>, Error> {
+
+        layouter.assign_region(
+            || "Top-level keys",
+            |mut region| {
+
+                let [active, out_slot, out_start, out_len] = self.keys;
+
+                (0..MAX_TOP_LEVEL_KEYS).map(|offset| {
+
+                    let key = ends.as_ref().map(|ends| ends.get(offset).copied());
+                    let a = key.map(|key| F::from(key.is_some() as u64));
+                    let [n, s, l] = [0, 1, 2].map(|j| key.map(|key| key.map_or(F::zero(), |key| key[j])));
+
+                    self.q_key.enable(&mut region, offset)?;
+                    match offset {
+                        0 => self.q_key_first.enable(&mut region, offset)?,
+                        _ => self.q_key_next.enable(&mut region, offset)?,
+                    }
+
+                    Ok([
+                        region.assign_advice(|| "active", active, offset, || a)?,
+                        region.assign_advice(|| "slot", out_slot, offset, || n)?,
+                        region.assign_advice(|| "start", out_start, offset, || s)?,
+                        region.assign_advice(|| "len", out_len, offset, || l)?,
+                    ])
+                })
+                .collect()
+            }
+        )
+    }
+
+    // The "Key windows" region, `MAX_KEY_LEN` rows for each slot of `keys`. Returns each slot's packed window
+    fn assign_windows<F: FieldExt>(
+        layouter: &mut impl Layouter<F>,
+        windows: KeyWindowsConfig,
+        json: &JsonAssigned<F>,
+        keys: &[[AssignedCell<F, F>; 4]],
+    ) -> Result<Vec<AssignedCell<F, F>>, Error> {
+
+        layouter.assign_region(
+            || "Key windows",
+            |mut region| {
+
+                let [w_slot, w_off] = windows.fixed;
+                let [w_in, count, byte, packed, key_slot, key_off] = windows.window;
+                let bytes = Value::<Vec<F>>::from_iter(json.raw.iter().map(|cell| cell.value().copied()));
+
+                keys.iter().enumerate().map(|(j, [_, _, start, len])| {
+
+                    let at = start.value().zip(len.value()).map(|(s, l)| (s.get_lower_128() as usize, l.get_lower_128() as usize));
+                    let s = Value::known(F::from(j as u64 + 1));
+                    let mut p = Value::known(F::zero());
+                    let mut last = None;
+
+                    for off in 0..MAX_KEY_LEN {
+
+                        let offset = j * MAX_KEY_LEN + off;
+                        let o = Value::known(F::from(off as u64));
+                        let i = at.map(|(_, l)| F::from((off < l) as u64));
+                        let n = at.map(|(_, l)| F::from(l.min(off + 1) as u64));
+                        let b = at.zip(bytes.as_ref()).map(|((s, l), bytes)| if off < l { bytes[s + off] } else { F::zero() });
+                        p = p * Value::known(F::from(256)) + b;
+
+                        region.assign_fixed(|| "slot", w_slot, offset, || s)?;
+                        region.assign_fixed(|| "off", w_off, offset, || o)?;
+                        region.assign_advice(|| "in", w_in, offset, || i)?;
+                        let n_cell = region.assign_advice(|| "count", count, offset, || n)?;
+                        region.assign_advice(|| "byte", byte, offset, || b)?;
+                        let p_cell = region.assign_advice(|| "packed", packed, offset, || p)?;
+                        region.assign_advice(|| "key_slot", key_slot, offset, || i * s)?;
+                        region.assign_advice(|| "key_off", key_off, offset, || i * o)?;
+
+                        windows.q_win.enable(&mut region, offset)?;
+                        match off {
+                            0 => windows.q_win_first.enable(&mut region, offset)?,
+                            _ => windows.q_win_next.enable(&mut region, offset)?,
+                        }
+
+                        // A key longer than the window can't have all its bytes counted
+                        if off == MAX_KEY_LEN - 1 {
+                            region.constrain_equal(n_cell.cell(), len.cell())?;
+                            last = Some(p_cell);
+                        }
+                    }
+
+                    last.ok_or(Error::Synthesis)
+                })
+                .collect()
+            }
+        )
+    }
+}
+
+impl ByteTableConfig {
+
+    // A probe at `pos`, the rest of its row read off `bytes` (0 off the document). It is looked up where `on` is 1, and
+    // `on` is fixed to 1 with None. Returns its cells, in the order of `probe`
+    fn assign_probe<F: FieldExt>(&self, region: &mut Region<'_, F>, offset: usize, bytes: &[ByteRow<F>], pos: Value<F>, on: Option<Value<F>>) -> Result<[AssignedCell<F, F>; 11], Error> {
+
+        self.q_probe.enable(region, offset)?;
+        let on = match on {
+            Some(on) => region.assign_advice(|| "on", self.probe[0], offset, || on)?,
+            None => region.assign_advice_from_constant(|| "on", self.probe[0], offset, F::one())?,
+        };
+
+        let idx = pos.map(|p| (p.get_lower_128() as usize).wrapping_sub(1));
+        let mut cells = vec![on, region.assign_advice(|| "pos", self.probe[1], offset, || pos)?];
+        for j in 1..10 {
+            let column = Value::<Vec<F>>::from_iter(bytes.iter().map(|row| row[j].value().copied()));
+            let v = idx.zip(column).map(|(idx, column)| column.get(idx).copied().unwrap_or_else(F::zero));
+            cells.push(region.assign_advice(|| "probe", self.probe[j + 1], offset, || v)?);
+        }
+
+        cells.try_into().map_err(|_| Error::Synthesis)
+    }
+}
+
+impl<F: FieldExt> KeyRangesCircuit<F> {
+
+    // Fails on documents the pre-flight rejects, as `KeyValueCircuit::from_str` does, and past `MAX_TOP_LEVEL_KEYS`
+    pub fn from_str(json: &str) -> Result<Self, ParseError> {
+
+        let document = JsonCircuit::<F>::from_str(json)?;
+        preflight(json.as_bytes(), &JsonConfigParams::default())?;
+
+        Ok(Self { raw: document.raw, ranges: top_level_runs(json.as_bytes())? })
+    }
+
+    pub fn instances(&self) -> Vec<F> {
+        let ranges = self.ranges.iter().flat_map(|&(start, len)| [start, len]);
+        ranges.chain(std::iter::repeat(0)).take(2 * MAX_TOP_LEVEL_KEYS).map(|x| F::from(x as u64)).collect()
+    }
+}
+
+impl<F: FieldExt> Circuit<F> for KeyRangesCircuit<F> {
+
+    type Config = KeyRunsConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { raw: vec![Value::unknown(); self.raw.len()], ..self.clone() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        KeyRunsConfig::configure(meta, false)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+
+        let assigned = config.synthesize(&mut layouter, &self.raw)?;
+
+        for (row, [_, _, start, len]) in assigned.keys.iter().enumerate() {
+            layouter.constrain_instance(start.cell(), config.instance, 2 * row)?;
+            layouter.constrain_instance(len.cell(), config.instance, 2 * row + 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+
+// Validity plus a commitment to each top-level key slot, public in document order (see `instances`), so that a subset
+// of the keys can be opened later. Each is `key_commitment` of the slot under a salt of its own, a witness, so that a
+// commitment gives away neither a short key nor an empty slot. The keys are witnessed as in `KeyRangesCircuit`
+#[derive(Clone, Default)]
+pub struct KeyCommitmentsCircuit<F: FieldExt> {
+    pub raw: Vec<Value<F>>,
+    pub salts: Vec<Value<F>>, // one per slot, `MAX_TOP_LEVEL_KEYS` of them
+    pub commitments: Vec<F>,
+}
+
+// From `KeyCommitmentsCircuit::from_str`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommitmentError {
+    Parse(ParseError),
+    SaltCount(usize, usize), // slots and salts
+}
+
+impl From<ParseError> for CommitmentError {
+    fn from(e: ParseError) -> Self {
+        CommitmentError::Parse(e)
+    }
+}
+
+impl Display for CommitmentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommitmentError::Parse(e) => write!(f, "{} at {}", e.message(), e.index()),
+            CommitmentError::SaltCount(slots, salts) => write!(f, "{} key slots but {} salts", slots, salts),
+        }
+    }
+}
+
+// Commitment to one key slot, as `KeyCommitmentsCircuit` computes it: Poseidon over the salt, 1, the key's length and
+// `pack_key` of its contents (escapes as written), or the salt and three 0 for an empty slot, with `MAX_KEY_LEN` as
+// the domain
+pub fn key_commitment<F: FieldExt>(params: &PoseidonParams<F>, salt: F, key: Option<&[u8]>) -> F {
+    let inputs = match key {
+        Some(key) => [salt, F::one(), F::from(key.len() as u64), pack_key(key)],
+        None => [salt, F::zero(), F::zero(), F::zero()],
+    };
+    params.hash(F::from(MAX_KEY_LEN as u64), &inputs)
+}
+
+impl<F: FieldExt> KeyCommitmentsCircuit<F> {
+
+    // Fails on documents the pre-flight rejects, as `KeyValueCircuit::from_str` does, past `MAX_TOP_LEVEL_KEYS` keys or
+    // `MAX_KEY_LEN` bytes in a key, and unless there is one salt per slot
+    pub fn from_str(json: &str, salts: &[F]) -> Result<Self, CommitmentError> {
+
+        let document = JsonCircuit::<F>::from_str(json)?;
+        preflight(json.as_bytes(), &JsonConfigParams::default())?;
+
+        let ranges = top_level_windows(json.as_bytes())?;
+        if salts.len() != MAX_TOP_LEVEL_KEYS {
+            return Err(CommitmentError::SaltCount(MAX_TOP_LEVEL_KEYS, salts.len()));
+        }
+
+        let params = PoseidonParams::new();
+        let commitments = salts.iter().enumerate()
+            .map(|(slot, &salt)| key_commitment(&params, salt, ranges.get(slot).map(|&(start, len)| &json.as_bytes()[start..start + len])))
+            .collect();
+        let salts = salts.iter().map(|&salt| Value::known(salt)).collect();
+
+        Ok(Self { raw: document.raw, salts, commitments })
+    }
+
+    pub fn instances(&self) -> Vec<F> {
+        self.commitments.clone()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct KeyCommitmentsConfig {
+    runs: KeyRunsConfig,
+    poseidon: PoseidonConfig,
+}
+
+impl<F: FieldExt> Circuit<F> for KeyCommitmentsCircuit<F> {
+
+    type Config = KeyCommitmentsConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { raw: vec![Value::unknown(); self.raw.len()], salts: vec![Value::unknown(); self.salts.len()], ..self.clone() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+
+        let runs = KeyRunsConfig::configure(meta, true);
+        let poseidon = PoseidonConfig::configure(meta);
+
+        KeyCommitmentsConfig { runs, poseidon }
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+
+        if self.salts.len() != MAX_TOP_LEVEL_KEYS {
+            return Err(Error::Synthesis);
+        }
+
+        let assigned = config.runs.synthesize(&mut layouter, &self.raw)?;
+
+        // The salt is the only input not copied from the slot
+        let params = PoseidonParams::new();
+        for (row, (([active, _, _, len], packed), &salt)) in assigned.keys.iter().zip(&assigned.packed).zip(&self.salts).enumerate() {
+            let inputs = [PoseidonInput::Witness(salt), PoseidonInput::Cell(active), PoseidonInput::Cell(len), PoseidonInput::Cell(packed)];
+            let (commitment, _) = config.poseidon.hash(&mut layouter, &params, F::from(MAX_KEY_LEN as u64), &inputs)?;
+            layouter.constrain_instance(commitment.cell(), config.runs.instance, row)?;
+        }
+
+        Ok(())
+    }
+}
+
+// A log record: validity plus the chain link (previous commitment, new commitment), public in that order as
+// `verify_chain` expects. The new one is `record_commitment` of the previous one and the document, computed here from
+// the document's bytes, so a record can only continue the chain with the document it was proven on
+#[derive(Clone, Default)]
+pub struct ChainRecordCircuit<F: FieldExt> {
+    pub raw: Vec<Value<F>>,
+    pub prev: F,
+    pub next: F,
+}
+
+// Commitment after one record, as `ChainRecordCircuit` computes it: Poseidon over the previous commitment, then each
+// byte of the document as one input, with the document's length as the domain
+pub fn record_commitment<F: FieldExt>(params: &PoseidonParams<F>, prev: F, record: &[u8]) -> F {
+    let inputs: Vec<F> = [prev].into_iter().chain(record.iter().map(|&b| F::from(b as u64))).collect();
+    params.hash(F::from(record.len() as u64), &inputs)
+}
+
+impl<F: FieldExt> ChainRecordCircuit<F> {
+
+    // Fails on documents the pre-flight rejects, as `KeyValueCircuit::from_str` does
+    pub fn from_str(json: &str, prev: F) -> Result<Self, ParseError> {
+
+        let document = JsonCircuit::<F>::from_str(json)?;
+        preflight(json.as_bytes(), &JsonConfigParams::default())?;
+
+        let next = record_commitment(&PoseidonParams::new(), prev, json.as_bytes());
+        Ok(Self { raw: document.raw, prev, next })
+    }
+
+    pub fn instances(&self) -> Vec<F> {
+        vec![self.prev, self.next]
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ChainRecordConfig {
+    json: JsonConfig,
+    poseidon: PoseidonConfig,
+    instance: Column<Instance>,
+}
+
+impl<F: FieldExt> Circuit<F> for ChainRecordCircuit<F> {
+
+    type Config = ChainRecordConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { raw: vec![Value::unknown(); self.raw.len()], ..self.clone() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+
+        let json = JsonChip::configure(meta);
+        let poseidon = PoseidonConfig::configure(meta);
+        let instance = meta.instance_column();
+        meta.enable_equality(instance);
+
+        ChainRecordConfig { json, poseidon, instance }
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+
+        let chip = JsonChip::construct(config.json);
+        chip.load(&mut layouter)?;
+        let assigned = chip.assign(&mut layouter, &self.raw)?;
+
+        // The previous commitment is the only input not copied from the document
+        let inputs: Vec<PoseidonInput<F>> = [PoseidonInput::Witness(Value::known(self.prev))].into_iter()
+            .chain(assigned.raw.iter().map(PoseidonInput::Cell))
+            .collect();
+        let domain = F::from(self.raw.len() as u64);
+        let (next, input_cells) = config.poseidon.hash(&mut layouter, &PoseidonParams::new(), domain, &inputs)?;
+
+        layouter.constrain_instance(input_cells[0].cell(), config.instance, 0)?;
+        layouter.constrain_instance(next.cell(), config.instance, 1)
+    }
+}
+
+
+// Validity plus the number of keys at each level 1..=cap, deeper keys folded into the last, public in that order. One
+// accumulator per level up to `MAX_DEPTH` counts the bytes where `key` starts at that level, and a column of running
+// sums from the deepest level up folds everything from the cap on into the last count. Which cells are exposed
+// depends on the cap only, so the verifying key is the same for every document of a length
+#[derive(Clone, Default)]
+pub struct KeyCountsCircuit<F: FieldExt> {
+    pub raw: Vec<Value<F>>,
+    pub counts: Vec<usize>, // as `key_counts_per_level`, so one per level up to the cap
+}
+
+#[derive(Clone, Debug)]
+pub struct KeyCountsConfig {
+    json: JsonConfig,
+    window: [Column<Advice>; 2], // key, level
+    acc: [Column<Advice>; MAX_DEPTH], // keys started so far at each level 1..=MAX_DEPTH
+    tails: [Column<Advice>; 2], // a level's count, and the sum of the counts from that level down
+    q_first: Selector,
+    q_byte: Selector,
+    q_tail: Selector,
+    q_tail_end: Selector,
+    instance: Column<Instance>,
+}
+
+impl<F: FieldExt> KeyCountsCircuit<F> {
+
+    // `cap` is at least 1. Fails on documents the pre-flight rejects, as `KeyValueCircuit::from_str` does
+    pub fn from_str(json: &str, cap: usize) -> Result<Self, ParseError> {
+
+        let document = JsonCircuit::<F>::from_str(json)?;
+        preflight(json.as_bytes(), &JsonConfigParams::default())?;
+
+        Ok(Self { raw: document.raw, counts: key_counts_per_level(json.as_bytes(), cap) })
+    }
+
+    pub fn instances(&self) -> Vec<F> {
+        self.counts.iter().map(|&count| F::from(count as u64)).collect()
+    }
+}
+
+impl<F: FieldExt> Circuit<F> for KeyCountsCircuit<F> {
+
+    type Config = KeyCountsConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { raw: vec![Value::unknown(); self.raw.len()], ..self.clone() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+
+        let json = JsonConfig::configure_with_params(meta, JsonConfigParams { key_flag: true, ..Default::default() });
+        let window = [(); 2].map(|_| meta.advice_column());
+        let acc = [(); MAX_DEPTH].map(|_| meta.advice_column());
+        let tails = [(); 2].map(|_| meta.advice_column());
+        let [q_first, q_byte, q_tail, q_tail_end] = [(); 4].map(|_| meta.selector());
+        let instance = meta.instance_column();
+
+        let [key, level] = window;
+        let [count, tail] = tails;
+        window.into_iter().chain(acc).chain(tails).for_each(|column| meta.enable_equality(column));
+        meta.enable_equality(instance);
+
+        meta.create_gate("Key counts (start)", |meta| {
+            let q = meta.query_selector(q_first);
+            [key].into_iter().chain(acc).map(|column| q.clone() * meta.query_advice(column, Rotation::cur())).collect::<Vec<_>>()
+        });
+
+        // Where a key starts, the accumulator of its level goes up by one, and no other does. Elsewhere none does
+        meta.create_gate("Key counts", |meta| {
+            let k = meta.query_advice(key, Rotation::cur());
+            let k_prev = meta.query_advice(key, Rotation::prev());
+            let l = meta.query_advice(level, Rotation::cur());
+            let q = meta.query_selector(q_byte);
+
+            let one = Expression::Constant(F::one());
+            let begins = k * (one.clone() - k_prev);
+            let steps: Vec<Expression<F>> = acc.iter()
+                .map(|&column| meta.query_advice(column, Rotation::cur()) - meta.query_advice(column, Rotation::prev()))
+                .collect();
+            let total = steps.iter().fold(Expression::Constant(F::zero()), |sum, step| sum + step.clone());
+            let at_level = steps.iter().enumerate()
+                .fold(Expression::Constant(F::zero()), |sum, (idx, step)| sum + step.clone() * Expression::Constant(F::from(idx as u64 + 1)));
+
+            let mut constraints: Vec<Expression<F>> = steps.iter().map(|step| q.clone() * step.clone() * (one.clone() - step.clone())).collect();
+            constraints.push(q.clone() * (total - begins.clone()));
+            constraints.push(q * (at_level - begins * l));
+            constraints
+        });
+
+        // Summed from the deepest level up, after a row of 0 for the levels past `MAX_DEPTH`
+        meta.create_gate("Key count tails", |meta| {
+            let n = meta.query_advice(count, Rotation::cur());
+            let t = meta.query_advice(tail, Rotation::cur());
+            let t_next = meta.query_advice(tail, Rotation::next());
+            let q = meta.query_selector(q_tail);
+            vec![q * (t - n - t_next)]
+        });
+
+        meta.create_gate("Key count tails (end)", |meta| {
+            let n = meta.query_advice(count, Rotation::cur());
+            let t = meta.query_advice(tail, Rotation::cur());
+            let q = meta.query_selector(q_tail_end);
+            vec![q.clone() * n, q * t]
+        });
+
+        KeyCountsConfig { json, window, acc, tails, q_first, q_byte, q_tail, q_tail_end, instance }
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+
+        let cap = self.counts.len();
+        if cap == 0 {
+            return Err(Error::Synthesis);
+        }
+
+        let chip = JsonChip::construct(config.json);
+        chip.load(&mut layouter)?;
+        let assigned = chip.assign(&mut layouter, &self.raw)?;
+
+        let totals = layouter.assign_region(
+            || "Key counts",
+            |mut region| {
+
+                let [key, level] = config.window;
+                let zero = Value::known(F::zero());
+                let one = Value::known(F::one());
+
+                for column in [key].into_iter().chain(config.acc) {
+                    region.assign_advice(|| "start row", column, 0, || zero)?;
+                }
+                config.q_first.enable(&mut region, 0)?;
+
+                let mut k_prev = zero;
+                let mut n = [zero; MAX_DEPTH];
+                let mut last = vec![];
+                for (idx, cell) in assigned.key.iter().enumerate() {
+
+                    let offset = idx + 1;
+                    let k = cell.copy_advice(|| "key", &mut region, key, offset)?.value().copied();
+                    let l = assigned.level[idx].copy_advice(|| "level", &mut region, level, offset)?.value().copied();
+                    let begins = k * (one - k_prev);
+
+                    last = n.iter_mut().zip(config.acc).enumerate()
+                        .map(|(depth, (n, column))| {
+                            *n = *n + begins * l.map(|l| F::from((l == F::from(depth as u64 + 1)) as u64));
+                            region.assign_advice(|| "acc", column, offset, || *n)
+                        })
+                        .collect::<Result<Vec<_>, Error>>()?;
+                    config.q_byte.enable(&mut region, offset)?;
+                    k_prev = k;
+                }
+
+                Ok(last)
+            }
+        )?;
+
+        let tails = layouter.assign_region(
+            || "Key count tails",
+            |mut region| {
+
+                let [count, tail] = config.tails;
+                let mut t = Value::known(F::zero());
+                let mut cells = vec![];
+
+                region.assign_advice(|| "count", count, MAX_DEPTH, || t)?;
+                cells.push(region.assign_advice(|| "tail", tail, MAX_DEPTH, || t)?);
+                config.q_tail_end.enable(&mut region, MAX_DEPTH)?;
+
+                for offset in (0..MAX_DEPTH).rev() {
+                    let n = totals[offset].copy_advice(|| "count", &mut region, count, offset)?.value().copied();
+                    t = t + n;
+                    cells.push(region.assign_advice(|| "tail", tail, offset, || t)?);
+                    config.q_tail.enable(&mut region, offset)?;
+                }
+
+                cells.reverse();
+                Ok(cells)
+            }
+        )?;
+
+        // Levels before the cap on their own, then everything from it on. A cap past `MAX_DEPTH` has only 0 after it
+        for row in 0..cap {
+            let cell = match row {
+                _ if row >= MAX_DEPTH => &tails[MAX_DEPTH],
+                _ if row == cap - 1 => &tails[row],
+                _ => &totals[row],
+            };
+            layouter.constrain_instance(cell.cell(), config.instance, row)?;
+        }
+
+        Ok(())
+    }
+}
+
+
+// Validity plus a closed schema: the top-level keys are exactly the schema's entries, public as each entry's length
+// and `pack_key`, in schema order (see `instances`). The keys are witnessed as in `KeyRangesCircuit`, and a lookup
+// each way between the active slots and the entries puts every key in the schema and every entry among the keys. The
+// number of active slots is the schema's, so with distinct entries no key is repeated
+#[derive(Clone, Default)]
+pub struct SchemaCircuit<F: FieldExt> {
+    pub raw: Vec<Value<F>>,
+    pub schema: Vec<Vec<u8>>, // distinct, at most `MAX_TOP_LEVEL_KEYS` of them
+}
+
+#[derive(Clone, Debug)]
+pub struct SchemaConfig {
+    runs: KeyRunsConfig,
+    keys: [Column<Advice>; 3],    // active, length and packed window of each slot
+    entries: [Column<Advice>; 2], // length and packed bytes of each entry
+    q_key: Selector,
+    q_entry: Selector,
+}
+
+// From `SchemaCircuit::from_str`: the document does not conform, so there is nothing to prove
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SchemaError {
+    Parse(ParseError),
+    Mismatch(Vec<Vec<u8>>, Vec<Vec<u8>>), // extra keys and missing entries, as from `schema_mismatches`
+    RepeatedEntry(Vec<u8>), // the lookups see the schema as a set
+}
+
+impl From<ParseError> for SchemaError {
+    fn from(e: ParseError) -> Self {
+        SchemaError::Parse(e)
+    }
+}
+
+impl Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaError::Parse(e) => write!(f, "{} at {}", e.message(), e.index()),
+            SchemaError::Mismatch(extra, missing) => write!(f, "{} extra keys, {} missing", extra.len(), missing.len()),
+            SchemaError::RepeatedEntry(entry) => write!(f, "schema entry {:?} repeated", String::from_utf8_lossy(entry)),
+        }
+    }
+}
+
+impl<F: FieldExt> SchemaCircuit<F> {
+
+    // Each entry takes the first unmatched top-level key with its bytes, so unlike `schema_mismatches` a repeated key
+    // is extra. Fails on documents the pre-flight rejects, as `KeyValueCircuit::from_str` does, and as
+    // `KeyCommitmentsCircuit::from_str` on too many or too long keys
+    pub fn from_str(json: &str, schema: &[&[u8]]) -> Result<Self, SchemaError> {
+
+        let document = JsonCircuit::<F>::from_str(json)?;
+        preflight(json.as_bytes(), &JsonConfigParams::default())?;
+
+        if let Some(idx) = (1..schema.len()).find(|&idx| schema[..idx].contains(&schema[idx])) {
+            return Err(SchemaError::RepeatedEntry(schema[idx].to_vec()));
+        }
+
+        let bytes = json.as_bytes();
+        let mut matched = vec![false; schema.len()];
+        let mut extra = vec![];
+        for (start, len) in top_level_windows(bytes)? {
+            let key = &bytes[start..start + len];
+            match (0..schema.len()).find(|&idx| !matched[idx] && schema[idx] == key) {
+                Some(idx) => matched[idx] = true,
+                None => extra.push(key.to_vec()),
+            }
+        }
+
+        let missing: Vec<Vec<u8>> = schema.iter().zip(&matched).filter(|(_, &m)| !m).map(|(entry, _)| entry.to_vec()).collect();
+        if !extra.is_empty() || !missing.is_empty() {
+            return Err(SchemaError::Mismatch(extra, missing));
+        }
+
+        Ok(Self { raw: document.raw, schema: schema.iter().map(|entry| entry.to_vec()).collect() })
+    }
+
+    pub fn instances(&self) -> Vec<F> {
+        self.schema.iter().flat_map(|entry| [F::from(entry.len() as u64), pack_key(entry)]).collect()
+    }
+}
+
+impl<F: FieldExt> Circuit<F> for SchemaCircuit<F> {
+
+    type Config = SchemaConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { raw: vec![Value::unknown(); self.raw.len()], ..self.clone() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+
+        let runs = KeyRunsConfig::configure(meta, true);
+        let keys = [(); 3].map(|_| meta.advice_column());
+        let entries = [(); 2].map(|_| meta.advice_column());
+        let [q_key, q_entry] = [(); 2].map(|_| meta.complex_selector());
+        let constant = meta.fixed_column();
+
+        keys.into_iter().chain(entries).for_each(|column| meta.enable_equality(column));
+        meta.enable_constant(constant);
+
+        // (1, length, packed) of an active slot or an entry, and (0, 0, 0) for an inactive slot and off the selectors
+        let slot = |meta: &mut VirtualCells<'_, F>| {
+            let q = meta.query_selector(q_key);
+            let [a, l, p] = keys.map(|column| meta.query_advice(column, Rotation::cur()));
+            vec![q.clone() * a.clone(), q.clone() * a.clone() * l, q * a * p]
+        };
+        let entry = |meta: &mut VirtualCells<'_, F>| {
+            let q = meta.query_selector(q_entry);
+            let [l, p] = entries.map(|column| meta.query_advice(column, Rotation::cur()));
+            vec![q.clone(), q.clone() * l, q * p]
+        };
+
+        meta.lookup_any("Top-level key is in the schema", |meta| slot(meta).into_iter().zip(entry(meta)).collect());
+        meta.lookup_any("Schema entry is a top-level key", |meta| entry(meta).into_iter().zip(slot(meta)).collect());
+
+        SchemaConfig { runs, keys, entries, q_key, q_entry }
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+
+        if self.schema.len() > MAX_TOP_LEVEL_KEYS {
+            return Err(Error::Synthesis);
+        }
+
+        let assigned = config.runs.synthesize(&mut layouter, &self.raw)?;
+
+        layouter.assign_region(
+            || "Schema",
+            |mut region| {
+
+                let [active, len, packed] = config.keys;
+                for (offset, ([a, _, _, l], p)) in assigned.keys.iter().zip(&assigned.packed).enumerate() {
+                    let a = a.copy_advice(|| "active", &mut region, active, offset)?;
+                    l.copy_advice(|| "len", &mut region, len, offset)?;
+                    p.copy_advice(|| "packed", &mut region, packed, offset)?;
+                    config.q_key.enable(&mut region, offset)?;
+
+                    // As many active slots as entries. The schema is public, so this says nothing about the document
+                    region.constrain_constant(a.cell(), F::from((offset < self.schema.len()) as u64))?;
+                }
+
+                let [entry_len, entry_packed] = config.entries;
+                for offset in 0..self.schema.len() {
+                    region.assign_advice_from_instance(|| "entry len", config.runs.instance, 2 * offset, entry_len, offset)?;
+                    region.assign_advice_from_instance(|| "entry packed", config.runs.instance, 2 * offset + 1, entry_packed, offset)?;
+                    config.q_entry.enable(&mut region, offset)?;
+                }
+
+                Ok(())
+            }
+        )
+    }
+}
+
+
+// Validity plus top-level keys in strictly increasing byte order, as `top_level_keys_sorted`. The keys are witnessed
+// as in `KeyRangesCircuit`, and each active slot's packed window is larger than the one before: their difference
+// less 1 is split into `MAX_KEY_LEN` bytes, each looked up in a 0..=255 table. Packed windows compare as the keys do
+// (see `KeyRunsConfig`), so a key after one it starts with sorts later, and a repeated key has a difference of 0
+#[derive(Clone, Default)]
+pub struct SortedKeysCircuit<F: FieldExt> {
+    pub raw: Vec<Value<F>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SortedKeysConfig {
+    runs: KeyRunsConfig,
+    // a byte of the difference, the difference so far, and on its last row the two windows and whether the later
+    // slot is active
+    diff: [Column<Advice>; 5],
+    byte_table: TableColumn,
+    q_byte: Selector,
+    q_first: Selector,
+    q_next: Selector,
+    q_last: Selector,
+}
+
+impl<F: FieldExt> SortedKeysCircuit<F> {
+
+    // Unsorted documents still give a circuit, which then fails to prove. Fails on documents the pre-flight rejects,
+    // as `KeyValueCircuit::from_str` does, and as `KeyCommitmentsCircuit::from_str` on too many or too long keys
+    pub fn from_str(json: &str) -> Result<Self, ParseError> {
+
+        let document = JsonCircuit::<F>::from_str(json)?;
+        preflight(json.as_bytes(), &JsonConfigParams::default())?;
+        top_level_windows(json.as_bytes())?;
+
+        Ok(Self { raw: document.raw })
+    }
+}
+
+impl<F: FieldExt> Circuit<F> for SortedKeysCircuit<F> {
+
+    type Config = SortedKeysConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { raw: vec![Value::unknown(); self.raw.len()] }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+
+        let runs = KeyRunsConfig::configure(meta, true);
+        let diff = [(); 5].map(|_| meta.advice_column());
+        let byte_table = meta.lookup_table_column();
+        let q_byte = meta.complex_selector();
+        let [q_first, q_next, q_last] = [(); 3].map(|_| meta.selector());
+
+        let [byte, acc, prev, next, active] = diff;
+        [prev, next, active].map(|column| meta.enable_equality(column));
+
+        meta.create_gate("Key order (first)", |meta| {
+            let b = meta.query_advice(byte, Rotation::cur());
+            let d = meta.query_advice(acc, Rotation::cur());
+            let q = meta.query_selector(q_first);
+            vec![q * (d - b)]
+        });
+
+        meta.create_gate("Key order (next)", |meta| {
+            let b = meta.query_advice(byte, Rotation::cur());
+            let d = meta.query_advice(acc, Rotation::cur());
+            let d_prev = meta.query_advice(acc, Rotation::prev());
+            let q = meta.query_selector(q_next);
+            vec![q * (d - d_prev * Expression::Constant(F::from(256)) - b)]
+        });
+
+        meta.create_gate("Key order", |meta| {
+            let d = meta.query_advice(acc, Rotation::cur());
+            let p = meta.query_advice(prev, Rotation::cur());
+            let n = meta.query_advice(next, Rotation::cur());
+            let a = meta.query_advice(active, Rotation::cur());
+            let q = meta.query_selector(q_last);
+            vec![q * a * (n - p - Expression::Constant(F::one()) - d)]
+        });
+
+        meta.lookup("Key order byte", |meta| {
+            let q = meta.query_selector(q_byte);
+            let b = meta.query_advice(byte, Rotation::cur());
+            vec![(q * b, byte_table)]
+        });
+
+        SortedKeysConfig { runs, diff, byte_table, q_byte, q_first, q_next, q_last }
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+
+        layouter.assign_table(
+            || "Byte table",
+            |mut table| {
+                for b in 0..256 {
+                    table.assign_cell(|| format!("byte {:#x}", b), config.byte_table, b, || Value::known(F::from(b as u64)))?;
+                }
+                Ok(())
+            }
+        )?;
+
+        let assigned = config.runs.synthesize(&mut layouter, &self.raw)?;
+
+        layouter.assign_region(
+            || "Key order",
+            |mut region| {
+
+                let [byte, acc, prev, next, active] = config.diff;
+
+                for (j, pair) in assigned.packed.windows(2).enumerate() {
+
+                    // Left at 0 for an inactive slot, where nothing is checked
+                    let a = &assigned.keys[j + 1][0];
+                    let d = pair[0].value().zip(pair[1].value()).zip(a.value())
+                        .map(|((&p, &n), &a)| if a == F::one() { n - p - F::one() } else { F::zero() });
+                    // Most significant first. Out of order, d wraps and its low bytes can't add up to it
+                    let bytes = d.map(|d| {
+                        let d = d.get_lower_128();
+                        (0..MAX_KEY_LEN).rev().map(|idx| F::from((d >> (8 * idx)) as u8 as u64)).collect::<Vec<F>>()
+                    });
+
+                    let mut sum = Value::known(F::zero());
+                    for idx in 0..MAX_KEY_LEN {
+                        let offset = j * MAX_KEY_LEN + idx;
+                        let b = bytes.as_ref().map(|bytes| bytes[idx]);
+                        sum = sum * Value::known(F::from(256)) + b;
+                        region.assign_advice(|| "byte", byte, offset, || b)?;
+                        region.assign_advice(|| "acc", acc, offset, || sum)?;
+                        config.q_byte.enable(&mut region, offset)?;
+                        match idx {
+                            0 => config.q_first.enable(&mut region, offset)?,
+                            _ => config.q_next.enable(&mut region, offset)?,
+                        }
+                    }
+
+                    let offset = (j + 1) * MAX_KEY_LEN - 1;
+                    pair[0].copy_advice(|| "prev", &mut region, prev, offset)?;
+                    pair[1].copy_advice(|| "next", &mut region, next, offset)?;
+                    a.copy_advice(|| "active", &mut region, active, offset)?;
+                    config.q_last.enable(&mut region, offset)?;
+                }
+
+                Ok(())
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use halo2_proofs::{
+        arithmetic::Field, circuit::{Layouter, SimpleFloorPlanner, Value}, dev::{FailureLocation, MockProver, VerifyFailure},
+        halo2curves::bn256::Fr,
+        plonk::{Circuit, ConstraintSystem, Error},
+    };
+    use rand::rngs::OsRng;
+    use super::{JsonChip, JsonCircuit, JsonConfig, JsonConfigParams, KeyCountsCircuit, KeyRangesCircuit, KeyRunsConfig, KeyValueCircuit, KeyValueError, MAX_TOP_LEVEL_KEYS};
+    use super::{ArrayCircuit, Base64Circuit, CanonicalCommitmentCircuit, CharsetScope, CommitmentError, ElementFieldCircuit, KeyCommitmentsCircuit, LengthCircuit, MatchedPair, MergePatchCircuit, PatchSource, PathEqualityCircuit, SchemaCircuit, SubstringCircuit, SchemaError, SortedKeysCircuit, StructuralEqualityCircuit, ValidityCircuit, ValueInSetCircuit};
+    use halo2_proofs::plonk::{Advice, Column, Instance};
+    use halo2_proofs::{
+        halo2curves::bn256::{Bn256, G1Affine},
+        plonk::{create_proof, keygen_pk, keygen_vk, ProvingKey, VerifyingKey},
+        poly::{commitment::Params, kzg::{commitment::{KZGCommitmentScheme, ParamsKZG}, multiopen::ProverSHPLONK}},
+        transcript::{Blake2bWrite, Challenge255, TranscriptWriterBuffer},
+    };
+    use super::{verify_chain, ChainRecordCircuit, ChainedProof};
+    use std::fs::File;
+    use std::path::Path;
+    use crate::state_machine_chip::json_parser::ParseError;
+    use crate::circuits::poseidon::PoseidonParams;
+
+    #[test]
+    fn field_operations_test() {
+
+        let test_str = String::from("abc");
+        let char_arr = test_str.chars().collect::<Vec<char>>();
+        println!("\nFirst Output: {:?}", char_arr.clone().into_iter().map(|x| format!("0x{:x}", x as u64)).collect::<Vec<String>>());
+
+        let _arr = char_arr.into_iter().map(|x| Value::known(Fr::from(x as u64))).collect::<Vec<Value<Fr>>>();
+        println!("\nSecond Output: {:?}", _arr[0]);
+
+    }
+
+    #[test]
+    fn test_simple_json() {
+        
+        let k = 9;
+
+        let test_json = String::from("{\"a\": 1, \"b\": 2}");
+        let arr = test_json.chars().map(|x| Value::known(Fr::from(x as u64))).collect::<Vec<Value<Fr>>>();
+        let circuit = JsonCircuit { raw: arr, invalid_at: None };
+
+        MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
+    }
+
+    #[test]
+    fn test_json_escaped_chars() {
+        
+        let k = 9;
+
+        let test_json = String::from("{\"a{}\": 1, \"b\": \"\\\"\"}");
+        let arr = test_json.chars().map(|x| Value::known(Fr::from(x as u64))).collect::<Vec<Value<Fr>>>();
+        let circuit = JsonCircuit { raw: arr, invalid_at: None };
+
+        MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
+    }
+
+    #[test]
+    fn test_json_escaped_chars_2() {
+        
+        let k = 9;
+
+        let test_json = String::from("{\"a{}\": \" \\\" { \\\" { \\\" \", \"b\": \"\\\"\"}");
+        let arr = test_json.chars().map(|x| Value::known(Fr::from(x as u64))).collect::<Vec<Value<Fr>>>();
+        let circuit = JsonCircuit { raw: arr, invalid_at: None };
+
+        MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
+    }
+
+    // Two strings in a row are not a value, so the grammar rejects the second one
+    #[test]
+    fn test_json_escaped_chars_3() {
+        
+        let k = 9;
+
+        let test_json = String::from("{\"a{}\": \"1\" \"2\", \"b\": \"\\\"\"}");
+        let arr = test_json.chars().map(|x| Value::known(Fr::from(x as u64))).collect::<Vec<Value<Fr>>>();
+        let circuit = JsonCircuit { raw: arr, invalid_at: None };
+
+        assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err());
+    }
+
+    #[test]
+    fn test_surrogate_pairs() {
+
+        let k = 9;
+
+        // Pairs in keys and values, and the escapes next to the surrogates that are no surrogates
+        let json = "{\"a\":\"\\ud83d\\ude00\", \"\\uD83D\\uDE00\":\"\\u00e9\\uD7FF\\uE000\\udbff\\udfff\"}";
+        let circuit = JsonCircuit::<Fr>::from_str(json).unwrap();
+        assert_eq!(circuit.invalid_at, None);
+        MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
+
+        // A lone high surrogate, witnessed as the plain escape the grammar had before pairing: the states of
+        // `{"a":"\u0800"}` with the byte classes of `{"a":"\ud800"}`
+        let lone = b"{\"a\":\"\\ud800\"}";
+        let raw: Vec<Value<Fr>> = lone.iter().map(|&b| Value::known(Fr::from(b as u64))).collect();
+        let mut forged = super::compute_trace(b"{\"a\":\"\\u0800\"}");
+        forged.class = super::compute_trace(lone).class;
+        let circuit = ForgedTraceCircuit { raw, trace: forged };
+        let failures = MockProver::run(k, &circuit, vec![]).unwrap().verify().unwrap_err();
+        assert!(failures.iter().all(|failure| matches!(failure,
+            VerifyFailure::Lookup { name, location: FailureLocation::InRegion { offset, .. }, .. } if *name == "Grammar" && *offset == 9
+        )), "{failures:?}");
+    }
+
+    #[test]
+    fn test_from_str() {
+
+        let k = 9;
+
+        let circuit = JsonCircuit::<Fr>::from_str("{\"a\": 1, \"b\": 2}").unwrap();
+        assert_eq!(circuit.invalid_at, None);
+
+        MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
+    }
+
+    #[test]
+    fn test_literals_and_numbers() {
+
+        let k = 9;
+
+        // Literals, negative numbers and exponents, at any level
+        for json in ["{\"a\":true}", "[null]", "[false, true, null]", "{\"a\":-1}", "[1e5]", "[-0.5E-3, 2e+10, 7E2]",
+                     "{\"a\":{\"b\":[-1.25e1, null]}, \"c\":false}"] {
+            let circuit = JsonCircuit::<Fr>::from_str(json).unwrap();
+            assert_eq!(circuit.invalid_at, None, "{}", json);
+            MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
+        }
+
+        // A number needs a digit after its '-', '.' and exponent, and an exponent has one sign at most
+        let cases: [(&str, usize); 9] = [
+            ("[-]", 2),
+            ("[--1]", 2),
+            ("[-.5]", 2),
+            ("[1.e5]", 3),
+            ("[1e]", 3),
+            ("[1e+]", 4),
+            ("[1e+-1]", 4),
+            ("[1e5.0]", 4),
+            ("{\"a\":nul}", 8),
+        ];
+        for (json, idx) in cases {
+            let circuit = JsonCircuit::<Fr>::from_str(json).unwrap();
+            assert_eq!(circuit.invalid_at, Some(idx), "{}", json);
+            assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err(), "{}", json);
+        }
+
+        // The value types of the new values
+        let value_type = super::compute_trace(b"[-1,1e2,true]").value_type;
+        assert_eq!(value_type[1], super::ValueType::Number);
+        assert_eq!(value_type[4], super::ValueType::Number);
+        assert_eq!(value_type[8], super::ValueType::Literal);
+    }
+
+    #[test]
+    fn test_max_len() {
+
+        let json = "{\"a\": \"0123456789\"}";
+
+        let circuit = JsonCircuit::<Fr>::from_bytes_with_max_len(json.as_bytes(), json.len());
+        assert!(circuit.is_ok());
+
+        let circuit = JsonCircuit::<Fr>::from_bytes_with_max_len(json.as_bytes(), json.len() - 1);
+        assert_eq!(circuit.err(), Some(ParseError::TooLong(json.len() - 1)));
+
+        let huge = format!("{{\"a\": \"{}\"}}", "x".repeat(super::DEFAULT_MAX_LEN));
+        assert_eq!(JsonCircuit::<Fr>::from_str(&huge).err(), Some(ParseError::TooLong(super::DEFAULT_MAX_LEN)));
+    }
+
+    #[test]
+    fn test_top_level_key_ranges() {
+        assert_eq!(super::top_level_key_ranges(b"{\"ab\":1,\"c\":2}"), vec![(2, 2), (9, 1)]);
+        assert_eq!(super::top_level_key_ranges(b"{\"a\": {\"b\": 1}, \"c\\\"\": [\"d\", 2]}"), vec![(2, 1), (17, 3)]);
+    }
+
+    #[test]
+    fn test_top_level_value_in_set() {
+
+        let set: [&[u8]; 3] = [b"\"active\"", b"\"inactive\"", b"\"pending\""];
+        assert!(super::top_level_value_in_set(b"{\"status\":\"active\"}", b"status", &set));
+        assert!(!super::top_level_value_in_set(b"{\"status\":\"deleted\"}", b"status", &set));
+        assert!(!super::top_level_value_in_set(b"{\"state\":\"active\"}", b"status", &set));
+
+        // Whitespace, nesting and strings containing delimiters
+        let json = b"{\"a\": {\"status\": \"x\"}, \"b\" : [1, {\"c\": 2}] , \"status\" : \"pending\" }";
+        assert_eq!(super::top_level_value(json, b"a"), Some(&b"{\"status\": \"x\"}"[..]));
+        assert_eq!(super::top_level_value(json, b"b"), Some(&b"[1, {\"c\": 2}]"[..]));
+        assert!(super::top_level_value_in_set(json, b"status", &set));
+        assert_eq!(super::top_level_value(b"{\"a\":\"},\\\"\",\"b\":1}", b"a"), Some(&b"\"},\\\"\""[..]));
+    }
+
+    #[test]
+    fn test_array_len() {
+
+        assert_eq!(super::array_len(b"[1,2,3]"), Some(3));
+        assert_eq!(super::array_len(b"[]"), Some(0));
+        assert_eq!(super::array_len(b"[ ]"), Some(0));
+
+        // Commas inside nested containers and strings are not element boundaries
+        assert_eq!(super::array_len(b"[[1,2], {\"a\":1,\"b\":2}, \"x,]y\"]"), Some(3));
+        assert_eq!(super::top_level_value(b"{\"a\": [1, [2, 3]], \"b\": 4}", b"a").and_then(super::array_len), Some(2));
+
+        // Not an array, or not closed
+        assert_eq!(super::array_len(b"{\"a\":1}"), None);
+        assert_eq!(super::array_len(b"[1,2"), None);
+    }
+
+    #[test]
+    fn test_commitment_chain_break() {
+
+        let feed = [Fr::from(7), Fr::from(11), Fr::from(13), Fr::from(17)];
+        let links = [(feed[0], feed[1]), (feed[1], feed[2]), (feed[2], feed[3])];
+        assert_eq!(super::commitment_chain_break(&links, &feed), None);
+
+        // Reordered records, a dropped record and a truncated feed
+        let reordered = [links[0], links[2], links[1]];
+        assert_eq!(super::commitment_chain_break(&reordered, &feed), Some(1));
+        assert_eq!(super::commitment_chain_break(&links[..2], &feed), Some(2));
+        assert_eq!(super::commitment_chain_break(&links, &feed[..3]), Some(2));
+    }
+
+    #[test]
+    fn test_verify_chain() {
+
+        let k = 9;
+        let params = ParamsKZG::<Bn256>::setup(k, OsRng);
+        let poseidon = PoseidonParams::<Fr>::new();
+
+        // Same length records, so one verifying key. Each commitment follows from the one before and the record
+        let records = ["{\"n\":1}", "{\"n\":2}", "{\"n\":3}"];
+        let mut feed = vec![Fr::from(7)];
+        for record in records {
+            feed.push(super::record_commitment(&poseidon, *feed.last().unwrap(), record.as_bytes()));
+        }
+
+        let circuit = |record: &str, prev: Fr| ChainRecordCircuit::from_str(record, prev).unwrap();
+        let prove = |circuit: &ChainRecordCircuit<Fr>, pk: &ProvingKey<G1Affine>| {
+            let instances = circuit.instances();
+            let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+            create_proof::<
+                KZGCommitmentScheme<Bn256>,
+                ProverSHPLONK<'_, Bn256>,
+                Challenge255<G1Affine>,
+                _,
+                Blake2bWrite<Vec<u8>, G1Affine, Challenge255<_>>,
+                _,
+            >(&params, pk, &[circuit.clone()], &[&[&instances[..]]], OsRng, &mut transcript)
+            .unwrap();
+            ChainedProof { proof: transcript.finalize(), instances }
+        };
+
+        let vk = keygen_vk(&params, &circuit(records[0], feed[0])).unwrap();
+        let pk = keygen_pk(&params, vk.clone(), &circuit(records[0], feed[0])).unwrap();
+        let proofs: Vec<ChainedProof> = records.iter().enumerate().map(|(idx, record)| prove(&circuit(record, feed[idx]), &pk)).collect();
+        assert_eq!(proofs.iter().map(|record| record.instances[1]).collect::<Vec<_>>(), feed[1..]);
+
+        assert_eq!(verify_chain(&params, &vk, &proofs, &feed), Ok(()));
+
+        // Reordered, or a record whose public inputs don't match its proof
+        let reordered = [proofs[0].clone(), proofs[2].clone(), proofs[1].clone()];
+        assert_eq!(verify_chain(&params, &vk, &reordered, &feed), Err(1));
+
+        let mut forged = proofs.clone();
+        forged[1].instances[1] = Fr::from(19);
+        assert_eq!(verify_chain(&params, &vk, &forged, &[feed[0], feed[1], Fr::from(19), feed[3]]), Err(1));
+
+        // A changed document: proven honestly it commits elsewhere and breaks the chain, and it can't claim the old
+        // commitment
+        let mut changed = proofs;
+        changed[1] = prove(&circuit("{\"n\":9}", feed[1]), &pk);
+        assert_eq!(verify_chain(&params, &vk, &changed, &feed), Err(1));
+
+        let claimed = ChainRecordCircuit { next: feed[2], ..circuit("{\"n\":9}", feed[1]) };
+        assert!(MockProver::run(k, &claimed, vec![claimed.instances()]).unwrap().verify().is_err());
+        MockProver::run(k, &circuit(records[1], feed[1]), vec![claimed.instances()]).unwrap().assert_satisfied();
+    }
+
+    #[test]
+    fn test_top_level_keys_sorted() {
+        assert!(super::top_level_keys_sorted(b"{\"a\":1,\"b\":2}"));
+        assert!(!super::top_level_keys_sorted(b"{\"b\":1,\"a\":2}"));
+        assert!(!super::top_level_keys_sorted(b"{\"a\":1,\"a\":2}"));
+
+        // A prefix sorts first, and nested keys are ignored
+        assert!(super::top_level_keys_sorted(b"{\"a\":{\"z\":1,\"y\":2},\"ab\":3}"));
+    }
+
+    #[test]
+    fn test_schema_mismatches() {
+
+        let schema: [&[u8]; 3] = [b"a", b"b", b"c"];
+        assert_eq!(super::schema_mismatches(b"{\"a\":1,\"b\":2,\"c\":3}", &schema), (vec![], vec![]));
+
+        let (extra, missing) = super::schema_mismatches(b"{\"a\":1,\"d\":4}", &schema);
+        assert_eq!(extra, vec![b"d".to_vec()]);
+        assert_eq!(missing, vec![b"b".to_vec(), b"c".to_vec()]);
+
+        // Nested keys are not top-level keys
+        let (extra, missing) = super::schema_mismatches(b"{\"a\":{\"b\":1,\"c\":2}}", &schema);
+        assert!(extra.is_empty());
+        assert_eq!(missing, vec![b"b".to_vec(), b"c".to_vec()]);
+    }
+
+    #[test]
+    fn test_compute_trace() {
+
+        let trace = super::compute_trace(b"{\"a\":1}");
+        assert_eq!(trace.not_str, vec![true, false, false, true, true, true, true]);
+        assert_eq!(trace.str_escaped, vec![false; 7]);
+        assert_eq!(trace.level, vec![1, 1, 1, 1, 1, 1, 0]);
+
+        // Braces inside strings do not count, and an escaped quote does not close the string
+        let trace = super::compute_trace(b"{\"{\\\"}\":1}");
+        assert_eq!(trace.not_str, vec![true, false, false, false, false, false, true, true, true, true]);
+        assert_eq!(trace.str_escaped, vec![false, false, false, true, false, false, false, false, false, false]);
+        assert_eq!(trace.level, vec![1, 1, 1, 1, 1, 1, 1, 1, 1, 0]);
+
+        // An escaped backslash does not escape the next char
+        let trace = super::trace_from(b"\"\\\\\"", super::GrammarState::StartScalar, false);
+        assert_eq!(trace.not_str, vec![false, false, false, true]);
+        assert_eq!(trace.str_escaped, vec![false, true, false, false]);
+
+        // A close with nothing open is rejected, and the level stays at 0
+        let trace = super::compute_trace(b"}}");
+        assert_eq!(trace.level, vec![0, 0]);
+        assert_eq!(trace.any_invalid, vec![true, true]);
+
+        // Arrays move the level too, and each close matches its own container
+        let trace = super::compute_trace(b"{\"a\":[{}]}");
+        assert_eq!(trace.level, vec![1, 1, 1, 1, 1, 2, 3, 2, 1, 0]);
+        assert_eq!(trace.stack, vec![2, 2, 2, 2, 2, 5, 10, 5, 2, 1]);
+        assert_eq!(super::compute_trace(b"{\"a\":[}]}").any_invalid.iter().position(|&x| x), Some(6));
+
+        // A second document after the first latches any_invalid, whatever follows
+        let trace = super::compute_trace(b"{}{}");
+        assert_eq!(trace.any_invalid, vec![false, false, true, true]);
+        assert_eq!(super::compute_trace(b"{\"a\":1}").any_invalid, vec![false; 7]);
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn test_render_trace() {
+
+        let out = super::render_trace(b"{\"a\":{\"b\":1}}");
+        assert!(out.contains("    0   '{'        1        0      1  level 0 -> 1"));
+        assert!(out.contains("    5   '{'        1        0      2  level 1 -> 2"));
+        assert!(out.contains("   11   '}'        1        0      1  level 2 -> 1"));
+        assert!(out.contains("   12   '}'        1        0      0  level 1 -> 0"));
+        assert_eq!(out.matches("level ").count(), 4);
+        assert_eq!(out.lines().count(), 14);
+    }
+
+    #[test]
+    fn test_key_counts_per_level() {
+        assert_eq!(super::key_counts_per_level(b"{\"a\":{\"b\":1,\"c\":2}}", 3), vec![1, 2, 0]);
+        assert_eq!(super::key_counts_per_level(b"{\"a\":{\"b\":{\"c\":{\"d\":1}}}, \"e\": \"f\"}", 2), vec![2, 3]);
+        assert_eq!(super::key_counts_per_level(b"{\"a\":[{\"b\":1}, {\"c\":2}]}", 3), vec![1, 0, 2]);
+    }
+
+    // Assigns a document with a caller-supplied trace, on the default config or with `soft_validity`
+    #[derive(Clone)]
+    struct ForgedCircuit<const SOFT: bool, const PADDED: bool> {
+        raw: Vec<Value<Fr>>,
+        trace: super::Trace,
+    }
+
+    type ForgedTraceCircuit = ForgedCircuit<false, false>;
+    type SoftForgedTraceCircuit = ForgedCircuit<true, false>;
+    type PaddedForgedTraceCircuit = ForgedCircuit<false, true>;
+
+    impl<const SOFT: bool, const PADDED: bool> Circuit<Fr> for ForgedCircuit<SOFT, PADDED> {
+
+        type Config = JsonConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            unimplemented!()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            JsonConfig::configure_with_params(meta, JsonConfigParams { soft_validity: SOFT, expose_length: PADDED, ..Default::default() })
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+            let chip = JsonChip::construct(config);
+            chip.load(&mut layouter)?;
+            chip.assign_with_trace(&mut layouter, &self.raw, Value::known(self.trace.clone()))?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_char_class() {
+
+        use super::CharClass::*;
+
+        let k = 9;
+
+        let json = "{\"a\":1}";
+        let trace = super::compute_trace(json.as_bytes());
+        assert_eq!(trace.char_class, vec![Structural, Structural, StringContent, Structural, Normal, Normal, Structural]);
+
+        // Escaped quotes and braces inside strings are content
+        let trace = super::compute_trace(b"{\"{\\\"\":1}");
+        assert_eq!(trace.char_class[1..6], [Structural, StringContent, StringContent, StringContent, Structural]);
+
+        let raw = JsonCircuit::<Fr>::from_str(json).unwrap().raw;
+        let trace = super::compute_trace(json.as_bytes());
+        let circuit = ForgedTraceCircuit { raw: raw.clone(), trace: trace.clone() };
+        MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
+
+        // Each row's class is pinned by the grammar, including the first and last bytes
+        for (idx, class) in [(0, Normal), (2, Structural), (3, StringContent), (5, Structural), (6, StringContent)] {
+            let mut forged = trace.clone();
+            forged.char_class[idx] = class;
+            let circuit = ForgedTraceCircuit { raw: raw.clone(), trace: forged };
+            assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err(), "{}", idx);
+        }
+    }
+
+    #[test]
+    fn test_packed_byte_class() {
+
+        let k = 9;
+
+        // One class column and its lookup in place of a flag and an inverse column per special char, with their gates
+        let mut meta = ConstraintSystem::<Fr>::default();
+        JsonConfig::configure(&mut meta);
+        let polynomials: usize = meta.gates().iter().map(|gate| gate.polynomials().len()).sum();
+        assert_eq!(polynomials, 18);
+
+        // Every byte from 0x20 up, and '\t' '\n' '\r', has exactly one class
+        let rows = super::byte_class_rows(false, false);
+        assert_eq!(rows.len(), 1 + 0xe0 + 3);
+        assert!(rows[1..].windows(2).all(|pair| pair[0][0] < pair[1][0]));
+
+        // The class is pinned by the byte: a quote passed off as a backslash, so that the next quote is escaped
+        let json = b"{\"a\":1}";
+        let raw = JsonCircuit::<Fr>::from_bytes(json).unwrap().raw;
+        let mut forged = super::compute_trace(json);
+        forged.class[1] = super::ByteClass::Backslash;
+        let circuit = ForgedTraceCircuit { raw, trace: forged };
+        assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err());
+    }
+
+    #[test]
+    fn test_sticky_invalid() {
+
+        let k = 9;
+
+        // The first object closes at idx 6 and the stray '}' is rejected. The tail would be fine on its own
+        let json = b"{\"a\":1}}{{\"b\":2}";
+        let raw: Vec<Value<Fr>> = json.iter().map(|&b| Value::known(Fr::from(b as u64))).collect();
+        let trace = super::compute_trace(json);
+        assert_eq!(trace.any_invalid.iter().position(|&x| x), Some(7));
+
+        let circuit = ForgedTraceCircuit { raw: raw.clone(), trace: trace.clone() };
+        assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err());
+
+        // Clearing the latch, from the bad row or just at the end, is rejected too
+        let mut forged = trace.clone();
+        forged.any_invalid = vec![false; json.len()];
+        let circuit = ForgedTraceCircuit { raw: raw.clone(), trace: forged };
+        assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err());
+
+        let mut forged = trace;
+        forged.any_invalid[json.len() - 1] = false;
+        let circuit = ForgedTraceCircuit { raw, trace: forged };
+        assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err());
+    }
+
+    #[test]
+    fn test_invalid_latch() {
+
+        // Each rejected condition latches any_invalid at its byte: a close with no container open (the level would go
+        // negative), a structural char after a backslash, control chars in and outside strings, and a key with no value
+        let cases: [(&[u8], usize); 5] = [
+            (b"{\"a\":1}}", 7),
+            (b"{\"a\":\"\\{\"}", 7),
+            (b"{\"a\":\"\t\"}", 6),
+            (b"{\"a\":\x01}", 5),
+            (b"{\"a\"}", 4),
+        ];
+
+        for (json, idx) in cases {
+
+            let trace = super::compute_trace(json);
+            assert_eq!(trace.any_invalid.iter().position(|&x| x), Some(idx));
+            assert!(trace.any_invalid[idx..].iter().all(|&x| x));
+
+            // With `soft_validity`, only is_valid = 0 proves
+            let circuit = ValidityCircuit::<Fr>::from_bytes(json).unwrap();
+            assert!(!circuit.valid);
+            MockProver::run(11, &circuit, vec![vec![Fr::zero()]]).unwrap().assert_satisfied();
+            assert!(MockProver::run(11, &circuit, vec![vec![Fr::one()]]).unwrap().verify().is_err());
+
+            // Without it there is no proof, and the lookups fail at the latched byte
+            let circuit = JsonCircuit::<Fr>::from_bytes(json).unwrap();
+            assert_eq!(circuit.invalid_at, Some(idx));
+            let failures = MockProver::run(9, &circuit, vec![]).unwrap().verify().unwrap_err();
+            assert!(failures.iter().any(|failure| matches!(failure, VerifyFailure::Lookup { .. })));
+            for failure in failures {
+                if let VerifyFailure::Lookup { location: FailureLocation::InRegion { offset, .. }, .. } = failure {
+                    assert_eq!(offset, idx + 1);
+                }
+            }
+        }
+
+        // The latch is set by the grammar table alone: a valid document can't be passed off as rejected, nor a rejected
+        // one cleared, with `soft_validity` either
+        let json = b"{\"a\":1}";
+        let raw: Vec<Value<Fr>> = json.iter().map(|&b| Value::known(Fr::from(b as u64))).collect();
+        let mut forged = super::compute_trace(json);
+        forged.state[6] = super::GrammarState::Invalid;
+        forged.char_class[6] = super::CharClass::Normal;
+        forged.pop[6] = false;
+        forged.stack[6] = 2;
+        forged.level[6] = 1;
+        forged.any_invalid[6] = true;
+        let circuit = SoftForgedTraceCircuit { raw, trace: forged };
+        assert!(MockProver::run(11, &circuit, vec![]).unwrap().verify().is_err());
+
+        let json = b"{\"a\"}";
+        let raw: Vec<Value<Fr>> = json.iter().map(|&b| Value::known(Fr::from(b as u64))).collect();
+        let trace = super::compute_trace(json);
+        MockProver::run(11, &SoftForgedTraceCircuit { raw: raw.clone(), trace: trace.clone() }, vec![]).unwrap().assert_satisfied();
+        let mut forged = trace;
+        forged.any_invalid[4] = false;
+        let circuit = SoftForgedTraceCircuit { raw, trace: forged };
+        assert!(MockProver::run(11, &circuit, vec![]).unwrap().verify().is_err());
+    }
+
+    #[test]
+    fn test_escape_at_closing_quote() {
+
+        let k = 9;
+
+        // The string ends in an escaped backslash, so the quote after it closes the string
+        let json = "{\"a\":\"x\\\\\"}";
+        let trace = super::compute_trace(json.as_bytes());
+        assert_eq!(trace.not_str[9], true);
+        let raw = JsonCircuit::<Fr>::from_str(json).unwrap().raw;
+
+        let circuit = ForgedTraceCircuit { raw: raw.clone(), trace: trace.clone() };
+        MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
+
+        // Marking the closing quote as an escape is rejected
+        let mut forged = trace;
+        forged.str_escaped[9] = true;
+        let circuit = ForgedTraceCircuit { raw, trace: forged };
+        assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err());
+    }
+
+    #[test]
+    fn test_check_k() {
+
+        // The grammar's transition table alone takes k = 9
+        let params = JsonConfigParams::default();
+        assert_eq!(super::min_k_for_json(1, &params), 9);
+        assert_eq!(super::min_k_for_json(300, &params), 9);
+        assert_eq!(super::min_k_for_json(600, &params), 10);
+        assert_eq!(super::min_k_for_json(super::DEFAULT_MAX_LEN, &params), 17);
+
+        // The tables: a small charset still leaves the grammar's tables, and the UTF-8 and element count tables
+        let ascii = JsonConfigParams { string_charset: Some(&PRINTABLE_ASCII), ..params };
+        assert_eq!(super::min_k_for_json(1, &ascii), 9);
+        let keys_only = JsonConfigParams { string_charset_scope: CharsetScope::KeysOnly, key_flag: true, ..ascii };
+        assert_eq!(super::min_k_for_json(1, &keys_only), 9);
+        let utf8 = JsonConfigParams { strict_utf8: true, ..params };
+        assert_eq!(super::min_k_for_json(1, &utf8), 9);
+        assert_eq!(super::min_k_for_json(600, &utf8), 10);
+        let elems = JsonConfigParams { max_elems: Some(600), ..params };
+        assert_eq!(super::min_k_for_json(1, &elems), 10);
+
+        let json = format!("{{\"a\": \"{}\"}}", "x".repeat(300));
+        let circuit = JsonCircuit::<Fr>::from_str(&json).unwrap();
+        let err = circuit.check_k(8, &params).unwrap_err();
+        assert_eq!(err, super::CapacityError { k: 8, required_k: 9, rows: 310 });
+        assert_eq!(err.to_string(), "a 310 row document does not fit in k = 8, use k >= 9");
+
+        circuit.check_k(9, &params).unwrap();
+        MockProver::run(9, &circuit, vec![]).unwrap().assert_satisfied();
+
+        // The config's tables count, not just the document
+        let circuit = JsonCircuit::<Fr>::from_str("{\"a\": 1}").unwrap();
+        assert_eq!(circuit.check_k(8, &params).unwrap_err(), super::CapacityError { k: 8, required_k: 9, rows: 9 });
+        circuit.check_k(9, &params).unwrap();
+        assert_eq!(circuit.check_k(9, &elems).unwrap_err(), super::CapacityError { k: 9, required_k: 10, rows: 9 });
+        circuit.check_k(10, &elems).unwrap();
+
+        // A rejected document still takes all its rows
+        let circuit = JsonCircuit::<Fr>::from_str(&format!("{{\"a\"}}{}", " ".repeat(300))).unwrap();
+        assert!(circuit.check_k(8, &params).is_err());
+    }
+
+    #[test]
+    fn test_empty_input() {
+
+        assert_eq!(JsonCircuit::<Fr>::from_str("").err(), Some(ParseError::Empty));
+        assert_eq!(JsonCircuit::<Fr>::from_str("   ").err(), Some(ParseError::Empty));
+
+        // A form feed is not JSON whitespace: the circuit is built and fails at it
+        let circuit = JsonCircuit::<Fr>::from_str("\x0c").unwrap();
+        assert_eq!(circuit.invalid_at, Some(0));
+        assert_ne!(super::preflight(b"\x0c", &JsonConfigParams::default()), Err(ParseError::Empty));
+
+        // Built by hand, bypassing the constructors
+        let circuit = JsonCircuit::<Fr> { raw: vec![], invalid_at: None };
+        assert!(MockProver::run(9, &circuit, vec![]).is_err());
+    }
+
+    #[test]
+    fn test_preflight_failure_row() {
+
+        let k = 9;
+
+        // Missing colon: the grammar rejects the `1`
+        let circuit = JsonCircuit::<Fr>::from_str("{\"a\" 1}").unwrap();
+        assert_eq!(circuit.invalid_at, Some(5));
+
+        // The grammar lookup fails at the `1`, one row below its index for the start row, and the end gate at the end
+        let failures = MockProver::run(k, &circuit, vec![]).unwrap().verify().unwrap_err();
+        assert!(!failures.is_empty());
+        for failure in failures {
+            match failure {
+                VerifyFailure::Lookup { location: FailureLocation::InRegion { offset, .. }, .. } => assert_eq!(offset, 6),
+                VerifyFailure::ConstraintNotSatisfied { location: FailureLocation::InRegion { offset, .. }, .. } => assert_eq!(offset, 7),
+                _ => panic!("Unexpected failure {:?}", failure),
+            }
+        }
+    }
+
+    #[test]
+    fn test_preflight_reject_row() {
+
+        let k = 9;
+
+        // Both end on a '}' that closes the document: a key with no value, and a trailing comma
+        for (json, idx) in [("{\"a\"}", 4), ("{\"a\":1,}", 7)] {
+
+            let circuit = JsonCircuit::<Fr>::from_str(json).unwrap();
+            assert_eq!(circuit.invalid_at, Some(idx));
+
+            let failures = MockProver::run(k, &circuit, vec![]).unwrap().verify().unwrap_err();
+            assert!(failures.iter().any(|failure| matches!(failure,
+                VerifyFailure::Lookup { location: FailureLocation::InRegion { offset, .. }, .. } if *offset == idx + 1
+            )), "{}", json);
+
+            // Whatever `invalid_at` says: a witness that closes the object there as if nothing was wrong
+            let raw = JsonCircuit::<Fr>::from_str(json).unwrap().raw;
+            let mut forged = super::compute_trace(json.as_bytes());
+            forged.state[idx] = super::GrammarState::AfterValue;
+            forged.char_class[idx] = super::CharClass::Structural;
+            forged.pop[idx] = true;
+            forged.stack[idx] = 1;
+            forged.level[idx] = 0;
+            forged.any_invalid[idx] = false;
+            let circuit = ForgedTraceCircuit { raw, trace: forged };
+            assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn test_preflight_after_document() {
+
+        let k = 9;
+
+        // Text after the top level closes, and a \u escape with bad digits
+        for (json, idx) in [("{\"a\":1}}", 7), ("{\"a\":1} 2", 8), ("{\"a\":\"\\uZZZZ\"}", 8)] {
+            let circuit = JsonCircuit::<Fr>::from_str(json).unwrap();
+            assert_eq!(circuit.invalid_at, Some(idx), "{}", json);
+            assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err(), "{}", json);
+        }
+
+        // An unpaired surrogate fails where the pair breaks, and the pre-flight reports it at its backslash
+        for (json, idx) in [("{\"a\":\"\\ud800\"}", 12), ("{\"a\":\"\\udc00\"}", 9), ("{\"\\uDBFF\\u0041\":1}", 10)] {
+            let circuit = JsonCircuit::<Fr>::from_str(json).unwrap();
+            assert_eq!(circuit.invalid_at, Some(idx), "{}", json);
+            assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err(), "{}", json);
+        }
+        assert_eq!(super::preflight(b"{\"a\":\"\\ud800\"}", &JsonConfigParams::default()), Err(ParseError::InvalidEscape(6)));
+
+        // Cut short is not a rejected byte, the end gate catches it
+        let circuit = JsonCircuit::<Fr>::from_str("{\"a\":1").unwrap();
+        assert_eq!(circuit.invalid_at, None);
+        assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err());
+
+        // The same errors as parse errors, with what a config rejects
+        let params = JsonConfigParams::default();
+        assert_eq!(super::preflight(b"{\"a\":1}}", &params), Err(ParseError::TrailingChars(7)));
+        assert_eq!(super::preflight(b"{\"a\":1,}", &params), Err(ParseError::UnexpectedChar(7)));
+        assert_eq!(super::preflight(b"{\"a\":1", &params), Err(ParseError::UnexpectedEnd(6)));
+        assert_eq!(super::preflight(b"[[[[[[[1]]]]]]]", &params), Err(ParseError::TooDeep(6)));
+        assert_eq!(super::preflight(b"{\"a\":[1,{}]}", &params), Ok(()));
+    }
+
+    #[test]
+    fn test_preflight_long_number() {
+
+        let k = 9;
+
+        // A 90-digit number is refused at its 77th digit rather than wrapping once accumulated
+        let json = format!("{{\"a\":{}}}", "9".repeat(90));
+        assert_eq!(JsonCircuit::<Fr>::from_str(&json).err(), Some(ParseError::NumberTooLong(5 + super::MAX_NUMBER_DIGITS)));
+
+        // Up to the limit is fine, and so is a long run of digits in a string
+        for json in [format!("{{\"a\":{}}}", "9".repeat(super::MAX_NUMBER_DIGITS)), format!("{{\"a\":\"{}\"}}", "9".repeat(90))] {
+            let circuit = JsonCircuit::<Fr>::from_str(&json).unwrap();
+            assert_eq!(circuit.invalid_at, None);
+            MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
+        }
+
+        // Built by hand past the constructor, the digit run lookup rejects the 77th digit
+        let raw: Vec<Value<Fr>> = json.bytes().map(|b| Value::known(Fr::from(b as u64))).collect();
+        let circuit = JsonCircuit::<Fr> { raw: raw.clone(), invalid_at: None };
+        let failures = MockProver::run(k, &circuit, vec![]).unwrap().verify().unwrap_err();
+        assert!(failures.iter().all(|failure| matches!(failure,
+            VerifyFailure::Lookup { location: FailureLocation::InRegion { offset, .. }, .. } if *offset > 5 + super::MAX_NUMBER_DIGITS
+        )), "{failures:?}");
+
+        // and a run restarted partway, to keep under the bound, breaks the count
+        let mut forged = super::compute_trace(json.as_bytes());
+        forged.digit_run[5 + 50..].iter_mut().for_each(|run| *run = run.saturating_sub(50));
+        let circuit = ForgedTraceCircuit { raw, trace: forged };
+        assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err());
+    }
+
+    #[test]
+    fn test_control_char_in_string() {
+
+        let k = 9;
+
+        // A literal tab inside a string is rejected
+        let circuit = JsonCircuit::<Fr>::from_str("{\"a\": \"x\ty\"}").unwrap();
+        assert_eq!(circuit.invalid_at, Some(8));
+        assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err());
+
+        // The escape sequence \t is fine
+        let circuit = JsonCircuit::<Fr>::from_str("{\"a\": \"x\\ty\"}").unwrap();
+        MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
+    }
+
+    #[test]
+    fn test_equality_columns() {
+
+        let mut meta = ConstraintSystem::<Fr>::default();
+        JsonConfig::configure(&mut meta);
+        assert_eq!(meta.permutation().get_columns().len(), 5);
+
+        let mut meta = ConstraintSystem::<Fr>::default();
+        JsonConfig::configure_with_params(&mut meta, JsonConfigParams { equality_on_all_columns: true, ..Default::default() });
+        assert_eq!(meta.permutation().get_columns().len(), 15);
+
+        // Still proves with equality off on the unused columns
+        let circuit = JsonCircuit::<Fr>::from_str("{\"a\": {\"b\": \"c\"}}").unwrap();
+        MockProver::run(9, &circuit, vec![]).unwrap().assert_satisfied();
+    }
+
+    // The chip restricted to printable ASCII inside strings
+    #[derive(Clone, Default)]
+    struct AsciiCircuit {
+        raw: Vec<Value<Fr>>,
+    }
+
+    const PRINTABLE_ASCII: [u8; 95] = {
+        let mut charset = [0; 95];
+        let mut idx = 0;
+        while idx < 95 {
+            charset[idx] = 0x20 + idx as u8;
+            idx += 1;
+        }
+        charset
+    };
+
+    impl Circuit<Fr> for AsciiCircuit {
+
+        type Config = JsonConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            JsonConfig::configure_with_params(meta, JsonConfigParams { string_charset: Some(&PRINTABLE_ASCII), ..Default::default() })
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+            let chip = JsonChip::construct(config);
+            chip.load(&mut layouter)?;
+            chip.assign(&mut layouter, &self.raw)?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_string_charset() {
+
+        let k = 9;
+        let circuit = |json: &[u8]| AsciiCircuit { raw: JsonCircuit::<Fr>::from_bytes(json).unwrap().raw };
+
+        MockProver::run(k, &circuit(b"{\"a b\": \"x~y\", \"c\": {\"d\": 1}}"), vec![]).unwrap().assert_satisfied();
+
+        // 'é' is outside the set, in a value or in a key. The default config accepts both
+        for json in ["{\"a\": \"caf\u{e9}\"}", "{\"caf\u{e9}\": 1}"] {
+            assert!(MockProver::run(k, &circuit(json.as_bytes()), vec![]).unwrap().verify().is_err(), "{}", json);
+            MockProver::run(k, &JsonCircuit::<Fr>::from_str(json).unwrap(), vec![]).unwrap().assert_satisfied();
+        }
+    }
+
+    // As `AsciiCircuit`, with the charset on the keys only or on the values only
+    #[derive(Clone, Default)]
+    struct ScopedAsciiCircuit<const KEYS_ONLY: bool> {
+        raw: Vec<Value<Fr>>,
+    }
+
+    impl<const KEYS_ONLY: bool> Circuit<Fr> for ScopedAsciiCircuit<KEYS_ONLY> {
+
+        type Config = JsonConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            let string_charset_scope = if KEYS_ONLY { CharsetScope::KeysOnly } else { CharsetScope::ValuesOnly };
+            JsonConfig::configure_with_params(meta, JsonConfigParams { string_charset: Some(&PRINTABLE_ASCII), string_charset_scope, key_flag: true, ..Default::default() })
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+            let chip = JsonChip::construct(config);
+            chip.load(&mut layouter)?;
+            chip.assign(&mut layouter, &self.raw)?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_string_charset_scope() {
+
+        let k = 9;
+        let raw = |json: &str| JsonCircuit::<Fr>::from_str(json).unwrap().raw;
+        let in_key = "{\"caf\u{e9}\": \"x\", \"b\": {\"c\": 1}}";
+        let in_value = "{\"a\": {\"b\": \"caf\u{e9}\"}}";
+
+        // Only the strings in scope are checked
+        MockProver::run(k, &ScopedAsciiCircuit::<false> { raw: raw(in_key) }, vec![]).unwrap().assert_satisfied();
+        assert!(MockProver::run(k, &ScopedAsciiCircuit::<false> { raw: raw(in_value) }, vec![]).unwrap().verify().is_err());
+
+        MockProver::run(k, &ScopedAsciiCircuit::<true> { raw: raw(in_value) }, vec![]).unwrap().assert_satisfied();
+        assert!(MockProver::run(k, &ScopedAsciiCircuit::<true> { raw: raw(in_key) }, vec![]).unwrap().verify().is_err());
+
+        // Control chars stay out of the other strings
+        let raw = raw("{\"a\u{1}\": 1}");
+        assert!(MockProver::run(k, &ScopedAsciiCircuit::<false> { raw }, vec![]).unwrap().verify().is_err());
+    }
+
+    // The chip configured for top-level scalars
+    #[derive(Clone, Default)]
+    struct ScalarCircuit {
+        raw: Vec<Value<Fr>>,
+    }
+
+    impl ScalarCircuit {
+        fn from_str(json: &str) -> Self {
+            Self { raw: json.bytes().map(|b| Value::known(Fr::from(b as u64))).collect() }
+        }
+    }
+
+    impl Circuit<Fr> for ScalarCircuit {
+
+        type Config = JsonConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            JsonConfig::configure_with_params(meta, JsonConfigParams { top_level_scalar: true, ..Default::default() })
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+            let chip = JsonChip::construct(config);
+            chip.load(&mut layouter)?;
+            chip.assign(&mut layouter, &self.raw)?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_top_level_scalar() {
+
+        let k = 9;
+
+        for json in ["42", "7", "\"a{b\\\"c\"", "true", "false", "null", "-1", "1e5", "-2.5E-3"] {
+            MockProver::run(k, &ScalarCircuit::from_str(json), vec![]).unwrap().assert_satisfied();
+        }
+
+        // Containers, strings left open, bare words, a second value and a cut-off literal are rejected in scalar mode,
+        // a lone '{' on the first byte too
+        for json in ["{\"a\":1}", "\"abc", "\"", "1}", "abc", "{", "1 2", "tru", "true false", "-", "1e"] {
+            assert!(MockProver::run(k, &ScalarCircuit::from_str(json), vec![]).unwrap().verify().is_err(), "{}", json);
+        }
+
+        // The pre-flight follows the mode
+        let scalar = JsonConfigParams { top_level_scalar: true, ..Default::default() };
+        assert_eq!(super::preflight(b"42", &scalar), Ok(()));
+        assert_eq!(super::preflight(b"\"a\"", &scalar), Ok(()));
+        assert_eq!(super::preflight(b"abc", &scalar), Err(ParseError::UnexpectedChar(0)));
+        assert_eq!(super::preflight(b"{", &scalar), Err(ParseError::UnexpectedChar(0)));
+        assert_eq!(super::preflight(b"1 2", &scalar), Err(ParseError::TrailingChars(2)));
+        assert_eq!(super::preflight(b"tru", &scalar), Err(ParseError::UnexpectedEnd(3)));
+        assert_eq!(super::preflight(b"null", &scalar), Ok(()));
+        assert_eq!(super::preflight(b"-1e5", &scalar), Ok(()));
+
+        // A literal is read the same at the top level of a scalar and inside containers at any depth
+        for json in ["[true]", "{\"a\":[{\"b\":null}]}", "[[[false]]]"] {
+            assert_eq!(super::preflight(json.as_bytes(), &JsonConfigParams::default()), Ok(()), "{}", json);
+        }
+        assert_eq!(super::preflight(b"[tru]", &JsonConfigParams::default()), Err(ParseError::UnexpectedChar(4)));
+        assert_eq!(super::preflight(b"[[truee]]", &JsonConfigParams::default()), Err(ParseError::UnexpectedChar(6)));
+        assert_eq!(super::preflight(b"42", &JsonConfigParams::default()), Err(ParseError::UnexpectedChar(0)));
+
+        // The default mode still requires an object
+        let raw = ScalarCircuit::from_str("42").raw;
+        let circuit = JsonCircuit::<Fr> { raw, invalid_at: None };
+        assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err());
+    }
+
+    // The chip configured with the strict UTF-8 check
+    #[derive(Clone, Default)]
+    struct Utf8Circuit {
+        raw: Vec<Value<Fr>>,
+    }
+
+    impl Circuit<Fr> for Utf8Circuit {
+
+        type Config = JsonConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            JsonConfig::configure_with_params(meta, JsonConfigParams { strict_utf8: true, ..Default::default() })
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+            let chip = JsonChip::construct(config);
+            chip.load(&mut layouter)?;
+            chip.assign(&mut layouter, &self.raw)?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_strict_utf8() {
+
+        let k = 9;
+        let circuit = |bytes: &[u8]| Utf8Circuit { raw: JsonCircuit::<Fr>::from_bytes(bytes).unwrap().raw };
+
+        // 2, 3 and 4 byte characters, in keys and values
+        for json in ["{\"a\": \"é\"}", "{\"€\": \"x𝄞y\"}", "{\"a\": {\"b\": \"\\\"ü\"}}"] {
+            MockProver::run(k, &circuit(json.as_bytes()), vec![]).unwrap().assert_satisfied();
+        }
+
+        // Bad continuation byte, sequence cut off by the closing quote, stray continuation byte, surrogate, overlong
+        let invalid: [&[u8]; 5] = [b"{\"a\": \"\xc3\x28\"}", b"{\"a\": \"\xc3\"}", b"{\"a\": \"\x80\"}", b"{\"a\": \"\xed\xa0\x80\"}", b"{\"a\": \"\xc0\xaf\"}"];
+        for json in invalid {
+            assert!(MockProver::run(k, &circuit(json), vec![]).unwrap().verify().is_err(), "{:?}", json);
+        }
+
+        // Off by default
+        let circuit = JsonCircuit::<Fr>::from_bytes(invalid[0]).unwrap();
+        MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
+    }
+
+    // The chip with at most `MAX` members / elements per container
+    #[derive(Clone, Default)]
+    struct MaxElemsCircuit<const MAX: usize> {
+        raw: Vec<Value<Fr>>,
+    }
+
+    impl<const MAX: usize> Circuit<Fr> for MaxElemsCircuit<MAX> {
+
+        type Config = JsonConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            JsonConfig::configure_with_params(meta, JsonConfigParams { max_elems: Some(MAX), ..Default::default() })
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+            let chip = JsonChip::construct(config);
+            chip.load(&mut layouter)?;
+            chip.assign(&mut layouter, &self.raw)?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_max_elems() {
+
+        let k = 9;
+        let raw = |json: &str| JsonCircuit::<Fr>::from_str(json).unwrap().raw;
+        let params = |max_elems| JsonConfigParams { max_elems: Some(max_elems), ..Default::default() };
+
+        // Five members: over a limit of 4, at the fifth value
+        let object = "{\"a\": 1, \"b\": 2, \"c\": 3, \"d\": 4, \"e\": 5}";
+        MockProver::run(k, &MaxElemsCircuit::<5> { raw: raw(object) }, vec![]).unwrap().assert_satisfied();
+        assert!(MockProver::run(k, &MaxElemsCircuit::<4> { raw: raw(object) }, vec![]).unwrap().verify().is_err());
+        assert_eq!(super::preflight(object.as_bytes(), &params(4)), Err(ParseError::TooManyElements(38)));
+
+        // Each container counts separately, and a nested container is one element of its parent
+        let nested = "{\"a\": [1, 2, 3], \"b\": [[1, 2], [3]]}";
+        MockProver::run(k, &MaxElemsCircuit::<3> { raw: raw(nested) }, vec![]).unwrap().assert_satisfied();
+        assert!(MockProver::run(k, &MaxElemsCircuit::<2> { raw: raw(nested) }, vec![]).unwrap().verify().is_err());
+        assert_eq!(super::preflight(nested.as_bytes(), &params(2)), Err(ParseError::TooManyElements(13)));
+
+        // A closed container's count does not carry over to the next one at its level
+        let siblings = "[[1, 2], [3, 4], {\"a\": [5, 6]}]";
+        MockProver::run(k, &MaxElemsCircuit::<3> { raw: raw(siblings) }, vec![]).unwrap().assert_satisfied();
+        assert!(super::preflight(siblings.as_bytes(), &params(3)).is_ok());
+
+        MockProver::run(k, &MaxElemsCircuit::<0> { raw: raw("[]") }, vec![]).unwrap().assert_satisfied();
+        assert!(MockProver::run(k, &MaxElemsCircuit::<0> { raw: raw("[1]") }, vec![]).unwrap().verify().is_err());
+    }
+
+    // A trivial outer circuit that embeds the chip and exposes the document's first byte
+    #[derive(Clone, Default)]
+    struct OuterCircuit {
+        raw: Vec<Value<Fr>>,
+    }
+
+    impl Circuit<Fr> for OuterCircuit {
+
+        type Config = (JsonConfig, Column<Instance>);
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            let instance = meta.instance_column();
+            meta.enable_equality(instance);
+            (JsonChip::configure(meta), instance)
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+
+            let chip = JsonChip::construct(config.0);
+            chip.load(&mut layouter)?;
+            let assigned = chip.assign(&mut layouter, &self.raw)?;
+
+            layouter.constrain_instance(assigned.raw[0].cell(), config.1, 0)
+        }
+    }
+
+    #[test]
+    fn test_embedded_chip() {
+
+        let k = 9;
+
+        let raw = JsonCircuit::<Fr>::from_str("{\"a\": {\"b\": 1}}").unwrap().raw;
+        let circuit = OuterCircuit { raw };
+
+        MockProver::run(k, &circuit, vec![vec![Fr::from(0x7b)]]).unwrap().assert_satisfied();
+        assert!(MockProver::run(k, &circuit, vec![vec![Fr::from(0x7d)]]).unwrap().verify().is_err());
+    }
+
+    #[test]
+    fn test_key_value_circuit() {
+
+        let k = 9;
+
+        let circuit = KeyValueCircuit::<Fr>::from_str("{\"a\":1}", "a", "1").unwrap();
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+
+        let circuit = KeyValueCircuit::<Fr>::from_str("{\"a\":1}", "a", "2").unwrap();
+        assert!(MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().verify().is_err());
+
+        // Later keys, longer values, and string values with their quotes
+        let json = "{\"x\":{\"a\":0},\"ab\":\"c,d\",\"a\":123}";
+        for (key, value) in [("ab", "\"c,d\""), ("a", "123"), ("x", "{\"a\":0}")] {
+            let circuit = KeyValueCircuit::<Fr>::from_str(json, key, value).unwrap();
+            MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+        }
+
+        // A value prefix is not the value
+        let circuit = KeyValueCircuit::<Fr>::from_str(json, "a", "12").unwrap();
+        assert!(MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().verify().is_err());
+
+        // Nor is a container value cut at a ',' or '}' inside it, which is at a deeper level
+        let circuit = KeyValueCircuit::<Fr>::from_str(json, "x", "{\"a\":0").unwrap();
+        assert!(MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().verify().is_err());
+        let circuit = KeyValueCircuit::<Fr>::from_str("{\"a\":[1,2]}", "a", "[1").unwrap();
+        assert!(MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().verify().is_err());
+        let circuit = KeyValueCircuit::<Fr>::from_str("{\"a\":[1,2]}", "a", "[1,2]").unwrap();
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+
+        // Whitespace around the ':' and after the value, but only whitespace
+        for json in ["{\"a\": 1}", "{ \"a\" :\t1 ,\"b\":2}", "{\"b\":2,\"a\"\n:\r\n1\n}"] {
+            let circuit = KeyValueCircuit::<Fr>::from_str(json, "a", "1").unwrap();
+            MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+        }
+
+        // Where the pair sits is a witness: a key moved elsewhere in a document of the same length has the same VK, and
+        // a witness pointing elsewhere fails
+        let [first, last] = ["{\"a\":1,\"bc\":2}", "{\"bc\":2,\"a\":1}"].map(|json| KeyValueCircuit::<Fr>::from_str(json, "a", "1").unwrap());
+        assert_ne!(first.key_start, last.key_start);
+        let params = ParamsKZG::<Bn256>::setup(k, OsRng);
+        let [vk, other]: [VerifyingKey<G1Affine>; 2] = [&first, &last].map(|circuit| keygen_vk(&params, circuit).unwrap());
+        assert_eq!(vk.transcript_repr(), other.transcript_repr());
+        for circuit in [&first, &last] {
+            MockProver::run(k, circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+        }
+        let forged = KeyValueCircuit { key_start: last.key_start, ..first };
+        assert!(MockProver::run(k, &forged, vec![forged.instances()]).unwrap().verify().is_err());
+
+        // No such top-level key: nothing to prove
+        assert_eq!(KeyValueCircuit::<Fr>::from_str(json, "b", "1").err(), Some(KeyValueError::MissingKey));
+
+        // The pair is there, but the document around it is rejected: a trailing comma, a stray '}'
+        assert_eq!(KeyValueCircuit::<Fr>::from_str("{\"a\":1,}", "a", "1").err(), Some(KeyValueError::Parse(ParseError::UnexpectedChar(7))));
+        assert_eq!(KeyValueCircuit::<Fr>::from_str("{\"a\":1}}", "a", "1").err(), Some(KeyValueError::Parse(ParseError::TrailingChars(7))));
+    }
+
+    #[test]
+    fn test_array_circuit() {
+
+        let k = 9;
+        let number = Some(super::ValueType::Number);
+
+        // Length 3 and 0, any type, and a typed array
+        for (json, len) in [("[1,2,3]", 3), ("[]", 0), (" [ ]", 0)] {
+            let circuit = ArrayCircuit::<Fr>::from_str(json, None, None).unwrap();
+            assert_eq!(circuit.len, len);
+            MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+        }
+        let circuit = ArrayCircuit::<Fr>::from_str("[1,2,3]", None, number).unwrap();
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+
+        // Any other length, and a mixed array claimed all-number
+        let mut forged = ArrayCircuit::<Fr>::from_str("[1,2,3]", None, None).unwrap();
+        for len in [2, 4] {
+            forged.len = len;
+            assert!(MockProver::run(k, &forged, vec![forged.instances()]).unwrap().verify().is_err());
+        }
+        let circuit = ArrayCircuit::<Fr>::from_str("[1,\"x\"]", None, number).unwrap();
+        assert!(MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().verify().is_err());
+        let circuit = ArrayCircuit::<Fr>::from_str("[1,\"x\"]", None, None).unwrap();
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+
+        // A key's array: only its own elements count, nested ones and those of later arrays don't
+        let json = "{\"a\":[1, [2,3], {\"b\":[4]}],\"c\":[5,6]}";
+        let circuit = ArrayCircuit::<Fr>::from_str(json, Some("a"), None).unwrap();
+        assert_eq!((circuit.len, circuit.close), (3, 25));
+        assert_eq!(circuit.instances()[2..], [Fr::from(b'a' as u64)]);
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+        let mut forged = circuit.clone();
+        forged.len = 5;
+        assert!(MockProver::run(k, &forged, vec![forged.instances()]).unwrap().verify().is_err());
+
+        // Nor does another ']': a nested array's, or the last array's
+        for close in [13, 23, json.len() - 2] {
+            let mut forged = circuit.clone();
+            forged.close = close;
+            assert!(MockProver::run(k, &forged, vec![forged.instances()]).unwrap().verify().is_err(), "{}", close);
+        }
+
+        // The public key is the one found: "c"'s array, of length 2, does not prove as "a"'s
+        let mut forged = circuit;
+        forged.key_start = json.find("\"c\"").map(|idx| idx + 1);
+        forged.len = 2;
+        assert!(MockProver::run(k, &forged, vec![forged.instances()]).unwrap().verify().is_err());
+
+        // The document's own array is at level 1, not a nested one
+        let mut forged = ArrayCircuit::<Fr>::from_str("[[1,2],3]", None, None).unwrap();
+        forged.open = 1;
+        assert!(MockProver::run(k, &forged, vec![forged.instances()]).unwrap().verify().is_err());
+        forged.len = 2;
+        assert!(MockProver::run(k, &forged, vec![forged.instances()]).unwrap().verify().is_err());
+
+        // The array's position is a witness
+        let first = ArrayCircuit::<Fr>::from_str("{\"a\":[1,2],\"b\":0}", Some("a"), number).unwrap();
+        let last = ArrayCircuit::<Fr>::from_str("{\"b\":0,\"a\":[1,2]}", Some("a"), number).unwrap();
+        assert_ne!(first.open, last.open);
+        for circuit in [&first, &last] {
+            MockProver::run(k, circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+        }
+        let params = ParamsKZG::<Bn256>::setup(k, OsRng);
+        let [vk, other]: [VerifyingKey<G1Affine>; 2] = [&first, &last].map(|circuit| keygen_vk(&params, circuit).unwrap());
+        assert_eq!(vk.transcript_repr(), other.transcript_repr());
+
+        assert_eq!(ArrayCircuit::<Fr>::from_str(json, Some("b"), None).err(), Some(KeyValueError::MissingKey));
+        assert_eq!(ArrayCircuit::<Fr>::from_str("{\"a\":1}", Some("a"), None).err(), Some(KeyValueError::NotAnArray));
+    }
+
+    #[test]
+    fn test_element_field_circuit() {
+
+        let k = 9;
+        let json = "[{\"id\":1},{\"id\":2}]";
+
+        // The index, then the key and value, are public
+        let circuit = ElementFieldCircuit::<Fr>::from_str(json, 1, "id", "2").unwrap();
+        assert_eq!(circuit.instances(), [1, b'i', b'd', b'2'].map(|b| Fr::from(b as u64)).to_vec());
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+        let circuit = ElementFieldCircuit::<Fr>::from_str(json, 0, "id", "1").unwrap();
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+
+        // Another index or value for the same element
+        let mut forged = ElementFieldCircuit::<Fr>::from_str(json, 1, "id", "2").unwrap();
+        forged.index = 0;
+        assert!(MockProver::run(k, &forged, vec![forged.instances()]).unwrap().verify().is_err());
+        let circuit = ElementFieldCircuit::<Fr>::from_str(json, 1, "id", "1").unwrap();
+        assert!(MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().verify().is_err());
+
+        // Elements of any type and nested objects count once each, and keys of nested objects are not the element's
+        let json = "[ [1,{\"id\":0}], \"x\", {\"a\":{\"id\":3},\"id\" : 4 } ]";
+        let circuit = ElementFieldCircuit::<Fr>::from_str(json, 2, "id", "4").unwrap();
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+        let mut forged = ElementFieldCircuit::<Fr>::from_str("[{\"a\":{\"id\":3},\"id\":4}]", 0, "id", "4").unwrap();
+        forged.key_start = Some(8);
+        forged.value = b"3".to_vec();
+        assert!(MockProver::run(k, &forged, vec![forged.instances()]).unwrap().verify().is_err());
+
+        // A nested object is no element: the one in the first element, claimed at index 0
+        let mut forged = circuit;
+        forged.index = 0;
+        forged.element = json.find("{\"id\":0").unwrap();
+        forged.key_start = Some(forged.element + 2);
+        forged.value = b"0".to_vec();
+        assert!(MockProver::run(k, &forged, vec![forged.instances()]).unwrap().verify().is_err());
+
+        // The element's position is a witness: index 0 and index 1 of two arrays of one length have the same VK
+        let first = ElementFieldCircuit::<Fr>::from_str("[{\"id\":2},{\"id\":1}]", 0, "id", "2").unwrap();
+        let last = ElementFieldCircuit::<Fr>::from_str("[{\"id\":1},{\"id\":2}]", 1, "id", "2").unwrap();
+        assert_ne!(first.element, last.element);
+        MockProver::run(k, &first, vec![first.instances()]).unwrap().assert_satisfied();
+        let params = ParamsKZG::<Bn256>::setup(k, OsRng);
+        let [vk, other]: [VerifyingKey<G1Affine>; 2] = [&first, &last].map(|circuit| keygen_vk(&params, circuit).unwrap());
+        assert_eq!(vk.transcript_repr(), other.transcript_repr());
+
+        // Out of bounds, not an object with the key, not an array
+        assert_eq!(ElementFieldCircuit::<Fr>::from_str("[{\"id\":1},{\"id\":2}]", 2, "id", "3").err(), Some(KeyValueError::NoSuchElement));
+        assert_eq!(ElementFieldCircuit::<Fr>::from_str("[]", 0, "id", "1").err(), Some(KeyValueError::NoSuchElement));
+        assert_eq!(ElementFieldCircuit::<Fr>::from_str("[{\"id\":1},2]", 1, "id", "2").err(), Some(KeyValueError::MissingKey));
+        assert_eq!(ElementFieldCircuit::<Fr>::from_str("{\"id\":1}", 0, "id", "1").err(), Some(KeyValueError::NotAnArray));
+    }
+
+    #[test]
+    fn test_merge_patch_circuit() {
+
+        let k = 9;
+        let no_instances = vec![vec![]; 3]; // one column per document, all witnesses
+
+        // "b" is deleted, "a" kept and "c" added
+        let circuit = MergePatchCircuit::<Fr>::from_str("{\"a\":1,\"b\":2}", "{\"b\":null,\"c\":3}", "{\"a\":1,\"c\":3}").unwrap();
+        assert_eq!(circuit.sources, [PatchSource::Base(0), PatchSource::Patch(1)]);
+        assert_eq!(circuit.replaced, [None, Some(0)]);
+        MockProver::run(k, &circuit, no_instances.clone()).unwrap().assert_satisfied();
+
+        // Keys in any order and replaced values, with whitespace
+        let circuit = MergePatchCircuit::<Fr>::from_str("{\"a\":1,\"b\":[2]}", "{ \"a\" : \"x\" }", "{\"b\":[2], \"a\":\"x\"}").unwrap();
+        MockProver::run(k, &circuit, no_instances.clone()).unwrap().assert_satisfied();
+        let circuit = MergePatchCircuit::<Fr>::from_str("{\"a\":1}", "{}", "{\"a\":1}").unwrap();
+        MockProver::run(k, &circuit, no_instances.clone()).unwrap().assert_satisfied();
+
+        // Another value, a deleted key kept as null, a replaced key with the base's value, a deleted key still there
+        for (base, patch, result) in [
+            ("{\"a\":1,\"b\":2}", "{\"b\":null,\"c\":3}", "{\"a\":1,\"c\":4}"),
+            ("{\"a\":1,\"b\":2}", "{\"b\":null,\"c\":3}", "{\"a\":1,\"b\":null,\"c\":3}"),
+            ("{\"a\":1,\"b\":2}", "{\"b\":3}", "{\"a\":1,\"b\":2}"),
+        ] {
+            let circuit = MergePatchCircuit::<Fr>::from_str(base, patch, result).unwrap();
+            assert!(MockProver::run(k, &circuit, no_instances.clone()).unwrap().verify().is_err(), "{}", result);
+        }
+
+        // The deleted key kept from the base, as though the patch had not got it
+        let mut forged = MergePatchCircuit::<Fr>::from_str("{\"a\":1,\"b\":2}", "{\"b\":null,\"c\":3}", "{\"a\":1,\"c\":3}").unwrap();
+        forged.sources[0] = PatchSource::Base(1);
+        forged.replaced = vec![Some(0), None];
+        forged.raw[2] = JsonCircuit::<Fr>::from_str("{\"b\":2,\"c\":3}").unwrap().raw;
+        assert!(MockProver::run(k, &forged, no_instances.clone()).unwrap().verify().is_err());
+
+        // Unlike `KeyValueCircuit`'s, a key count is baked in: an extra key in the result is no copy of anything
+        let mut forged = MergePatchCircuit::<Fr>::from_str("{\"a\":1}", "{}", "{\"a\":1}").unwrap();
+        forged.raw[2] = JsonCircuit::<Fr>::from_str("{\"a\":1,\"z\":0}").unwrap().raw;
+        assert!(MockProver::run(k, &forged, no_instances).unwrap().verify().is_err());
+
+        // A key from nowhere or a kept key missing, documents that are not objects, and an object patch value
+        assert_eq!(MergePatchCircuit::<Fr>::from_str("{\"a\":1}", "{}", "{\"a\":1,\"z\":0}").err(), Some(KeyValueError::MissingKey));
+        assert_eq!(MergePatchCircuit::<Fr>::from_str("{\"a\":1}", "{\"b\":2}", "{\"b\":2}").err(), Some(KeyValueError::MissingKey));
+        assert_eq!(MergePatchCircuit::<Fr>::from_str("[1]", "{}", "[1]").err(), Some(KeyValueError::NotAnObject));
+        assert_eq!(MergePatchCircuit::<Fr>::from_str("{\"a\":{\"x\":1}}", "{\"a\":{\"y\":2}}", "{\"a\":{\"y\":2}}").err(), Some(KeyValueError::NestedPatch));
+    }
+
+    #[test]
+    fn test_structural_equality_circuit() {
+
+        let k = 9;
+        let no_instances = vec![vec![]; 2]; // one column per document, both witnesses
+
+        // Nested keys in another order, then other values under the same keys
+        let json = "{\"a\":{\"x\":1,\"y\":2}}";
+        let circuit = StructuralEqualityCircuit::<Fr>::from_str(json, "{\"a\":{\"y\":2,\"x\":1}}").unwrap();
+        assert_eq!(circuit.pairs[0], MatchedPair { key_len: 1, parent: None, key_starts: [2, 2], value_len: None });
+        assert_eq!(circuit.pairs[1], MatchedPair { key_len: 1, parent: Some(0), key_starts: [7, 13], value_len: Some(1) });
+        MockProver::run(k, &circuit, no_instances.clone()).unwrap().assert_satisfied();
+        let circuit = StructuralEqualityCircuit::<Fr>::from_str(json, "{\"a\":{\"x\":2,\"y\":1}}").unwrap();
+        assert!(MockProver::run(k, &circuit, no_instances.clone()).unwrap().verify().is_err());
+
+        // Whitespace between tokens, ':' in strings, arrays and empty objects
+        let circuit = StructuralEqualityCircuit::<Fr>::from_str(
+            "{\"b\":{\"c\":\"x:y\",\"d\":{}},\"a\":[1,\"2\"]}",
+            "{ \"a\" : [1,\"2\"], \"b\": { \"d\":{ }, \"c\":\"x:y\" } }",
+        ).unwrap();
+        MockProver::run(k, &circuit, no_instances.clone()).unwrap().assert_satisfied();
+
+        // Arrays keep their order, and a key left over in the second document, nested or not, is no match
+        for second in ["{\"a\":[\"2\",1],\"b\":{\"c\":\"x:y\",\"d\":{}}}", "{\"a\":[1,\"2\"],\"b\":{\"c\":\"x:y\",\"d\":{\"e\":0}}}", "{\"a\":[1,\"2\"],\"b\":{\"c\":\"x:y\",\"d\":{}},\"e\":0}"] {
+            let circuit = StructuralEqualityCircuit::<Fr>::from_str("{\"b\":{\"c\":\"x:y\",\"d\":{}},\"a\":[1,\"2\"]}", second).unwrap();
+            assert!(MockProver::run(k, &circuit, no_instances.clone()).unwrap().verify().is_err(), "{}", second);
+        }
+
+        // A key matched in the wrong object: "x" of "b" for the one of "a"
+        let mut forged = StructuralEqualityCircuit::<Fr>::from_str("{\"a\":{\"x\":1},\"b\":{\"x\":1}}", "{\"a\":{\"x\":1},\"b\":{\"x\":1}}").unwrap();
+        MockProver::run(k, &forged, no_instances.clone()).unwrap().assert_satisfied();
+        forged.pairs[1].key_starts[1] = forged.pairs[3].key_starts[1];
+        assert!(MockProver::run(k, &forged, no_instances.clone()).unwrap().verify().is_err());
+
+        // The positions are witnesses: the second document in either order gives the same VK
+        let first = StructuralEqualityCircuit::<Fr>::from_str(json, json).unwrap();
+        let last = StructuralEqualityCircuit::<Fr>::from_str(json, "{\"a\":{\"y\":2,\"x\":1}}").unwrap();
+        MockProver::run(k, &first, no_instances).unwrap().assert_satisfied();
+        let params = ParamsKZG::<Bn256>::setup(k, OsRng);
+        let [vk, other]: [VerifyingKey<G1Affine>; 2] = [&first, &last].map(|circuit| keygen_vk(&params, circuit).unwrap());
+        assert_eq!(vk.transcript_repr(), other.transcript_repr());
+
+        // Keys the second document lacks, documents that are not objects, and objects in arrays
+        assert_eq!(StructuralEqualityCircuit::<Fr>::from_str(json, "{\"a\":{\"x\":1},\"y\":2}").err(), Some(KeyValueError::MissingKey));
+        assert_eq!(StructuralEqualityCircuit::<Fr>::from_str("[1]", "[1]").err(), Some(KeyValueError::NotAnObject));
+        assert_eq!(StructuralEqualityCircuit::<Fr>::from_str("{\"a\":[{\"x\":1}]}", "{\"a\":[{\"x\":1}]}").err(), Some(KeyValueError::ObjectInArray));
+    }
+
+    #[test]
+    fn test_canonical_commitment_circuit() {
+
+        let k = 11;
+        let params = PoseidonParams::<Fr>::new();
+        // The commitment is on the canonical form's column; the document's has nothing public
+
+        // Two encodings of one value commit alike, to the value's commitment
+        let value = serde_json::json!({"a": 1, "b": {"c": "x", "d": [1, 2]}});
+        let circuit = CanonicalCommitmentCircuit::<Fr>::from_str("{\"b\":{\"d\":[1,2],\"c\":\"x\"},\"a\":1}").unwrap();
+        let other = CanonicalCommitmentCircuit::<Fr>::from_str("{ \"a\" : 1, \"b\" : { \"c\":\"x\", \"d\":[1,2] } }").unwrap();
+        assert_eq!(circuit.instances(), vec![super::value_commitment(&params, &value)]);
+        assert_eq!(circuit.instances(), other.instances());
+        MockProver::run(k, &circuit, vec![circuit.instances(), vec![]]).unwrap().assert_satisfied();
+        MockProver::run(k, &other, vec![other.instances(), vec![]]).unwrap().assert_satisfied();
+
+        // Another value
+        let another = CanonicalCommitmentCircuit::<Fr>::from_str("{\"a\":2,\"b\":{\"c\":\"x\",\"d\":[1,2]}}").unwrap();
+        assert_ne!(another.instances(), circuit.instances());
+        assert!(MockProver::run(k, &another, vec![circuit.instances(), vec![]]).unwrap().verify().is_err());
+
+        // The canonical form is fixed: not with the keys of an object out of order, nor with whitespace
+        let sorted = "{\"a\":1,\"b\":{\"c\":\"x\",\"d\":[1,2]}}";
+        for form in ["{\"b\":{\"c\":\"x\",\"d\":[1,2]},\"a\":1}", "{\"a\":1,\"b\":{\"d\":[1,2],\"c\":\"x\"}}", "{\"a\":1,\"b\":{\"c\":\"x\", \"d\":[1,2]}}"] {
+            let mut forged = CanonicalCommitmentCircuit::<Fr>::from_str(sorted).unwrap();
+            let equal = StructuralEqualityCircuit::<Fr>::from_str(form, sorted).unwrap();
+            forged.raw = equal.raw;
+            forged.pairs = equal.pairs;
+            forged.commitment = super::canonical_commitment(&params, form.as_bytes());
+            assert!(MockProver::run(k, &forged, vec![forged.instances(), vec![]]).unwrap().verify().is_err(), "{}", form);
+        }
+
+        // Arrays are compared as written, so one with whitespace has no canonical form to match
+        let circuit = CanonicalCommitmentCircuit::<Fr>::from_str("{\"d\":[1, 2]}").unwrap();
+        assert!(MockProver::run(k, &circuit, vec![circuit.instances(), vec![]]).unwrap().verify().is_err());
+    }
+
+    #[test]
+    fn test_value_in_set_circuit() {
+
+        let k = 9;
+        let set: [&[u8]; 3] = [b"\"active\"", b"\"inactive\"", b"\"pending\""];
+
+        let circuit = ValueInSetCircuit::<Fr>::from_str("{\"status\":\"active\",\"n\":1}", "status", &set).unwrap();
+        assert_eq!(circuit.chosen, Some(0));
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+
+        // Another entry's bit, or another set
+        let mut forged = circuit.clone();
+        forged.chosen = Some(1);
+        assert!(MockProver::run(k, &forged, vec![forged.instances()]).unwrap().verify().is_err());
+        let mut other = circuit;
+        other.set = vec![b"\"actives\"".to_vec(), b"\"pending\"".to_vec()];
+        assert!(MockProver::run(k, &other, vec![other.instances()]).unwrap().verify().is_err());
+
+        // Not in the set: no bit is set, and setting one of the same length anyway doesn't match the bytes
+        let mut circuit = ValueInSetCircuit::<Fr>::from_str("{\"status\":\"deleted\"}", "status", &set).unwrap();
+        assert_eq!(circuit.chosen, None);
+        assert!(MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().verify().is_err());
+        circuit.chosen = Some(2);
+        assert!(MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().verify().is_err());
+
+        assert_eq!(ValueInSetCircuit::<Fr>::from_str("{\"state\":\"active\"}", "status", &set).err(), Some(KeyValueError::MissingKey));
+
+        // The value's length is only witnessed: one layout proves values of either length, so its VK is the same
+        let short = ValueInSetCircuit::<Fr>::from_str("{\"status\":\"active\",\"n\":12}", "status", &set).unwrap();
+        let long = ValueInSetCircuit::<Fr>::from_str("{\"status\":\"pending\",\"n\":1}", "status", &set).unwrap();
+        assert_eq!((short.raw.len(), short.key_start), (long.raw.len(), long.key_start));
+        assert_ne!(short.value_len, long.value_len);
+        let swapped = ValueInSetCircuit { raw: long.raw.clone(), value_len: long.value_len, chosen: long.chosen, ..short.clone() };
+        MockProver::run(k, &short, vec![short.instances()]).unwrap().assert_satisfied();
+        MockProver::run(k, &swapped, vec![swapped.instances()]).unwrap().assert_satisfied();
+
+        // Nor is the key's position, and the window may run past the end of the document once the value has ended
+        let last = ValueInSetCircuit::<Fr>::from_str("{\"n\":12,\"status\":\"active\"}", "status", &set).unwrap();
+        assert_eq!(short.raw.len(), last.raw.len());
+        assert_ne!(short.key_start, last.key_start);
+        MockProver::run(k, &last, vec![last.instances()]).unwrap().assert_satisfied();
+        let params = ParamsKZG::<Bn256>::setup(k, OsRng);
+        let [vk, other]: [VerifyingKey<G1Affine>; 2] = [&short, &last].map(|circuit| keygen_vk(&params, circuit).unwrap());
+        assert_eq!(vk.transcript_repr(), other.transcript_repr());
+
+        // The value ends at a ',' or '}' outside strings: "inactive" cut short to "inactiv" does not end there, and
+        // a nested value cut at its inner '}' is not at level 0
+        let mut forged = ValueInSetCircuit::<Fr>::from_str("{\"status\":\"inactive\"}", "status", &[b"\"inactiv"]).unwrap();
+        forged.value_len = 8;
+        forged.chosen = Some(0);
+        assert!(MockProver::run(k, &forged, vec![forged.instances()]).unwrap().verify().is_err());
+        let mut forged = ValueInSetCircuit::<Fr>::from_str("{\"s\":{\"a\":0},\"b\":1}", "s", &[b"{\"a\":0"]).unwrap();
+        forged.value_len = 6;
+        forged.chosen = Some(0);
+        assert!(MockProver::run(k, &forged, vec![forged.instances()]).unwrap().verify().is_err());
+        let honest = ValueInSetCircuit::<Fr>::from_str("{\"s\":{\"a\":0},\"b\":1}", "s", &[b"{\"a\":0}"]).unwrap();
+        MockProver::run(k, &honest, vec![honest.instances()]).unwrap().assert_satisfied();
+
+        // Whitespace around the ':', and after the value, where it ends the value
+        for json in ["{\"status\" : \"active\" }", "{\"status\":1 ,\"n\":1}", "{\"status\":\t[1, 2]\n}"] {
+            let circuit = ValueInSetCircuit::<Fr>::from_str(json, "status", &[b"1", b"\"active\"", b"[1, 2]"]).unwrap();
+            assert!(circuit.chosen.is_some());
+            MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+        }
+    }
+
+    #[test]
+    fn test_key_value_non_empty() {
+
+        let k = 9;
+
+        // Only the key is public, and the value's length is not baked in
+        let name = KeyValueCircuit::<Fr>::non_empty_from_str("{\"name\":\"x\"}  ", "name").unwrap();
+        assert_eq!(name.instances(), b"name".iter().map(|&b| Fr::from(b as u64)).collect::<Vec<_>>());
+        MockProver::run(k, &name, vec![name.instances()]).unwrap().assert_satisfied();
+        let longer = KeyValueCircuit::<Fr>::non_empty_from_str("{\"name\":\"xyz\"}", "name").unwrap();
+        MockProver::run(k, &longer, vec![longer.instances()]).unwrap().assert_satisfied();
+
+        let params = ParamsKZG::<Bn256>::setup(k, OsRng);
+        let [vk, other]: [VerifyingKey<G1Affine>; 2] = [&name, &longer].map(|circuit| keygen_vk(&params, circuit).unwrap());
+        assert_eq!(vk.transcript_repr(), other.transcript_repr());
+
+        // Nor is the key's position
+        let first = KeyValueCircuit::<Fr>::non_empty_from_str("{\"name\":\"x\",\"i\":1}", "name").unwrap();
+        let last = KeyValueCircuit::<Fr>::non_empty_from_str("{\"i\":1,\"name\":\"x\"}", "name").unwrap();
+        assert_ne!(first.key_start, last.key_start);
+        let [vk, other]: [VerifyingKey<G1Affine>; 2] = [&first, &last].map(|circuit| keygen_vk(&params, circuit).unwrap());
+        assert_eq!(vk.transcript_repr(), other.transcript_repr());
+        for circuit in [&first, &last] {
+            MockProver::run(k, circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+        }
+
+        // Both prove the pair without the flag, but an empty string and a number are not non-empty strings
+        for (json, value) in [("{\"name\":\"\"}", "\"\""), ("{\"name\":12}", "12")] {
+            let circuit = KeyValueCircuit::<Fr>::from_str(json, "name", value).unwrap();
+            MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+
+            let circuit = KeyValueCircuit::<Fr>::non_empty_from_str(json, "name").unwrap();
+            assert!(MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().verify().is_err(), "{}", json);
+        }
+
+        // A missing key has no circuit from the constructor, and the circuit for "name" does not prove another key
+        assert_eq!(KeyValueCircuit::<Fr>::non_empty_from_str("{\"age\":1}", "name").err(), Some(KeyValueError::MissingKey));
+        let other = KeyValueCircuit::<Fr> { raw: JsonCircuit::<Fr>::from_str("{\"nome\":\"x\"}  ").unwrap().raw, ..name };
+        assert!(MockProver::run(k, &other, vec![other.instances()]).unwrap().verify().is_err());
+    }
+
+    #[test]
+    fn test_path_equality() {
+
+        let k = 9;
+        let paths: [&[&str]; 2] = [&["a", "id"], &["b", "ref"]];
+
+        // Only the keys are public, first path then second
+        let circuit = PathEqualityCircuit::<Fr>::from_str("{\"a\":{\"id\":\"x\"},\"b\":{\"ref\":\"x\"}}", paths).unwrap();
+        assert_eq!(circuit.instances(), b"aidbref".iter().map(|&b| Fr::from(b as u64)).collect::<Vec<_>>());
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+
+        // Whitespace, other keys around the steps, and a top-level path against a nested one
+        let json = "{\"b\": {\"x\":{\"ref\":1},\"ref\" : [1, {}] },\"a\":{\"id\":[1, {}]}}";
+        let circuit = PathEqualityCircuit::<Fr>::from_str(json, paths).unwrap();
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+        let circuit = PathEqualityCircuit::<Fr>::from_str("{\"c\":2,\"d\":{\"e\":2}}", [&["c"], &["d", "e"]]).unwrap();
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+
+        // Different values, of the same length or not
+        for json in ["{\"a\":{\"id\":\"x\"},\"b\":{\"ref\":\"y\"}}", "{\"a\":{\"id\":\"x\"},\"b\":{\"ref\":\"xy\"}}", "{\"a\":{\"id\":\"xy\"},\"b\":{\"ref\":\"x\"}}"] {
+            let circuit = PathEqualityCircuit::<Fr>::from_str(json, paths).unwrap();
+            assert!(MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().verify().is_err(), "{}", json);
+        }
+
+        // A key outside the object it is claimed in does not prove the path
+        let json = "{\"a\":{\"x\":1},\"id\":\"x\",\"b\":{\"ref\":\"x\"}}";
+        let mut circuit = PathEqualityCircuit::<Fr>::from_str("{\"a\":{\"x\":1,\"id\":\"x\"},\"b\":{\"ref\":\"x\"}}", paths).unwrap();
+        circuit.raw = JsonCircuit::<Fr>::from_str(json).unwrap().raw;
+        assert!(MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().verify().is_err());
+
+        // Nor does a key in a sibling object at the same level: "id" is in "c", not in "a"
+        let json = "{\"a\":{\"q\":1},\"c\":{\"id\":\"x\"},\"b\":{\"ref\":\"x\"}}";
+        let mut circuit = PathEqualityCircuit::<Fr>::from_str(json, [&["c", "id"], &["b", "ref"]]).unwrap();
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+        circuit.paths[0][0] = b"a".to_vec();
+        circuit.key_starts[0][0] = 2;
+        assert!(MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().verify().is_err());
+
+        // The positions are witnesses: the paths in the other order give the same VK
+        let first = PathEqualityCircuit::<Fr>::from_str("{\"a\":{\"id\":\"x\"},\"b\":{\"ref\":\"x\"}}", paths).unwrap();
+        let last = PathEqualityCircuit::<Fr>::from_str("{\"b\":{\"ref\":\"x\"},\"a\":{\"id\":\"x\"}}", paths).unwrap();
+        assert_ne!(first.key_starts, last.key_starts);
+        MockProver::run(k, &last, vec![last.instances()]).unwrap().assert_satisfied();
+        let params = ParamsKZG::<Bn256>::setup(k, OsRng);
+        let [vk, other]: [VerifyingKey<G1Affine>; 2] = [&first, &last].map(|circuit| keygen_vk(&params, circuit).unwrap());
+        assert_eq!(vk.transcript_repr(), other.transcript_repr());
+
+        // Both paths must exist, through objects
+        assert_eq!(PathEqualityCircuit::<Fr>::from_str("{\"a\":{\"id\":1}}", paths).err(), Some(KeyValueError::MissingKey));
+        assert_eq!(PathEqualityCircuit::<Fr>::from_str("{\"a\":[{\"id\":1}],\"b\":{\"ref\":1}}", paths).err(), Some(KeyValueError::MissingKey));
+        assert_eq!(PathEqualityCircuit::<Fr>::from_str("{\"a\":1,\"id\":1,\"b\":{\"ref\":1}}", paths).err(), Some(KeyValueError::MissingKey));
+    }
+
+    #[test]
+    fn test_substring_circuit() {
+
+        let k = 9;
+
+        // The key, then the substring, are public
+        let circuit = SubstringCircuit::<Fr>::from_str("{\"log\":\"got ERROR here\"}", "log", "ERROR").unwrap();
+        assert_eq!(circuit.offset, 4);
+        assert_eq!(circuit.instances(), b"logERROR".iter().map(|&b| Fr::from(b as u64)).collect::<Vec<_>>());
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+        assert_eq!(super::top_level_value_substring(b"{\"log\":\"ERROR\"}", b"log", b"ERROR"), Some(0));
+
+        // Not in the value: too long for the document, or there but another substring
+        let circuit = SubstringCircuit::<Fr>::from_str("{\"log\":\"ok\"}", "log", "ERROR").unwrap();
+        assert!(MockProver::run(k, &circuit, vec![circuit.instances()]).map_or(true, |prover| prover.verify().is_err()));
+        let circuit = SubstringCircuit::<Fr>::from_str("{\"log\":\"ok, no errors\"}", "log", "ERROR").unwrap();
+        assert!(MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().verify().is_err());
+
+        // Only inside the key's value: not in another key's value, nor in a non-string value
+        let mut circuit = SubstringCircuit::<Fr>::from_str("{\"log\":\"ok\",\"x\":\"ERROR\"}", "log", "ERROR").unwrap();
+        assert_eq!(super::top_level_value_substring(b"{\"log\":\"ok\",\"x\":\"ERROR\"}", b"log", b"ERROR"), None);
+        circuit.offset = 9;
+        assert!(MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().verify().is_err());
+        let mut circuit = SubstringCircuit::<Fr>::from_str("{\"log\":[\"ERROR\"]}", "log", "ERROR").unwrap();
+        circuit.offset = 1;
+        assert!(MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().verify().is_err());
+
+        assert_eq!(SubstringCircuit::<Fr>::from_str("{\"msg\":\"ERROR\"}", "log", "ERROR").err(), Some(KeyValueError::MissingKey));
+
+        // Neither the key's position nor the substring's offset is baked in
+        let first = SubstringCircuit::<Fr>::from_str("{\"log\":\"xERROR\",\"a\":1}", "log", "ERROR").unwrap();
+        let last = SubstringCircuit::<Fr>::from_str("{\"a\":1,\"log\":\"ERRORx\"}", "log", "ERROR").unwrap();
+        assert_ne!((first.key_start, first.offset), (last.key_start, last.offset));
+        for circuit in [&first, &last] {
+            MockProver::run(k, circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+        }
+        let params = ParamsKZG::<Bn256>::setup(k, OsRng);
+        let [vk, other]: [VerifyingKey<G1Affine>; 2] = [&first, &last].map(|circuit| keygen_vk(&params, circuit).unwrap());
+        assert_eq!(vk.transcript_repr(), other.transcript_repr());
+    }
+
+    // Assigns a document with a caller-supplied trace, with the key flags on
+    #[derive(Clone)]
+    struct ForgedKeysCircuit {
+        raw: Vec<Value<Fr>>,
+        trace: super::Trace,
+    }
+
+    impl Circuit<Fr> for ForgedKeysCircuit {
+
+        type Config = JsonConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            unimplemented!()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            JsonConfig::configure_with_params(meta, JsonConfigParams { key_flag: true, ..Default::default() })
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+            let chip = JsonChip::construct(config);
+            chip.load(&mut layouter)?;
+            chip.assign_with_trace(&mut layouter, &self.raw, Value::known(self.trace.clone()))?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_key_flags() {
+
+        let k = 9;
+
+        let trace = super::compute_trace(b"{\"ab\":1,\"c\":2}");
+        assert_eq!((0..trace.key.len()).filter(|&idx| trace.key[idx]).collect::<Vec<_>>(), vec![2, 3, 4, 9, 10]);
+        assert_eq!(trace.top_key, trace.key);
+        assert_eq!(trace.key_count.last(), Some(&2));
+
+        // Whitespace before the ':', a ':' and a brace inside a key, an empty key, nested keys and strings in an array
+        let json = b"{\"a\" : {\"b:{\" :\"c\", \"\": 1}, \"d\": [\"e\", 1]}";
+        let trace = super::compute_trace(json);
+        assert_eq!(trace.key.iter().filter(|&&k| k).count(), 11);
+        assert_eq!(trace.top_key.iter().filter(|&&k| k).count(), 5);
+        assert_eq!(trace.key_count.last(), Some(&4));
+
+        let raw = JsonCircuit::<Fr>::from_bytes(json).unwrap().raw;
+        let circuit = ForgedKeysCircuit { raw: raw.clone(), trace: trace.clone() };
+        MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
+
+        // Flags changed by hand, with top_key and the count kept in line
+        let forge = |flags: &[(usize, bool)]| {
+            let mut forged = trace.clone();
+            for &(idx, flag) in flags {
+                forged.key[idx] = flag;
+            }
+            let mut count = 0;
+            for idx in 0..json.len() {
+                count += (forged.key[idx] && (idx == 0 || !forged.key[idx - 1])) as u64;
+                forged.top_key[idx] = forged.key[idx] && forged.level[idx] == 1;
+                forged.key_count[idx] = count;
+            }
+            ForgedKeysCircuit { raw: raw.clone(), trace: forged }
+        };
+
+        // The value "c" (idx 16) as a key, the key "d" (idx 29, 30) as a value, a key cut short before its ':'
+        for flags in [vec![(16, true)], vec![(29, false), (30, false)], vec![(4, false)]] {
+            assert!(MockProver::run(k, &forge(&flags), vec![]).unwrap().verify().is_err(), "{:?}", flags);
+        }
+
+        // A wrong count
+        let mut forged = trace.clone();
+        *forged.key_count.last_mut().unwrap() = 3;
+        let circuit = ForgedKeysCircuit { raw, trace: forged };
+        assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err());
+    }
+
+    #[test]
+    fn test_key_ranges_circuit() {
+
+        let k = 9;
+        let padded = |ranges: &[u64]| ranges.iter().copied().chain(std::iter::repeat(0)).take(2 * MAX_TOP_LEVEL_KEYS).map(Fr::from).collect::<Vec<_>>();
+
+        let circuit = KeyRangesCircuit::<Fr>::from_str("{\"ab\":1,\"c\":2}").unwrap();
+        assert_eq!(circuit.instances(), padded(&[2, 2, 9, 1]));
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+
+        // Other ranges than the document's, one left out, or one made up in a free slot
+        for wrong in [padded(&[2, 2, 9, 2]), padded(&[2, 2]), padded(&[2, 2, 9, 1, 11, 1])] {
+            assert!(MockProver::run(k, &circuit, vec![wrong]).unwrap().verify().is_err());
+        }
+
+        // Nested keys aren't exposed, and an empty key is a range of length 0
+        let circuit = KeyRangesCircuit::<Fr>::from_str("{\"a\":{\"b\":1},\"\":2}").unwrap();
+        assert_eq!(circuit.instances(), padded(&[2, 1, 14, 0]));
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+
+        // Another document of the same length, with the first one's ranges
+        let mut forged = KeyRangesCircuit::<Fr>::from_str("{\"ab\":1,\"c\":2}").unwrap();
+        forged.raw = JsonCircuit::<Fr>::from_str("{\"a\":12,\"c\":2}").unwrap().raw;
+        assert!(MockProver::run(k, &forged, vec![forged.instances()]).unwrap().verify().is_err());
+
+        // Every slot filled, and one key too many
+        let json = format!("{{{}}}", (0..MAX_TOP_LEVEL_KEYS).map(|idx| format!("\"{}\":1", idx)).collect::<Vec<_>>().join(","));
+        let circuit = KeyRangesCircuit::<Fr>::from_str(&json).unwrap();
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+        let json = json.replacen('{', "{\"x\":1,", 1);
+        assert_eq!(KeyRangesCircuit::<Fr>::from_str(&json).err(), Some(ParseError::TooManyElements(1 + 6 * MAX_TOP_LEVEL_KEYS)));
+    }
+
+    // Assigns a document with the key slots filled from a caller-supplied (slot, start, len) per key
+    #[derive(Clone)]
+    struct ForgedKeyRunsCircuit {
+        raw: Vec<Value<Fr>>,
+        keys: Vec<[u64; 3]>,
+    }
+
+    impl Circuit<Fr> for ForgedKeyRunsCircuit {
+
+        type Config = KeyRunsConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            unimplemented!()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            KeyRunsConfig::configure(meta, false)
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+            let chip = JsonChip::construct(config.json);
+            chip.load(&mut layouter)?;
+            let json = chip.assign(&mut layouter, &self.raw)?;
+            config.assign_runs(&mut layouter, &json)?;
+            config.assign_keys(&mut layouter, Value::known(self.keys.iter().map(|key| key.map(Fr::from)).collect()))?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_key_runs() {
+
+        let k = 9;
+        let raw = JsonCircuit::<Fr>::from_str("{\"ab\":1,\"c\":{\"d\":2},\"e\":3}").unwrap().raw;
+        let circuit = |keys: &[[u64; 3]]| ForgedKeyRunsCircuit { raw: raw.clone(), keys: keys.to_vec() };
+        MockProver::run(k, &circuit(&[[1, 2, 2], [2, 9, 1], [3, 21, 1]]), vec![vec![]]).unwrap().assert_satisfied();
+
+        // A key left out, two swapped, a nested key or a value passed off as one, a range cut short, a slot skipped
+        for keys in [
+            vec![[1, 2, 2], [2, 9, 1]],
+            vec![[1, 2, 2], [2, 21, 1], [3, 9, 1]],
+            vec![[1, 2, 2], [2, 9, 1], [3, 14, 1]],
+            vec![[1, 2, 2], [2, 9, 1], [3, 21, 1], [4, 24, 1]],
+            vec![[1, 2, 1], [2, 9, 1], [3, 21, 1]],
+            vec![[1, 2, 2], [3, 9, 1], [4, 21, 1]],
+        ] {
+            assert!(MockProver::run(k, &circuit(&keys), vec![vec![]]).unwrap().verify().is_err(), "{:?}", keys);
+        }
+    }
+
+    #[test]
+    fn test_key_ranges_one_key() {
+
+        use halo2_proofs::{poly::commitment::ParamsProver, transcript::TranscriptReadBuffer};
+
+        let k = 9;
+        let params = ParamsKZG::<Bn256>::setup(k, OsRng);
+
+        // Same length, keys elsewhere and of other lengths: one verifying key serves both
+        let circuits = ["{\"ab\":1,\"c\":2}", "{\"a\":{\"bc\":1}}"].map(|json| KeyRangesCircuit::<Fr>::from_str(json).unwrap());
+        let [vk, other]: [VerifyingKey<G1Affine>; 2] = circuits.clone().map(|circuit| keygen_vk(&params, &circuit).unwrap());
+        assert_eq!(vk.transcript_repr(), other.transcript_repr());
+
+        let pk = keygen_pk(&params, vk.clone(), &circuits[0]).unwrap();
+        for circuit in &circuits {
+            let instances = circuit.instances();
+            let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+            create_proof::<
+                KZGCommitmentScheme<Bn256>,
+                ProverSHPLONK<'_, Bn256>,
+                Challenge255<G1Affine>,
+                _,
+                Blake2bWrite<Vec<u8>, G1Affine, Challenge255<_>>,
+                _,
+            >(&params, &pk, &[circuit.clone()], &[&[&instances[..]]], OsRng, &mut transcript)
+            .unwrap();
+            let proof = transcript.finalize();
+            let mut transcript = super::Blake2bRead::<_, G1Affine, Challenge255<_>>::init(&proof[..]);
+            super::verify_proof::<
+                KZGCommitmentScheme<Bn256>,
+                super::VerifierSHPLONK<'_, Bn256>,
+                Challenge255<G1Affine>,
+                super::Blake2bRead<&[u8], G1Affine, Challenge255<G1Affine>>,
+                super::SingleStrategy<'_, Bn256>,
+            >(params.verifier_params(), &vk, super::SingleStrategy::new(&params), &[&[&instances[..]]], &mut transcript)
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_key_counts_circuit() {
+
+        let k = 9;
+        let json = "{\"a\":{\"b\":1,\"c\":{\"d\":2}}}";
+
+        let circuit = KeyCountsCircuit::<Fr>::from_str(json, 3).unwrap();
+        assert_eq!(circuit.instances(), [1u64, 2, 1].map(Fr::from).to_vec());
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+
+        // Deeper keys fold into the last level, and levels past the deepest key, or past `MAX_DEPTH`, count 0
+        for (cap, counts) in [(2, vec![1u64, 3]), (1, vec![4]), (8, vec![1, 2, 1, 0, 0, 0, 0, 0])] {
+            let circuit = KeyCountsCircuit::<Fr>::from_str(json, cap).unwrap();
+            assert_eq!(circuit.instances(), counts.into_iter().map(Fr::from).collect::<Vec<_>>());
+            MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+        }
+
+        // Other counts, and a document of the same length with its keys at other levels
+        let circuit = KeyCountsCircuit::<Fr>::from_str(json, 2).unwrap();
+        let wrong = [3u64, 1].map(Fr::from).to_vec();
+        assert!(MockProver::run(k, &circuit, vec![wrong]).unwrap().verify().is_err());
+
+        let mut forged = circuit.clone();
+        forged.raw = JsonCircuit::<Fr>::from_str("{\"a\":{\"b\":1},\"c\":{\"d\":2}}").unwrap().raw;
+        assert_eq!(forged.raw.len(), circuit.raw.len());
+        assert!(MockProver::run(k, &forged, vec![circuit.instances()]).unwrap().verify().is_err());
+        let honest = KeyCountsCircuit::<Fr>::from_str("{\"a\":{\"b\":1},\"c\":{\"d\":2}}", 2).unwrap();
+        MockProver::run(k, &forged, vec![honest.instances()]).unwrap().assert_satisfied();
+
+        // The counts are not in the verifying key
+        let setup = ParamsKZG::<Bn256>::setup(k, OsRng);
+        let [vk, other]: [VerifyingKey<G1Affine>; 2] = [circuit, honest].map(|circuit| keygen_vk(&setup, &circuit).unwrap());
+        assert_eq!(vk.transcript_repr(), other.transcript_repr());
+    }
+
+    #[test]
+    fn test_schema_circuit() {
+
+        let k = 9;
+        let schema: [&[u8]; 3] = [b"a", b"b", b"c"];
+
+        // In any order, and nested keys don't count
+        for json in ["{\"a\":1,\"b\":2,\"c\":3}", "{\"c\":1,\"a\":{\"d\":2},\"b\":3}"] {
+            let circuit = SchemaCircuit::<Fr>::from_str(json, &schema).unwrap();
+            MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+        }
+
+        // Extra and missing keys, a repeated key, and a repeated entry
+        let mismatch = SchemaError::Mismatch(vec![b"d".to_vec()], vec![b"b".to_vec(), b"c".to_vec()]);
+        assert_eq!(SchemaCircuit::<Fr>::from_str("{\"a\":1,\"d\":4}", &schema).err(), Some(mismatch));
+        let mismatch = SchemaError::Mismatch(vec![b"a".to_vec()], vec![]);
+        assert_eq!(SchemaCircuit::<Fr>::from_str("{\"a\":1,\"a\":2}", &schema[..1]).err(), Some(mismatch));
+        let repeated = SchemaError::RepeatedEntry(b"a".to_vec());
+        assert_eq!(SchemaCircuit::<Fr>::from_str("{\"a\":1,\"a\":2}", &[b"a", b"a"]).err(), Some(repeated));
+
+        // In circuit: another key in place of one of the schema's, a key repeated in place of one, a key too many,
+        // or other schema bytes
+        let circuit = SchemaCircuit::<Fr>::from_str("{\"a\":1,\"b\":2,\"c\":3}", &schema).unwrap();
+        for json in ["{\"a\":1,\"d\":2,\"c\":3}", "{\"a\":1,\"a\":2,\"c\":3}", "{\"a\":1,\"b\":2,\"c\":3,\"d\":4}"] {
+            let forged = SchemaCircuit { raw: JsonCircuit::<Fr>::from_str(json).unwrap().raw, ..circuit.clone() };
+            assert!(MockProver::run(k, &forged, vec![forged.instances()]).unwrap().verify().is_err(), "{}", json);
+        }
+
+        let wrong = SchemaCircuit::<Fr> { schema: vec![b"a".to_vec(), b"e".to_vec(), b"c".to_vec()], ..circuit.clone() };
+        assert!(MockProver::run(k, &circuit, vec![wrong.instances()]).unwrap().verify().is_err());
+
+        // Where the keys sit is not in the verifying key
+        let setup = ParamsKZG::<Bn256>::setup(k, OsRng);
+        let circuits = ["{\"a\":12,\"b\":2,\"c\":3}", "{\"c\":1,\"a\":[],\"b\":3}"].map(|json| SchemaCircuit::<Fr>::from_str(json, &schema).unwrap());
+        let [vk, other]: [VerifyingKey<G1Affine>; 2] = circuits.map(|circuit| keygen_vk(&setup, &circuit).unwrap());
+        assert_eq!(vk.transcript_repr(), other.transcript_repr());
+    }
+
+    #[test]
+    fn test_sorted_keys_circuit() {
+
+        let k = 9;
+
+        // A prefix sorts first, and nested keys don't count
+        for json in ["{\"a\":1,\"b\":2}", "{\"a\":1,\"ab\":{\"z\":1,\"y\":2},\"b\":3}", "{\"a\":1}", "{\"\":1,\"0123456789abcdef\":2}"] {
+            let circuit = SortedKeysCircuit::<Fr>::from_str(json).unwrap();
+            MockProver::run(k, &circuit, vec![vec![]]).unwrap().assert_satisfied();
+        }
+
+        // Out of order at a differing byte, a repeated key, a key after its own prefix, and equal up to the window
+        for json in ["{\"ab\":1,\"aa\":2}", "{\"a\":1,\"a\":2}", "{\"ab\":1,\"a\":2}", "{\"a\":1,\"c\":2,\"b\":3}"] {
+            let circuit = SortedKeysCircuit::<Fr>::from_str(json).unwrap();
+            assert!(MockProver::run(k, &circuit, vec![vec![]]).unwrap().verify().is_err(), "{}", json);
+        }
+
+        let json = format!("{{\"{}\":1}}", "k".repeat(super::MAX_KEY_LEN + 1));
+        assert_eq!(SortedKeysCircuit::<Fr>::from_str(&json).err(), Some(ParseError::TooLong(2 + super::MAX_KEY_LEN)));
+
+        // Where the keys differ is not in the verifying key
+        let setup = ParamsKZG::<Bn256>::setup(k, OsRng);
+        let circuits = ["{\"ab\":1,\"ac\":2}", "{\"a\":1,\"bcd\":2}"].map(|json| SortedKeysCircuit::<Fr>::from_str(json).unwrap());
+        let [vk, other]: [VerifyingKey<G1Affine>; 2] = circuits.map(|circuit| keygen_vk(&setup, &circuit).unwrap());
+        assert_eq!(vk.transcript_repr(), other.transcript_repr());
+    }
+
+    #[test]
+    fn test_validity_circuit() {
+
+        let k = 11;
+
+        // Each outcome proves, and only that one
+        for (json, valid) in [("{\"a\":1}", true), ("{\"a\"}", false), ("{\"a\":1", false), ("  ", false), ("[1,{}]", true)] {
+            let circuit = ValidityCircuit::<Fr>::from_str(json).unwrap();
+            assert_eq!(circuit.valid, valid, "{}", json);
+            MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+            let flipped = vec![Fr::from(!valid as u64)];
+            assert!(MockProver::run(k, &circuit, vec![flipped]).unwrap().verify().is_err(), "{}", json);
+        }
+
+        // The transition table alone needs k = 11
+        let params = JsonConfigParams { soft_validity: true, ..Default::default() };
+        assert_eq!(super::min_k_for_json(1, &params), 11);
+
+        assert_eq!(ValidityCircuit::<Fr>::from_str("").err(), Some(ParseError::Empty));
+        assert_eq!(ValidityCircuit::<Fr>::from_str("[[[[[[[1]]]]]]]").err(), Some(ParseError::TooDeep(6)));
+
+        // The grammar accepts a long number, and the circuit has no witness for it, valid or cut short
+        let digits = "1".repeat(super::MAX_NUMBER_DIGITS + 1);
+        assert_eq!(ValidityCircuit::<Fr>::from_str(&format!("[{}]", digits)).err(), Some(ParseError::NumberTooLong(77)));
+        assert_eq!(ValidityCircuit::<Fr>::from_str(&format!("[{}", digits)).err(), Some(ParseError::NumberTooLong(77)));
+
+        // An unpaired surrogate proves invalid
+        let circuit = ValidityCircuit::<Fr>::from_str("{\"a\":\"\\uD800\"}").unwrap();
+        assert!(!circuit.valid);
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+
+        // Where the grammar rejects the document anyway, it proves invalid
+        let circuit = ValidityCircuit::<Fr>::from_str(&format!("{{\"a\"}} [{}]", digits)).unwrap();
+        assert!(!circuit.valid);
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+    }
+
+    #[test]
+    fn test_length_circuit() {
+
+        let k = 9;
+        let padded_len = 16;
+
+        // Documents of different lengths, each padded to the same size, and the length exposed is the real one
+        for json in ["{\"a\":1}", "[1,{},\"x\"]  ", "{\"abcdefghij\":1}", "[]"] {
+            let circuit = LengthCircuit::<Fr>::from_str(json, padded_len).unwrap();
+            assert_eq!(circuit.raw.len(), padded_len);
+            assert_eq!(circuit.instances(), vec![Fr::from(json.len() as u64)]);
+            MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+            for forged in [json.len() - 1, json.len() + 1] {
+                let instances = vec![vec![Fr::from(forged as u64)]];
+                assert!(MockProver::run(k, &circuit, instances).unwrap().verify().is_err(), "{}", json);
+            }
+        }
+
+        // A top-level scalar pads the same way
+        let params = JsonConfigParams { expose_length: true, top_level_scalar: true, ..Default::default() };
+        let trace = super::trace_from(b"12\0\0", super::GrammarState::StartScalar, true);
+        assert_eq!((trace.is_padding, trace.length), (vec![false, false, true, true], vec![1, 2, 2, 2]));
+        assert_eq!(super::preflight(b"12\0\0", &params), Ok(()));
+
+        // Padding only follows a whole document, and nothing but padding follows it
+        let params = JsonConfigParams { expose_length: true, ..Default::default() };
+        assert_eq!(super::preflight(b"{\"a\":1\0}", &params), Err(ParseError::UnexpectedChar(6)));
+        assert_eq!(super::preflight(b"{\"a\0\":1}", &params), Err(ParseError::UnexpectedChar(3)));
+        assert_eq!(super::preflight(b"{\"a\":1}\0 ", &params), Err(ParseError::TrailingChars(8)));
+        assert_eq!(LengthCircuit::<Fr>::from_str("{\"a\":1}", 6).err(), Some(ParseError::TooLong(6)));
+        let raw: Vec<Value<Fr>> = b"{\"a\":1}\0{}".iter().map(|&b| Value::known(Fr::from(b as u64))).collect();
+        let circuit = LengthCircuit { raw, len: 7 };
+        assert!(MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().verify().is_err());
+
+        // Without `expose_length`, the pad byte is a control char like any other
+        assert_eq!(super::preflight(b"{\"a\":1}\0", &JsonConfigParams::default()), Err(ParseError::TrailingChars(7)));
+
+        // A forged boundary: the closing '}' passed off as padding, so that the length comes out one short, and the first
+        // pad byte passed off as the document, one long. The grammar sets is_padding, so neither matches a step
+        let json = b"{\"a\":1}\0\0";
+        let raw: Vec<Value<Fr>> = json.iter().map(|&b| Value::known(Fr::from(b as u64))).collect();
+        let trace = super::trace_from(json, super::GrammarState::Start, true);
+        let circuit = PaddedForgedTraceCircuit { raw: raw.clone(), trace: trace.clone() };
+        MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
+
+        for idx in [6, 7] {
+            let mut forged = trace.clone();
+            forged.is_padding[idx] = !forged.is_padding[idx];
+            let shift = if forged.is_padding[idx] { -1 } else { 1 };
+            for len in &mut forged.length[idx..] {
+                *len = (*len as i64 + shift) as u64;
+            }
+            let circuit = PaddedForgedTraceCircuit { raw: raw.clone(), trace: forged };
+            assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err(), "{}", idx);
+        }
+    }
+
+    #[test]
+    fn test_verify_fixed() {
+
+        // The shipped artifacts, else a throwaway setup where `gen_srs` would write one, and its key
+        if !Path::new(super::FIXED_PARAMS).exists() {
+            std::fs::create_dir_all("params").unwrap();
+            ParamsKZG::<Bn256>::setup(super::FIXED_K, OsRng).write(&mut File::create(super::FIXED_PARAMS).unwrap()).unwrap();
+        }
+        if !Path::new(super::FIXED_VK).exists() {
+            super::write_fixed_vk().unwrap();
+        }
+
+        // The prover's side, keygen included
+        let prove = |params: &ParamsKZG<Bn256>, circuit: &LengthCircuit<Fr>| {
+            let vk = keygen_vk(params, circuit).unwrap();
+            let pk = keygen_pk(params, vk, circuit).unwrap();
+            let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+            create_proof::<
+                KZGCommitmentScheme<Bn256>,
+                ProverSHPLONK<'_, Bn256>,
+                Challenge255<G1Affine>,
+                _,
+                Blake2bWrite<Vec<u8>, G1Affine, Challenge255<_>>,
+                _,
+            >(params, &pk, &[circuit.clone()], &[&[&circuit.instances()[..]]], OsRng, &mut transcript)
+            .unwrap();
+            transcript.finalize()
+        };
+
+        // Documents of any length up to `FIXED_LEN`, each with its own length
+        let params = ParamsKZG::<Bn256>::read(&mut File::open(super::FIXED_PARAMS).unwrap()).unwrap();
+        for json in ["{\"a\":1}", "[1,{\"b\":[true,null]},\"x\"]"] {
+            let circuit = LengthCircuit::<Fr>::from_str(json, super::FIXED_LEN).unwrap();
+            let proof = prove(&params, &circuit);
+            assert!(super::verify_fixed(&proof, &circuit.instances()).unwrap(), "{}", json);
+            assert!(!super::verify_fixed(&proof, &[Fr::from(json.len() as u64 + 1)]).unwrap(), "{}", json);
+        }
+
+        // Another padded length or another k is another layout, whose proof the fixed key rejects
+        let circuit = LengthCircuit::<Fr>::from_str("{\"a\":1}", super::FIXED_LEN - 16).unwrap();
+        assert!(!super::verify_fixed(&prove(&params, &circuit), &circuit.instances()).unwrap());
+        let circuit = LengthCircuit::<Fr>::from_str("{\"a\":1}", super::FIXED_LEN).unwrap();
+        let other = ParamsKZG::<Bn256>::setup(super::FIXED_K + 1, OsRng);
+        assert!(!super::verify_fixed(&prove(&other, &circuit), &circuit.instances()).unwrap());
+        assert!(!super::verify_fixed(&[], &circuit.instances()).unwrap());
+    }
+
+    #[test]
+    fn test_base64_circuit() {
+
+        let k = 11;
+
+        // Two '=', one and none, each with the one outcome it proves
+        for (b64, json, valid) in [("eyJhIjoxfQ==", "{\"a\":1}", true), ("eyJhIn0=", "{\"a\"}", false), ("WzEsMjJd", "[1,22]", true)] {
+            assert_eq!(super::base64_decode(b64.as_bytes()).unwrap(), json.as_bytes());
+            let circuit = Base64Circuit::<Fr>::from_str(b64).unwrap();
+            assert_eq!((circuit.len, circuit.valid), (json.len(), valid), "{}", b64);
+            MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+            let flipped = vec![Fr::from(!valid as u64)];
+            assert!(MockProver::run(k, &circuit, vec![flipped]).unwrap().verify().is_err(), "{}", b64);
+        }
+
+        // Not base64: a char outside the alphabet, '=' before the end, a cut group, and bits a '=' drops set
+        assert_eq!(super::base64_decode(b"eyJh!joxfQ=="), Err(ParseError::UnexpectedChar(4)));
+        assert_eq!(super::base64_decode(b"ey=hIjoxfQ=="), Err(ParseError::UnexpectedChar(2)));
+        assert_eq!(super::base64_decode(b"eyJhIjoxfQ="), Err(ParseError::UnexpectedEnd(11)));
+        assert_eq!(super::base64_decode(b"eyJhIjoxfR=="), Err(ParseError::UnexpectedChar(9)));
+        assert_eq!(Base64Circuit::<Fr>::from_str("").err(), Some(ParseError::Empty));
+
+        // The same in circuit, on a layout for 7 bytes
+        let circuit = Base64Circuit::<Fr>::from_str("eyJhIjoxfQ==").unwrap();
+        for forged in ["eyJh!joxfQ==", "eyJhIjoxfR==", "eyJhIjoxf==", "eyJhIjoxfQ=A"] {
+            let chars: Vec<Value<Fr>> = forged.bytes().map(|c| Value::known(Fr::from(c as u64))).collect();
+            let forged_circuit = Base64Circuit { chars, ..circuit.clone() };
+            let verified = MockProver::run(k, &forged_circuit, vec![circuit.instances()]).map(|prover| prover.verify().is_ok());
+            assert!(!verified.unwrap_or(false), "{}", forged);
+        }
+
+        // A '=' in a middle group
+        let chars: Vec<Value<Fr>> = b"eyJ=IjoxfQ==".iter().map(|&c| Value::known(Fr::from(c as u64))).collect();
+        let forged_circuit = Base64Circuit { chars, ..circuit };
+        assert!(MockProver::run(k, &forged_circuit, vec![vec![Fr::zero()]]).unwrap().verify().is_err());
+    }
+
+    #[test]
+    fn test_key_commitments_circuit() {
+
+        let k = 11;
+        let params = PoseidonParams::<Fr>::new();
+        let salts: Vec<Fr> = (0..MAX_TOP_LEVEL_KEYS as u64).map(|salt| Fr::from(7 + salt)).collect();
+
+        // One commitment per slot, in document order, equal to the off-circuit ones. The nested key has none
+        let circuit = KeyCommitmentsCircuit::from_str("{\"a\":1,\"b\":2}", &salts).unwrap();
+        let expected: Vec<Fr> = [Some(&b"a"[..]), Some(b"b")].into_iter().chain(std::iter::repeat(None)).zip(&salts)
+            .map(|(key, &salt)| super::key_commitment(&params, salt, key))
+            .collect();
+        assert_eq!(circuit.instances(), expected);
+        MockProver::run(k, &circuit, vec![circuit.instances()]).unwrap().assert_satisfied();
+
+        let nested = KeyCommitmentsCircuit::from_str("{\"ab\":{\"c\":1},\"\":2}", &salts).unwrap();
+        assert_eq!(nested.instances()[0], super::key_commitment(&params, salts[0], Some(b"ab")));
+        assert_eq!(nested.instances()[1], super::key_commitment(&params, salts[1], Some(b"")));
+        assert_eq!(nested.instances()[2], super::key_commitment(&params, salts[2], None));
+        MockProver::run(k, &nested, vec![nested.instances()]).unwrap().assert_satisfied();
+
+        // Swapped, or under another salt, they no longer match
+        let mut swapped = expected.clone();
+        swapped.swap(0, 1);
+        assert!(MockProver::run(k, &circuit, vec![swapped]).unwrap().verify().is_err());
+        let mut resalted = circuit.clone();
+        resalted.salts[0] = Value::known(Fr::from(8));
+        assert!(MockProver::run(k, &resalted, vec![expected.clone()]).unwrap().verify().is_err());
+
+        // Commitments to keys the document does not have: "c" in place of "b", or "bc"
+        for json in ["{\"a\":1,\"c\":2}", "{\"a\":1,\"bc\":2}"] {
+            let forged = KeyCommitmentsCircuit { raw: JsonCircuit::<Fr>::from_bytes(json.as_bytes()).unwrap().raw, ..circuit.clone() };
+            assert!(MockProver::run(k, &forged, vec![expected.clone()]).unwrap().verify().is_err(), "{}", json);
+        }
+
+        // The layout is not in the verifying key
+        let other = KeyCommitmentsCircuit::from_str("{\"abcd\":[12]}", &salts).unwrap();
+        let setup = ParamsKZG::<Bn256>::setup(k, OsRng);
+        let [vk, other]: [VerifyingKey<G1Affine>; 2] = [circuit, other].map(|circuit| keygen_vk(&setup, &circuit).unwrap());
+        assert_eq!(vk.transcript_repr(), other.transcript_repr());
+
+        assert_eq!(KeyCommitmentsCircuit::<Fr>::from_str("{\"a\":1}", &salts[1..]).err(), Some(CommitmentError::SaltCount(MAX_TOP_LEVEL_KEYS, MAX_TOP_LEVEL_KEYS - 1)));
+        let json = format!("{{\"{}\":1}}", "k".repeat(super::MAX_KEY_LEN + 1));
+        assert_eq!(KeyCommitmentsCircuit::<Fr>::from_str(&json, &salts).err(), Some(CommitmentError::Parse(ParseError::TooLong(2 + super::MAX_KEY_LEN))));
+    }
+
+    // Exposes the final level and not_str cells, as a caller checking completeness would
+    #[derive(Clone, Default)]
+    struct FinalStateCircuit {
+        raw: Vec<Value<Fr>>,
+    }
+
+    impl Circuit<Fr> for FinalStateCircuit {
+
+        type Config = (JsonConfig, Column<Instance>);
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            OuterCircuit::configure(meta)
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+
+            let chip = JsonChip::construct(config.0);
+            chip.load(&mut layouter)?;
+            let assigned = chip.assign(&mut layouter, &self.raw)?;
+
+            layouter.constrain_instance(assigned.final_level.cell(), config.1, 0)?;
+            layouter.constrain_instance(assigned.final_not_str.cell(), config.1, 1)
+        }
+    }
+
+    #[test]
+    fn test_final_level() {
+
+        let k = 9;
+
+        let raw = JsonCircuit::<Fr>::from_str("{\"a\": {\"b\": \"c\"}}").unwrap().raw;
+        let circuit = FinalStateCircuit { raw };
+
+        MockProver::run(k, &circuit, vec![vec![Fr::zero(), Fr::one()]]).unwrap().assert_satisfied();
+        assert!(MockProver::run(k, &circuit, vec![vec![Fr::one(), Fr::one()]]).unwrap().verify().is_err());
+    }
+
+    // Assigns the start row alone, with `level` in place of its 0
+    #[derive(Clone)]
+    struct StartLevelCircuit {
+        level: u64,
+    }
+
+    impl Circuit<Fr> for StartLevelCircuit {
+
+        type Config = JsonConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            unimplemented!()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            JsonConfig::configure(meta)
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+
+            let chip = JsonChip::construct(config);
+            chip.load(&mut layouter)?;
+
+            let row = [
+                (config.state, super::GrammarState::Start as u64), (config.stack, 1), (config.not_str, 1), (config.raw, 0),
+                (config.class, 0), (config.ctx_flag, 0), (config.container, 0), (config.push, 0), (config.pop, 0),
+                (config.value_type, 0), (config.accept, 0), (config.str_escaped, 0), (config.level, self.level),
+                (config.char_class, 0), (config.any_invalid, 0), (config.digit_run, 0),
+            ];
+            layouter.assign_region(
+                || "Json start row",
+                |mut region| {
+                    for (column, value) in row {
+                        region.assign_advice(|| "start row", column, 0, || Value::known(Fr::from(value)))?;
+                    }
+                    config.start_selector.enable(&mut region, 0)
+                }
+            )
+        }
+    }
+
+    #[test]
+    fn test_start_row_level() {
+
+        let k = 9;
+
+        // The start gate alone rejects a nonzero level before the first byte
+        MockProver::run(k, &StartLevelCircuit { level: 0 }, vec![]).unwrap().assert_satisfied();
+        let failures = MockProver::run(k, &StartLevelCircuit { level: 1 }, vec![]).unwrap().verify().unwrap_err();
+        assert_eq!(failures.len(), 1);
+        assert!(matches!(&failures[0],
+            VerifyFailure::ConstraintNotSatisfied { constraint, location: FailureLocation::InRegion { offset: 0, .. }, .. }
+                if constraint.to_string().contains("Start state")
+        ), "{failures:?}");
+
+        let json = b"{\"a\":1}";
+        let raw = JsonCircuit::<Fr>::from_bytes(json).unwrap().raw;
+        let trace = super::compute_trace(json);
+
+        // Level 0 before the first byte, so its '{' is at level 1
+        let mut forged = trace;
+        forged.level.iter_mut().for_each(|l| *l += 1);
+        forged.stack.iter_mut().for_each(|s| *s *= 2);
+        let circuit = ForgedTraceCircuit { raw, trace: forged };
+        assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err());
+    }
+
+    #[test]
+    fn test_start_row_string_state() {
+
+        let k = 9;
+        let json = b"\"a\":1}";
+        let raw: Vec<Value<Fr>> = json.iter().map(|&b| Value::known(Fr::from(b as u64))).collect();
+
+        // The document cannot begin inside a string, or with the first byte escaped: `"a":1}` passed off as the
+        // rest of `{"a":1}`, and a quote passed off as escaped
+        let mut forged = super::compute_trace(b"{\"a\":1}");
+        forged.class.remove(0);
+        forged.state.remove(0);
+        forged.stack.remove(0);
+        forged.push.remove(0);
+        forged.pop.remove(0);
+        forged.value_type.remove(0);
+        forged.not_str.remove(0);
+        forged.str_escaped.remove(0);
+        forged.level.remove(0);
+        forged.utf8_pending.remove(0);
+        forged.char_class.remove(0);
+        forged.any_invalid.remove(0);
+        forged.digit_run.remove(0);
+        let circuit = ForgedTraceCircuit { raw: raw.clone(), trace: forged };
+        assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err());
+
+        let mut forged = super::compute_trace(json);
+        forged.str_escaped[0] = true;
+        forged.not_str[0] = false;
+        let circuit = ForgedTraceCircuit { raw, trace: forged };
+        assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err());
     }
 
 }
\ No newline at end of file
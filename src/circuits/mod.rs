@@ -6,4 +6,5 @@
 pub mod is_zero;
 pub mod or;
 pub mod standard_plonk;
-pub mod json;
\ No newline at end of file
+pub mod json;
+pub mod poseidon;
\ No newline at end of file
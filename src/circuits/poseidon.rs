@@ -0,0 +1,391 @@
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter, Region, Value},
+    halo2curves::FieldExt,
+    plonk::{Advice, Column, ConstraintSystem, Error, Expression, Fixed, Selector},
+    poly::Rotation,
+};
+use std::collections::VecDeque;
+
+// Poseidon with the parameters of examples/poseidon.rs: a width 3 state (capacity 1, rate 2), x^5, R_F = 8 full and
+// R_P = 57 partial rounds. The round constants and the Cauchy MDS matrix are drawn from the Grain LFSR as the Poseidon
+// reference script (generate_parameters_grain.sage) does, so over bn254 the permutation is the reference one. The
+// script's extra security checks on the matrix are not repeated
+pub const T: usize = 3;
+pub const RATE: usize = 2;
+pub const R_F: usize = 8;
+pub const R_P: usize = 57;
+const ROUNDS: usize = R_F + R_P;
+
+// Round constants, T per round, and the MDS matrix
+#[derive(Clone, Debug)]
+pub struct PoseidonParams<F: FieldExt> {
+    pub round_constants: Vec<[F; T]>,
+    pub mds: [[F; T]; T],
+}
+
+impl<F: FieldExt> Default for PoseidonParams<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: FieldExt> PoseidonParams<F> {
+
+    pub fn new() -> Self {
+
+        let mut grain = Grain::new(F::NUM_BITS, T, R_F, R_P);
+        let round_constants = (0..ROUNDS).map(|_| [(); T].map(|_| grain.next_field_element())).collect();
+
+        // Fresh xs and ys until they are distinct and no x + y is 0
+        let mds = loop {
+            let xs_ys = [(); 2 * T].map(|_| grain.next_field_element_without_rejection::<F>());
+            if (0..2 * T).any(|i| xs_ys[i + 1..].contains(&xs_ys[i])) {
+                continue;
+            }
+            let (xs, ys) = xs_ys.split_at(T);
+            if xs.iter().any(|&x| ys.iter().any(|&y| x + y == F::zero())) {
+                continue;
+            }
+            break [0, 1, 2].map(|i| [0, 1, 2].map(|j| (xs[i] + ys[j]).invert().unwrap()));
+        };
+
+        Self { round_constants, mds }
+    }
+
+    fn full_round(r: usize) -> bool {
+        !(R_F / 2..R_F / 2 + R_P).contains(&r)
+    }
+
+    // Round `r`: add its constants, x^5 on every element in a full round and on the first in a partial one, then the MDS
+    fn round(&self, r: usize, state: [F; T]) -> [F; T] {
+        let constants = self.round_constants[r];
+        let sboxed = [0, 1, 2].map(|i| {
+            let x = state[i] + constants[i];
+            if Self::full_round(r) || i == 0 { pow5(x) } else { x }
+        });
+        self.mds.map(|row| row.iter().zip(sboxed).map(|(&m, x)| m * x).sum())
+    }
+
+    pub fn permute(&self, state: &mut [F; T]) {
+        for r in 0..ROUNDS {
+            *state = self.round(r, *state);
+        }
+    }
+
+    // Sponge over `inputs`: the capacity starts at `domain`, each RATE inputs (the last ones padded with 0) are added
+    // to the rate before a permutation, and the first rate element is the hash. `PoseidonConfig::hash` in circuit.
+    // The padding makes a trailing 0 invisible, so inputs of varying length need their length in `domain`
+    pub fn hash(&self, domain: F, inputs: &[F]) -> F {
+        let mut state = [domain, F::zero(), F::zero()];
+        for chunk in inputs.chunks(RATE) {
+            state[1] += chunk[0];
+            state[2] += chunk.get(1).copied().unwrap_or_else(F::zero);
+            self.permute(&mut state);
+        }
+        state[1]
+    }
+}
+
+fn pow5<F: FieldExt>(x: F) -> F {
+    x.square().square() * x
+}
+
+// The 80-bit Grain LFSR of the reference script, seeded with the field and the parameters
+struct Grain {
+    bits: VecDeque<bool>,
+}
+
+impl Grain {
+
+    fn new(field_bits: u32, t: usize, r_f: usize, r_p: usize) -> Self {
+
+        let mut bits = VecDeque::with_capacity(80);
+        let fields = [(1, 2), (0, 4), (field_bits as u64, 12), (t as u64, 12), (r_f as u64, 10), (r_p as u64, 10), ((1 << 30) - 1, 30)];
+        for (value, width) in fields {
+            bits.extend((0..width).rev().map(|i| value >> i & 1 == 1));
+        }
+
+        let mut grain = Self { bits };
+        for _ in 0..160 {
+            grain.clock();
+        }
+        grain
+    }
+
+    fn clock(&mut self) -> bool {
+        let b = &self.bits;
+        let new = b[62] ^ b[51] ^ b[38] ^ b[23] ^ b[13] ^ b[0];
+        self.bits.pop_front();
+        self.bits.push_back(new);
+        new
+    }
+
+    // Bits come in pairs, and only the second of a pair whose first is set is kept
+    fn next_bit(&mut self) -> bool {
+        loop {
+            let (keep, bit) = (self.clock(), self.clock());
+            if keep {
+                return bit;
+            }
+        }
+    }
+
+    // NUM_BITS bits, most significant first, redrawn until they are below the modulus
+    fn next_field_element<F: FieldExt>(&mut self) -> F {
+        loop {
+            let mut repr = F::Repr::default();
+            let bytes = repr.as_mut();
+            for i in (0..F::NUM_BITS as usize).rev() {
+                if self.next_bit() {
+                    bytes[i / 8] |= 1 << (i % 8);
+                }
+            }
+            if let Some(x) = Option::from(F::from_repr(repr)) {
+                return x;
+            }
+        }
+    }
+
+    // NUM_BITS bits, most significant first, reduced modulo the field
+    fn next_field_element_without_rejection<F: FieldExt>(&mut self) -> F {
+        (0..F::NUM_BITS).fold(F::zero(), |acc, _| acc.double() + F::from(self.next_bit()))
+    }
+}
+
+// The hash and the cells the inputs were assigned to, from `PoseidonConfig::hash`
+pub type HashCells<F> = (AssignedCell<F, F>, Vec<AssignedCell<F, F>>);
+
+// An input to `PoseidonConfig::hash`: a cell copied in, or a fresh witness
+#[derive(Clone, Debug)]
+pub enum PoseidonInput<'a, F: FieldExt> {
+    Cell(&'a AssignedCell<F, F>),
+    Witness(Value<F>),
+}
+
+// `PoseidonParams::hash` in circuit, one row per round. A hash of n inputs takes ceil(n / RATE) * (R_F + R_P + 1) + 1
+// rows: per permutation one row absorbing the inputs, then one per round, and the output after the last
+#[derive(Clone, Copy, Debug)]
+pub struct PoseidonConfig {
+    state: [Column<Advice>; T],
+    input: [Column<Advice>; RATE],
+    constants: [Column<Fixed>; T], // the round's constants, and the domain on the first row
+    q_init: Selector,
+    q_absorb: [Selector; RATE], // absorb the first input, and the first two
+    q_full: Selector,
+    q_partial: Selector,
+}
+
+impl PoseidonConfig {
+
+    pub fn configure<F: FieldExt>(meta: &mut ConstraintSystem<F>) -> Self {
+
+        let state = [(); T].map(|_| meta.advice_column());
+        let input = [(); RATE].map(|_| meta.advice_column());
+        let constants = [(); T].map(|_| meta.fixed_column());
+        [state[1], input[0], input[1]].map(|column| meta.enable_equality(column));
+
+        let q_init = meta.selector();
+        let q_absorb = [(); RATE].map(|_| meta.selector());
+        let q_full = meta.selector();
+        let q_partial = meta.selector();
+
+        let mds = PoseidonParams::<F>::new().mds;
+
+        meta.create_gate("Poseidon init", |meta| {
+
+            let s = state.map(|column| meta.query_advice(column, Rotation::cur()));
+            let domain = meta.query_fixed(constants[0], Rotation::cur());
+            let q = meta.query_selector(q_init);
+
+            let [s0, s1, s2] = s;
+            vec![q.clone() * (s0 - domain), q.clone() * s1, q * s2]
+
+        });
+
+        // With one input left, the second rate element is carried over, i.e. padded with 0
+        for (name, selector, width) in [("Poseidon absorb one", q_absorb[0], 1), ("Poseidon absorb", q_absorb[1], 2)] {
+            meta.create_gate(name, |meta| {
+
+                let s = state.map(|column| meta.query_advice(column, Rotation::cur()));
+                let next = state.map(|column| meta.query_advice(column, Rotation::next()));
+                let q = meta.query_selector(selector);
+
+                let [x0, x1] = [0, 1].map(|i| match i < width {
+                    true => meta.query_advice(input[i], Rotation::cur()),
+                    false => Expression::Constant(F::zero()),
+                });
+
+                vec![
+                    q.clone() * (next[0].clone() - s[0].clone()),
+                    q.clone() * (next[1].clone() - s[1].clone() - x0),
+                    q * (next[2].clone() - s[2].clone() - x1),
+                ]
+
+            });
+        }
+
+        for (name, selector, full) in [("Poseidon full round", q_full, true), ("Poseidon partial round", q_partial, false)] {
+            meta.create_gate(name, |meta| {
+
+                let s = state.map(|column| meta.query_advice(column, Rotation::cur()));
+                let next = state.map(|column| meta.query_advice(column, Rotation::next()));
+                let c = constants.map(|column| meta.query_fixed(column, Rotation::cur()));
+                let q = meta.query_selector(selector);
+
+                let sboxed: Vec<Expression<F>> = (0..T).map(|i| {
+                    let x = s[i].clone() + c[i].clone();
+                    if full || i == 0 { x.clone() * x.clone() * x.clone() * x.clone() * x } else { x }
+                }).collect();
+
+                (0..T).map(|i| {
+                    let mixed = (0..T).map(|j| Expression::Constant(mds[i][j]) * sboxed[j].clone())
+                        .reduce(|acc, term| acc + term).unwrap();
+                    q.clone() * (next[i].clone() - mixed)
+                }).collect::<Vec<_>>()
+
+            });
+        }
+
+        Self { state, input, constants, q_init, q_absorb, q_full, q_partial }
+    }
+
+    // Hash `inputs` under `domain` in a region of its own. Returns the hash and the cells the inputs were assigned to,
+    // in order
+    pub fn hash<F: FieldExt>(
+        &self,
+        layouter: &mut impl Layouter<F>,
+        params: &PoseidonParams<F>,
+        domain: F,
+        inputs: &[PoseidonInput<F>],
+    ) -> Result<HashCells<F>, Error> {
+
+        if inputs.is_empty() {
+            return Err(Error::Synthesis);
+        }
+
+        layouter.assign_region(
+            || "Poseidon",
+            |mut region| {
+
+                let mut state = Value::known([domain, F::zero(), F::zero()]);
+                let mut input_cells = Vec::with_capacity(inputs.len());
+
+                region.assign_fixed(|| "domain", self.constants[0], 0, || Value::known(domain))?;
+                self.q_init.enable(&mut region, 0)?;
+
+                let mut offset = 0;
+                for chunk in inputs.chunks(RATE) {
+
+                    // Absorb, on the row holding the state before it
+                    self.assign_state(&mut region, offset, state)?;
+                    for (i, x) in chunk.iter().enumerate() {
+                        let cell = match x {
+                            PoseidonInput::Cell(cell) => cell.copy_advice(|| "input", &mut region, self.input[i], offset)?,
+                            PoseidonInput::Witness(x) => region.assign_advice(|| "input", self.input[i], offset, || *x)?,
+                        };
+                        state = state.zip(cell.value().copied()).map(|(mut s, x)| {
+                            s[i + 1] += x;
+                            s
+                        });
+                        input_cells.push(cell);
+                    }
+                    self.q_absorb[chunk.len() - 1].enable(&mut region, offset)?;
+                    offset += 1;
+
+                    // One row per round, each holding the state before it
+                    for (r, round_constants) in params.round_constants.iter().enumerate() {
+                        self.assign_state(&mut region, offset, state)?;
+                        for (i, &c) in round_constants.iter().enumerate() {
+                            region.assign_fixed(|| "round constant", self.constants[i], offset, || Value::known(c))?;
+                        }
+                        let selector = if PoseidonParams::<F>::full_round(r) { self.q_full } else { self.q_partial };
+                        selector.enable(&mut region, offset)?;
+                        state = state.map(|s| params.round(r, s));
+                        offset += 1;
+                    }
+                }
+
+                let output = self.assign_state(&mut region, offset, state)?;
+                Ok((output, input_cells))
+            }
+        )
+    }
+
+    // Assign `state` at `offset`, returning the cell of the first rate element
+    fn assign_state<F: FieldExt>(&self, region: &mut Region<'_, F>, offset: usize, state: Value<[F; T]>) -> Result<AssignedCell<F, F>, Error> {
+        let mut cells = (0..T)
+            .map(|i| region.assign_advice(|| "state", self.state[i], offset, || state.map(|s| s[i])))
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(cells.swap_remove(1))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner, Value},
+        dev::MockProver,
+        halo2curves::bn256::Fr,
+        plonk::{Circuit, Column, ConstraintSystem, Error, Instance},
+    };
+
+    use super::{PoseidonConfig, PoseidonInput, PoseidonParams};
+
+    // Hashes its inputs under a domain, with the hash as the instance
+    #[derive(Clone, Default)]
+    struct HashCircuit {
+        domain: Fr,
+        inputs: Vec<Value<Fr>>,
+    }
+
+    impl Circuit<Fr> for HashCircuit {
+
+        type Config = (PoseidonConfig, Column<Instance>);
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            unimplemented!()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            let instance = meta.instance_column();
+            meta.enable_equality(instance);
+            (PoseidonConfig::configure(meta), instance)
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+            let params = PoseidonParams::new();
+            let inputs: Vec<_> = self.inputs.iter().map(|&x| PoseidonInput::Witness(x)).collect();
+            let (hash, _) = config.0.hash(&mut layouter, &params, self.domain, &inputs)?;
+            layouter.constrain_instance(hash.cell(), config.1, 0)
+        }
+    }
+
+    // The reference script's test vector for x^5 over bn254 with t = 3
+    #[test]
+    fn test_reference_permutation() {
+        let mut state = [0, 1, 2].map(Fr::from);
+        PoseidonParams::new().permute(&mut state);
+        assert_eq!(format!("{:?}", state[0]), "0x115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a");
+    }
+
+    #[test]
+    fn test_hash_circuit() {
+
+        let k = 9;
+        let params = PoseidonParams::<Fr>::new();
+
+        // One input, a whole chunk, and a chunk and a half
+        for len in 1..=3 {
+            let inputs: Vec<Fr> = (1..=len).map(Fr::from).collect();
+            let hash = params.hash(Fr::from(7), &inputs);
+            let circuit = HashCircuit { domain: Fr::from(7), inputs: inputs.iter().map(|&x| Value::known(x)).collect() };
+            MockProver::run(k, &circuit, vec![vec![hash]]).unwrap().assert_satisfied();
+
+            // Another domain is another hash
+            assert_ne!(params.hash(Fr::from(8), &inputs), hash);
+            let circuit = HashCircuit { domain: Fr::from(8), ..circuit };
+            assert!(MockProver::run(k, &circuit, vec![vec![hash]]).unwrap().verify().is_err());
+        }
+    }
+}
@@ -2,12 +2,22 @@ use clap::error;
 use halo2_base::{
     gates::flex_gate::{GateChip, FlexGateConfig, GateInstructions, GateStrategy, MAX_PHASE},
     halo2_proofs::{
-        circuit::{Layouter, Value},
+        circuit::{Layouter, Region, SimpleFloorPlanner, Value},
+        halo2curves::bn256::{Bn256, Fr, G1Affine},
         plonk::{
-            Advice, Column, ConstraintSystem, Error, SecondPhase, Selector, TableColumn, ThirdPhase,
-            Assigned
+            Advice, Column, ConstraintSystem, Error, Fixed, Instance, SecondPhase, Selector, TableColumn, ThirdPhase,
+            Assigned, Expression, Circuit, ProvingKey, VerifyingKey, create_proof, keygen_pk, keygen_vk, verify_proof,
         },
-        poly::Rotation,
+        poly::{
+            commitment::{Params, ParamsProver},
+            kzg::{
+                commitment::{KZGCommitmentScheme, ParamsKZG},
+                multiopen::{ProverSHPLONK, VerifierSHPLONK},
+                strategy::SingleStrategy,
+            },
+            Rotation,
+        },
+        transcript::{Blake2bRead, Blake2bWrite, Challenge255, TranscriptReadBuffer, TranscriptWriterBuffer},
     },
     utils::{
         ScalarField,
@@ -15,9 +25,14 @@ use halo2_base::{
     AssignedValue, Context,
     QuantumCell::{self, Constant, Existing, Witness},
 };
-use crate::state_machine_chip::json_state_machine::{State, SpecialChar, StateEncoding, StateCheck, JsonStateMutation, StateBit};
+use crate::state_machine_chip::json_state_machine::{State, SpecialChar, StateEncoding, StateCheck, JsonStateMutation, StateBit, StateId, ActionId, transition_table, with_class_rows, decode_checked, nesting, NUM_STATE_BITS};
 
 use super::state_machine::StateMachine;
+use std::fs::File;
+use std::marker::PhantomData;
+use std::io::{self, BufRead, BufReader};
+use std::ops::Range;
+use rand::rngs::OsRng;
 
 
 /// Specifies the gate strategy -- aligning with rest of system
@@ -32,6 +47,83 @@ pub enum StateMachineStrategy {
     ///
     /// Using `a + b * c` instead of `a * b + c` allows for "chaining" of gates, i.e., the output of one gate becomes `a` in the next gate.
     Vertical, // vanilla implementation with vertical basic gate(s)
+    /// # Tuple Lookup Strategy:
+    /// Same gates as `Vertical`, but each transition is looked up as one packed value
+    /// `curr + B * action + B^2 * next` (see `pack_transition`) against a single table column.
+    /// Every transcript cell is range checked below `B` (see `ByteLimbs`), which costs three advice columns
+    Tuple,
+}
+
+// Packing base for the Tuple strategy. Packing is injective only while curr, action and next are all below the
+// base, and the transcript cells are field elements, so the Tuple strategy range checks each of them (see `ByteLimbs`)
+const TUPLE_BASE: u64 = 1 << 24;
+
+// State encodings must fit under the base as well as the class ids (0x100..)
+const _: () = assert!(NUM_STATE_BITS <= 24, "state encodings must fit in 24 bits to be packed");
+
+// Up to 2^72, past u64, so loaded with `from_u128`
+pub fn pack_transition(curr: StateId, action: ActionId, next: StateId) -> u128 {
+    let base = TUPLE_BASE as u128;
+    curr.0 as u128 + base * action.0 as u128 + base * base * next.0 as u128
+}
+
+// Range check for the Tuple strategy: each transcript cell t is split into bytes t = b0 + 256 * b1 + 256^2 * b2,
+// and each byte is looked up in a 0..=255 table, so t < 2^24 = TUPLE_BASE
+#[derive(Clone, Debug)]
+pub struct ByteLimbs {
+    pub limbs: [Column<Advice>; 3],
+    pub q_range: Selector,
+    pub table: TableColumn,
+}
+
+impl ByteLimbs {
+
+    fn configure<F: ScalarField>(meta: &mut ConstraintSystem<F>, transcript: Column<Advice>) -> Self {
+
+        let limbs = [(); 3].map(|_| meta.advice_column());
+        let q_range = meta.complex_selector();
+        let table = meta.lookup_table_column();
+
+        meta.create_gate("Transcript byte limbs", |meta| {
+            let q = meta.query_selector(q_range);
+            let t = meta.query_advice(transcript, Rotation::cur());
+            let [b0, b1, b2] = limbs.map(|limb| meta.query_advice(limb, Rotation::cur()));
+            vec![q * (t - b0 - b1 * Expression::Constant(F::from(256)) - b2 * Expression::Constant(F::from(1 << 16)))]
+        });
+
+        for (limb, name) in limbs.iter().zip(["Transcript low byte", "Transcript middle byte", "Transcript high byte"]) {
+            meta.lookup(name, |meta| {
+                let q = meta.query_selector(q_range);
+                let b = meta.query_advice(*limb, Rotation::cur());
+                vec![(q * b, table)]
+            });
+        }
+
+        Self { limbs, q_range, table }
+    }
+
+    fn load<F: ScalarField>(&self, layouter: &mut impl Layouter<F>) -> Result<(), Error> {
+
+        layouter.assign_table(
+            || "Byte Table",
+            |mut table| {
+                for b in 0..=0xffu64 {
+                    table.assign_cell(|| format!("byte {}", b), self.table, b as usize, || Value::known(F::from(b)))?;
+                }
+                Ok(())
+            }
+        )
+    }
+
+    // Out of range values get limbs that do not add up to them, so the gate rejects them
+    fn assign<F: ScalarField>(&self, region: &mut Region<F>, offset: usize, value: F) -> Result<(), Error> {
+
+        let v = value.get_lower_32() as u64;
+        for (i, limb) in self.limbs.iter().enumerate() {
+            region.assign_advice(*limb, offset, Value::known(F::from((v >> (8 * i)) & 0xff)));
+        }
+        self.q_range.enable(region, offset)
+    }
 }
 
 
@@ -44,7 +136,9 @@ pub struct StateMachineConfig<F: ScalarField> {
     pub transcript: Column<Advice>,
     pub q_lookup: Selector,
     pub lookup: [TableColumn; 3],
-    _strategy: StateMachineStrategy,
+    pub instance: Column<Instance>, // s_0 and s_n of the transcript, so chunks can be linked through their boundary states
+    pub byte_limbs: Option<ByteLimbs>, // Tuple strategy only
+    strategy: StateMachineStrategy,
 
 }
 
@@ -62,7 +156,7 @@ impl<F: ScalarField> StateMachineConfig<F> {
         let gate = FlexGateConfig::configure(
             meta,
             match state_machine_strategy {
-                StateMachineStrategy::Vertical => GateStrategy::Vertical,
+                StateMachineStrategy::Vertical | StateMachineStrategy::Tuple => GateStrategy::Vertical,
             },
             num_advice,
             num_fixed,
@@ -72,15 +166,24 @@ impl<F: ScalarField> StateMachineConfig<F> {
         let transcript = meta.advice_column();
         let q_lookup = meta.complex_selector();
         let lookup = [();3].map(|_| meta.lookup_table_column());
+        let instance = meta.instance_column();
 
         meta.enable_equality(transcript);
+        meta.enable_equality(instance);
+
+        let byte_limbs = match state_machine_strategy {
+            StateMachineStrategy::Vertical => None,
+            StateMachineStrategy::Tuple => Some(ByteLimbs::configure(meta, transcript)),
+        };
 
         let config = Self {
             gate,
             transcript,
             q_lookup,
             lookup,
-            _strategy: state_machine_strategy,
+            instance,
+            byte_limbs,
+            strategy: state_machine_strategy,
         };
 
         config.create_lookup(meta);
@@ -94,50 +197,82 @@ impl<F: ScalarField> StateMachineConfig<F> {
 
     fn create_lookup(&self, meta: &mut ConstraintSystem<F>) {
 
-        meta.lookup(
-            "State Transition Lookups", 
-            |meta| {
-                let ql = meta.query_selector(self.q_lookup); // only turned on for odd idx
-                let curr_state = meta.query_advice(self.transcript, Rotation::cur());
-                let mutation = meta.query_advice(self.transcript, Rotation::next());
-                let next_state = meta.query_advice(self.transcript, Rotation(2));
-
-                vec![
-                    (ql.clone() * curr_state, self.lookup[0]),
-                    (ql.clone() * next_state, self.lookup[1]),
-                    (ql * mutation, self.lookup[2]),
-                ]
-            }
-        );
+        // When q_lookup is off, look up (start, start, ' ') instead of (0, 0, 0). The table has no
+        // all-zero row to fall back on: state 0 is the start state and '\0' is not a valid action from it
+        let start: StateId = State::start().encode();
+        let space = ActionId::from(' ');
+        let default_row = [start.0, start.0, space.0].map(|x| Expression::Constant(F::from(x)));
 
-    }
+        match self.strategy {
+
+            StateMachineStrategy::Vertical => {
+                meta.lookup(
+                    "State Transition Lookups", 
+                    |meta| {
+                        let ql = meta.query_selector(self.q_lookup); // only turned on for even idx
+                        let curr_state = meta.query_advice(self.transcript, Rotation::cur());
+                        let mutation = meta.query_advice(self.transcript, Rotation::next());
+                        let next_state = meta.query_advice(self.transcript, Rotation(2));
+
+                        let one = Expression::Constant(F::one());
+                        let not_ql = one - ql.clone();
+
+                        vec![
+                            (ql.clone() * curr_state + not_ql.clone() * default_row[0].clone(), self.lookup[0]),
+                            (ql.clone() * next_state + not_ql.clone() * default_row[1].clone(), self.lookup[1]),
+                            (ql * mutation + not_ql * default_row[2].clone(), self.lookup[2]),
+                        ]
+                    }
+                );
+            },
+
+            StateMachineStrategy::Tuple => {
+                let default_packed = Expression::Constant(F::from_u128(pack_transition(start, space, start)));
+                let base = Expression::Constant(F::from(TUPLE_BASE));
+
+                meta.lookup(
+                    "State Transition Tuple Lookup",
+                    |meta| {
+                        let ql = meta.query_selector(self.q_lookup); // only turned on for even idx
+                        let curr_state = meta.query_advice(self.transcript, Rotation::cur());
+                        let mutation = meta.query_advice(self.transcript, Rotation::next());
+                        let next_state = meta.query_advice(self.transcript, Rotation(2));
+
+                        let one = Expression::Constant(F::one());
+                        let not_ql = one - ql.clone();
+                        let packed = curr_state + base.clone() * mutation + base.clone() * base * next_state;
 
-    fn load_lookup_table(&self, layouter: &mut impl Layouter<F>) -> Result<(),Error>{
+                        vec![(ql * packed + not_ql * default_packed, self.lookup[0])]
+                    }
+                );
+            },
 
-        // load data from text file
-        // use std::fs::File;
-        // use std::io::{BufRead, BufReader};
+        }
 
-        let mut contents: Vec<(u64, u64, char)> = Vec::new();
-        // let file = File::open("./data/state_transition_table.txt").expect("Failed to open file");
-        // let reader = BufReader::new(file);
-        // for line in reader.lines(){
-        //     let row = line.unwrap();
-        //     let buffer: Vec<_> = row.split_ascii_whitespace().collect();
-        //     let start_state = buffer[0].parse::<u64>().unwrap();
-        //     let end_state = buffer[1].parse::<u64>().unwrap();
-        //     let mutation = buffer[2].parse::<char>().unwrap();
-        //     contents.push((start_state, end_state, mutation));
-        // }
+    }
 
-        // Test lookup
-        contents.push((0, 1, 'a'));
-        contents.push((1, 2, 'b'));
+    // Load the transition table written by `gen_lookup`, e.g. "./data/lookup_table.txt"
+    pub fn load_lookup_table(&self, layouter: &mut impl Layouter<F>, path: &str) -> Result<(),Error>{
 
+        // load data from text file. A missing or malformed file has no table to load
+        let contents = read_lookup_table(path).map_err(|_| Error::Synthesis)?;
+        self.load_transitions(layouter, &contents)
+    }
+
+    // Same table as `load_lookup_table`, generated in memory by `transition_table` instead of read from a file
+    pub fn load_generated_table(&self, layouter: &mut impl Layouter<F>) -> Result<(),Error>{
+        self.load_transitions(layouter, &transition_table())
+    }
+
+    // Assign (begin, end, char) rows into the lookup columns in the layout the strategy expects
+    fn load_transitions(&self, layouter: &mut impl Layouter<F>, contents: &[(StateId, StateId, ActionId)]) -> Result<(),Error>{
 
         // metadata
         let n = contents.len();
-        let columns = vec![(0, "begin_state"), (1, "end_state"), (2, "mutation")];
+        let columns = match self.strategy {
+            StateMachineStrategy::Vertical => vec![(0, "begin_state"), (1, "end_state"), (2, "mutation")],
+            StateMachineStrategy::Tuple => vec![(0, "packed")],
+        };
 
         // build lookup table
         layouter.assign_table(
@@ -147,10 +282,12 @@ impl<F: ScalarField> StateMachineConfig<F> {
                 for col in columns.clone() {
                     for idx in 0..n {
 
-                        let value = match col.0 {
-                            0 => contents[idx].0,
-                            1 => contents[idx].1,
-                            2 => contents[idx].2 as u64,
+                        let (begin, end, action) = contents[idx];
+                        let value = match (self.strategy, col.0) {
+                            (StateMachineStrategy::Vertical, 0) => begin.0 as u128,
+                            (StateMachineStrategy::Vertical, 1) => end.0 as u128,
+                            (StateMachineStrategy::Vertical, 2) => action.0 as u128,
+                            (StateMachineStrategy::Tuple, 0) => pack_transition(begin, action, end),
                             _ => unreachable!(),
                         };
 
@@ -158,7 +295,7 @@ impl<F: ScalarField> StateMachineConfig<F> {
                               || format!("State Transition Table: row {:?} {:?}", idx, col.1),
                               self.lookup[col.0],
                               idx,
-                              || Value::known(F::from(value)),
+                              || Value::known(F::from_u128(value)),
                         )?;  
                     }
                 }
@@ -166,16 +303,158 @@ impl<F: ScalarField> StateMachineConfig<F> {
             }
         )?;
 
+        if let Some(byte_limbs) = &self.byte_limbs {
+            byte_limbs.load(layouter)?;
+        }
+
         Ok(())
     }
+
+    // Lay the advice of a Context built with `StateMachineChip::assign_input` out in the transcript column
+    // | s_0 | a_0 | s_1 | a_1 | ... | s_n |, turning the lookup on at every state except the last.
+    // s_0 and s_n are constrained to instance rows 0 and 1, see `StateMachineChip::instances`
+    pub fn assign_transcript(&self, layouter: &mut impl Layouter<F>, ctx: &Context<F>) -> Result<(), Error> {
+
+        let n = ctx.advice.len();
+
+        let ends = layouter.assign_region(
+            || "State Machine Transcript",
+            |mut region| {
+                let mut ends = vec![];
+                for (idx, value) in ctx.advice.iter().enumerate() {
+                    let cell = region.assign_advice(self.transcript, idx, Value::known(*value));
+                    if idx == 0 || idx + 1 == n {
+                        ends.push(cell.cell());
+                    }
+                    if idx % 2 == 0 && idx + 2 < n {
+                        self.q_lookup.enable(&mut region, idx)?;
+                    }
+                    if let Some(byte_limbs) = &self.byte_limbs {
+                        byte_limbs.assign(&mut region, idx, *value)?;
+                    }
+                }
+                Ok(ends)
+            }
+        )?;
+
+        match ends[..] {
+            [s_0, s_n] => {
+                layouter.constrain_instance(s_0, self.instance, 0)?;
+                layouter.constrain_instance(s_n, self.instance, 1)
+            },
+            [s_0] => { // no input: s_0 is also s_n
+                layouter.constrain_instance(s_0, self.instance, 0)?;
+                layouter.constrain_instance(s_0, self.instance, 1)
+            },
+            _ => Err(Error::Synthesis), // an empty Context has no s_0
+        }
+    }
     
 }
 
+// Read the transition table written by `gen_lookup`, one "begin end 'c'" row per line.
+// Transitions into the invalid state are dropped, so that invalid input has no satisfying transcript.
+// The class rows are added as in `transition_table`, so both give the same table. A line that is not such a row is
+// an InvalidData error
+pub fn read_lookup_table(path: &str) -> io::Result<Vec<(StateId, StateId, ActionId)>> {
+
+    let invalid: StateId = State::invalid().encode();
+
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+
+    let mut contents: Vec<(StateId, StateId, ActionId)> = Vec::new();
+    for (idx, line) in reader.lines().enumerate() {
+        let row = line?;
+        let bad_row = || io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}: not a transition row: {:?}", path, idx + 1, row));
+        let buffer: Vec<_> = row.splitn(3, ' ').collect(); // the char itself may be ' '
+        let (start_state, end_state, mutation) = match buffer[..] {
+            [begin, end, c] => (begin.parse::<u64>().ok(), end.parse::<u64>().ok(), parse_char_literal(c)),
+            _ => return Err(bad_row()),
+        };
+        let (start_state, end_state, mutation) = match (start_state, end_state, mutation) {
+            (Some(begin), Some(end), Some(c)) => (StateId(begin), StateId(end), ActionId::from(c)),
+            _ => return Err(bad_row()),
+        };
+        if end_state != invalid {
+            contents.push((start_state, end_state, mutation));
+        }
+    }
+    Ok(with_class_rows(contents))
+}
+
+// Rows halo2 keeps back for blinding, as in halo2-lib's default `minimum_rows`
+const UNUSABLE_ROWS: usize = 9;
+
+// Number of rows the transition table occupies in the lookup columns
+pub fn transition_table_rows() -> usize {
+    transition_table().len()
+}
+
+// Smallest k that fits both the transition table and the transcript | s_0 | a_0 | ... | s_n | for `input_len` bytes
+pub fn min_k_for_state_machine(input_len: usize) -> usize {
+    let transcript_rows = 2 * input_len + 1;
+    let rows = transition_table_rows().max(transcript_rows) + UNUSABLE_ROWS;
+    rows.next_power_of_two().trailing_zeros() as usize
+}
+
+// Inverse of `{:?}` for the chars 0..=255 that appear in the table. None for anything else
+fn parse_char_literal(literal: &str) -> Option<char> {
+
+    let inner = literal.strip_prefix('\'')?.strip_suffix('\'')?;
+    match inner {
+        "\\0" => Some('\0'),
+        "\\t" => Some('\t'),
+        "\\n" => Some('\n'),
+        "\\r" => Some('\r'),
+        "\\'" => Some('\''),
+        "\\\\" => Some('\\'),
+        _ if inner.starts_with("\\u{") => {
+            let code = u32::from_str_radix(inner.strip_prefix("\\u{")?.strip_suffix('}')?, 16).ok()?;
+            char::from_u32(code)
+        },
+        _ => {
+            let mut chars = inner.chars();
+            let c = chars.next()?;
+            chars.next().is_none().then_some(c)
+        },
+    }
+}
+
+// Inputs `mutate_state` cannot read a plain field element from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateMachineError {
+    NonTrivialValue, // an Existing cell holding a fraction
+    UnsupportedCell, // a WitnessFraction
+    UnknownToken,    // a pre-tokenized action that is `Value::unknown()`
+    UnknownState,    // an initial state that no input reaches, see `decode_checked`
+}
+
+impl std::fmt::Display for StateMachineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateMachineError::NonTrivialValue => write!(f, "state machine input is an assigned fraction, not a field element"),
+            StateMachineError::UnsupportedCell => write!(f, "state machine input is a WitnessFraction, not a field element"),
+            StateMachineError::UnknownToken => write!(f, "state machine token is unknown"),
+            StateMachineError::UnknownState => write!(f, "state machine initial state is not a reachable state"),
+        }
+    }
+}
+
+// For `synthesize`, which can only return halo2's error
+impl From<StateMachineError> for Error {
+    fn from(_: StateMachineError) -> Self {
+        Error::Synthesis
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct StateMachineChip<F: ScalarField> {
     strategy: StateMachineStrategy,
+    initial_state: StateId,
     pub gate: GateChip<F>,
-    pub transition_table: Vec<(F, F, F)>,
+    pub transition_table: Vec<(StateId, StateId, ActionId)>,
+    _marker: PhantomData<F>,
 }
 
 pub trait StateMachineInstructions<F: ScalarField> {
@@ -184,31 +463,98 @@ pub trait StateMachineInstructions<F: ScalarField> {
 
     fn gate(&self) -> &Self::Gate;
     fn strategy(&self) -> StateMachineStrategy;
-    fn next_state(&self, start: F, action: F) -> F;
+    /// Off-circuit step through the transition table. States and actions are distinct types, so they cannot be swapped:
+    /// ```compile_fail
+    /// use halo2_base::halo2_proofs::halo2curves::bn256::Fr;
+    /// use halo2_scaffold::state_machine_chip::json_gate::{StateMachineChip, StateMachineInstructions, StateMachineStrategy};
+    /// use halo2_scaffold::state_machine_chip::json_state_machine::{transition_table, ActionId, StateId};
+    ///
+    /// let chip = StateMachineChip::<Fr>::new(StateMachineStrategy::Vertical, transition_table());
+    /// chip.next_state(ActionId::from('{'), StateId(0));
+    /// ```
+    fn next_state(&self, start: StateId, action: ActionId) -> StateId;
     fn mutate_state(
         &self,
         ctx: &mut Context<F>,
         start: impl Into<QuantumCell<F>>,
         action: impl Into<QuantumCell<F>>,
-    ) -> AssignedValue<F>;
+    ) -> Result<AssignedValue<F>, StateMachineError>;
 
 }
 
 impl<F> StateMachineChip<F>
-where F: ScalarField + JsonStateMutation<State, StateBit, SpecialChar> + StateEncoding<u64>
+where F: ScalarField
 {
-    pub fn new(strategy: StateMachineStrategy, transition_table:Vec<(F,F,F)>) -> Self{
+    pub fn new(strategy: StateMachineStrategy, transition_table: Vec<(StateId, StateId, ActionId)>) -> Self{
         let gate = GateChip::new(
             match strategy {
-                StateMachineStrategy::Vertical => GateStrategy::Vertical,
+                StateMachineStrategy::Vertical | StateMachineStrategy::Tuple => GateStrategy::Vertical,
             },
         );
 
         Self {
             strategy,
+            initial_state: State::start().encode(),
             gate,
             transition_table,
+            _marker: PhantomData,
+        }
+    }
+
+    // Start the transcript from a state carried in from a previous chunk instead of `State::start()`
+    pub fn with_initial_state(mut self, initial_state: StateId) -> Self {
+        self.initial_state = initial_state;
+        self
+    }
+
+    pub fn initial_state(&self) -> StateId {
+        self.initial_state
+    }
+
+    // Public inputs of a transcript laid out by `StateMachineConfig::assign_transcript`: the initial state, and the
+    // state after `actions`. Without the final state any valid prefix would pass. A full document ends in a state
+    // whose stack bits are back to 1 (see `nesting`), which no nested close reaches, so the final state tells whether
+    // the containers balanced
+    pub fn instances(&self, actions: impl IntoIterator<Item = ActionId>) -> Vec<F> {
+        let last = actions.into_iter().fold(self.initial_state, |state, action| self.next_state(state, action));
+        vec![F::from(self.initial_state.0), F::from(last.0)]
+    }
+
+    pub fn from_lookup_table(strategy: StateMachineStrategy, table: &[(StateId, StateId, ActionId)]) -> Self {
+        Self::new(strategy, table.to_vec())
+    }
+
+    // Run the input through the state machine from `initial_state()`, appending s_0, a_0, s_1, a_1, ... to the Context.
+    // Returns the final state
+    pub fn assign_input(&self, ctx: &mut Context<F>, input: &[u8]) -> Result<AssignedValue<F>, StateMachineError> {
+
+        let mut state = self.load_initial_state(ctx)?;
+
+        for &b in input {
+            state = self.mutate_state(ctx, state, Witness(F::from(ActionId::from(b).0)))?;
         }
+        Ok(state)
+    }
+
+    // As `assign_input`, for input that was tokenized ahead of time into action ids, e.g. `SpecialChar::id()` per byte.
+    // The ids are trusted to match the bytes (say through a separate commitment); only the transitions are looked up
+    pub fn assign_tokens(&self, ctx: &mut Context<F>, tokens: &[Value<F>]) -> Result<AssignedValue<F>, StateMachineError> {
+
+        let mut state = self.load_initial_state(ctx)?;
+
+        for token in tokens {
+            let mut action = None;
+            token.map(|t| action = Some(t));
+            let action = action.ok_or(StateMachineError::UnknownToken)?;
+            state = self.mutate_state(ctx, state, Witness(action))?;
+        }
+        Ok(state)
+    }
+
+    // s_0, once `with_initial_state` is known to hold a reachable state
+    fn load_initial_state(&self, ctx: &mut Context<F>) -> Result<AssignedValue<F>, StateMachineError> {
+        decode_checked(self.initial_state.0).ok_or(StateMachineError::UnknownState)?;
+        Ok(ctx.load_witness(F::from(self.initial_state.0)))
     }
 }
 
@@ -227,9 +573,8 @@ where
         self.strategy
     }
 
-    fn next_state(&self, start: F, action: F) -> F {
+    fn next_state(&self, start: StateId, action: ActionId) -> StateId {
         let mut next_state = start;
-        let mut mutation = action;
         for (start_state, end_state, action_flag) in self.transition_table.iter() {
             if start == *start_state && action == *action_flag {
                 next_state = *end_state;
@@ -243,42 +588,706 @@ where
         ctx: &mut Context<F>,
         start: impl Into<QuantumCell<F>>,
         action: impl Into<QuantumCell<F>>,
-    ) -> AssignedValue<F>  {
+    ) -> Result<AssignedValue<F>, StateMachineError>  {
 
-        fn unpack<F: ScalarField>(qc: impl Into<QuantumCell<F>>) -> F {
+        fn unpack<F: ScalarField>(qc: impl Into<QuantumCell<F>>) -> Result<F, StateMachineError> {
 
-            fn unpack_assignedvalue<F: ScalarField>(av: AssignedValue<F>) -> F {
+            fn unpack_assignedvalue<F: ScalarField>(av: AssignedValue<F>) -> Result<F, StateMachineError> {
                 match av.value {
-                    Assigned::Trivial(av) => av,
-                    _ => panic!("Invalid assigned value"),
+                    Assigned::Trivial(av) => Ok(av),
+                    Assigned::Zero => Ok(F::zero()),
+                    _ => Err(StateMachineError::NonTrivialValue),
                 }
             }
 
-            let value = match qc.into() {
+            match qc.into() {
                 QuantumCell::Existing(f) => unpack_assignedvalue(f),
-                QuantumCell::Witness(f) => f,
-                QuantumCell::Constant(f) => f,
-                _ => panic!("Invalid start state"),
-            };
-
-            value 
+                QuantumCell::Witness(f) => Ok(f),
+                QuantumCell::Constant(f) => Ok(f),
+                _ => Err(StateMachineError::UnsupportedCell),
+            }
+        }
+        // State encodings and actions fit in 32 bits. Anything larger maps to an id no table row uses
+        fn to_id<F: ScalarField>(x: F) -> u64 {
+            let v = x.get_lower_32() as u64;
+            if F::from(v) == x { v } else { u64::MAX }
         }
-        let start_f = unpack(start);
-        let action_f = unpack(action);
-        let next_f = self.next_state(start_f, action_f);
 
-        // THIS IS almost 100% WRONG....
-        // Intent is to assign the incremental action, state pair to the column
-        // How do you initialize the first row? How do you choose which selector to flip?
+        let start_f = unpack(start)?;
+        let action_f = unpack(action)?;
+        let next = self.next_state(StateId(to_id(start_f)), ActionId(to_id(action_f)));
+        let next_f = F::from(next.0);
+
+        // Append the incremental action, state pair after `start`, which is the last cell in the Context:
         // | s_0 | a_0 | s_1 | a_1 | ... 
+        // No gates are turned on here; the transitions are checked by the lookup in `StateMachineConfig`
         ctx.assign_region(
             [Witness(action_f), Witness(next_f)],
-            [1]
+            []
         );
-        ctx.get(-1)
+        Ok(ctx.get(-1))
         
     }
 
 }
 
-// TODO: I think I need to make a builder...
\ No newline at end of file
+// k of every `ChunkCircuit`: the smallest that fits the transition table, and with it chunks of up to ~16K bytes
+pub const CHUNK_K: usize = 15;
+
+// One chunk of a document, run through the chip from the state the chunk before it ended in (`State::start()` for the
+// first). The layout only depends on the chunk's length, so chunks of one length share their keys
+#[derive(Clone, Debug)]
+pub struct ChunkCircuit {
+    pub initial: StateId,
+    pub input: Vec<u8>,
+}
+
+impl ChunkCircuit {
+
+    // Any chunk of `len` bytes, for keygen
+    fn blank(len: usize) -> Self {
+        Self { initial: State::start().encode(), input: vec![b' '; len] }
+    }
+}
+
+impl Circuit<Fr> for ChunkCircuit {
+
+    type Config = StateMachineConfig<Fr>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        self.clone()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+        StateMachineConfig::configure(meta, StateMachineStrategy::Vertical, &[1], 1, CHUNK_K)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+
+        let chip = StateMachineChip::<Fr>::from_lookup_table(StateMachineStrategy::Vertical, &transition_table())
+            .with_initial_state(self.initial);
+        let mut ctx = Context::new(false, 0);
+        chip.assign_input(&mut ctx, &self.input)?;
+
+        config.load_generated_table(&mut layouter)?;
+        config.assign_transcript(&mut layouter, &ctx)
+    }
+}
+
+// A chunk's proof with its public inputs, the states it starts from and ends in. The bytes stay hidden; their number
+// is baked into the layout, so it is public too
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkProof {
+    pub len: usize,
+    pub initial: StateId,
+    pub last: StateId,
+    pub proof: Vec<u8>,
+}
+
+impl ChunkProof {
+
+    // As `StateMachineChip::instances`
+    pub fn instances(&self) -> Vec<Fr> {
+        vec![Fr::from(self.initial.0), Fr::from(self.last.0)]
+    }
+}
+
+// A document proven in chunks of `chunk_len` bytes, one `ChunkCircuit` each, with what it takes to re-prove some of
+// them: the KZG params (k = `CHUNK_K`) and a proving key per chunk length. Every chunk costs a full `CHUNK_K` proof,
+// so chunks pay off on documents of many of them where a change touches few, see `reprove_with_change`
+pub struct ChunkedProofContext {
+    params: ParamsKZG<Bn256>,
+    keys: Vec<(usize, ProvingKey<G1Affine>)>,
+    chunk_len: usize,
+    document: Vec<u8>,
+    chunks: Vec<ChunkProof>,
+}
+
+impl ChunkedProofContext {
+
+    // Fails on chunks of no bytes or of more than `CHUNK_K` fits, and on params for another k
+    pub fn new(params: ParamsKZG<Bn256>, chunk_len: usize) -> Result<Self, Error> {
+
+        if chunk_len == 0 || min_k_for_state_machine(chunk_len) > CHUNK_K || params.k() as usize != CHUNK_K {
+            return Err(Error::Synthesis);
+        }
+        Ok(Self { params, keys: vec![], chunk_len, document: vec![], chunks: vec![] })
+    }
+
+    pub fn params(&self) -> &ParamsKZG<Bn256> {
+        &self.params
+    }
+
+    pub fn document(&self) -> &[u8] {
+        &self.document
+    }
+
+    pub fn chunks(&self) -> &[ChunkProof] {
+        &self.chunks
+    }
+
+    // Prove every chunk of `document` anew, reusing the keys only. Returns the chunks proven, all of them. On an error
+    // the context is left with no document
+    pub fn prove(&mut self, document: &[u8]) -> Result<Vec<usize>, Error> {
+        self.document.clear();
+        self.chunks.clear();
+        self.prove_from(document.to_vec(), 0)
+    }
+
+    // Prove `document` from chunk `first` on, keeping the proofs before it, and after it those of the chunks with the
+    // same bytes and initial state as before: the same transcript. Returns the chunks proven. On an error the document
+    // and its proofs stay as they were
+    fn prove_from(&mut self, document: Vec<u8>, first: usize) -> Result<Vec<usize>, Error> {
+
+        let chip = StateMachineChip::<Fr>::from_lookup_table(StateMachineStrategy::Vertical, &transition_table());
+        let previous: Vec<&[u8]> = self.document.chunks(self.chunk_len).collect();
+        let mut chunks = self.chunks[..first].to_vec();
+        let mut initial: StateId = match chunks.last() {
+            Some(chunk) => chunk.last,
+            None => State::start().encode(),
+        };
+        let mut proven = vec![];
+
+        for (idx, input) in document.chunks(self.chunk_len).enumerate().skip(first) {
+
+            let kept = self.chunks.get(idx).filter(|chunk| chunk.initial == initial && previous.get(idx) == Some(&input));
+            let chunk = match kept {
+                Some(chunk) => chunk.clone(),
+                None => {
+                    let last = input.iter().fold(initial, |state, &b| chip.next_state(state, ActionId::from(b)));
+                    let mut chunk = ChunkProof { len: input.len(), initial, last, proof: vec![] };
+                    let pk = chunk_key(&self.params, &mut self.keys, input.len())?;
+                    chunk.proof = prove_chunk(&self.params, pk, ChunkCircuit { initial, input: input.to_vec() }, &chunk.instances())?;
+                    proven.push(idx);
+                    chunk
+                },
+            };
+
+            initial = chunk.last;
+            chunks.push(chunk);
+        }
+
+        self.document = document;
+        self.chunks = chunks;
+        Ok(proven)
+    }
+}
+
+// The proving key for chunks of `len` bytes, generated on first use
+fn chunk_key<'a>(params: &ParamsKZG<Bn256>, keys: &'a mut Vec<(usize, ProvingKey<G1Affine>)>, len: usize) -> Result<&'a ProvingKey<G1Affine>, Error> {
+
+    let idx = match keys.iter().position(|(key_len, _)| *key_len == len) {
+        Some(idx) => idx,
+        None => {
+            let circuit = ChunkCircuit::blank(len);
+            let vk = keygen_vk(params, &circuit)?;
+            keys.push((len, keygen_pk(params, vk, &circuit)?));
+            keys.len() - 1
+        },
+    };
+    Ok(&keys[idx].1)
+}
+
+// KZG, SHPLONK and a Blake2b transcript, as in examples/json_bench.rs
+fn prove_chunk(params: &ParamsKZG<Bn256>, pk: &ProvingKey<G1Affine>, circuit: ChunkCircuit, instances: &[Fr]) -> Result<Vec<u8>, Error> {
+
+    let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
+    create_proof::<
+        KZGCommitmentScheme<Bn256>,
+        ProverSHPLONK<'_, Bn256>,
+        Challenge255<G1Affine>,
+        _,
+        Blake2bWrite<Vec<u8>, G1Affine, Challenge255<_>>,
+        _,
+    >(params, pk, &[circuit], &[&[instances]], OsRng, &mut transcript)?;
+    Ok(transcript.finalize())
+}
+
+fn verify_chunk(params: &ParamsKZG<Bn256>, vk: &VerifyingKey<G1Affine>, chunk: &ChunkProof) -> bool {
+
+    let mut transcript = Blake2bRead::<_, G1Affine, Challenge255<_>>::init(&chunk.proof[..]);
+    verify_proof::<
+        KZGCommitmentScheme<Bn256>,
+        VerifierSHPLONK<'_, Bn256>,
+        Challenge255<G1Affine>,
+        Blake2bRead<&[u8], G1Affine, Challenge255<G1Affine>>,
+        SingleStrategy<'_, Bn256>,
+    >(params.verifier_params(), vk, SingleStrategy::new(params), &[&[&chunk.instances()[..]]], &mut transcript)
+    .is_ok()
+}
+
+// Replace `byte_range` of the document with `new_bytes` and re-prove only what that reaches: the chunks the change
+// falls in, then each later chunk whose bytes or initial state moved. A change of the same length leaves the bytes
+// after it in their chunks, so their proofs stand once a chunk ends in the same state as before; any other change
+// shifts every chunk after it. Returns the chunks re-proven
+pub fn reprove_with_change(base_proof_ctx: &mut ChunkedProofContext, byte_range: Range<usize>, new_bytes: &[u8]) -> Result<Vec<usize>, Error> {
+
+    if byte_range.start > byte_range.end || byte_range.end > base_proof_ctx.document.len() {
+        return Err(Error::Synthesis);
+    }
+
+    let old = &base_proof_ctx.document;
+    let document = [&old[..byte_range.start], new_bytes, &old[byte_range.end..]].concat();
+
+    // The chunks wholly before the change keep their bytes and states
+    let first = byte_range.start / base_proof_ctx.chunk_len;
+    base_proof_ctx.prove_from(document, first)
+}
+
+// Verify every chunk's proof, each against the key of its length, and link them: the first starts from
+// `State::start()`, every other one from the state the one before ended in. Err with the first chunk that fails
+// either way, or with `chunks.len()` if the last one leaves the document open (see `nesting`)
+pub fn verify_chunked(params: &ParamsKZG<Bn256>, chunks: &[ChunkProof]) -> Result<(), usize> {
+
+    let mut vk: Option<(usize, VerifyingKey<G1Affine>)> = None;
+    let mut expected: StateId = State::start().encode();
+    for (idx, chunk) in chunks.iter().enumerate() {
+
+        // Chunks come in runs of one length, all full ones then the last
+        if vk.as_ref().map(|(len, _)| *len) != Some(chunk.len) {
+            vk = keygen_vk(params, &ChunkCircuit::blank(chunk.len)).ok().map(|vk| (chunk.len, vk));
+        }
+
+        let verified = vk.as_ref().map_or(false, |(_, vk)| verify_chunk(params, vk, chunk));
+        if chunk.initial != expected || !verified {
+            return Err(idx);
+        }
+        expected = chunk.last;
+    }
+
+    match decode_checked(expected.0) {
+        Some(state) if !chunks.is_empty() && nesting(&state) == 1 => Ok(()),
+        _ => Err(chunks.len()),
+    }
+}
+
+// TODO: I think I need to make a builder...
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use halo2_base::halo2_proofs::dev::MockProver;
+
+    const K: usize = 15;
+
+    const LOOKUP_TABLE: &str = "./data/lookup_table.txt";
+
+    // Public inputs of a transcript of `input` that starts from `State::start()`
+    fn instances(input: &str) -> Vec<Vec<Fr>> {
+        let chip = StateMachineChip::<Fr>::from_lookup_table(StateMachineStrategy::Vertical, &transition_table());
+        vec![chip.instances(input.bytes().map(ActionId::from))]
+    }
+
+    // Runs the input through the chip in a fresh Context, then checks the transcript against the table
+    #[derive(Clone, Default)]
+    struct TranscriptCircuit {
+        input: String,
+    }
+
+    impl Circuit<Fr> for TranscriptCircuit {
+
+        type Config = StateMachineConfig<Fr>;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            StateMachineConfig::configure(meta, StateMachineStrategy::Vertical, &[1], 1, K)
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+
+            let chip = StateMachineChip::<Fr>::from_lookup_table(StateMachineStrategy::Vertical, &read_lookup_table(LOOKUP_TABLE).expect("Unable to read the lookup table"));
+            let mut ctx = Context::new(false, 0);
+            chip.assign_input(&mut ctx, self.input.as_bytes())?;
+
+            config.load_lookup_table(&mut layouter, LOOKUP_TABLE)?;
+            config.assign_transcript(&mut layouter, &ctx)
+        }
+    }
+
+    // As `TranscriptCircuit`, with the table generated in memory
+    #[derive(Clone, Default)]
+    struct GeneratedTableCircuit(TranscriptCircuit);
+
+    impl Circuit<Fr> for GeneratedTableCircuit {
+
+        type Config = StateMachineConfig<Fr>;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            StateMachineConfig::configure(meta, StateMachineStrategy::Vertical, &[1], 1, K)
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+
+            let chip = StateMachineChip::<Fr>::from_lookup_table(StateMachineStrategy::Vertical, &transition_table());
+            let mut ctx = Context::new(false, 0);
+            chip.assign_input(&mut ctx, self.0.input.as_bytes())?;
+
+            config.load_generated_table(&mut layouter)?;
+            config.assign_transcript(&mut layouter, &ctx)
+        }
+    }
+
+    // Pre-tokenized input, checked against the class rows of the generated table
+    #[derive(Clone, Default)]
+    struct TokenCircuit {
+        tokens: Vec<Value<Fr>>,
+    }
+
+    impl TokenCircuit {
+        fn from_str(input: &str) -> Self {
+            let tokens = input.chars().map(|c| Value::known(Fr::from(SpecialChar::from(c).id()))).collect();
+            Self { tokens }
+        }
+    }
+
+    impl Circuit<Fr> for TokenCircuit {
+
+        type Config = StateMachineConfig<Fr>;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            StateMachineConfig::configure(meta, StateMachineStrategy::Vertical, &[1], 1, K)
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+
+            let chip = StateMachineChip::<Fr>::from_lookup_table(StateMachineStrategy::Vertical, &transition_table());
+            let mut ctx = Context::new(false, 0);
+            chip.assign_tokens(&mut ctx, &self.tokens)?;
+
+            config.load_generated_table(&mut layouter)?;
+            config.assign_transcript(&mut layouter, &ctx)
+        }
+    }
+
+    // Same transcript, checked with the packed tuple lookup
+    #[derive(Clone, Default)]
+    struct TupleTranscriptCircuit(TranscriptCircuit);
+
+    impl Circuit<Fr> for TupleTranscriptCircuit {
+
+        type Config = StateMachineConfig<Fr>;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            StateMachineConfig::configure(meta, StateMachineStrategy::Tuple, &[1], 1, K)
+        }
+
+        fn synthesize(&self, config: Self::Config, layouter: impl Layouter<Fr>) -> Result<(), Error> {
+            self.0.synthesize(config, layouter)
+        }
+    }
+
+    // A `{"a":1}` transcript whose last action is moved out of range, with the next state adjusted so that
+    // the packed transition is unchanged: curr + B * (a - B) + B^2 * (next + 1) == curr + B * a + B^2 * next
+    #[derive(Clone, Default)]
+    struct ForgedTupleCircuit;
+
+    impl Circuit<Fr> for ForgedTupleCircuit {
+
+        type Config = StateMachineConfig<Fr>;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            StateMachineConfig::configure(meta, StateMachineStrategy::Tuple, &[1], 1, K)
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fr>) -> Result<(), Error> {
+
+            let chip = StateMachineChip::<Fr>::from_lookup_table(StateMachineStrategy::Tuple, &transition_table());
+            let mut honest = Context::new(false, 0);
+            chip.assign_input(&mut honest, "{\"a\":1}".as_bytes())?;
+
+            let mut cells = honest.advice.clone();
+            let n = cells.len();
+            cells[n - 2] -= Fr::from(TUPLE_BASE);
+            cells[n - 1] += Fr::from(1);
+
+            let mut ctx = Context::new(false, 0);
+            ctx.assign_region(cells.into_iter().map(Witness), []);
+
+            config.load_generated_table(&mut layouter)?;
+            config.assign_transcript(&mut layouter, &ctx)
+        }
+    }
+
+    #[test]
+    fn test_parse_char_literal() {
+        assert_eq!(parse_char_literal("' '"), Some(' '));
+        assert_eq!(parse_char_literal("'\"'"), Some('"'));
+        assert_eq!(parse_char_literal("'\\''"), Some('\''));
+        assert_eq!(parse_char_literal("'\\\\'"), Some('\\'));
+        assert_eq!(parse_char_literal("'\\u{1f}'"), Some('\u{1f}'));
+        assert_eq!(parse_char_literal("'ÿ'"), Some('ÿ'));
+
+        assert_eq!(parse_char_literal("''"), None);
+        assert_eq!(parse_char_literal("'ab'"), None);
+        assert_eq!(parse_char_literal("'\\u{zz}'"), None);
+        assert_eq!(parse_char_literal("x"), None);
+    }
+
+    #[test]
+    fn test_read_lookup_table_errors() {
+
+        let missing = std::env::temp_dir().join("zk_json_no_such_table.txt");
+        assert_eq!(read_lookup_table(missing.to_str().unwrap()).unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        let malformed = std::env::temp_dir().join("zk_json_malformed_table.txt");
+        std::fs::write(&malformed, "0 1 '{'\n0 x '}'\n").unwrap();
+        assert_eq!(read_lookup_table(malformed.to_str().unwrap()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_next_state_typed() {
+
+        let chip = StateMachineChip::<Fr>::from_lookup_table(StateMachineStrategy::Vertical, &transition_table());
+        let start: StateId = State::start().encode();
+
+        let new_dict: StateId = State::start().mutate(SpecialChar::OpenBrace).encode();
+        assert_eq!(chip.next_state(start, ActionId::from('{')), new_dict);
+        assert_eq!(chip.next_state(start, ActionId::from(b' ')), start);
+
+        // The file and the generated table agree
+        assert_eq!(read_lookup_table(LOOKUP_TABLE).unwrap(), transition_table());
+    }
+
+    #[test]
+    fn test_initial_state() {
+
+        // The state after the prefix {"a": of {"a":1}
+        let mut carried = State::start();
+        for c in "{\"a\":".chars() {
+            carried = carried.mutate(SpecialChar::from(c));
+        }
+        let carried: StateId = carried.encode();
+        let start: StateId = State::start().encode();
+
+        let chip = StateMachineChip::<Fr>::from_lookup_table(StateMachineStrategy::Vertical, &transition_table());
+        let suffix = || "1}".bytes().map(ActionId::from);
+        let from_carried = chip.clone().with_initial_state(carried).instances(suffix());
+        let from_start = chip.instances(suffix());
+
+        let circuit = ChunkCircuit { initial: carried, input: b"1}".to_vec() };
+        MockProver::run(K as u32, &circuit, vec![from_carried.clone()]).unwrap().assert_satisfied();
+
+        // The final state is the one the whole document ends in, so the next chunk can start from it
+        assert_eq!(from_carried[1], instances("{\"a\":1}")[0][1]);
+
+        // The suffix alone is not a document
+        let circuit = ChunkCircuit { initial: start, input: b"1}".to_vec() };
+        assert!(MockProver::run(K as u32, &circuit, vec![from_start]).unwrap().verify().is_err());
+
+        // s_0 must be the public initial state, even when the transitions are valid
+        let circuit = ChunkCircuit { initial: carried, input: b"1}".to_vec() };
+        let wrong_start = vec![Fr::from(start.0), from_carried[1]];
+        assert!(MockProver::run(K as u32, &circuit, vec![wrong_start]).unwrap().verify().is_err());
+    }
+
+    #[test]
+    fn test_reprove_with_change() {
+
+        // Chunks "[12", ", 3" and "]"
+        let params = ParamsKZG::<Bn256>::setup(CHUNK_K as u32, OsRng);
+        let mut ctx = ChunkedProofContext::new(params, 3).unwrap();
+        assert_eq!(ctx.prove(b"[12, 3]").unwrap(), vec![0, 1, 2]);
+        let before = ctx.chunks().to_vec();
+        assert_eq!(before.iter().map(|chunk| chunk.len).collect::<Vec<_>>(), vec![3, 3, 1]);
+        verify_chunked(ctx.params(), ctx.chunks()).unwrap();
+
+        // A string for the number: the second chunk keeps its bytes but starts from another state, and ends in the
+        // same one as before, so the third keeps its proof
+        assert_eq!(reprove_with_change(&mut ctx, 1..3, b"\"\"").unwrap(), vec![0, 1]);
+        assert_eq!(ctx.document(), b"[\"\", 3]");
+        assert_ne!(ctx.chunks()[1].initial, before[1].initial);
+        assert_eq!(ctx.chunks()[2], before[2]);
+        verify_chunked(ctx.params(), ctx.chunks()).unwrap();
+
+        // A longer number in the second chunk: it keeps its bytes and state, the last one is new
+        assert_eq!(reprove_with_change(&mut ctx, 5..6, b"34").unwrap(), vec![2]);
+        assert_eq!(ctx.document(), b"[\"\", 34]");
+        verify_chunked(ctx.params(), ctx.chunks()).unwrap();
+
+        // Past the end, nothing changes
+        assert!(reprove_with_change(&mut ctx, 7..9, b"").is_err());
+        assert_eq!(ctx.document(), b"[\"\", 34]");
+
+        // Chunks out of order, a document left open, and boundary states not the ones proven
+        let mut swapped = ctx.chunks().to_vec();
+        swapped.swap(0, 1);
+        assert_eq!(verify_chunked(ctx.params(), &swapped), Err(0));
+        assert_eq!(verify_chunked(ctx.params(), &ctx.chunks()[..2]), Err(2));
+        let mut forged = ctx.chunks().to_vec();
+        forged[1].last = State::start().encode();
+        forged[2].initial = forged[1].last;
+        assert_eq!(verify_chunked(ctx.params(), &forged), Err(1));
+    }
+
+    #[test]
+    fn test_mutate_state_errors() {
+
+        let chip = StateMachineChip::<Fr>::from_lookup_table(StateMachineStrategy::Vertical, &transition_table());
+        let mut ctx = Context::new(false, 0);
+        let start = ctx.load_witness(Fr::from(0));
+        let brace = Witness(Fr::from(ActionId::from('{').0));
+
+        let fraction = Assigned::Rational(Fr::from(1), Fr::from(2));
+        assert_eq!(chip.mutate_state(&mut ctx, QuantumCell::WitnessFraction(fraction), brace).err(), Some(StateMachineError::UnsupportedCell));
+
+        ctx.assign_region([QuantumCell::WitnessFraction(fraction)], []);
+        let existing_fraction = ctx.get(-1);
+        assert_eq!(chip.mutate_state(&mut ctx, existing_fraction, brace).err(), Some(StateMachineError::NonTrivialValue));
+
+        assert!(chip.mutate_state(&mut ctx, start, brace).is_ok());
+
+        // A carried-in state outside the reachable ones is an error, not a panic
+        for id in [1 << NUM_STATE_BITS, u64::MAX] {
+            let carried = chip.clone().with_initial_state(StateId(id));
+            assert_eq!(carried.assign_input(&mut ctx, b"1}").err(), Some(StateMachineError::UnknownState));
+            assert_eq!(carried.assign_tokens(&mut ctx, &[]).err(), Some(StateMachineError::UnknownState));
+        }
+    }
+
+    #[test]
+    fn test_load_generated_table() {
+
+        // (start, '{') -> NewDict is one of the rows
+        let start: StateId = State::start().encode();
+        let new_dict: StateId = State::start().mutate(SpecialChar::OpenBrace).encode();
+        assert!(transition_table().contains(&(start, new_dict, ActionId::from('{'))));
+
+        let input = "{\"a\": 1, \"b\": {\"c\": \"d\"}}";
+        let circuit = GeneratedTableCircuit(TranscriptCircuit { input: String::from(input) });
+        MockProver::run(K as u32, &circuit, instances(input)).unwrap().assert_satisfied();
+
+        let circuit = GeneratedTableCircuit(TranscriptCircuit { input: String::from("{\"a\"}") });
+        assert!(MockProver::run(K as u32, &circuit, instances("{\"a\"}")).unwrap().verify().is_err());
+    }
+
+    #[test]
+    fn test_nested_containers() {
+
+        // Containers of either kind inside each other, and the empty object, with both strategies
+        for input in ["{\"a\":[1,2]}", "[{\"a\":1}]", "{}"] {
+            let circuit = TranscriptCircuit { input: String::from(input) };
+            MockProver::run(K as u32, &circuit, instances(input)).unwrap().assert_satisfied();
+
+            let circuit = TupleTranscriptCircuit(circuit);
+            MockProver::run(K as u32, &circuit, instances(input)).unwrap().assert_satisfied();
+        }
+
+        // A container closed with the other kind has no row
+        let circuit = TranscriptCircuit { input: String::from("[{\"a\":1]") };
+        assert!(MockProver::run(K as u32, &circuit, instances("[{\"a\":1]")).unwrap().verify().is_err());
+
+        // A nested close does not end in the state of a closed document
+        assert_ne!(instances("[[1]")[0][1], instances("[[1]]")[0][1]);
+        let circuit = TranscriptCircuit { input: String::from("[[1]") };
+        assert!(MockProver::run(K as u32, &circuit, instances("[[1]]")).unwrap().verify().is_err());
+    }
+
+    #[test]
+    fn test_pre_tokenized_input() {
+
+        // {"a":1} as class ids: the letter and the digit are Other and Numeric, not their bytes
+        // The bytes and their class ids take the same transitions, so the public states are the same
+        let circuit = TokenCircuit::from_str("{\"a\":1}");
+        MockProver::run(K as u32, &circuit, instances("{\"a\":1}")).unwrap().assert_satisfied();
+
+        let circuit = TokenCircuit::from_str("{\"a\"}");
+        assert!(MockProver::run(K as u32, &circuit, instances("{\"a\"}")).unwrap().verify().is_err());
+
+        let chip = StateMachineChip::<Fr>::from_lookup_table(StateMachineStrategy::Vertical, &transition_table());
+        let mut ctx = Context::new(false, 0);
+        assert_eq!(chip.assign_tokens(&mut ctx, &[Value::unknown()]).err(), Some(StateMachineError::UnknownToken));
+    }
+
+    #[test]
+    fn test_transcript_lookup() {
+
+        let circuit = TranscriptCircuit { input: String::from("{\"a\":1}") };
+        MockProver::run(K as u32, &circuit, instances("{\"a\":1}")).unwrap().assert_satisfied();
+
+        // A valid prefix has valid transitions, but does not end in the public final state
+        let circuit = TranscriptCircuit { input: String::from("{\"a\":") };
+        MockProver::run(K as u32, &circuit, instances("{\"a\":")).unwrap().assert_satisfied();
+        assert!(MockProver::run(K as u32, &circuit, instances("{\"a\":1}")).unwrap().verify().is_err());
+    }
+
+    #[test]
+    fn test_min_k_for_state_machine() {
+
+        let input = String::from("{\"a\": 1, \"b\": {\"c\": \"xyz\"}}");
+        let k = min_k_for_state_machine(input.len());
+        assert_eq!(k, K);
+
+        let public = instances(&input);
+        let circuit = TranscriptCircuit { input };
+        MockProver::run(k as u32, &circuit, public).unwrap().assert_satisfied();
+
+        // A long transcript outgrows the table
+        assert_eq!(min_k_for_state_machine(1 << 14), 16);
+    }
+
+    #[test]
+    fn test_transcript_lookup_invalid() {
+        let circuit = TranscriptCircuit { input: String::from("{\"a\"}") };
+        assert!(MockProver::run(K as u32, &circuit, instances("{\"a\"}")).unwrap().verify().is_err());
+    }
+
+    #[test]
+    fn test_tuple_strategy() {
+
+        for input in ["{\"a\":1}", "{\"a\": {\"b\": \"c\\\"d\"}, \"e\": 2}"] {
+            let circuit = TranscriptCircuit { input: String::from(input) };
+            MockProver::run(K as u32, &circuit, instances(input)).unwrap().assert_satisfied();
+
+            let circuit = TupleTranscriptCircuit(circuit);
+            MockProver::run(K as u32, &circuit, instances(input)).unwrap().assert_satisfied();
+        }
+
+        let circuit = TupleTranscriptCircuit(TranscriptCircuit { input: String::from("{\"a\"}") });
+        assert!(MockProver::run(K as u32, &circuit, instances("{\"a\"}")).unwrap().verify().is_err());
+    }
+
+    #[test]
+    fn test_tuple_range_check() {
+
+        // The forged transition packs to a table row, so only the range check on the action can reject it
+        let (curr, action, next) = (5u64, u64::from('}'), 2u64);
+        let packed = Fr::from(curr) + Fr::from(TUPLE_BASE) * (Fr::from(action) - Fr::from(TUPLE_BASE))
+            + Fr::from(TUPLE_BASE) * Fr::from(TUPLE_BASE) * Fr::from(next + 1);
+        assert_eq!(packed, Fr::from_u128(pack_transition(StateId(curr), ActionId(action), StateId(next))));
+
+        // Public final state as forged, so that the copy constraint holds too
+        let mut public = instances("{\"a\":1}");
+        public[0][1] += Fr::from(1);
+
+        let errors = MockProver::run(K as u32, &ForgedTupleCircuit, public).unwrap().verify().unwrap_err();
+        assert!(errors.iter().all(|e| e.to_string().contains("Transcript byte limbs")), "{:?}", errors);
+    }
+
+}
\ No newline at end of file
@@ -0,0 +1,717 @@
+/// Reference JSON parser ==============================================
+/// Plain Rust, no circuits. Builds an owned tree of the document so it can be
+/// used directly and serve as an oracle for the circuit's path queries.
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonNode {
+    Null,
+    Bool(bool),
+    Number(String), // kept as written to avoid any float rounding
+    Str(String),
+    Array(Vec<JsonNode>),
+    Object(Vec<(String, JsonNode)>), // in document order, duplicate keys kept; lookups return the first
+}
+
+// Type of a node, without its contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonKind {
+    Null,
+    Bool,
+    Number,
+    Str,
+    Array,
+    Object,
+}
+
+// All errors carry the byte index into the input where parsing stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    UnexpectedChar(usize),
+    UnexpectedEnd(usize),
+    TrailingChars(usize),
+    InvalidEscape(usize), // index of the backslash
+    TooLong(usize), // index of the first byte over the limit, i.e. the limit itself
+    Empty, // nothing but whitespace, so there is no value at all
+    DuplicateKey(usize), // index of the repeated key's opening quote. Only from `parse_strict`
+    TooManyElements(usize), // index where a container went over the limit. From `validate_with_max_elems` and `JsonConfigParams::max_elems`, and from the top-level key circuits
+    NumberTooLong(usize), // index of the first digit past the limit. Only from the JSON circuit's pre-flight
+    TooDeep(usize), // index of the bracket that opens one container too many. Only from the JSON circuit's pre-flight
+}
+
+impl ParseError {
+
+    pub fn index(&self) -> usize {
+        use ParseError::*;
+        match self {
+            UnexpectedChar(idx) | UnexpectedEnd(idx) | TrailingChars(idx) | InvalidEscape(idx) | TooLong(idx) => *idx,
+            DuplicateKey(idx) | TooManyElements(idx) | NumberTooLong(idx) | TooDeep(idx) => *idx,
+            Empty => 0,
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        use ParseError::*;
+        match self {
+            UnexpectedChar(_) => "unexpected character",
+            UnexpectedEnd(_) => "unexpected end of input",
+            TrailingChars(_) => "trailing characters after the document",
+            InvalidEscape(_) => "invalid escape sequence",
+            TooLong(_) => "input too long",
+            Empty => "empty document",
+            DuplicateKey(_) => "duplicate key",
+            TooManyElements(_) => "too many elements in a container",
+            NumberTooLong(_) => "number too long",
+            TooDeep(_) => "containers nested too deep",
+        }
+    }
+
+    // Two lines: the line of `input` containing the error, then a caret under the offending char.
+    // Line and column are 1-based, and the column counts chars, not bytes
+    pub fn render(&self, input: &str) -> String {
+
+        let mut idx = self.index().min(input.len());
+        while !input.is_char_boundary(idx) {
+            idx -= 1;
+        }
+
+        let line_start = input[..idx].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = input[idx..].find('\n').map_or(input.len(), |i| idx + i);
+        let line_no = input[..idx].matches('\n').count() + 1;
+
+        // Keep tabs so the caret lines up however the line is displayed
+        let padding: String = input[line_start..idx].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+        let column = padding.chars().count() + 1;
+
+        format!("{}\n{}^ {} at line {}, column {}", &input[line_start..line_end], padding, self.message(), line_no, column)
+    }
+
+}
+
+// Duplicate keys are allowed (RFC 8259 only says SHOULD be unique) and kept in document order
+pub fn parse(input: &str) -> Result<JsonNode, ParseError> {
+    parse_with(input, false)
+}
+
+// As `parse`, but a key repeated within one object is a `DuplicateKey` error
+pub fn parse_strict(input: &str) -> Result<JsonNode, ParseError> {
+    parse_with(input, true)
+}
+
+fn parse_with(input: &str, strict: bool) -> Result<JsonNode, ParseError> {
+
+    let mut parser = Parser { bytes: input.as_bytes(), idx: 0, strict };
+
+    parser.skip_whitespace();
+    if parser.idx == parser.bytes.len() {
+        return Err(ParseError::Empty);
+    }
+    let node = parser.parse_value()?;
+    parser.skip_whitespace();
+
+    if parser.idx < parser.bytes.len() {
+        return Err(ParseError::TrailingChars(parser.idx));
+    }
+    Ok(node)
+}
+
+impl JsonNode {
+
+    // Value of the first entry with this key, if this is an object
+    pub fn get(&self, key: &str) -> Option<&JsonNode> {
+        match self {
+            JsonNode::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn kind(&self) -> JsonKind {
+        match self {
+            JsonNode::Null => JsonKind::Null,
+            JsonNode::Bool(_) => JsonKind::Bool,
+            JsonNode::Number(_) => JsonKind::Number,
+            JsonNode::Str(_) => JsonKind::Str,
+            JsonNode::Array(_) => JsonKind::Array,
+            JsonNode::Object(_) => JsonKind::Object,
+        }
+    }
+
+    // Whether this is an array whose elements are all of `kind`. An empty array is trivially homogeneous.
+    // The reference for `ArrayCircuit`'s element type
+    pub fn is_array_of(&self, kind: JsonKind) -> bool {
+        match self {
+            JsonNode::Array(elements) => elements.iter().all(|element| element.kind() == kind),
+            _ => false,
+        }
+    }
+
+    // Resolve a JSON Pointer (RFC 6901), e.g. "/a/b/0". The empty pointer is the whole document
+    pub fn pointer(&self, pointer: &str) -> Option<&JsonNode> {
+
+        if pointer.is_empty() {
+            return Some(self);
+        }
+        if !pointer.starts_with('/') {
+            return None;
+        }
+
+        pointer[1..].split('/').try_fold(self, |node, token| {
+
+            // ~1 must be decoded before ~0, so that "~01" becomes "~1" and not "/"
+            let token = token.replace("~1", "/").replace("~0", "~");
+
+            match node {
+                JsonNode::Object(_) => node.get(&token),
+                JsonNode::Array(elements) => array_index(&token).and_then(|idx| elements.get(idx)),
+                _ => None,
+            }
+        })
+    }
+
+    // Apply a JSON Merge Patch (RFC 7386): null deletes a key, objects merge recursively, anything else replaces
+    pub fn merge_patch(&self, patch: &JsonNode) -> JsonNode {
+
+        let patch_entries = match patch {
+            JsonNode::Object(entries) => entries,
+            _ => return patch.clone(),
+        };
+
+        let mut entries = match self {
+            JsonNode::Object(entries) => entries.clone(),
+            _ => vec![],
+        };
+
+        for (key, value) in patch_entries {
+            let existing = entries.iter().position(|(k, _)| k == key);
+            match (value, existing) {
+                (JsonNode::Null, Some(idx)) => { entries.remove(idx); },
+                (JsonNode::Null, None) => {},
+                (_, Some(idx)) => entries[idx].1 = entries[idx].1.merge_patch(value),
+                (_, None) => entries.push((key.clone(), JsonNode::Null.merge_patch(value))),
+            }
+        }
+
+        JsonNode::Object(entries)
+    }
+
+    pub fn from_value(value: &serde_json::Value) -> JsonNode {
+        use serde_json::Value;
+        match value {
+            Value::Null => JsonNode::Null,
+            Value::Bool(b) => JsonNode::Bool(*b),
+            Value::Number(n) => JsonNode::Number(n.to_string()),
+            Value::String(s) => JsonNode::Str(s.clone()),
+            Value::Array(elements) => JsonNode::Array(elements.iter().map(JsonNode::from_value).collect()),
+            Value::Object(entries) => JsonNode::Object(entries.iter().map(|(k, v)| (k.clone(), JsonNode::from_value(v))).collect()),
+        }
+    }
+
+    // Compact serialization with object keys sorted, so equivalent documents serialize identically.
+    // Numbers are kept as written, i.e. 1 and 1.0 stay distinct
+    pub fn to_canonical_string(&self) -> String {
+        let mut out = String::new();
+        self.write_canonical(&mut out);
+        out
+    }
+
+    fn write_canonical(&self, out: &mut String) {
+
+        match self {
+            JsonNode::Null => out.push_str("null"),
+            JsonNode::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            JsonNode::Number(n) => out.push_str(n),
+            JsonNode::Str(s) => write_canonical_str(s, out),
+            JsonNode::Array(elements) => {
+                out.push('[');
+                for (idx, element) in elements.iter().enumerate() {
+                    if idx > 0 {
+                        out.push(',');
+                    }
+                    element.write_canonical(out);
+                }
+                out.push(']');
+            },
+            JsonNode::Object(entries) => {
+                let mut sorted: Vec<&(String, JsonNode)> = entries.iter().collect();
+                sorted.sort_by(|a, b| a.0.cmp(&b.0));
+
+                out.push('{');
+                for (idx, (key, value)) in sorted.into_iter().enumerate() {
+                    if idx > 0 {
+                        out.push(',');
+                    }
+                    write_canonical_str(key, out);
+                    out.push(':');
+                    value.write_canonical(out);
+                }
+                out.push('}');
+            },
+        }
+    }
+
+}
+
+// Only '"', '\\' and control chars are escaped, everything else is written as is
+fn write_canonical_str(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// Array indices are decimal with no leading zeros; "-" (one past the end) never resolves
+fn array_index(token: &str) -> Option<usize> {
+    if token.is_empty() || !token.bytes().all(|b| b.is_ascii_digit()) || (token.len() > 1 && token.starts_with('0')) {
+        return None;
+    }
+    token.parse::<usize>().ok()
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    idx: usize,
+    strict: bool, // reject duplicate keys
+}
+
+impl<'a> Parser<'a> {
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.idx).copied()
+    }
+
+    fn next(&mut self) -> Result<u8, ParseError> {
+        let b = self.peek().ok_or(ParseError::UnexpectedEnd(self.idx))?;
+        self.idx += 1;
+        Ok(b)
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), ParseError> {
+        match self.next()? {
+            b if b == expected => Ok(()),
+            _ => Err(ParseError::UnexpectedChar(self.idx - 1)),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.idx += 1;
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<JsonNode, ParseError> {
+
+        match self.peek() {
+            Some(b'{') => self.parse_object(),
+            Some(b'[') => self.parse_array(),
+            Some(b'"') => Ok(JsonNode::Str(self.parse_string()?)),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(b't') => self.parse_literal("true", JsonNode::Bool(true)),
+            Some(b'f') => self.parse_literal("false", JsonNode::Bool(false)),
+            Some(b'n') => self.parse_literal("null", JsonNode::Null),
+            Some(_) => Err(ParseError::UnexpectedChar(self.idx)),
+            None => Err(ParseError::UnexpectedEnd(self.idx)),
+        }
+    }
+
+    fn parse_literal(&mut self, literal: &str, node: JsonNode) -> Result<JsonNode, ParseError> {
+        for b in literal.bytes() {
+            self.expect(b)?;
+        }
+        Ok(node)
+    }
+
+    fn parse_object(&mut self) -> Result<JsonNode, ParseError> {
+
+        let mut entries = vec![];
+        self.expect(b'{')?;
+        self.skip_whitespace();
+
+        if self.peek() == Some(b'}') {
+            self.idx += 1;
+            return Ok(JsonNode::Object(entries));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.unexpected());
+            }
+            let key_idx = self.idx;
+            let key = self.parse_string()?;
+            if self.strict && entries.iter().any(|(k, _)| *k == key) {
+                return Err(ParseError::DuplicateKey(key_idx));
+            }
+
+            self.skip_whitespace();
+            self.expect(b':')?;
+            self.skip_whitespace();
+            let value = self.parse_value()?;
+            entries.push((key, value));
+
+            self.skip_whitespace();
+            match self.next()? {
+                b',' => continue,
+                b'}' => return Ok(JsonNode::Object(entries)),
+                _ => return Err(ParseError::UnexpectedChar(self.idx - 1)),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<JsonNode, ParseError> {
+
+        let mut elements = vec![];
+        self.expect(b'[')?;
+        self.skip_whitespace();
+
+        if self.peek() == Some(b']') {
+            self.idx += 1;
+            return Ok(JsonNode::Array(elements));
+        }
+
+        loop {
+            self.skip_whitespace();
+            elements.push(self.parse_value()?);
+
+            self.skip_whitespace();
+            match self.next()? {
+                b',' => continue,
+                b']' => return Ok(JsonNode::Array(elements)),
+                _ => return Err(ParseError::UnexpectedChar(self.idx - 1)),
+            }
+        }
+    }
+
+    // Escapes are decoded into the characters they stand for
+    fn parse_string(&mut self) -> Result<String, ParseError> {
+
+        let mut buffer: Vec<u8> = vec![];
+        self.expect(b'"')?;
+
+        loop {
+            match self.next()? {
+                b'"' => break,
+                b'\\' => {
+                    let start = self.idx - 1;
+                    match self.next()? {
+                        b'"' => buffer.push(b'"'),
+                        b'\\' => buffer.push(b'\\'),
+                        b'/' => buffer.push(b'/'),
+                        b'n' => buffer.push(b'\n'),
+                        b't' => buffer.push(b'\t'),
+                        b'r' => buffer.push(b'\r'),
+                        b'b' => buffer.push(0x08),
+                        b'f' => buffer.push(0x0c),
+                        b'u' => {
+                            let c = self.parse_unicode_escape(start)?;
+                            let mut utf8 = [0u8; 4];
+                            buffer.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+                        },
+                        _ => return Err(ParseError::InvalidEscape(start)),
+                    }
+                },
+                b if b < 0x20 => return Err(ParseError::UnexpectedChar(self.idx - 1)),
+                b => buffer.push(b),
+            }
+        }
+
+        // Input is a &str and only whole code points are copied, so this cannot fail
+        Ok(String::from_utf8(buffer).unwrap())
+    }
+
+    // After "\u": four hex digits, or a surrogate pair "D83D\uDE00" for code points above U+FFFF.
+    // An unpaired surrogate has no char, so it is an InvalidEscape at its own backslash
+    fn parse_unicode_escape(&mut self, start: usize) -> Result<char, ParseError> {
+
+        let code = self.parse_hex4(start)?;
+
+        if (0xD800..=0xDBFF).contains(&code) && self.bytes[self.idx..].starts_with(b"\\u") {
+            let resume = self.idx;
+            self.idx += 2;
+            let low = self.parse_hex4(resume)?;
+            if (0xDC00..=0xDFFF).contains(&low) {
+                let c = char::from_u32(0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00)).unwrap();
+                return Ok(c);
+            }
+        }
+
+        char::from_u32(code).ok_or(ParseError::InvalidEscape(start))
+    }
+
+    fn parse_hex4(&mut self, start: usize) -> Result<u32, ParseError> {
+
+        let digits = self.bytes.get(self.idx..self.idx + 4).ok_or(ParseError::InvalidEscape(start))?;
+        if !digits.iter().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ParseError::InvalidEscape(start));
+        }
+        self.idx += 4;
+
+        // Only ASCII hex digits, so this cannot fail
+        Ok(u32::from_str_radix(std::str::from_utf8(digits).unwrap(), 16).unwrap())
+    }
+
+    fn parse_number(&mut self) -> Result<JsonNode, ParseError> {
+
+        let start = self.idx;
+
+        if self.peek() == Some(b'-') {
+            self.idx += 1;
+        }
+
+        // Integer part: a single 0, or digits without a leading 0
+        match self.next()? {
+            b'0' => {},
+            b'1'..=b'9' => self.skip_digits(),
+            _ => return Err(ParseError::UnexpectedChar(self.idx - 1)),
+        }
+
+        if self.peek() == Some(b'.') {
+            self.idx += 1;
+            self.expect_digits()?;
+        }
+
+        if let Some(b'e' | b'E') = self.peek() {
+            self.idx += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.idx += 1;
+            }
+            self.expect_digits()?;
+        }
+
+        // Only ASCII was consumed, so the slice is on char boundaries
+        let literal = std::str::from_utf8(&self.bytes[start..self.idx]).unwrap();
+        Ok(JsonNode::Number(literal.to_string()))
+    }
+
+    fn skip_digits(&mut self) {
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.idx += 1;
+        }
+    }
+
+    fn expect_digits(&mut self) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(b'0'..=b'9') => {
+                self.skip_digits();
+                Ok(())
+            },
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn unexpected(&self) -> ParseError {
+        match self.peek() {
+            Some(_) => ParseError::UnexpectedChar(self.idx),
+            None => ParseError::UnexpectedEnd(self.idx),
+        }
+    }
+
+}
+
+
+// Test cases
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+
+        let input = " {\"a\": {\"b\": [1, -2.5e3, \"x\\\"y\"]}, \"c\": true, \"d\": null} ";
+        let node = parse(input).unwrap();
+
+        let expected = JsonNode::Object(vec![
+            (String::from("a"), JsonNode::Object(vec![
+                (String::from("b"), JsonNode::Array(vec![
+                    JsonNode::Number(String::from("1")),
+                    JsonNode::Number(String::from("-2.5e3")),
+                    JsonNode::Str(String::from("x\"y")),
+                ])),
+            ])),
+            (String::from("c"), JsonNode::Bool(true)),
+            (String::from("d"), JsonNode::Null),
+        ]);
+        assert_eq!(node, expected);
+    }
+
+    #[test]
+    fn test_parse_escaped_slash() {
+        let node = parse("{\"a\": \"a\\/b\"}").unwrap();
+        assert_eq!(node.pointer("/a"), Some(&JsonNode::Str(String::from("a/b"))));
+    }
+
+    #[test]
+    fn test_parse_unescape() {
+        assert_eq!(parse("\"a\\n\\t\\r\\b\\f\\\\\\\"b\""), Ok(JsonNode::Str(String::from("a\n\t\r\u{8}\u{c}\\\"b"))));
+        assert_eq!(parse("\"\\u00e9\""), Ok(JsonNode::Str(String::from("é"))));
+        assert_eq!(parse("\"\\u00E9t\\u00e9\""), Ok(JsonNode::Str(String::from("été"))));
+    }
+
+    #[test]
+    fn test_parse_surrogate_pair() {
+        assert_eq!(parse("\"\\uD83D\\uDE00\""), Ok(JsonNode::Str(String::from("\u{1F600}"))));
+        assert_eq!(parse("\"x\\ud83d\\ude00y\""), Ok(JsonNode::Str(String::from("x😀y"))));
+    }
+
+    #[test]
+    fn test_parse_lone_surrogate() {
+        assert_eq!(parse("\"\\uD83D\""), Err(ParseError::InvalidEscape(1)));
+        assert_eq!(parse("\"\\uDE00\""), Err(ParseError::InvalidEscape(1)));
+        assert_eq!(parse("\"ab\\uD83D\\u0041\""), Err(ParseError::InvalidEscape(3)));
+        assert_eq!(parse("\"\\uDE00\\uD83D\""), Err(ParseError::InvalidEscape(1)));
+    }
+
+    #[test]
+    fn test_parse_invalid_escape() {
+        assert_eq!(parse("\"ab\\x\""), Err(ParseError::InvalidEscape(3)));
+        assert_eq!(parse("\"\\u12\""), Err(ParseError::InvalidEscape(1)));
+        assert_eq!(parse("\"\\u12G4\""), Err(ParseError::InvalidEscape(1)));
+        assert_eq!(parse("\"\\uD83D\\u12\""), Err(ParseError::InvalidEscape(7)));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("{\"a\" 1}"), Err(ParseError::UnexpectedChar(5)));
+        assert_eq!(parse("{\"a\": 1"), Err(ParseError::UnexpectedEnd(7)));
+        assert_eq!(parse("{\"a\": 1} x"), Err(ParseError::TrailingChars(9)));
+        assert_eq!(parse("[01]"), Err(ParseError::UnexpectedChar(2)));
+        assert_eq!(parse("\"a\tb\""), Err(ParseError::UnexpectedChar(2)));
+        assert_eq!(parse(""), Err(ParseError::Empty));
+        assert_eq!(parse("   "), Err(ParseError::Empty));
+    }
+
+    #[test]
+    fn test_render() {
+
+        let input = "{\n  \"a\": 1,\n  \"b\" 2\n}";
+        let err = parse(input).unwrap_err();
+        assert_eq!(err.render(input), "  \"b\" 2\n      ^ unexpected character at line 3, column 7");
+
+        // Columns count chars, not bytes
+        let input = "{\"é\": 1,}";
+        let err = parse(input).unwrap_err();
+        assert_eq!(err.render(input), "{\"é\": 1,}\n        ^ unexpected character at line 1, column 9");
+
+        let input = "{\"a\": 1";
+        assert_eq!(parse(input).unwrap_err().render(input), "{\"a\": 1\n       ^ unexpected end of input at line 1, column 8");
+    }
+
+    #[test]
+    fn test_pointer() {
+
+        let node = parse("{\"a\": {\"b\": 1, \"c\": [true, {\"d\": null}]}, \"a/b\": 2, \"m~n\": 3}").unwrap();
+
+        assert_eq!(node.pointer(""), Some(&node));
+        assert_eq!(node.pointer("/a/b"), Some(&JsonNode::Number(String::from("1"))));
+        assert_eq!(node.pointer("/a/c/0"), Some(&JsonNode::Bool(true)));
+        assert_eq!(node.pointer("/a/c/1/d"), Some(&JsonNode::Null));
+        assert_eq!(node.pointer("/a~1b"), Some(&JsonNode::Number(String::from("2"))));
+        assert_eq!(node.pointer("/m~0n"), Some(&JsonNode::Number(String::from("3"))));
+    }
+
+    #[test]
+    fn test_pointer_array_index() {
+
+        let node = parse("{\"a\": [\"x\", \"y\"]}").unwrap();
+
+        assert_eq!(node.pointer("/a/1"), Some(&JsonNode::Str(String::from("y"))));
+        assert_eq!(node.pointer("/a/2"), None);
+        assert_eq!(node.pointer("/a/01"), None);
+        assert_eq!(node.pointer("/a/-"), None);
+    }
+
+    #[test]
+    fn test_merge_patch() {
+
+        let base = parse("{\"a\":1,\"b\":2}").unwrap();
+        let patch = parse("{\"b\":null,\"c\":3}").unwrap();
+        assert_eq!(base.merge_patch(&patch), parse("{\"a\":1,\"c\":3}").unwrap());
+
+        // Nested objects merge; nulls inside added objects are dropped; non-objects replace
+        let base = parse("{\"a\": {\"x\": 1, \"y\": 2}, \"b\": [1]}").unwrap();
+        let patch = parse("{\"a\": {\"y\": null}, \"b\": [2], \"c\": {\"d\": null}}").unwrap();
+        assert_eq!(base.merge_patch(&patch), parse("{\"a\": {\"x\": 1}, \"b\": [2], \"c\": {}}").unwrap());
+    }
+
+    #[test]
+    fn test_canonical_string() {
+
+        let a = parse("{\"b\": [1, \"x\\\"\"], \"a\": {\"d\": null, \"c\": true}}").unwrap();
+        let b = parse("{\"a\":{\"c\":true,\"d\":null},\"b\":[1,\"x\\\"\"]}").unwrap();
+        assert_eq!(a.to_canonical_string(), "{\"a\":{\"c\":true,\"d\":null},\"b\":[1,\"x\\\"\"]}");
+        assert_eq!(a.to_canonical_string(), b.to_canonical_string());
+
+        let value = serde_json::json!({"a": {"c": true, "d": null}, "b": [1, "x\""]});
+        assert_eq!(JsonNode::from_value(&value).to_canonical_string(), a.to_canonical_string());
+    }
+
+    #[test]
+    fn test_canonical_equality() {
+
+        let a = parse("{\"a\":{\"x\":1,\"y\":2}}").unwrap();
+        let b = parse("{\"a\":{\"y\":2,\"x\":1}}").unwrap();
+        let c = parse("{\"a\":{\"x\":2,\"y\":1}}").unwrap();
+        assert_eq!(a.to_canonical_string(), b.to_canonical_string());
+        assert_ne!(a.to_canonical_string(), c.to_canonical_string());
+
+        // Arrays stay ordered
+        assert_ne!(parse("[1,2]").unwrap().to_canonical_string(), parse("[2,1]").unwrap().to_canonical_string());
+    }
+
+    #[test]
+    fn test_is_array_of() {
+
+        assert!(parse("[1,2,3]").unwrap().is_array_of(JsonKind::Number));
+        assert!(!parse("[1,\"x\"]").unwrap().is_array_of(JsonKind::Number));
+        assert!(!parse("[1,\"x\"]").unwrap().is_array_of(JsonKind::Str));
+
+        // Nested arrays count as arrays, not as their elements' type; non-arrays are never homogeneous
+        assert!(parse("[[1], [\"x\"]]").unwrap().is_array_of(JsonKind::Array));
+        assert!(parse("[]").unwrap().is_array_of(JsonKind::Null));
+        assert!(!parse("{\"a\": 1}").unwrap().is_array_of(JsonKind::Number));
+
+        let node = parse("{\"tags\": [\"a\", \"b\"], \"ids\": [1, null]}").unwrap();
+        assert!(node.pointer("/tags").unwrap().is_array_of(JsonKind::Str));
+        assert!(!node.pointer("/ids").unwrap().is_array_of(JsonKind::Number));
+    }
+
+    #[test]
+    fn test_object_order_and_duplicates() {
+
+        let node = parse("{\"b\": 1, \"a\": 2, \"b\": 3}").unwrap();
+        match &node {
+            JsonNode::Object(entries) => {
+                let keys: Vec<&str> = entries.iter().map(|(k, _)| k.as_str()).collect();
+                assert_eq!(keys, vec!["b", "a", "b"]);
+            },
+            _ => panic!("expected an object"),
+        }
+
+        // First wins, for get and pointer alike
+        assert_eq!(node.get("b"), Some(&JsonNode::Number(String::from("1"))));
+        assert_eq!(node.pointer("/b"), Some(&JsonNode::Number(String::from("1"))));
+        assert_eq!(node.get("c"), None);
+        assert_eq!(JsonNode::Null.get("b"), None);
+
+        assert_eq!(parse_strict("{\"b\": 1, \"a\": 2, \"b\": 3}"), Err(ParseError::DuplicateKey(17)));
+        assert!(parse_strict("{\"b\": {\"b\": 1}, \"a\": [{\"b\": 2}, {\"b\": 3}]}").is_ok());
+    }
+
+    #[test]
+    fn test_pointer_missing() {
+
+        let node = parse("{\"a\": {\"b\": 1}}").unwrap();
+
+        assert_eq!(node.pointer("/b"), None);
+        assert_eq!(node.pointer("/a/b/c"), None);
+        assert_eq!(node.pointer("/a/c"), None);
+        assert_eq!(node.pointer("a"), None);
+    }
+
+}
@@ -1,3 +1,5 @@
+use super::json_parser::ParseError;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum StateBit {
     IsInvalid = 0,
@@ -11,46 +13,97 @@ pub enum StateBit {
     IsStrEscaped = 8,
     WordBuffering = 9,
     WordComplete = 10,
+    NewArray = 11,
+    EndArray = 12,
+    InArray = 13,
+    AfterComma = 14, // an element or member is expected after ',', so ']' or '}' is invalid until one starts
+    SeenDecimalPoint = 15, // the number being read has its '.', so another one is invalid
+    // The open containers, as a number with a leading 1: 1 then one bit per container, outermost first, set for an
+    // array. 0 before the document opens, 1 once it closed. See `nesting`
+    Stack0 = 16,
+    Stack1 = 17,
+    Stack2 = 18,
+    Stack3 = 19,
 }
+// Number of StateBits, i.e. the bit width of a state encoding
+pub const NUM_STATE_BITS: usize = 20;
+
+// Deepest nesting of objects and arrays the state machine tracks. Every open container takes a stack bit, and the
+// leading 1 takes one more
+pub const MAX_NESTING: usize = 3;
+const STACK_BITS: [StateBit; MAX_NESTING + 1] = [Stack0, Stack1, Stack2, Stack3];
+
 impl StateBit {
-    fn from(id: u64) -> StateBit {
+
+    // Every bit, indexed by discriminant
+    pub const ALL: [StateBit; NUM_STATE_BITS] = {
         use StateBit::*;
-        match id {
-            0 => IsInvalid,
-            1 => NewDict,
-            2 => EndDict,
-            3 => Separator,
-            4 => IsKey,
-            5 => IsValue,
-            6 => KeyValueDelimiter,
-            7 => IsStr,
-            8 => IsStrEscaped,
-            9 => WordBuffering,
-            10 => WordComplete,
-            _ => panic!("Invalid state bit id: {}", id),
+        [IsInvalid, NewDict, EndDict, Separator, IsKey, IsValue, KeyValueDelimiter, IsStr, IsStrEscaped,
+         WordBuffering, WordComplete, NewArray, EndArray, InArray, AfterComma,
+         SeenDecimalPoint, Stack0, Stack1, Stack2, Stack3]
+    };
+
+    fn from(id: u64) -> StateBit {
+        match Self::ALL.get(id as usize) {
+            Some(&bit) => bit,
+            None => panic!("Invalid state bit id: {}", id),
         }
     }
 }
 
+// Discriminants must be exactly 0..NUM_STATE_BITS, or `1 << bit` could collide or overflow the encoding
+const _: () = {
+    let mut i = 0;
+    while i < NUM_STATE_BITS {
+        assert!(StateBit::ALL[i] as usize == i, "StateBit discriminants must match their index in StateBit::ALL");
+        i += 1;
+    }
+};
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct State(Vec<StateBit>);
 
+impl State {
+
+    // Build a state directly, e.g. to test a single transition. Repeated bits are kept once
+    pub fn from_bits(bits: &[StateBit]) -> State {
+        let mut state = State::new();
+        for &bit in bits {
+            state.on(bit);
+        }
+        state
+    }
+
+    pub fn bits(&self) -> &[StateBit] {
+        &self.0
+    }
+
+    // Clear the word bits, see `reset_word`
+    pub fn reset_word(&mut self) {
+        reset_word(self);
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum SpecialChar {
     Backslash = 0x5c,
     DoubleQuote = 0x22,
     OpenBrace = 0x7b,
     CloseBrace = 0x7d,
+    OpenBracket = 0x5b,
+    CloseBracket = 0x5d,
     Colon = 0x3a,
     Comma = 0x2c,
-    WhiteSpace,
-    Numeric,
-    Other
+    DecimalPoint = 0x2e,
+    // Classes of bytes rather than bytes, so their ids sit above 0xff and cannot collide with a real byte
+    WhiteSpace = 0x100,
+    Numeric = 0x101,
+    Other = 0x102,
 }
 
 impl SpecialChar {
 
-    fn from(ch: char) -> SpecialChar {
+    pub fn from(ch: char) -> SpecialChar {
 
         use SpecialChar::*;
         match ch {
@@ -58,13 +111,73 @@ impl SpecialChar {
             '"' => DoubleQuote,
             '{' => OpenBrace,
             '}' => CloseBrace,
+            '[' => OpenBracket,
+            ']' => CloseBracket,
             ':' => Colon,
             ',' => Comma,
+            '.' => DecimalPoint,
             c if c.is_whitespace() => WhiteSpace,
-            c if c.is_digit(10) || c == '.' => Numeric,
+            c if c.is_digit(10) => Numeric,
             _ => Other,
         }
     }
+
+    // Action id: the byte itself for the structural chars, 0x100.. for the classes
+    pub fn id(&self) -> u64 {
+        *self as u64
+    }
+
+    // Inverse of `ActionId::from`, also classifying raw bytes. None for ids that are neither a byte nor a class
+    pub fn from_action(action: ActionId) -> Option<SpecialChar> {
+
+        use SpecialChar::*;
+        match action.0 {
+            0..=0xff => Some(SpecialChar::from(char::from(action.0 as u8))),
+            id if id == WhiteSpace.id() => Some(WhiteSpace),
+            id if id == Numeric.id() => Some(Numeric),
+            id if id == Other.id() => Some(Other),
+            _ => None,
+        }
+    }
+}
+
+// State encoding, as produced by `StateEncoding::encode`. Kept apart from `ActionId` so the two cannot be swapped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StateId(pub u64);
+
+// Action as it appears in the transcript and the transition table: the raw input byte, or the id of its
+// `SpecialChar` class for input that was classified ahead of time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ActionId(pub u64);
+
+impl From<u64> for StateId {
+    fn from(id: u64) -> Self {
+        StateId(id)
+    }
+}
+
+impl From<StateId> for u64 {
+    fn from(id: StateId) -> Self {
+        id.0
+    }
+}
+
+impl From<u8> for ActionId {
+    fn from(b: u8) -> Self {
+        ActionId(b as u64)
+    }
+}
+
+impl From<char> for ActionId {
+    fn from(c: char) -> Self {
+        ActionId(c as u64)
+    }
+}
+
+impl From<SpecialChar> for ActionId {
+    fn from(c: SpecialChar) -> Self {
+        ActionId(c.id())
+    }
 }
 
 pub trait StateEncoding<T> {
@@ -107,17 +220,16 @@ where
         EncodeField::from(state_id)
     }
 
+    // Reads the low NUM_STATE_BITS bits only, so it is meant for ids out of the table.
+    // Ids from callers go through `decode_checked`, which rejects every id no input reaches
     fn decode(id: EncodeField) -> State
     {
         let mut state = State::new();
-        let mut id = id.into();
-        let mut i = 0;
-        while id != 0 {
-            if id % 2 == 1 {
-                state.on(StateBit::from(i));
+        let id = id.into();
+        for i in 0..NUM_STATE_BITS {
+            if (id >> i) & 1 == 1 {
+                state.on(StateBit::from(i as u64));
             }
-            id = id >> 1;
-            i += 1;
         }
         state
     }
@@ -200,14 +312,30 @@ where
         // Complete unconditional step mutations
         if state.check(NewDict){
             state.on(IsKey);
+            state.off(InArray);
             state.off(NewDict);
         }
         if state.check(EndDict){
             state.on(WordComplete); // for outer state
             state.off(EndDict);
         }
+        if state.check(NewArray){
+            state.on(IsValue);
+            state.on(InArray);
+            state.off(NewArray);
+        }
+        if state.check(EndArray){
+            state.on(WordComplete); // for outer state
+            state.off(EndArray);
+        }
         if state.check(Separator){
-            state.on(IsKey);
+            // Object commas expect a key next, array commas expect a value next. Either way something has to follow
+            if state.check(InArray) {
+                state.on(IsValue);
+            } else {
+                state.on(IsKey);
+            }
+            state.on(AfterComma);
             state.off(Separator);
         }
         if state.check(KeyValueDelimiter){
@@ -225,6 +353,12 @@ where
                     
                 OpenBrace => {
                     state.on(NewDict);
+                    push(&mut state, Container::Object);
+                },
+
+                OpenBracket => {
+                    state.on(NewArray);
+                    push(&mut state, Container::Array);
                 },
 
                 WhiteSpace => {
@@ -237,6 +371,13 @@ where
 
             }
 
+        } else if nesting(&state) == 1 {
+
+            // The document closed, so only whitespace may follow
+            if action != WhiteSpace {
+                state = S::invalid();
+            }
+
         } else if state.check(IsStr){
 
             match action {
@@ -257,6 +398,14 @@ where
 
             }
 
+        } else if awaiting_fraction_digit(&state) {
+
+            // Only a digit may follow the '.', so 1. is invalid before ',' '}' ']' and whitespace alike
+            match action {
+                Numeric => state.on(WordBuffering),
+                _ => state = S::invalid(),
+            }
+
         } else {
 
             match action {
@@ -265,37 +414,68 @@ where
                     if state.check(IsValue) && !state.check(WordComplete) {
                         state.on(NewDict);
                         state.off(IsValue);
+                        state.off(AfterComma);
+                        push(&mut state, Container::Object);
                     } else {
                         state = S::invalid();
                     }
                 }, 
 
+                // A pending key is fine only right after the '{', i.e. for {}: not after a ',' (e.g. {"a":1,}) and
+                // not once the key was read (e.g. {"a"}). Otherwise the value must have been read (see `value_read`)
                 CloseBrace => {
-                    if state.check(IsValue) {
+                    if state.check(IsKey) && !state.check(AfterComma) && !value_read(&state) {
                         state.on(EndDict);
-                        state.off(WordComplete); // For inner states
-                        state.off(WordBuffering); // just in case value is ... 123}
+                        state.off(IsKey);
+                        state.on(IsValue); // the object is the value of the container around it
+                        pop(&mut state);
+                    } else if state.check(IsValue) && !state.check(InArray) && value_read(&state) {
+                        state.on(EndDict);
+                        reset_word(&mut state);
+                        pop(&mut state);
+                    } else {
+                        state = S::invalid();
+                    }
+                },
+
+                OpenBracket => {
+                    if state.check(IsValue) && !state.check(WordComplete) {
+                        state.on(NewArray);
+                        state.off(IsValue);
+                        state.off(AfterComma);
+                        push(&mut state, Container::Array);
+                    } else {
+                        state = S::invalid();
+                    }
+                },
+
+                // No value is needed, as `[]` is valid, but a ',' must be followed by one (e.g. [1,] is invalid)
+                CloseBracket => {
+                    if state.check_and(vec![IsValue, InArray]) && !state.check(AfterComma) {
+                        state.on(EndArray);
+                        reset_word(&mut state); // ends the value, e.g. the number in [1,2]
+                        pop(&mut state);
                     } else {
                         state = S::invalid();
                     }
                 },
 
                 Comma => {
-                    if state.check(IsValue) {
+                    if state.check(IsValue) && value_read(&state) {
                         state.on(Separator);
                         state.off(IsValue);
-                        state.off(WordComplete);
-                        state.off(WordBuffering); // just in case value is ... 123,
+                        reset_word(&mut state);
                     } else {
                         state = S::invalid();
                     }
                 },
 
+                // Only after a complete key, and never when a value is already expected (e.g. "a"::1)
                 Colon => {
-                    if state.check(IsKey) {
+                    if state.check_and(vec![IsKey, WordComplete]) && !state.check_or(vec![IsValue, KeyValueDelimiter]) {
                         state.on(KeyValueDelimiter);
                         state.off(IsKey);
-                        state.off(WordComplete);
+                        reset_word(&mut state);
                     } else {
                         state = S::invalid();
                     }
@@ -307,6 +487,7 @@ where
                     } else {
                         state.on(IsStr);
                         state.on(WordBuffering);
+                        state.off(AfterComma);
                     }
                 },
 
@@ -314,18 +495,30 @@ where
                     if state.check_and(vec![IsValue, WordBuffering]) {
                         state.on(WordComplete);
                         state.off(WordBuffering);
+                        state.off(SeenDecimalPoint);
                     }
                 },
 
-                // TODO: A small bug with more than one decimal point, which should be invalid but is not
                 Numeric => {
                     if state.check(IsValue) && !state.check(WordComplete) {
                         state.on(WordBuffering);
+                        state.off(AfterComma);
                     } else {
                         state = S::invalid();
                     } 
                 },
 
+                // Only inside a number, so at least one digit comes before it (e.g. .5 is invalid), and only once (1.2.3).
+                // The number stops buffering until a digit follows, see `awaiting_fraction_digit`
+                DecimalPoint => {
+                    if state.check_and(vec![IsValue, WordBuffering]) && !state.check_or(vec![WordComplete, SeenDecimalPoint]) {
+                        state.on(SeenDecimalPoint);
+                        state.off(WordBuffering);
+                    } else {
+                        state = S::invalid();
+                    }
+                },
+
                 _ => state = S::invalid(),
             }
         }
@@ -334,6 +527,265 @@ where
     }
 }
 
+// Word lifecycle, for keys and values alike:
+//      - WordBuffering while a string or number is being read
+//      - WordComplete once it is done: closing quote, whitespace after a number, or the step after a nested '}' / ']'.
+//        A complete word cannot be followed by another word, hence '"' is invalid while WordComplete
+//      - Both are cleared by the token that ends the key or value: ':' ',' '}' ']', along with the number's SeenDecimalPoint
+fn reset_word<S: StateCheck<StateBit>>(state: &mut S) {
+    state.off(WordComplete);
+    state.off(WordBuffering);
+    state.off(SeenDecimalPoint);
+}
+
+// Whether a value has been read in the current slot, so that ',' or '}' may end it: either complete (a closed string,
+// a closed container, or a number followed by whitespace) or a number still buffering. A number has no closing char
+// of its own, so the ',' '}' ']' after it is what completes it
+fn value_read<S: StateCheck<StateBit>>(state: &S) -> bool {
+    state.check_or(vec![WordComplete, WordBuffering])
+}
+
+// Right after a number's '.': SeenDecimalPoint is only ever on without WordBuffering until the next digit
+fn awaiting_fraction_digit<S: StateCheck<StateBit>>(state: &S) -> bool {
+    state.check(SeenDecimalPoint) && !state.check(WordBuffering)
+}
+
+// Open containers in the stack bits, as a number: 0 before the document opens, then 1 followed by one bit per open
+// container (see `Container`), outermost first. 1 again once the document closed
+pub fn nesting<S: StateCheck<StateBit>>(state: &S) -> u64 {
+    STACK_BITS.iter().enumerate().filter(|&(_, &bit)| state.check(bit)).map(|(i, _)| 1 << i).sum()
+}
+
+fn set_nesting<S: StateCheck<StateBit>>(state: &mut S, stack: u64) {
+    for (i, &bit) in STACK_BITS.iter().enumerate() {
+        if (stack >> i) & 1 == 1 { state.on(bit) } else { state.off(bit) }
+    }
+}
+
+// Open a container on the stack. Past `MAX_NESTING` the stack would not fit its bits, so the state is invalid
+fn push<S: StateCheck<StateBit>>(state: &mut S, container: Container) {
+    let stack = 2 * nesting(state).max(1) + container as u64;
+    if stack >> STACK_BITS.len() == 0 {
+        set_nesting(state, stack);
+    } else {
+        *state = S::invalid();
+    }
+}
+
+// Close the innermost container. InArray follows the container around it right away, so that ',' and the closing
+// char after it are read against the right kind
+fn pop<S: StateCheck<StateBit>>(state: &mut S) {
+    let stack = nesting(state) / 2;
+    set_nesting(state, stack);
+    if stack > 1 && stack & 1 == Container::Array as u64 {
+        state.on(InArray);
+    } else {
+        state.off(InArray);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Container {
+    Object = 0,
+    Array = 1,
+}
+
+// Run the state machine over a full document. The stack bits only fit `MAX_NESTING` containers, so `walk` keeps the
+// full stack itself and hands the machine its innermost ones: any depth validates here, only the chip is bounded
+pub fn validate(input: &str) -> Result<(), ParseError> {
+    validate_with_max_elems(input, usize::MAX)
+}
+
+// As `validate`, but no object or array may hold more than `max_elems` members / elements. In circuit, see
+// `JsonConfigParams::max_elems`
+pub fn validate_with_max_elems(input: &str, max_elems: usize) -> Result<(), ParseError> {
+    walk(input.as_bytes(), input.char_indices(), max_elems, None)
+}
+
+// As `validate`, over raw bytes: each byte is one action, as in the circuit's transcript. Non-ASCII bytes are
+// Other, so this does not check UTF-8
+pub fn validate_bytes(bytes: &[u8]) -> Result<(), ParseError> {
+    walk(bytes, bytes.iter().map(|&b| b as char).enumerate(), usize::MAX, None)
+}
+
+// Validate, and return the document without whitespace outside strings
+pub fn minify(input: &str) -> Result<String, ParseError> {
+    let mut out = String::with_capacity(input.len());
+    walk(input.as_bytes(), input.char_indices(), usize::MAX, Some(&mut out))?;
+    Ok(out)
+}
+
+// The validation pass behind `validate_with_max_elems`, `validate_bytes` and `minify`. `chars` yields each char of
+// `bytes` with its byte index. Chars that are not blank outside a string are copied to `out`, if any
+fn walk(bytes: &[u8], chars: impl Iterator<Item = (usize, char)>, max_elems: usize, mut out: Option<&mut String>) -> Result<(), ParseError> {
+
+    let mut state = State::start();
+    let mut stack: Vec<(Container, usize, bool)> = Vec::new(); // open containers, their element counts, and whether an element may start
+    let mut closed = false;
+    let mut escape_end = 0; // end of the last \u escape checked by `unicode_escape_len`
+
+    for (idx, ch) in chars {
+
+        let action = SpecialChar::from(ch);
+        if closed {
+            if action == SpecialChar::WhiteSpace {
+                continue;
+            }
+            return Err(ParseError::TrailingChars(idx));
+        }
+
+        // An element is counted at its first non-blank char, after the container opens or after a ','. A trailing ','
+        // is then rejected by the state machine, not counted as another element
+        let starts_element = !matches!(action, SpecialChar::WhiteSpace | SpecialChar::CloseBrace | SpecialChar::CloseBracket);
+        if let Some((_, count, expecting)) = stack.last_mut() {
+            if *expecting && starts_element {
+                *count += 1;
+                *expecting = false;
+            }
+        }
+        if stack.last().map_or(false, |&(_, count, _)| count > max_elems) {
+            return Err(ParseError::TooManyElements(idx));
+        }
+
+        if !stack.is_empty() {
+            set_nesting(&mut state, innermost(&stack));
+        }
+        state = state.mutate(action);
+        if state.check(IsInvalid) {
+            return Err(ParseError::UnexpectedChar(idx));
+        }
+
+        // The second backslash of a surrogate pair was checked along with the first
+        if state.check(IsStrEscaped) && idx >= escape_end {
+            escape_end = idx + escape_len(bytes, idx)?;
+        }
+
+        if let Some(out) = out.as_mut() {
+            if action != SpecialChar::WhiteSpace || state.check(IsStr) {
+                out.push(ch);
+            }
+        }
+
+        if state.check(Separator) {
+            if let Some((_, _, expecting)) = stack.last_mut() {
+                *expecting = true;
+            }
+        }
+
+        if state.check(NewDict) {
+            stack.push((Container::Object, 0, true));
+        } else if state.check(NewArray) {
+            stack.push((Container::Array, 0, true));
+        } else if state.check_or(vec![EndDict, EndArray]) {
+            stack.pop();
+            closed = stack.is_empty();
+        }
+    }
+
+    if closed {
+        Ok(())
+    } else if state.is_null() {
+        Err(ParseError::Empty)
+    } else {
+        Err(ParseError::UnexpectedEnd(bytes.len()))
+    }
+}
+
+// The innermost open containers of `stack` as stack bits, leaving room for the machine to push one more. Two at least
+// stay, so that closing the innermost one never looks like the end of the document
+fn innermost(stack: &[(Container, usize, bool)]) -> u64 {
+    let kept = stack.len().min(MAX_NESTING - 1);
+    stack[stack.len() - kept..].iter().fold(1, |bits, &(container, _, _)| 2 * bits + container as u64)
+}
+
+// Length of the escape at `idx` (the backslash). The bitset takes any byte after a backslash, so the escape char is
+// checked here: one of "\/bfnrt, or u with its hex digits. Anything else is an InvalidEscape at the backslash
+pub(crate) fn escape_len(bytes: &[u8], idx: usize) -> Result<usize, ParseError> {
+    match bytes.get(idx + 1) {
+        Some(b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't') => Ok(2),
+        Some(b'u') => unicode_escape_len(bytes, idx),
+        Some(_) => Err(ParseError::InvalidEscape(idx)),
+        None => Ok(1), // the document ends in the string, an UnexpectedEnd
+    }
+}
+
+// Length of the \u escape at `idx` (the backslash): 6, or 12 for a surrogate pair. The bitset only sees the hex digits
+// as Numeric / Other, so the digits and the pairing are checked here, with the same errors as the parser:
+// an unpaired surrogate or a bad digit is an InvalidEscape at its backslash
+// TODO: off-circuit only. In circuit this needs action classes for the hex digits
+fn unicode_escape_len(bytes: &[u8], idx: usize) -> Result<usize, ParseError> {
+
+    let hex4 = |at: usize| -> Option<u32> {
+        let digits = bytes.get(at..at + 4)?;
+        if !digits.iter().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        u32::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+    };
+
+    let code = hex4(idx + 2).ok_or(ParseError::InvalidEscape(idx))?;
+    match code {
+        0xD800..=0xDBFF => {
+            let paired = bytes[idx + 6..].starts_with(b"\\u") && hex4(idx + 8).map_or(false, |low| (0xDC00..=0xDFFF).contains(&low));
+            if paired { Ok(12) } else { Err(ParseError::InvalidEscape(idx)) }
+        },
+        0xDC00..=0xDFFF => Err(ParseError::InvalidEscape(idx)),
+        _ => Ok(6),
+    }
+}
+
+// Enumerate every state reachable from the start state, as (encoding, bit names), sorted by encoding
+pub fn reachable_states() -> Vec<(u64, String)> {
+
+    let mut encodings: Vec<u64> = gen_lookup::bfs_gen_lookup_table().iter().map(|row| row.0).collect();
+    encodings.sort();
+    encodings.dedup();
+
+    encodings.into_iter().map(|id| {
+        let mut bits = State::decode(id).0;
+        bits.sort_by_key(|&bit| bit as u64);
+        let names = if bits.is_empty() {
+            String::from("Start")
+        } else {
+            bits.iter().map(|bit| format!("{:?}", bit)).collect::<Vec<String>>().join(" | ")
+        };
+        (id, names)
+    }).collect()
+}
+
+// Like `StateEncoding::decode`, but None for encodings that no input can reach (including out of range ones).
+// The decoding for ids that come from outside the table, e.g. a state carried in from a previous chunk
+pub fn decode_checked(id: u64) -> Option<State> {
+    reachable_states().binary_search_by_key(&id, |state| state.0).ok().map(|_| State::decode(id))
+}
+
+// The transition table as the circuit loads it: deduplicated, without transitions into the invalid state.
+// Each byte row comes with a row keyed on the byte's class id (see `with_class_rows`)
+pub fn transition_table() -> Vec<(StateId, StateId, ActionId)> {
+
+    let invalid: StateId = State::invalid().encode();
+    let rows: Vec<(StateId, StateId, ActionId)> = gen_lookup::bfs_gen_lookup_table()
+        .into_iter()
+        .map(|(before, after, c)| (StateId(before), StateId(after), ActionId::from(c)))
+        .filter(|row| row.1 != invalid)
+        .collect();
+    with_class_rows(rows)
+}
+
+// Add (begin, end, class id) for every (begin, end, byte) row, then sort and deduplicate. Every byte of a class
+// takes the same transition, so the class rows add one row per state and class. For the structural chars the
+// class id is the byte, so nothing is added
+pub fn with_class_rows(rows: Vec<(StateId, StateId, ActionId)>) -> Vec<(StateId, StateId, ActionId)> {
+
+    let mut table: Vec<(StateId, StateId, ActionId)> = rows.iter()
+        .filter_map(|&(begin, end, action)| SpecialChar::from_action(action).map(|c| (begin, end, ActionId::from(c))))
+        .collect();
+    table.extend(rows);
+    table.sort();
+    table.dedup();
+    table
+}
+
 // Generate a lookup table
 mod gen_lookup {
 
@@ -347,12 +799,14 @@ mod gen_lookup {
             let mut bfs_memory: HashSet<u64> = HashSet::new();
 
             // BFS
-            bfs_buffer.push(State::encode(&State::start()));
+            // A state is remembered once queued, so the buffer never holds it twice
+            let start = State::encode(&State::start());
+            bfs_buffer.push(start);
+            bfs_memory.insert(start);
             while !bfs_buffer.is_empty() {
 
                 let before = bfs_buffer.pop().unwrap();
                 let state = State::decode(before);
-                bfs_memory.insert(before);
 
                 for j in 0..=255 {
 
@@ -364,7 +818,7 @@ mod gen_lookup {
 
                     let row = (before, after, c);
                     lookup_table.push(row);
-                    if !bfs_memory.contains(&after) && !bfs_buffer.contains(&after){
+                    if bfs_memory.insert(after) {
                         bfs_buffer.push(after);
                     }
                 }
@@ -381,6 +835,31 @@ mod gen_lookup {
             use std::fs::File;
             use std::io::Write;
     
+            // FNV-1a over the sorted, deduplicated rows. Independent of the std hasher, so stable across toolchains
+            fn lookup_table_digest() -> u64 {
+
+                let mut table = bfs_gen_lookup_table();
+                table.sort();
+                table.dedup();
+
+                let mut digest: u64 = 0xcbf29ce484222325;
+                for (before, after, c) in table {
+                    let bytes = [before.to_le_bytes(), after.to_le_bytes(), (c as u64).to_le_bytes()].concat();
+                    for b in bytes {
+                        digest ^= b as u64;
+                        digest = digest.wrapping_mul(0x100000001b3);
+                    }
+                }
+                digest
+            }
+
+            // Guards the grammar: if this fails, the lookup table changed.
+            // When the change is intentional, update the expected digest (and regenerate data/lookup_table.txt)
+            #[test]
+            fn test_lookup_table_snapshot() {
+                assert_eq!(lookup_table_digest(), 0x33425f97fef9f057, "lookup table changed: {:#018x}", lookup_table_digest());
+            }
+
             #[test]
             fn test_gen_lookup_table() {
 
@@ -444,6 +923,18 @@ mod tests {
         assert_eq!(state.check_or(vec![IsValue, IsKey]), true);
     }
 
+    #[test]
+    fn test_from_bits() {
+
+        let state = State::from_bits(&[IsKey, WordComplete, IsKey]);
+        assert_eq!(state.bits(), &[IsKey, WordComplete]);
+        assert_eq!(StateEncoding::<u64>::encode(&state), (1 << 4) | (1 << 10));
+        assert_eq!(State::from_bits(&[]), State::start());
+
+        // A key that was read can take the ':'
+        assert_eq!(state.mutate(SpecialChar::Colon), State::from_bits(&[KeyValueDelimiter]));
+    }
+
     #[test]
     fn test_state_mutation() {
         
@@ -461,6 +952,347 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_escaped_slash() {
+
+        let input = "{\"a\": \"a\\/b\"}".to_string();
+        let mut state = State::start();
+
+        for c in input.chars() {
+            state = state.mutate(SpecialChar::from(c));
+            state.assert_valid();
+        }
+        assert!(!state.check(IsStr));
+    }
+
+    #[test]
+    fn test_validate_arrays() {
+
+        assert!(validate("{\"a\":[1,2]}").is_ok());
+        assert!(validate("[1, 2, 3]").is_ok());
+        assert!(validate("[{\"a\": 1}, {\"b\": [\"x\", \"y\"]}]").is_ok());
+        assert!(validate("{\"a\": [[1], [2]], \"b\": 3}").is_ok());
+
+        assert_eq!(validate("[1:2]"), Err(ParseError::UnexpectedChar(2)));
+        assert_eq!(validate("{\"a\": [1}"), Err(ParseError::UnexpectedChar(8)));
+
+        // A ',' needs an element after it, even across whitespace
+        assert_eq!(validate("[1,]"), Err(ParseError::UnexpectedChar(3)));
+        assert_eq!(validate("{\"a\":[1,]}"), Err(ParseError::UnexpectedChar(8)));
+        assert_eq!(validate("[1 , ]"), Err(ParseError::UnexpectedChar(5)));
+        assert_eq!(validate("[[1],]"), Err(ParseError::UnexpectedChar(5)));
+        assert!(validate("[1, [], [2]]").is_ok());
+        assert_eq!(validate("[1]]"), Err(ParseError::TrailingChars(3)));
+        assert_eq!(validate("{\"a\": [1]"), Err(ParseError::UnexpectedEnd(9)));
+    }
+
+    #[test]
+    fn test_nesting() {
+
+        // Every step is a row of the table the chip looks up, and the document ends with the stack back to 1
+        let table = transition_table();
+        for input in ["{\"a\":[1,2]}", "[{\"a\":1}]", "{}", "[[1],{\"b\":{}}]", "{\"a\":{},\"b\":[]}", "[[[1]]]"] {
+            assert_eq!(validate(input), Ok(()), "{}", input);
+            let mut state: StateId = State::start().encode();
+            for b in input.bytes() {
+                let next: StateId = State::decode(state).mutate(SpecialChar::from(b as char)).encode();
+                assert!(table.contains(&(state, next, ActionId::from(b))), "{}", input);
+                state = next;
+            }
+            assert_eq!(nesting(&State::decode(state)), 1, "{}", input);
+        }
+
+        // Only an empty object may close on a pending key
+        assert_eq!(validate("{\"a\"}"), Err(ParseError::UnexpectedChar(4)));
+        assert_eq!(validate("{,}"), Err(ParseError::UnexpectedChar(1)));
+        assert_eq!(validate("[{}]"), Ok(()));
+
+        // A container closes with its own kind, and closing a nested one does not end the document
+        assert_eq!(validate("[{\"a\":1]"), Err(ParseError::UnexpectedChar(7)));
+        assert_eq!(validate("{\"a\":[1}"), Err(ParseError::UnexpectedChar(7)));
+        assert_eq!(validate("[[1]"), Err(ParseError::UnexpectedEnd(4)));
+
+        // After the end the table only takes whitespace, so trailing chars have no row
+        let closed: StateId = State::start().mutate(SpecialChar::OpenBrace).mutate(SpecialChar::CloseBrace).mutate(SpecialChar::WhiteSpace).encode();
+        assert!(table.iter().any(|&(begin, _, action)| begin == closed && action == ActionId::from(b' ')));
+        assert!(!table.iter().any(|&(begin, _, action)| begin == closed && action == ActionId::from(b',')));
+
+        // The stack bits hold MAX_NESTING containers, but `validate` keeps its own stack: deeper documents still
+        // validate, and still close each container with its own kind. Brackets in strings don't count
+        assert_eq!(validate("[[[[1]]]]"), Ok(()));
+        assert_eq!(validate("{\"a\":[[{\"b\":1}]]}"), Ok(()));
+        assert_eq!(validate("[[[[[[[[[[{\"a\":[1,{}]}]]]]]]]]]]"), Ok(()));
+        assert_eq!(validate("[[[{\"a\":1]]]]"), Err(ParseError::UnexpectedChar(9)));
+        assert_eq!(validate("[[[[1]}]]"), Err(ParseError::UnexpectedChar(6)));
+        assert_eq!(validate("[[[[1]]]"), Err(ParseError::UnexpectedEnd(8)));
+        assert_eq!(minify("[ [ [ [ 1 ] ] ] ]"), Ok(String::from("[[[[1]]]]")));
+        assert_eq!(validate("[\"[[[[\"]"), Ok(()));
+    }
+
+    #[test]
+    fn test_colon_guard() {
+        assert!(validate("{\"a\":1}").is_ok());
+        assert!(validate("{\"a\" : 1}").is_ok());
+        assert_eq!(validate("{\"a\"::1}"), Err(ParseError::UnexpectedChar(5)));
+        assert_eq!(validate("{:1}"), Err(ParseError::UnexpectedChar(1)));
+        assert_eq!(validate("{\"a\":1:2}"), Err(ParseError::UnexpectedChar(6)));
+    }
+
+    #[test]
+    fn test_trailing_comma_in_object() {
+        assert!(validate("{\"a\":1}").is_ok());
+        assert!(validate("{\"a\":{\"b\":1},\"c\":2}").is_ok());
+        assert_eq!(validate("{\"a\":1,}"), Err(ParseError::UnexpectedChar(7)));
+        assert_eq!(validate("{\"a\":1 , }"), Err(ParseError::UnexpectedChar(9)));
+        assert_eq!(validate("{\"a\":{\"b\":1,}}"), Err(ParseError::UnexpectedChar(12)));
+
+        let state = State::start().mutate(SpecialChar::OpenBrace).mutate(SpecialChar::Comma);
+        assert!(state.check(IsInvalid));
+    }
+
+    #[test]
+    fn test_validate_max_elems() {
+
+        let object = "{\"a\": 1, \"b\": 2, \"c\": 3, \"d\": 4, \"e\": 5}";
+        assert!(validate_with_max_elems(object, 5).is_ok());
+        assert_eq!(validate_with_max_elems(object, 4), Err(ParseError::TooManyElements(33)));
+
+        // Each container counts separately, and nested containers count as one element of their parent
+        let nested = "{\"a\": [1, 2, 3], \"b\": [[1, 2], [3]]}";
+        assert!(validate_with_max_elems(nested, 3).is_ok());
+        assert_eq!(validate_with_max_elems(nested, 2), Err(ParseError::TooManyElements(13)));
+
+        assert!(validate_with_max_elems("[]", 0).is_ok());
+        assert_eq!(validate_with_max_elems("[1]", 0), Err(ParseError::TooManyElements(1)));
+
+        // A trailing ',' is rejected as such, not counted as another element
+        assert_eq!(validate_with_max_elems("[1,]", 1), Err(ParseError::UnexpectedChar(3)));
+    }
+
+    #[test]
+    fn test_typed_ids() {
+
+        let start: StateId = State::start().encode();
+        assert_eq!(start, StateId(0));
+        assert_eq!(State::decode(StateId(1 << 5)), State(vec![IsValue]));
+        assert_eq!(ActionId::from(b'{'), ActionId::from('{'));
+
+        // Every row of the table round-trips through the typed ids, byte and class rows alike
+        for (begin, end, action) in transition_table() {
+            let c = SpecialChar::from_action(action).expect("table action should be a byte or a class");
+            let next: StateId = State::decode(begin).mutate(c).encode();
+            assert_eq!(next, end);
+        }
+    }
+
+    #[test]
+    fn test_class_rows() {
+
+        let table = transition_table();
+        let start: StateId = State::start().encode();
+        let new_dict: StateId = State::start().mutate(SpecialChar::OpenBrace).encode();
+
+        // The class id takes the same transition as each byte in the class
+        assert!(table.contains(&(start, start, ActionId::from(' '))));
+        assert!(table.contains(&(start, start, ActionId::from(SpecialChar::WhiteSpace))));
+        assert!(table.contains(&(start, new_dict, ActionId::from(SpecialChar::OpenBrace))));
+        assert!(!table.iter().any(|&(begin, _, action)| begin == start && action == ActionId::from(SpecialChar::Other)));
+
+        assert_eq!(SpecialChar::from_action(ActionId::from(b'7')), Some(SpecialChar::Numeric));
+        assert_eq!(SpecialChar::from_action(ActionId::from(SpecialChar::Numeric)), Some(SpecialChar::Numeric));
+        assert_eq!(SpecialChar::from_action(ActionId(0x103)), None);
+    }
+
+    #[test]
+    fn test_number_termination() {
+
+        for input in ["{\"a\":123}", "[1,2]", "{\"a\":1.5}", "{\"a\":[1],\"b\":2}", "[[1],2]"] {
+            assert_eq!(validate(input), Ok(()), "{}", input);
+        }
+
+        // ',' and '}' need a value before them
+        assert_eq!(validate("{\"a\":}"), Err(ParseError::UnexpectedChar(5)));
+        assert_eq!(validate("{\"a\": ,\"b\":1}"), Err(ParseError::UnexpectedChar(6)));
+        assert_eq!(validate("[,1]"), Err(ParseError::UnexpectedChar(1)));
+
+        // A decimal point needs a digit before it
+        assert_eq!(validate("{\"a\":0.5}"), Ok(()));
+        assert_eq!(validate("{\"a\":.5}"), Err(ParseError::UnexpectedChar(5)));
+        assert_eq!(validate("[1, .5]"), Err(ParseError::UnexpectedChar(4)));
+        assert_eq!(validate("{\"a\":\".5\"}"), Ok(()));
+
+        // And only one per number
+        assert_eq!(validate("{\"a\":1.2.3}"), Err(ParseError::UnexpectedChar(8)));
+        assert_eq!(validate("[1.5, 2.5, 3]"), Ok(()));
+        assert_eq!(validate("[1.5 , 2.5]"), Ok(()));
+        assert_eq!(validate("{\"a\":\"1.2.3\"}"), Ok(()));
+
+        // And a digit after it
+        assert_eq!(validate("{\"a\":1.}"), Err(ParseError::UnexpectedChar(7)));
+        assert_eq!(validate("[1.,2]"), Err(ParseError::UnexpectedChar(3)));
+        assert_eq!(validate("[1.]"), Err(ParseError::UnexpectedChar(3)));
+        assert_eq!(validate("{\"a\":1. }"), Err(ParseError::UnexpectedChar(7)));
+        assert_eq!(validate("{\"a\":1.\"b\"}"), Err(ParseError::UnexpectedChar(7)));
+        assert_eq!(validate("{\"a\":10.25 }"), Ok(()));
+    }
+
+    #[test]
+    fn test_minify() {
+
+        assert_eq!(minify("{\"a\": 1}"), Ok(String::from("{\"a\":1}")));
+        assert_eq!(
+            minify(" {\n\t\"a b\" : [ 1 , \"c d\" ],\r\n \"e\\\" \": {\"f\" : 2 } }\n"),
+            Ok(String::from("{\"a b\":[1,\"c d\"],\"e\\\" \":{\"f\":2}}"))
+        );
+
+        // Errors are those of `validate`, at the same index
+        for input in ["{\"a\": 1,}", "{\"a\" 1}", "{\"a\": 1} x", "  ", "{\"a\": "] {
+            assert_eq!(minify(input).err(), validate(input).err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_validate_bytes() {
+
+        for input in ["{\"a\": 1}", "{\"a\": 1,}", "{\"a\":1}}", "{\"a\":\"\\ud800\"}", "  ", "{\"a\": "] {
+            assert_eq!(validate_bytes(input.as_bytes()), validate(input), "{}", input);
+        }
+
+        // Non-ASCII bytes are each one Other action, so the indices are byte indices either way
+        assert_eq!(validate_bytes("{\"é\":1}".as_bytes()), Ok(()));
+        assert_eq!(validate_bytes("{\"é\" 1}".as_bytes()), Err(ParseError::UnexpectedChar(6)));
+        assert_eq!(validate("{\"é\" 1}"), Err(ParseError::UnexpectedChar(6)));
+    }
+
+    #[test]
+    fn test_validate_surrogates() {
+
+        assert_eq!(validate("[\"\\uD83D\\uDE00\"]"), Ok(()));
+        assert_eq!(validate("{\"a\\u00e9\": \"x\\ud83d\\ude00y\"}"), Ok(()));
+
+        assert_eq!(validate("[\"\\uD83D\"]"), Err(ParseError::InvalidEscape(2)));
+        assert_eq!(validate("[\"\\uDE00\"]"), Err(ParseError::InvalidEscape(2)));
+        assert_eq!(validate("[\"ab\\uD83D\\u0041\"]"), Err(ParseError::InvalidEscape(4)));
+        assert_eq!(validate("[\"\\u12G4\"]"), Err(ParseError::InvalidEscape(2)));
+        assert_eq!(validate("[\"\\u12"), Err(ParseError::InvalidEscape(2)));
+
+        // Only escapes: an escaped backslash followed by 'u' is just text
+        assert_eq!(validate("[\"\\\\uD83D\"]"), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_escapes() {
+
+        assert_eq!(validate("[\"\\\"\\\\\\/\\b\\f\\n\\r\\t\"]"), Ok(()));
+        assert_eq!(validate("{\"a\\n\": \"\\u0041\"}"), Ok(()));
+
+        assert_eq!(validate("[\"\\q\"]"), Err(ParseError::InvalidEscape(2)));
+        assert_eq!(validate("{\"a\": \"x\\'\"}"), Err(ParseError::InvalidEscape(8)));
+        assert_eq!(validate("[\"\\ \"]"), Err(ParseError::InvalidEscape(2)));
+        assert_eq!(validate_bytes(b"[\"\\q\"]"), Err(ParseError::InvalidEscape(2)));
+        assert_eq!(validate("[\"\\"), Err(ParseError::UnexpectedEnd(3)));
+    }
+
+    #[test]
+    fn test_validate_empty() {
+        assert_eq!(validate(""), Err(ParseError::Empty));
+        assert_eq!(validate("   "), Err(ParseError::Empty));
+        assert_eq!(validate(" \n\t"), Err(ParseError::Empty));
+    }
+
+    #[test]
+    fn test_reachable_states() {
+
+        let states = reachable_states();
+
+        // Update deliberately when the grammar changes
+        assert_eq!(states.len(), 308);
+        assert_eq!(states[0], (0, String::from("Start")));
+        assert!(states.iter().any(|(id, _)| *id == 1));
+    }
+
+    #[test]
+    fn test_encoding_round_trip() {
+
+        // Every state the BFS visits, both as a source and as a target
+        let table = gen_lookup::bfs_gen_lookup_table();
+        for &(before, after, c) in table.iter() {
+            let state = State::decode(before);
+            assert_eq!(StateEncoding::<u64>::encode(&state), before);
+            assert_eq!(StateEncoding::<u64>::encode(&state.mutate(SpecialChar::from(c))), after);
+        }
+
+        let mut targets: Vec<u64> = table.iter().map(|row| row.1).collect();
+        targets.sort();
+        targets.dedup();
+        for id in targets {
+            let decoded = decode_checked(id).expect("BFS target should be reachable");
+            assert_eq!(StateEncoding::<u64>::encode(&decoded), id);
+        }
+
+        // Reachable ids only: bits that never occur together, and bits past NUM_STATE_BITS
+        assert_eq!(decode_checked(0), Some(State::start()));
+        assert_eq!(decode_checked((1 << NUM_STATE_BITS) - 1), None);
+        assert_eq!(decode_checked(1 << NUM_STATE_BITS), None);
+        assert_eq!(decode_checked(u64::MAX), None);
+    }
+
+    #[test]
+    fn test_word_lifecycle() {
+
+        let input = "{\"a\":\"b\"} ";
+        let mut state = State::start();
+        let mut complete = vec![];
+        let mut buffering = vec![];
+
+        for c in input.chars() {
+            state = state.mutate(SpecialChar::from(c));
+            state.assert_valid();
+            complete.push(state.check(WordComplete));
+            buffering.push(state.check(WordBuffering));
+        }
+
+        //                     {      "      a      "      :      "      b      "      }      ' '
+        assert_eq!(complete,  vec![false, false, false, true,  false, false, false, true,  false, true]);
+        assert_eq!(buffering, vec![false, true,  true,  false, false, true,  true,  false, false, false]);
+
+        let mut state = State(vec![IsValue, WordComplete, WordBuffering]);
+        state.reset_word();
+        assert_eq!(state, State(vec![IsValue]));
+    }
+
+    #[test]
+    fn test_special_char_ids() {
+
+        use SpecialChar::*;
+        let all = [Backslash, DoubleQuote, OpenBrace, CloseBrace, OpenBracket, CloseBracket, Colon, Comma, DecimalPoint, WhiteSpace, Numeric, Other];
+
+        let mut ids: Vec<u64> = all.iter().map(|c| c.id()).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), all.len());
+
+        // Every byte classifies to either its own id or a class id above 0xff
+        for b in 0..=255u8 {
+            let id = SpecialChar::from(b as char).id();
+            assert!(id == b as u64 || id > 0xff, "byte {:#x} classified with colliding id {:#x}", b, id);
+        }
+    }
+
+    #[test]
+    fn test_state_bits_distinct() {
+
+        let mut encodings: Vec<u64> = StateBit::ALL.iter().map(|&bit| State(vec![bit]).encode()).collect();
+        encodings.sort();
+        encodings.dedup();
+        assert_eq!(encodings.len(), NUM_STATE_BITS);
+
+        for (i, &bit) in StateBit::ALL.iter().enumerate() {
+            assert_eq!(bit as usize, i);
+            assert_eq!(State::decode(1u64 << i), State(vec![bit]));
+        }
+    }
+
     #[test]
     fn test_encoding_decoding() {
         
@@ -5,4 +5,5 @@
 
 pub mod state_machine;
 pub mod json_state_machine;
-pub mod json_gate;
\ No newline at end of file
+pub mod json_gate;
+pub mod json_parser;
\ No newline at end of file